@@ -0,0 +1,177 @@
+//! Benchmarks of the discovery of tests: walking a build tree, probing the ELF files it contains
+//! and enumerating the tests of the test executables.
+//!
+//! The build tree is generated in the target directory, with executables compiled by the C++
+//! compiler of the system. Run with `cargo bench -p cpp_test_runner`, and set
+//! `CPP_TEST_RUNNER_BENCH_SCALE` to change the size of the tree (default: 1). Criterion compares
+//! every run with the previous one, or with a baseline saved with `-- --save-baseline <name>` and
+//! compared with `-- --baseline <name>`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use elf_parser::{Elf, Section};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// A fake gtest executable listing as many tests as its first compile definition asks for
+const FAKE_GTEST: &str = r#"
+#include <cstdio>
+#include <string>
+namespace testing { void InitGoogleTest(int*, char**) {} }
+int main(int argc, char** argv) {
+    testing::InitGoogleTest(&argc, argv);
+    std::string output;
+    for (int i = 1; i < argc; ++i) {
+        std::string arg = argv[i];
+        if (arg.rfind("--gtest_output=json:", 0) == 0) output = arg.substr(20);
+    }
+    FILE* file = fopen(output.c_str(), "w");
+    if (!file) return 1;
+    fprintf(file, "{\"testsuites\":[");
+    for (int suite = 0; suite < NUM_TESTS / 100; ++suite) {
+        fprintf(file, "%s{\"name\":\"Suite%d\",\"testsuite\":[", suite ? "," : "", suite);
+        for (int test = 0; test < 100; ++test) {
+            fprintf(file, "%s{\"name\":\"Test%d\",\"file\":\"test.cpp\",\"line\":%d}",
+                    test ? "," : "", test, test);
+        }
+        fprintf(file, "]}");
+    }
+    fprintf(file, "]}\n");
+    fclose(file);
+    return 0;
+}
+"#;
+
+const NOT_A_TEST: &str = "int main() { return 0; }\n";
+
+struct BuildTree {
+    root: PathBuf,
+    elf_files: Vec<PathBuf>,
+}
+
+fn compile(compiler: &str, source: &str, output: &Path, args: &[&str]) -> bool {
+    let source_path = output.with_extension("cpp");
+    fs::write(&source_path, source).unwrap();
+    Command::new(compiler)
+        .args(args)
+        .arg("-o")
+        .arg(output)
+        .arg(&source_path)
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn generate_build_tree(compiler: &str, scale: usize) -> Option<BuildTree> {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("discovery_bench");
+    if root.exists() {
+        fs::remove_dir_all(&root).unwrap();
+    }
+    let sources = root.join("sources");
+    let tree = root.join("build");
+    fs::create_dir_all(&sources).unwrap();
+
+    let test_executable = sources.join("fake_gtest");
+    let other_executable = sources.join("not_a_test");
+    if !compile(
+        compiler,
+        FAKE_GTEST,
+        &test_executable,
+        &["-O1", "-DNUM_TESTS=1000"],
+    ) || !compile(compiler, NOT_A_TEST, &other_executable, &["-O1"])
+    {
+        return None;
+    }
+
+    // Most of a build tree is made of object files and executables that aren't tests
+    let mut elf_files = Vec::new();
+    for directory in 0..100 * scale {
+        let directory = tree.join(format!("module{directory}"));
+        fs::create_dir_all(directory.join("CMakeFiles")).unwrap();
+        for file in 0..20 {
+            fs::write(
+                directory.join("CMakeFiles").join(format!("file{file}.o.d")),
+                "dependencies",
+            )
+            .unwrap();
+        }
+
+        let tool = directory.join("tool");
+        fs::copy(&other_executable, &tool).unwrap();
+        elf_files.push(tool);
+
+        if elf_files.len() % 20 == 1 {
+            let tests = directory.join("tests");
+            fs::copy(&test_executable, &tests).unwrap();
+            elf_files.push(tests);
+        }
+    }
+
+    Some(BuildTree {
+        root: tree,
+        elf_files,
+    })
+}
+
+fn list(tree: &Path, extra_args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_cpp_test_runner"))
+        .args(["list", "--output", "plain", "--test-dir"])
+        .arg(tree)
+        .args(extra_args)
+        .env("XDG_CACHE_HOME", tree.with_file_name("cache"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "cpp_test_runner list failed");
+}
+
+fn discovery(c: &mut Criterion) {
+    let scale = std::env::var("CPP_TEST_RUNNER_BENCH_SCALE")
+        .ok()
+        .and_then(|scale| scale.parse().ok())
+        .unwrap_or(1);
+
+    let compiler = std::env::var("CXX").unwrap_or_else(|_| String::from("c++"));
+    let Some(tree) = generate_build_tree(&compiler, scale) else {
+        println!("Skipping the discovery benchmarks, {compiler} can't compile the executables");
+        return;
+    };
+
+    // Every iteration walks the whole tree, so fewer samples than the default are enough
+    let mut group = c.benchmark_group("discovery");
+    group.sample_size(10);
+
+    group.bench_function("ELF probing", |b| {
+        b.iter(|| {
+            for path in &tree.elf_files {
+                let elf = Elf::new(path).unwrap();
+                let headers = elf.get_all_section_headers().unwrap();
+                let symbol_table = headers.find_symbol_table_header().unwrap();
+                let strings = headers.find_linked_header(symbol_table).unwrap();
+                let (Section::Symbols(_), Section::Strings(_)) = (
+                    elf.get_section(symbol_table).unwrap(),
+                    elf.get_section(strings).unwrap(),
+                ) else {
+                    panic!("{} has no symbol table", path.display());
+                };
+            }
+        })
+    });
+
+    group.bench_function("Tree walk and ELF probing", |b| {
+        b.iter(|| list(&tree.root, &["--executables-only", "--no-cache"]))
+    });
+
+    group.bench_function("Enumeration of 1000 tests per executable", |b| {
+        b.iter(|| list(&tree.root, &["--no-cache"]))
+    });
+
+    group.bench_function("Enumeration from the discovery cache", |b| {
+        b.iter(|| list(&tree.root, &[]))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, discovery);
+criterion_main!(benches);
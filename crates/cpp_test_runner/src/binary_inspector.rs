@@ -0,0 +1,216 @@
+use anyhow::{bail, Result};
+use elf_parser::{pe::Pe, string_at, Elf, SectionHeader, SectionHeaders};
+use std::{cell::OnceCell, path::Path};
+
+/// What the discovery reads from a test binary, whatever its object format, so that the
+/// recognition of the frameworks and the metadata of the executables don't depend on ELF. ELF and
+/// PE implement it, and `inspect` picks the implementation from the content of the file.
+pub trait TestBinaryInspector {
+    /// Like x86-64 or aarch64, or the hexadecimal value of the less common architectures
+    fn machine(&self) -> String;
+
+    /// Like executable or shared-object, in the words of the format
+    fn kind(&self) -> String;
+
+    /// Whether the binary can be run, unlike the libraries and the object files
+    fn is_runnable(&self) -> bool;
+
+    /// The shared libraries that the binary needs, like libgtest.so.1.14 or gtest.dll
+    fn needed_libraries(&self) -> Result<Vec<String>>;
+
+    /// Whether only a part of the symbols are in the binary, like the dynamic symbols of a
+    /// stripped ELF, or none at all, like in a PE, whose symbols are in a PDB file
+    fn is_stripped(&self) -> bool;
+
+    /// Returns the first symbol for which `predicate` is true. The symbols are only read until
+    /// then.
+    fn find_symbol(&self, predicate: &mut dyn FnMut(&str) -> bool) -> Result<Option<String>>;
+
+    /// The content of the sections with the strings that the code embeds, like .rodata
+    fn string_sections(&self) -> Result<Vec<Vec<u8>>>;
+
+    /// What identifies the build of the binary, in hexadecimal, like the GNU build-id
+    fn build_id(&self) -> Option<String>;
+
+    /// Whether the debug info is in the binary, or referenced by it
+    fn has_debug_info(&self) -> bool;
+}
+
+/// Opens the binary with the inspector of its format, or returns None when it's neither an ELF
+/// nor a PE
+pub fn inspect(path: &Path) -> Result<Option<Box<dyn TestBinaryInspector>>> {
+    match Elf::new(path) {
+        Ok(elf) => return Ok(Some(Box::new(ElfInspector::new(elf)))),
+        Err(elf_parser::Error::NotAnElf) => {}
+        Err(error) => return Err(error.into()),
+    }
+    match Pe::new(path) {
+        Ok(pe) => Ok(Some(Box::new(PeInspector { pe }))),
+        Err(elf_parser::Error::NotAPe) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub struct ElfInspector {
+    elf: Elf,
+    // Only read when the program headers aren't enough
+    headers: OnceCell<SectionHeaders>,
+}
+
+impl ElfInspector {
+    pub fn new(elf: Elf) -> Self {
+        Self {
+            elf,
+            headers: OnceCell::new(),
+        }
+    }
+
+    fn headers(&self) -> Result<&SectionHeaders> {
+        if let Some(headers) = self.headers.get() {
+            return Ok(headers);
+        }
+        let headers = self.elf.get_all_section_headers()?;
+        Ok(self.headers.get_or_init(|| headers))
+    }
+
+    fn find_symbol_in(
+        &self,
+        symbol_table_header: &SectionHeader,
+        predicate: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<Option<String>> {
+        let Some(string_table_header) = self.headers()?.find_linked_header(symbol_table_header)
+        else {
+            bail!("Invalid ELF");
+        };
+        if string_table_header.sh_type() != 0x3 {
+            bail!("Invalid ELF");
+        }
+        let strings = self.elf.get_section_data(string_table_header)?;
+
+        // The symbols are decoded lazily, so the search stops at the first match
+        Ok(self.elf.symbols(symbol_table_header)?.find_map(|symbol| {
+            let name = string_at(&strings, symbol.st_name as usize)?.to_string_lossy();
+            predicate(&name).then(|| name.into_owned())
+        }))
+    }
+}
+
+impl TestBinaryInspector for ElfInspector {
+    fn machine(&self) -> String {
+        match self.elf.header.machine_name() {
+            Some(machine) => machine.to_string(),
+            None => format!("{:#x}", self.elf.header.e_machine()),
+        }
+    }
+
+    fn kind(&self) -> String {
+        match self.elf.header.e_type() {
+            0x02 => "executable".to_string(),
+            0x03 => "shared-object".to_string(),
+            elf_type => format!("{elf_type:#x}"),
+        }
+    }
+
+    // The position-independent executables are shared objects
+    fn is_runnable(&self) -> bool {
+        matches!(self.elf.header.e_type(), 0x02 | 0x03)
+    }
+
+    // From the program headers, which is faster than reading the symbols, and they are kept by
+    // stripped executables
+    fn needed_libraries(&self) -> Result<Vec<String>> {
+        Ok(self.elf.get_needed_libraries()?)
+    }
+
+    fn is_stripped(&self) -> bool {
+        self.headers()
+            .map(|headers| headers.find_symbol_table_header().is_none())
+            .unwrap_or(true)
+    }
+
+    // Stripped executables only keep their dynamic symbols
+    fn find_symbol(&self, predicate: &mut dyn FnMut(&str) -> bool) -> Result<Option<String>> {
+        let headers = self.headers()?;
+        match headers
+            .find_symbol_table_header()
+            .or_else(|| headers.find_dynamic_symbol_table_header())
+        {
+            Some(symbol_table_header) => self.find_symbol_in(symbol_table_header, predicate),
+            None => Ok(None),
+        }
+    }
+
+    fn string_sections(&self) -> Result<Vec<Vec<u8>>> {
+        let headers = self.headers()?;
+        [".rodata", ".dynstr"]
+            .iter()
+            .filter_map(|name| headers.find_by_name(name))
+            .map(|header| Ok(self.elf.get_section_data(header)?))
+            .collect()
+    }
+
+    fn build_id(&self) -> Option<String> {
+        let build_id = self.elf.get_build_id().ok()??;
+        Some(build_id.iter().map(|byte| format!("{byte:02x}")).collect())
+    }
+
+    fn has_debug_info(&self) -> bool {
+        self.headers()
+            .is_ok_and(|headers| headers.find_by_name(".debug_info").is_some())
+    }
+}
+
+pub struct PeInspector {
+    pe: Pe,
+}
+
+impl TestBinaryInspector for PeInspector {
+    fn machine(&self) -> String {
+        match self.pe.machine_name() {
+            Some(machine) => machine.to_string(),
+            None => format!("{:#x}", self.pe.machine),
+        }
+    }
+
+    fn kind(&self) -> String {
+        if self.pe.is_dll() {
+            "dll".to_string()
+        } else {
+            "executable".to_string()
+        }
+    }
+
+    fn is_runnable(&self) -> bool {
+        !self.pe.is_dll()
+    }
+
+    fn needed_libraries(&self) -> Result<Vec<String>> {
+        Ok(self.pe.get_imported_dlls()?)
+    }
+
+    fn is_stripped(&self) -> bool {
+        true
+    }
+
+    fn find_symbol(&self, _predicate: &mut dyn FnMut(&str) -> bool) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn string_sections(&self) -> Result<Vec<Vec<u8>>> {
+        [".rdata", ".data"]
+            .iter()
+            .filter_map(|name| self.pe.find_section(name))
+            .map(|section| Ok(self.pe.get_section_data(section)?))
+            .collect()
+    }
+
+    // The signature of the PDB file
+    fn build_id(&self) -> Option<String> {
+        self.pe.get_pdb_id().ok()?
+    }
+
+    // The debug info of a PE is in the PDB file that it references
+    fn has_debug_info(&self) -> bool {
+        self.build_id().is_some()
+    }
+}
@@ -0,0 +1,302 @@
+use crate::BisectCommand;
+use anyhow::{anyhow, bail, Result};
+use cpp_test_runner::{types::TestStatus, TestDiscovery, TestRun};
+use std::{
+    cmp::Ordering,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// What a version tells about the test
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Good,
+    Bad,
+    /// The version can't tell, like when it doesn't build or doesn't have the test
+    Skip,
+}
+
+/// A version to bisect: a build directory, or a git revision that is built in the working tree
+struct Version {
+    label: String,
+    revision: Option<String>,
+    test_dir: PathBuf,
+}
+
+/// Finds the first bad version between the good first version and the bad last version, checking
+/// the one closest to the middle of the untested versions between the last good and the first bad
+/// ones until none is left. The skipped versions are left untested. Returns the last good and the
+/// first bad version.
+pub fn search(
+    count: usize,
+    mut check: impl FnMut(usize) -> Result<Verdict>,
+) -> Result<(usize, usize)> {
+    let mut skipped = vec![false; count];
+    let (mut good, mut bad) = (0, count.saturating_sub(1));
+    loop {
+        let middle = (good + bad) / 2;
+        let Some(next) = (good + 1..bad)
+            .filter(|&version| !skipped[version])
+            .min_by_key(|version| version.abs_diff(middle))
+        else {
+            return Ok((good, bad));
+        };
+        match check(next)? {
+            Verdict::Good => good = next,
+            Verdict::Bad => bad = next,
+            Verdict::Skip => skipped[next] = true,
+        }
+    }
+}
+
+// Compares the names with their numbers compared by value, so that v9 is before v10
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    fn chunks(name: &str) -> Vec<(bool, &str)> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for (index, char) in name.char_indices().skip(1) {
+            let previous = name[..index].chars().next_back().unwrap();
+            if previous.is_ascii_digit() != char.is_ascii_digit() {
+                chunks.push((previous.is_ascii_digit(), &name[start..index]));
+                start = index;
+            }
+        }
+        if start < name.len() {
+            let is_number = name[start..].starts_with(|char: char| char.is_ascii_digit());
+            chunks.push((is_number, &name[start..]));
+        }
+        chunks
+    }
+
+    for (a, b) in chunks(a).into_iter().zip(chunks(b)) {
+        let ordering = match (a, b) {
+            ((true, a), (true, b)) => {
+                let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            }
+            ((_, a), (_, b)) => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.cmp(b)
+}
+
+// The good and the bad directories, and the directories between them in their parent
+fn directory_versions(good: &Path, bad: &Path) -> Result<Vec<Version>> {
+    let canonicalize = |dir: &Path| {
+        if !dir.is_dir() {
+            bail!("{} is not a directory", dir.display());
+        }
+        Ok(dir.canonicalize()?)
+    };
+    // Labeled like they are given, instead of with their canonical path
+    let given_parent = good.parent().unwrap_or(Path::new(""));
+    let (good, bad) = (canonicalize(good)?, canonicalize(bad)?);
+    let parent = good.parent().unwrap_or(&good);
+    if bad.parent() != Some(parent) {
+        bail!(
+            "{} and {} must be in the same directory, with the versions between them",
+            good.display(),
+            bad.display()
+        );
+    }
+
+    let name = |dir: &Path| {
+        dir.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let mut dirs = fs::read_dir(parent)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    dirs.sort_by(|a, b| natural_cmp(&name(a), &name(b)));
+    let position = |dir: &Path| {
+        dirs.iter()
+            .position(|other| other == dir)
+            .unwrap_or_default()
+    };
+    let (good_position, bad_position) = (position(&good), position(&bad));
+    let mut dirs = if good_position <= bad_position {
+        dirs[good_position..=bad_position].to_vec()
+    } else {
+        // The versions fail since the older ones
+        dirs[bad_position..=good_position]
+            .iter()
+            .rev()
+            .cloned()
+            .collect()
+    };
+    dirs.dedup();
+
+    Ok(dirs
+        .into_iter()
+        .map(|dir| Version {
+            label: given_parent.join(name(&dir)).display().to_string(),
+            revision: None,
+            test_dir: dir,
+        })
+        .collect())
+}
+
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|error| anyhow!("Failed to run git: {error}"))?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// The good revision, and the revisions of the first parents after it until the bad one
+fn git_versions(good: &str, bad: &str, test_dir: &str) -> Result<Vec<Version>> {
+    let format = "--format=%H %h %s";
+    let range = format!("{good}..{bad}");
+    let log = [
+        git(&["log", "-1", format, good])?,
+        git(&[
+            "log",
+            "--reverse",
+            "--first-parent",
+            "--ancestry-path",
+            format,
+            &range,
+        ])?,
+    ];
+
+    Ok(log
+        .iter()
+        .flat_map(|log| log.lines())
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, label)| Version {
+            label: label.to_string(),
+            revision: Some(hash.to_string()),
+            test_dir: PathBuf::from(test_dir),
+        })
+        .collect())
+}
+
+fn build(build_command: &str) -> Result<bool> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let status = shell
+        .arg(build_command)
+        .status()
+        .map_err(|error| anyhow!("Failed to run {build_command}: {error}"))?;
+    Ok(status.success())
+}
+
+// Builds the version if needed, and runs the tests matching the pattern in it
+fn check(version: &Version, command: &BisectCommand) -> Result<(Verdict, String)> {
+    if let Some(revision) = &version.revision {
+        git(&["checkout", "--quiet", "--detach", revision])?;
+        if let Some(build_command) = &command.build_command {
+            if !build(build_command)? {
+                return Ok((Verdict::Skip, String::from("skipped, the build failed")));
+            }
+        }
+    }
+
+    let tests = TestDiscovery::in_dir(&version.test_dir)
+        .executable_types(&command.executable_types)
+        .include_hidden(true)
+        .use_cache(false)
+        .discover();
+    let mut tests = match tests {
+        Ok(tests) => tests,
+        Err(error) => return Ok((Verdict::Skip, format!("skipped, {error}"))),
+    };
+    tests.retain(|test| command.test.is_match(&test.name));
+    if tests.is_empty() {
+        return Ok((Verdict::Skip, String::from("skipped, no test matches")));
+    }
+
+    let statuses = TestRun::new(tests)
+        .run()?
+        .into_iter()
+        .filter_map(|(_, outcome)| outcome.map(|outcome| outcome.status))
+        .collect::<Vec<_>>();
+    Ok(if statuses.contains(&TestStatus::NotRunnable) {
+        (Verdict::Skip, String::from("skipped, the test can't start"))
+    } else if statuses.contains(&TestStatus::Failed) {
+        (Verdict::Bad, String::from("failed"))
+    } else {
+        (Verdict::Good, String::from("passed"))
+    })
+}
+
+/// Runs the test in the versions between --good and --bad until finding the first one in which it
+/// fails, and prints it
+pub fn run_bisect(command: &BisectCommand) -> Result<()> {
+    let git_head = match &command.build_command {
+        Some(_) => {
+            if !git(&["status", "--porcelain", "--untracked-files=no"])?.is_empty() {
+                bail!("The working tree has changes, commit or stash them before bisecting");
+            }
+            let branch = git(&["symbolic-ref", "--quiet", "--short", "HEAD"]);
+            Some(branch.or_else(|_| git(&["rev-parse", "HEAD"]))?)
+        }
+        None => None,
+    };
+    let versions = match &git_head {
+        Some(_) => git_versions(&command.good, &command.bad, &command.test_dir)?,
+        None => directory_versions(Path::new(&command.good), Path::new(&command.bad))?,
+    };
+    if versions.len() < 2 {
+        bail!("There is no version after {}", command.good);
+    }
+
+    let between = versions.len() - 2;
+    println!(
+        "Bisecting {} across the {between} versions between {} and {}, in about {} steps",
+        command.test.as_str(),
+        versions[0].label,
+        versions[versions.len() - 1].label,
+        (between + 1).next_power_of_two().trailing_zeros()
+    );
+    let result = search(versions.len(), |index| {
+        let (verdict, description) = check(&versions[index], command)?;
+        println!("{}: {description}", versions[index].label);
+        Ok(verdict)
+    });
+    if let Some(head) = &git_head {
+        git(&["checkout", "--quiet", head])?;
+    }
+    let (good, bad) = result?;
+
+    println!(
+        "{} first fails in {}, after {}",
+        command.test.as_str(),
+        versions[bad].label,
+        versions[good].label
+    );
+    if bad - good > 1 {
+        let untested = versions[good + 1..bad]
+            .iter()
+            .map(|version| version.label.as_str())
+            .collect::<Vec<_>>();
+        println!(
+            "It may also first fail in the versions that couldn't be tested: {}",
+            untested.join(", ")
+        );
+    }
+    Ok(())
+}
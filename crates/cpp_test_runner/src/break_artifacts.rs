@@ -0,0 +1,82 @@
+use crate::{
+    child_environment,
+    types::{ExecutableType, Test},
+};
+use anyhow::{bail, Result};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+// gdb stops at the first failing assertion, where gtest breaks into the debugger, and prints the
+// backtrace with the local variables of every frame
+const GDB_ARGS: &[&str] = &[
+    "-batch",
+    "-nx",
+    "-ex",
+    "run",
+    "-ex",
+    "bt full",
+    "-ex",
+    "info registers",
+    "--args",
+];
+
+fn run_under(catcher: &str, catcher_args: &[&str], test: &Test) -> std::io::Result<Output> {
+    let mut command = Command::new(catcher);
+    child_environment::apply(&mut command)
+        .args(catcher_args)
+        .arg(&test.executable.path)
+        .args(&test.executable.base_arguments)
+        .args(test.arguments.iter().map(|argument| &**argument))
+        .args(["--gtest_break_on_failure", "--gtest_color=no"])
+        .envs(&test.environment);
+    if let Some(cwd) = &test.cwd {
+        command.current_dir(cwd);
+    }
+    command.output()
+}
+
+/// Reruns a failed gtest test with --gtest_break_on_failure under gdb, or under catchsegv when gdb
+/// isn't installed, and writes what they captured at the first failing assertion to a file of
+/// `dir`. gtest breaks by writing to a null pointer, so catchsegv reports it like a crash.
+pub fn capture_break_artifact(test: &Test, dir: &Path) -> Result<PathBuf> {
+    if test.executable.executable_type != ExecutableType::Gtest {
+        bail!("Only gtest tests can break on failures");
+    }
+
+    let (catcher, output) = match run_under("gdb", GDB_ARGS, test) {
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            match run_under("catchsegv", &[], test) {
+                Err(error) if error.kind() == ErrorKind::NotFound => {
+                    bail!("Neither gdb nor catchsegv is installed")
+                }
+                output => ("catchsegv", output?),
+            }
+        }
+        output => ("gdb", output?),
+    };
+
+    // Test names like Values/Suite.Test/0 aren't valid file names
+    let file_name = test
+        .display_name()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    let artifact = dir.join(format!("{file_name}.txt"));
+
+    fs::create_dir_all(dir)?;
+    fs::write(
+        &artifact,
+        format!(
+            "{} captured by {catcher} in {}\n\n{}\n{}",
+            test.display_name(),
+            test.executable.path.display(),
+            String::from_utf8_lossy(&output.stdout).trim(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ),
+    )?;
+    Ok(artifact)
+}
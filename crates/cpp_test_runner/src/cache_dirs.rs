@@ -0,0 +1,247 @@
+use crate::{discovery_cache::hash_of, source_files::workspace_root};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Bumped when the files change in a way the previous versions can't read, which leaves the data
+// of the previous layouts to `gc`
+const LAYOUT_VERSION: &str = "v1";
+
+const WORKSPACE_FILE_NAME: &str = "workspace.json";
+
+/// The data of all the versions of the layout, in $XDG_CACHE_HOME/cpp-test-runner, or in
+/// ~/.cache if XDG_CACHE_HOME isn't set
+fn cache_home() -> Option<PathBuf> {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_home.join("cpp-test-runner"))
+}
+
+/// The data of the current layout, like $XDG_CACHE_HOME/cpp-test-runner/v1
+pub fn cache_root() -> Option<PathBuf> {
+    Some(cache_home()?.join(LAYOUT_VERSION))
+}
+
+/// The data shared by all the workspaces of the machine, like the shim of the fork servers
+pub fn shared_dir() -> Option<PathBuf> {
+    Some(cache_root()?.join("shared"))
+}
+
+#[derive(Serialize, Deserialize)]
+struct WorkspaceFile {
+    path: PathBuf,
+    /// In seconds since the Unix epoch
+    last_used: u64,
+}
+
+fn seconds_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// The data of a workspace, in a directory of the cache root named after the hash of its identity:
+///
+/// ```text
+/// workspaces/<hash>/workspace.json  the path of the workspace and when it was last used
+/// workspaces/<hash>/discovery/      the tests listed by every executable
+/// workspaces/<hash>/memory.json     the peak memory of every test in its last run
+/// workspaces/<hash>/artifacts/      what is captured when tests fail
+/// workspaces/<hash>/runs/           the results of the last runs, for grep and show
+/// workspaces/<hash>/serve.sock      the socket of the serve subcommand
+/// ```
+#[derive(Debug, Clone)]
+pub struct WorkspaceCache {
+    dir: PathBuf,
+}
+
+// The git directory of a repository, which is .git, or the directory that a .git file points to
+// in the worktrees and the submodules. The config of a worktree is in the common directory.
+fn git_dir(root: &Path) -> Option<PathBuf> {
+    let dot_git = root.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let content = fs::read_to_string(&dot_git).ok()?;
+    let git_dir = root.join(content.strip_prefix("gitdir:")?.trim());
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => Some(git_dir.join(common_dir.trim())),
+        Err(_) => Some(git_dir),
+    }
+}
+
+// The URL of the origin remote, or of the first remote, from the config of the repository
+fn remote_url(root: &Path) -> Option<String> {
+    let config = fs::read_to_string(git_dir(root)?.join("config")).ok()?;
+    let mut remotes = Vec::new();
+    let mut remote = None;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            remote = line
+                .strip_prefix("[remote \"")
+                .and_then(|line| line.strip_suffix("\"]"))
+                .map(str::to_string);
+        } else if let (Some(name), Some((key, value))) = (&remote, line.split_once('=')) {
+            if key.trim() == "url" {
+                remotes.push((name.clone(), value.trim().to_string()));
+            }
+        }
+    }
+    remotes
+        .iter()
+        .find(|(name, _)| name == "origin")
+        .or(remotes.first())
+        .map(|(_, url)| url.clone())
+}
+
+/// What identifies a workspace: the root of the repository containing the directory and its
+/// remote, or the directory itself outside of a repository. The directory is canonicalized first,
+/// so that the subdirectories of a repository, relative or not, share the workspace of its root.
+/// The remote keeps a repository cloned where another one was from reusing its data.
+fn workspace_identity(dir: &Path) -> (PathBuf, String) {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let root = workspace_root(&dir).to_path_buf();
+    let identity = if root.join(".git").exists() {
+        format!(
+            "git:{}\n{}",
+            root.display(),
+            remote_url(&root).unwrap_or_default()
+        )
+    } else {
+        format!("dir:{}", root.display())
+    };
+    (root, identity)
+}
+
+impl WorkspaceCache {
+    /// The data of the repository containing `dir`, or of `dir` outside of a repository. Marks the
+    /// workspace as used, so that `gc` keeps its data.
+    pub fn for_workspace(dir: &Path) -> Option<Self> {
+        let (workspace, identity) = workspace_identity(dir);
+        let cache = Self {
+            dir: cache_root()?
+                .join("workspaces")
+                .join(format!("{:016x}", hash_of(&identity))),
+        };
+
+        // Only used by gc, which treats the workspaces without this file as unused
+        let workspace_file = WorkspaceFile {
+            path: workspace,
+            last_used: seconds_since_epoch(SystemTime::now()),
+        };
+        if fs::create_dir_all(&cache.dir).is_ok() {
+            if let Ok(content) = serde_json::to_string(&workspace_file) {
+                let _ = fs::write(cache.dir.join(WORKSPACE_FILE_NAME), content);
+            }
+        }
+        Some(cache)
+    }
+
+    pub fn discovery_dir(&self) -> PathBuf {
+        self.dir.join("discovery")
+    }
+
+    pub fn memory_history(&self) -> PathBuf {
+        self.dir.join("memory.json")
+    }
+
+    pub fn artifacts_dir(&self) -> PathBuf {
+        self.dir.join("artifacts")
+    }
+
+    pub fn runs_dir(&self) -> PathBuf {
+        self.dir.join("runs")
+    }
+
+    pub fn server_socket(&self) -> PathBuf {
+        self.dir.join("serve.sock")
+    }
+}
+
+/// What `collect_garbage` removed, or would remove in a dry run
+#[derive(Debug, Default)]
+pub struct Garbage {
+    pub paths: Vec<PathBuf>,
+    pub bytes: u64,
+}
+
+fn size_of_tree(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn last_used(workspace_dir: &Path) -> Option<SystemTime> {
+    let content = fs::read_to_string(workspace_dir.join(WORKSPACE_FILE_NAME)).ok()?;
+    let workspace_file: WorkspaceFile = serde_json::from_str(&content).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(workspace_file.last_used))
+}
+
+/// Removes the data of the workspaces that weren't used for `max_age`, the shared files that
+/// weren't modified for `max_age`, and the data of the other versions of the layout
+pub fn collect_garbage(max_age: Duration, dry_run: bool) -> Result<Garbage> {
+    let mut garbage = Garbage::default();
+    let Some(cache_home) = cache_home() else {
+        return Ok(garbage);
+    };
+    let cutoff = SystemTime::now().checked_sub(max_age).unwrap_or(UNIX_EPOCH);
+    let is_old = |time: Option<SystemTime>| time.is_none_or(|time| time < cutoff);
+
+    // The data of the versions before this layout
+    let mut to_remove = cache_home
+        .parent()
+        .map(|dir| dir.join("cpp_test_runner"))
+        .filter(|dir| dir.exists())
+        .into_iter()
+        .collect::<Vec<_>>();
+    for entry in fs::read_dir(&cache_home).into_iter().flatten().flatten() {
+        if entry.file_name() != LAYOUT_VERSION {
+            to_remove.push(entry.path());
+        }
+    }
+
+    let current = cache_home.join(LAYOUT_VERSION);
+    for entry in fs::read_dir(current.join("workspaces"))
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        if is_old(last_used(&entry.path())) {
+            to_remove.push(entry.path());
+        }
+    }
+    for entry in fs::read_dir(current.join("shared"))
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let modified = entry.metadata().and_then(|metadata| metadata.modified());
+        if is_old(modified.ok()) {
+            to_remove.push(entry.path());
+        }
+    }
+
+    for path in to_remove {
+        garbage.bytes += size_of_tree(&path);
+        if !dry_run {
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+        }
+        garbage.paths.push(path);
+    }
+
+    Ok(garbage)
+}
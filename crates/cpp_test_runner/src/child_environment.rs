@@ -0,0 +1,113 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    process::Command,
+    sync::{LazyLock, Mutex},
+};
+
+/// The variables that the tests get by default, with which they find their tools, their files and
+/// their libraries, and format text and dates. A trailing * matches any suffix.
+pub const DEFAULT_ALLOWED_VARIABLES: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "TERM",
+    "TMPDIR",
+    "LANG",
+    "LC_*",
+    "TZ",
+    "LD_LIBRARY_PATH",
+];
+
+// The environment the runner was launched with, before anything in the process changed it
+static LAUNCH_ENVIRONMENT: LazyLock<BTreeMap<OsString, OsString>> =
+    LazyLock::new(|| env::vars_os().collect());
+
+// None until set, for the default allowlist
+static POLICY: Mutex<Option<EnvironmentPolicy>> = Mutex::new(None);
+
+/// Which variables of the environment the runner was launched with are passed to the test
+/// executables, when they list and run their tests
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvironmentPolicy {
+    /// All of them
+    Inherit,
+    /// Those whose name matches one of these, where a trailing * matches any suffix, like LC_*
+    Allow(Vec<String>),
+}
+
+impl Default for EnvironmentPolicy {
+    fn default() -> Self {
+        Self::allow(&[])
+    }
+}
+
+impl EnvironmentPolicy {
+    /// The default variables and these ones
+    pub fn allow(additions: &[String]) -> Self {
+        Self::Allow(
+            DEFAULT_ALLOWED_VARIABLES
+                .iter()
+                .map(|name| name.to_string())
+                .chain(additions.iter().cloned())
+                .collect(),
+        )
+    }
+
+    fn allows(&self, name: &OsString) -> bool {
+        let Self::Allow(patterns) = self else {
+            return true;
+        };
+        let Some(name) = name.to_str() else {
+            return false;
+        };
+        patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+}
+
+/// Captures the environment the runner was launched with, which the test executables get instead
+/// of the current one. Call it before anything changes the environment of the process.
+pub fn capture() {
+    LazyLock::force(&LAUNCH_ENVIRONMENT);
+}
+
+pub fn set_policy(policy: EnvironmentPolicy) {
+    *POLICY.lock().unwrap() = Some(policy);
+}
+
+/// The variables of the launch environment that the test executables get
+pub fn passed_variables() -> BTreeMap<OsString, OsString> {
+    let policy = POLICY.lock().unwrap().clone().unwrap_or_default();
+    LAUNCH_ENVIRONMENT
+        .iter()
+        .filter(|(name, _)| policy.allows(name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// The names of the passed variables, or None when the whole launch environment is passed
+pub fn passed_names() -> Option<Vec<String>> {
+    if POLICY.lock().unwrap().as_ref() == Some(&EnvironmentPolicy::Inherit) {
+        return None;
+    }
+    Some(
+        passed_variables()
+            .into_keys()
+            .filter_map(|name| name.into_string().ok())
+            .collect(),
+    )
+}
+
+/// Replaces the environment of a command starting a test executable with the passed variables.
+/// Call it before setting the variables of the command, like those of its tests, which it clears.
+pub fn apply(command: &mut Command) -> &mut Command {
+    command.env_clear().envs(passed_variables())
+}
@@ -0,0 +1,167 @@
+use crate::types::CompileCommand;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+// The compilers, after the target triple in the name of the cross compilers like
+// aarch64-linux-gnu-g++
+const COMPILER_NAMES: [&str; 6] = ["gcc", "g++", "clang", "clang++", "c++", "cc"];
+// Run the compiler that follows them
+const COMPILER_WRAPPERS: [&str; 3] = ["ccache", "sccache", "distcc"];
+
+#[derive(Debug, Deserialize)]
+struct Entry {
+    directory: PathBuf,
+    file: PathBuf,
+    #[serde(default)]
+    arguments: Option<Vec<String>>,
+    #[serde(default)]
+    command: Option<String>,
+}
+
+/// The compilation database of a build, read from its compile_commands.json, which tells the
+/// directory that every source was compiled from and with which arguments. The executables report
+/// the sources as they were given to the compiler, so this resolves them without searching.
+#[derive(Debug, Default)]
+pub struct CompilationDatabase {
+    /// By the absolute path of the source
+    commands: HashMap<PathBuf, Arc<CompileCommand>>,
+    /// The absolute paths of the sources, by their path as given to the compiler
+    files_as_compiled: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl CompilationDatabase {
+    /// Loads compile_commands.json, or the one in a directory
+    pub fn load(path: &Path) -> Result<Self> {
+        let path = if path.is_dir() {
+            path.join("compile_commands.json")
+        } else {
+            path.to_path_buf()
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|error| anyhow!("Failed to read {}: {error}", path.display()))?;
+        let entries = serde_json::from_str::<Vec<Entry>>(&content)
+            .map_err(|error| anyhow!("Failed to parse {}: {error}", path.display()))?;
+
+        let mut database = Self::default();
+        for entry in entries {
+            let file = entry.directory.join(&entry.file);
+            let file = file.canonicalize().unwrap_or(file);
+            let arguments = match (entry.arguments, entry.command) {
+                (Some(arguments), _) => arguments,
+                (None, Some(command)) => split_command(&command),
+                (None, None) => Vec::new(),
+            };
+
+            let files = database.files_as_compiled.entry(entry.file).or_default();
+            if !files.contains(&file) {
+                files.push(file.clone());
+            }
+            // A source compiled several times, e.g. in several targets, keeps its first command
+            database.commands.entry(file).or_insert_with(|| {
+                Arc::new(CompileCommand {
+                    directory: entry.directory,
+                    target: target_of(&arguments),
+                    arguments,
+                })
+            });
+        }
+        Ok(database)
+    }
+
+    /// The absolute path of a source reported by an executable, when only one source of the
+    /// database was compiled as this path, or else ends with it
+    pub fn resolve(&self, reported: &Path) -> Option<PathBuf> {
+        if let Some(files) = self.files_as_compiled.get(reported) {
+            return match &files[..] {
+                [file] => Some(file.clone()),
+                // Like the same relative path in two directories of the build
+                _ => None,
+            };
+        }
+
+        let mut candidates = self.commands.keys().filter(|file| file.ends_with(reported));
+        match (candidates.next(), candidates.next()) {
+            (Some(file), None) => Some(file.clone()),
+            _ => None,
+        }
+    }
+
+    /// How an absolute source was compiled
+    pub fn command(&self, file: &Path) -> Option<Arc<CompileCommand>> {
+        self.commands.get(file).cloned()
+    }
+}
+
+// The target triple of --target=, -target, or of the name of a cross compiler
+fn target_of(arguments: &[String]) -> Option<String> {
+    for (index, argument) in arguments.iter().enumerate() {
+        if let Some(target) = argument.strip_prefix("--target=") {
+            return Some(target.to_string());
+        }
+        if argument == "-target" || argument == "--target" {
+            return arguments.get(index + 1).cloned();
+        }
+    }
+
+    let mut compilers = arguments.iter().map(|argument| {
+        Path::new(argument)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    });
+    let compiler = compilers
+        .find(|name| !COMPILER_WRAPPERS.contains(&name.as_str()))
+        .unwrap_or_default();
+    // Like aarch64-linux-gnu-g++-12
+    let components = compiler.split('-').collect::<Vec<_>>();
+    let compiler_index = components
+        .iter()
+        .position(|component| COMPILER_NAMES.contains(component))?;
+    (compiler_index >= 2).then(|| components[..compiler_index].join("-"))
+}
+
+// Splits the command of an entry into its arguments, like a POSIX shell without expansions
+fn split_command(command: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut argument = String::new();
+    let mut in_argument = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (Some(open), _) if char == open => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(escaped) = chars.next() {
+                    argument.push(escaped);
+                }
+                in_argument = true;
+            }
+            (Some(_), _) => argument.push(char),
+            (None, '"' | '\'') => {
+                quote = Some(char);
+                in_argument = true;
+            }
+            (None, _) if char.is_whitespace() => {
+                if in_argument {
+                    arguments.push(std::mem::take(&mut argument));
+                    in_argument = false;
+                }
+            }
+            (None, _) => {
+                argument.push(char);
+                in_argument = true;
+            }
+        }
+    }
+    if in_argument {
+        arguments.push(argument);
+    }
+    arguments
+}
@@ -0,0 +1,199 @@
+use crate::{
+    test_runner::{run_all, RunOptions},
+    types::Test,
+    CoverageCommand, CoverageFormat, CoverageTool,
+};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct CoverageManifest {
+    tool: CoverageTool,
+    merged_profile: Option<PathBuf>,
+    tests: Vec<CoverageManifestEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct CoverageManifestEntry {
+    name: String,
+    executable: PathBuf,
+    profile: PathBuf,
+}
+
+// Only keep characters that are safe to use in a file name
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn run_tool(command: &mut Command) -> Result<Vec<u8>> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(error) => bail!("Failed to execute {program}: {error}"),
+    };
+
+    if !output.status.success() {
+        bail!(
+            "{program} failed:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(output.stdout)
+}
+
+pub fn run_coverage(tests: &[Test], command: &CoverageCommand, use_color: bool) -> Result<()> {
+    fs::create_dir_all(&command.coverage_dir)?;
+    let coverage_dir = command.coverage_dir.canonicalize()?;
+    let profiles_dir = coverage_dir.join("profiles");
+    if profiles_dir.exists() {
+        fs::remove_dir_all(&profiles_dir)?;
+    }
+    fs::create_dir_all(&profiles_dir)?;
+
+    let mut entries = Vec::with_capacity(tests.len());
+    let instrumented_tests = tests
+        .iter()
+        .enumerate()
+        .map(|(index, test)| {
+            let file_name = format!("{index}-{}", sanitize_file_name(&test.name));
+            let mut test = test.clone();
+
+            // Every test gets its own profile so the coverage can be inspected per test
+            let profile = match command.coverage_tool {
+                CoverageTool::Llvm => {
+                    let profile = profiles_dir.join(format!("{file_name}.profraw"));
+                    test.environment.insert(
+                        String::from("LLVM_PROFILE_FILE"),
+                        profile.to_string_lossy().to_string(),
+                    );
+                    profile
+                }
+                CoverageTool::Gcov => {
+                    let profile = profiles_dir.join(file_name);
+                    test.environment.insert(
+                        String::from("GCOV_PREFIX"),
+                        profile.to_string_lossy().to_string(),
+                    );
+                    profile
+                }
+            };
+
+            entries.push(CoverageManifestEntry {
+                name: test.name.clone(),
+                executable: test.executable.path.clone(),
+                profile,
+            });
+
+            test
+        })
+        .collect::<Vec<_>>();
+
+    run_all(
+        &instrumented_tests,
+        &RunOptions {
+            use_color,
+            ..Default::default()
+        },
+    )?;
+
+    let merged_profile = match command.coverage_tool {
+        CoverageTool::Llvm => Some(merge_llvm_profiles(&entries, &coverage_dir, command)?),
+        CoverageTool::Gcov => {
+            println!(
+                "gcov data of every test written to {}",
+                profiles_dir.display()
+            );
+            None
+        }
+    };
+
+    let manifest = CoverageManifest {
+        tool: command.coverage_tool.clone(),
+        merged_profile,
+        tests: entries,
+    };
+    fs::write(
+        coverage_dir.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    Ok(())
+}
+
+fn merge_llvm_profiles(
+    entries: &[CoverageManifestEntry],
+    coverage_dir: &Path,
+    command: &CoverageCommand,
+) -> Result<PathBuf> {
+    // Tests that crashed don't write their profile
+    let profiles = entries
+        .iter()
+        .map(|entry| &entry.profile)
+        .filter(|profile| profile.exists())
+        .collect::<Vec<_>>();
+
+    if profiles.is_empty() {
+        bail!("No coverage profile was generated. Are the executables instrumented with -fprofile-instr-generate?");
+    }
+
+    let merged_profile = coverage_dir.join("merged.profdata");
+    run_tool(
+        Command::new(&command.llvm_profdata)
+            .args(["merge", "-sparse", "-o"])
+            .arg(&merged_profile)
+            .args(profiles),
+    )?;
+
+    let mut executables = entries
+        .iter()
+        .map(|entry| &entry.executable)
+        .collect::<Vec<_>>();
+    executables.sort();
+    executables.dedup();
+
+    let mut llvm_cov = Command::new(&command.llvm_cov);
+    match command.report_format {
+        CoverageFormat::Text => llvm_cov.arg("report"),
+        CoverageFormat::Lcov => llvm_cov.args(["export", "-format=lcov"]),
+        CoverageFormat::Json => llvm_cov.args(["export", "-format=text"]),
+        CoverageFormat::Html => llvm_cov.args(["show", "-format=html"]).arg(format!(
+            "-output-dir={}",
+            coverage_dir.join("html").display()
+        )),
+    };
+    llvm_cov.arg(format!("-instr-profile={}", merged_profile.display()));
+    for (index, executable) in executables.iter().enumerate() {
+        if index != 0 {
+            llvm_cov.arg("-object");
+        }
+        llvm_cov.arg(executable);
+    }
+
+    let report = run_tool(&mut llvm_cov)?;
+    match command.report_format {
+        CoverageFormat::Text => print!("{}", String::from_utf8_lossy(&report)),
+        CoverageFormat::Lcov => fs::write(coverage_dir.join("coverage.lcov"), report)?,
+        CoverageFormat::Json => fs::write(coverage_dir.join("coverage.json"), report)?,
+        CoverageFormat::Html => println!(
+            "Coverage report written to {}",
+            coverage_dir.join("html").display()
+        ),
+    }
+
+    Ok(merged_profile)
+}
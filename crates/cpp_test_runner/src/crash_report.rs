@@ -0,0 +1,178 @@
+use elf_parser::{debug_line::LineTable, Elf, Section};
+use regex::Regex;
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+use std::{path::Path, process::ExitStatus, sync::LazyLock};
+
+// The frames that the sanitizers couldn't symbolize without llvm-symbolizer, like
+// `#3 0x55d0c0e1a2b3  (/path/to/test+0x1a2b3)`
+static UNSYMBOLIZED_FRAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^\s*#(\d+) 0x[0-9a-f]+\s+\((.+)\+0x([0-9a-f]+)\)\s*$").unwrap()
+});
+
+// The lines of the sanitizer reports that tell what they found and where, like
+// `SUMMARY: AddressSanitizer: heap-use-after-free /src/math.cpp:12:5 in f`, and the errors of
+// UndefinedBehaviorSanitizer, like `/src/math.cpp:12:5: runtime error: signed integer overflow`
+static SANITIZER_FINDING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^(SUMMARY: \w+Sanitizer: .+|.+:\d+:\d+: runtime error: .+?)\s*$").unwrap()
+});
+
+#[cfg(unix)]
+fn signal_name(signal: i32) -> String {
+    match signal {
+        libc::SIGABRT => "SIGABRT".to_string(),
+        libc::SIGBUS => "SIGBUS".to_string(),
+        libc::SIGFPE => "SIGFPE".to_string(),
+        libc::SIGILL => "SIGILL".to_string(),
+        libc::SIGKILL => "SIGKILL".to_string(),
+        libc::SIGSEGV => "SIGSEGV".to_string(),
+        libc::SIGTERM => "SIGTERM".to_string(),
+        libc::SIGTRAP => "SIGTRAP".to_string(),
+        signal => format!("signal {signal}"),
+    }
+}
+
+// What killed the test: the signal on Unix
+#[cfg(unix)]
+fn crash_cause(status: ExitStatus) -> Option<String> {
+    status.signal().map(signal_name)
+}
+
+// Processes that crash on Windows exit with the NTSTATUS of the exception, like 0xC0000005 for an
+// access violation, instead of being killed by a signal
+#[cfg(not(unix))]
+fn crash_cause(status: ExitStatus) -> Option<String> {
+    let code = status.code()? as u32;
+    if code & 0xC000_0000 != 0xC000_0000 {
+        return None;
+    }
+    Some(match code {
+        0xC000_0005 => "STATUS_ACCESS_VIOLATION".to_string(),
+        0xC000_001D => "STATUS_ILLEGAL_INSTRUCTION".to_string(),
+        0xC000_0094 => "STATUS_INTEGER_DIVIDE_BY_ZERO".to_string(),
+        0xC000_00FD => "STATUS_STACK_OVERFLOW".to_string(),
+        0xC000_0409 => "STATUS_STACK_BUFFER_OVERRUN".to_string(),
+        code => format!("exception 0x{code:08X}"),
+    })
+}
+
+/// The functions and the lines of an executable, read from its symbols and its DWARF debug info
+struct Symbolizer {
+    // (start, end, name), sorted by start
+    functions: Vec<(u64, u64, String)>,
+    lines: Option<LineTable>,
+}
+
+impl Symbolizer {
+    fn new(executable: &Path) -> Option<Self> {
+        let elf = Elf::new(executable).ok()?;
+        let headers = elf.get_all_section_headers().ok()?;
+
+        let mut functions = Vec::new();
+        let symbol_table_header = headers
+            .find_symbol_table_header()
+            .or_else(|| headers.find_dynamic_symbol_table_header());
+        if let Some(symbol_table_header) = symbol_table_header {
+            if let (Some(string_table_header), Ok(Section::Symbols(symbols))) = (
+                headers.find_linked_header(symbol_table_header),
+                elf.get_section(symbol_table_header),
+            ) {
+                if let Ok(Section::Strings(strings)) = elf.get_section(string_table_header) {
+                    // STT_FUNC, in the low bits of st_info
+                    functions = symbols
+                        .iter()
+                        .filter(|symbol| symbol.st_info & 0xf == 2 && symbol.st_value != 0)
+                        .filter_map(|symbol| {
+                            let name = strings.get_symbol_name(symbol)?.to_string_lossy();
+                            Some((
+                                symbol.st_value,
+                                symbol.st_value + symbol.st_size.max(1),
+                                name.into_owned(),
+                            ))
+                        })
+                        .collect();
+                    functions.sort_unstable();
+                }
+            }
+        }
+
+        let lines = LineTable::read(&elf, &headers).ok().flatten();
+        Some(Self { functions, lines })
+    }
+
+    fn function(&self, address: u64) -> Option<&str> {
+        let index = self
+            .functions
+            .partition_point(|(start, _, _)| *start <= address);
+        let (_, end, name) = self.functions.get(index.checked_sub(1)?)?;
+        (address < *end).then_some(name.as_str())
+    }
+
+    // `file:line function`, with ?? for what isn't known
+    fn symbolize(&self, address: u64) -> Option<String> {
+        let location = self.lines.as_ref().and_then(|lines| lines.find(address));
+        let function = self.function(address);
+        if location.is_none() && function.is_none() {
+            return None;
+        }
+
+        let location = match location {
+            Some((file, line)) => format!("{}:{line}", file.display()),
+            None => "??".to_string(),
+        };
+        Some(format!("{location} {}", function.unwrap_or("??")))
+    }
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    a == b
+        || matches!(
+            (a.canonicalize(), b.canonicalize()),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+/// Summarizes how a test crashed: the signal that killed it, or the exception it exited with on
+/// Windows, what the sanitizers found, and the source location of the frames of the executable
+/// that the sanitizers reported without symbolizing them. The frames are symbolized from the debug
+/// info of the executable, which doesn't need gdb nor addr2line.
+pub fn crash_summary(executable: &Path, status: ExitStatus, output: &str) -> Option<String> {
+    let mut summary = Vec::new();
+    if let Some(cause) = crash_cause(status) {
+        summary.push(format!("The test crashed with {cause}"));
+    }
+    // The sanitizers report on stderr, which isn't in the output of the test otherwise
+    for captures in SANITIZER_FINDING.captures_iter(output) {
+        if !summary.iter().any(|line| *line == captures[1]) {
+            summary.push(captures[1].to_string());
+        }
+    }
+
+    let mut symbolizer = None;
+    let mut has_frames = false;
+    for captures in UNSYMBOLIZED_FRAME.captures_iter(output) {
+        // The frames in the shared libraries can't be symbolized from the executable
+        if !is_same_file(Path::new(&captures[2]), executable) {
+            continue;
+        }
+        let Ok(address) = u64::from_str_radix(&captures[3], 16) else {
+            continue;
+        };
+
+        let Some(symbolizer) = symbolizer
+            .get_or_insert_with(|| Symbolizer::new(executable))
+            .as_ref()
+        else {
+            break;
+        };
+        if let Some(location) = symbolizer.symbolize(address) {
+            if !has_frames {
+                summary.push("Frames of the sanitizer report:".to_string());
+                has_frames = true;
+            }
+            summary.push(format!("    #{} {location}", &captures[1]));
+        }
+    }
+
+    (!summary.is_empty()).then(|| summary.join("\n"))
+}
@@ -0,0 +1,33 @@
+use crate::types::Test;
+use std::path::{Path, PathBuf};
+
+/// Resolves the placeholders of a working directory for a specific test:
+/// - `{exe_dir}`: the directory of the test executable
+/// - `{src_dir}`: the directory of the source file of the test, or `{exe_dir}` if it is unknown
+/// - `{workspace}`: the test directory, or the current directory if executables were given
+/// - `{tmp}`: the temporary directory of the system
+pub fn resolve_cwd_template(template: &str, test: &Test, workspace: &Path) -> PathBuf {
+    if !template.contains('{') {
+        return PathBuf::from(template);
+    }
+
+    let exe_dir = test
+        .executable
+        .path
+        .parent()
+        .unwrap_or(&test.executable.path);
+
+    let src_dir = test
+        .file
+        .as_deref()
+        .and_then(|file| file.parent())
+        .unwrap_or(exe_dir);
+
+    let resolved = template
+        .replace("{exe_dir}", &exe_dir.to_string_lossy())
+        .replace("{src_dir}", &src_dir.to_string_lossy())
+        .replace("{workspace}", &workspace.to_string_lossy())
+        .replace("{tmp}", &std::env::temp_dir().to_string_lossy());
+
+    PathBuf::from(resolved)
+}
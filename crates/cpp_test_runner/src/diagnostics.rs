@@ -0,0 +1,148 @@
+//! What the discovery, the listing and the runs left out, guessed or couldn't do, reported where it
+//! happens and printed together once the command finished, instead of among its output
+//!
+//! Every diagnostic has a code, like `CTR0201`, which stays the same across the versions so that
+//! scripts and the CI can look for them in the diagnostics report. The first two digits are the
+//! step: 01 for the search of the executables, 02 for the listing and the selection of their
+//! tests, and 03 for the runs.
+
+use serde::Serialize;
+use std::{fmt, sync::Mutex};
+
+/// A path couldn't be read while searching for executables, like a directory without permission
+pub const UNREADABLE_PATH: &str = "CTR0101";
+/// A followed symlink loops back to one of its parents, and was skipped
+pub const SYMLINK_LOOP: &str = "CTR0102";
+/// The discovery timed out before finishing some paths
+pub const DISCOVERY_TIMED_OUT: &str = "CTR0103";
+/// An executable was found, but listing its tests failed
+pub const UNLISTED_EXECUTABLE: &str = "CTR0201";
+/// The source file of some tests is unknown
+pub const MISSING_LOCATIONS: &str = "CTR0202";
+/// Some tests are registered by several executables from the same source file
+pub const DUPLICATE_REGISTRATIONS: &str = "CTR0203";
+/// A filter or a test name given on the command line matches no test
+pub const NO_MATCHING_TEST: &str = "CTR0204";
+/// Tests of several executables have the same name
+pub const DUPLICATE_NAMES: &str = "CTR0205";
+/// The tests were shuffled, with the seed that reproduces their order
+pub const SHUFFLED: &str = "CTR0301";
+/// The results of the run couldn't be archived for grep and show
+pub const RUN_NOT_ARCHIVED: &str = "CTR0302";
+/// Tests failed outside of the gates of tests.gates.json
+pub const FAILED_OUTSIDE_GATES: &str = "CTR0303";
+
+// The number of details printed, the others are only counted. The reports have them all.
+const PRINTED_DETAILS: usize = 5;
+
+static REPORTED: Mutex<Vec<Diagnostic>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    /// What the message is about, like tests or paths, a line each
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+    /// How to fix it or to silence it, like a flag to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            details: Vec::new(),
+            hint: None,
+        }
+    }
+
+    pub fn info(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Info, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn details(mut self, details: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.details = details
+            .into_iter()
+            .map(|detail| detail.to_string())
+            .collect();
+        self
+    }
+
+    pub fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+/// Like `warning[CTR0202]: the message`, then the first details and the hint, indented
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        for detail in self.details.iter().take(PRINTED_DETAILS) {
+            write!(f, "\n    {detail}")?;
+        }
+        if self.details.len() > PRINTED_DETAILS {
+            write!(f, "\n    and {} more", self.details.len() - PRINTED_DETAILS)?;
+        }
+        if let Some(hint) = &self.hint {
+            write!(f, "\n    {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the diagnostic until the command finishes. Callable from any thread of the discovery.
+pub fn report(diagnostic: Diagnostic) {
+    REPORTED.lock().unwrap().push(diagnostic);
+}
+
+/// The diagnostics reported so far, which are then forgotten, the most severe first. The order
+/// doesn't depend on the threads that reported them.
+pub fn take() -> Vec<Diagnostic> {
+    let mut diagnostics = std::mem::take(&mut *REPORTED.lock().unwrap());
+    diagnostics.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then_with(|| a.code.cmp(b.code))
+            .then_with(|| a.message.cmp(&b.message))
+    });
+    diagnostics
+}
+
+/// The diagnostics reported so far, without forgetting them
+pub fn reported() -> Vec<Diagnostic> {
+    let diagnostics = take();
+    REPORTED.lock().unwrap().extend(diagnostics.iter().cloned());
+    diagnostics
+}
@@ -0,0 +1,149 @@
+use crate::types::{Executable, Test};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+/// Stores the tests listed by every executable, so that an executable is only listed again when
+/// its content or the options of the listing change. There is one file per executable, named
+/// after the hash of its path.
+#[derive(Debug, Clone)]
+pub struct DiscoveryCache {
+    dir: PathBuf,
+    read_only: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<'a> {
+    path: PathBuf,
+    // Checked first, so that the content only has to be hashed when the executable was touched
+    size: u64,
+    modified: u128,
+    content_hash: u32,
+    options_hash: u64,
+    tests: Cow<'a, [Test]>,
+}
+
+// The size and the modification time of a file
+fn file_stamp(path: &Path) -> Option<(u64, u128)> {
+    let metadata = path.metadata().ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_nanos()))
+}
+
+fn content_hash(path: &Path) -> Option<u32> {
+    let mut file = File::open(path).ok()?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        match file.read(&mut buffer).ok()? {
+            0 => return Some(hasher.finalize()),
+            len => hasher.update(&buffer[..len]),
+        }
+    }
+}
+
+pub fn hash_of(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl DiscoveryCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            read_only: false,
+        }
+    }
+
+    /// Only returns the saved tests, and never saves the tests listed again
+    pub fn read_only(self) -> Self {
+        Self {
+            read_only: true,
+            ..self
+        }
+    }
+
+    fn entry_path(&self, executable: &Path) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", hash_of(executable)))
+    }
+
+    /// Returns the cached tests of the executable, if they were listed with the same options and
+    /// the executable didn't change since
+    pub fn get(&self, executable: &Executable, options_hash: u64) -> Option<Vec<Test>> {
+        let file = File::open(self.entry_path(&executable.path)).ok()?;
+        let mut entry: CacheEntry = serde_json::from_reader(BufReader::new(file)).ok()?;
+        if entry.path != executable.path || entry.options_hash != options_hash {
+            return None;
+        }
+
+        let (size, modified) = file_stamp(&executable.path)?;
+        if (size, modified) != (entry.size, entry.modified) {
+            // A relinked executable can be identical to the previous one
+            if size != entry.size || content_hash(&executable.path)? != entry.content_hash {
+                return None;
+            }
+            entry.modified = modified;
+            self.write_entry(&entry);
+        }
+
+        // The cached tests have their own copy of the executable, which may be outdated
+        let shared_executable = Arc::new(executable.clone());
+        let mut tests = entry.tests.into_owned();
+        for test in &mut tests {
+            test.executable = Arc::clone(&shared_executable);
+        }
+        Some(tests)
+    }
+
+    /// Errors are ignored, since the tests will just be listed again next time
+    pub fn insert(&self, executable: &Executable, options_hash: u64, tests: &[Test]) {
+        if self.read_only {
+            return;
+        }
+        let Some(((size, modified), content_hash)) =
+            file_stamp(&executable.path).zip(content_hash(&executable.path))
+        else {
+            return;
+        };
+
+        self.write_entry(&CacheEntry {
+            path: executable.path.clone(),
+            size,
+            modified,
+            content_hash,
+            options_hash,
+            tests: Cow::Borrowed(tests),
+        });
+    }
+
+    fn write_entry(&self, entry: &CacheEntry) {
+        // The refreshed modification time of an entry isn't saved either
+        if self.read_only {
+            return;
+        }
+        let path = self.entry_path(&entry.path);
+        // Written to a temporary file first, so that concurrent runs never read a partial entry
+        let temporary_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        let write = || -> Result<()> {
+            fs::create_dir_all(&self.dir)?;
+            let mut writer = BufWriter::new(File::create(&temporary_path)?);
+            serde_json::to_writer(&mut writer, entry)?;
+            writer.flush()?;
+            fs::rename(&temporary_path, &path)?;
+            Ok(())
+        };
+        if write().is_err() {
+            let _ = fs::remove_file(&temporary_path);
+        }
+    }
+}
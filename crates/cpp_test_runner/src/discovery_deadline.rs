@@ -0,0 +1,91 @@
+//! The time limit of the discovery, after which it returns the tests found so far
+
+use anyhow::{bail, Result};
+use std::{
+    collections::BTreeSet,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::Mutex,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// How often a listing process is checked while waiting for it
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// When the discovery stops searching for executables and listing their tests, and what it left
+/// unfinished then: the directories it didn't search, or not entirely, and the executables it
+/// didn't list. The executables already in the discovery cache are still listed from it.
+#[derive(Debug)]
+pub struct DiscoveryDeadline {
+    at: Instant,
+    unfinished: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl DiscoveryDeadline {
+    pub fn after(timeout: Duration) -> Self {
+        Self {
+            at: Instant::now() + timeout,
+            unfinished: Mutex::default(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.at
+    }
+
+    pub fn mark_unfinished(&self, path: &Path) {
+        self.unfinished.lock().unwrap().insert(path.to_path_buf());
+    }
+
+    /// The paths left unfinished, sorted
+    pub fn unfinished(&self) -> Vec<PathBuf> {
+        self.unfinished.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+// Read on their own thread, which a process that outlives the killed one can keep waiting
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut content = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut content);
+        }
+        content
+    })
+}
+
+/// Runs a command of the listing like `Command::output`, and kills it when the deadline passes
+pub fn output_until(command: &mut Command, deadline: Option<&DiscoveryDeadline>) -> Result<Output> {
+    tracing::debug!(?command, "starting the listing");
+    let Some(deadline) = deadline else {
+        return Ok(command.output()?);
+    };
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if deadline.is_expired() {
+            tracing::debug!(?command, "killed the listing, the discovery timed out");
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("The discovery timed out");
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
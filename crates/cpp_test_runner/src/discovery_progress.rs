@@ -0,0 +1,7 @@
+//! Counters updated during the discovery, so that its progress can be shown while it runs
+
+use std::sync::atomic::AtomicUsize;
+
+pub static EXECUTABLES_FOUND: AtomicUsize = AtomicUsize::new(0);
+pub static EXECUTABLES_LISTED: AtomicUsize = AtomicUsize::new(0);
+pub static TESTS_LISTED: AtomicUsize = AtomicUsize::new(0);
@@ -0,0 +1,244 @@
+use anyhow::{bail, Result};
+use cpp_test_runner::{
+    diagnostics::{self, Diagnostic},
+    types::Test,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+// The same test in several executables has the same name and location. The tests without a source
+// file can't be told apart from different tests with the same name.
+fn registration(test: &Test) -> Option<(&Path, Option<u32>, &str)> {
+    Some((test.file.as_deref()?, test.line, test.name.as_str()))
+}
+
+/// Sets the other executables that register every test from the same source location, which
+/// happens when an object library or the sources of a test are linked into several executables
+pub fn mark_duplicates(tests: &mut [Test]) {
+    let mut executables = HashMap::<_, Vec<PathBuf>>::new();
+    for test in tests.iter() {
+        if let Some(registration) = registration(test) {
+            let registered_by = executables.entry(registration).or_default();
+            if !registered_by.contains(&test.executable.path) {
+                registered_by.push(test.executable.path.clone());
+            }
+        }
+    }
+
+    let also_registered_by = tests
+        .iter()
+        .map(|test| {
+            registration(test)
+                .and_then(|registration| executables.get(&registration))
+                .filter(|executables| executables.len() > 1)
+                .map(|executables| {
+                    executables
+                        .iter()
+                        .filter(|executable| **executable != test.executable.path)
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    for (test, also_registered_by) in tests.iter_mut().zip(also_registered_by) {
+        test.also_registered_by = also_registered_by;
+    }
+}
+
+/// Keeps one of the tests registered by several executables from the same source location, the one
+/// of the executable with the first path, as the executables are discovered in any order
+pub fn dedupe_by_source(tests: &mut Vec<Test>) {
+    tests.retain(|test| {
+        test.also_registered_by
+            .iter()
+            .all(|other| test.executable.path < *other)
+    });
+}
+
+/// Warns about the tests registered by several executables, which run several times
+pub fn warn_about_duplicates(tests: &[Test]) {
+    let mut duplicated = tests
+        .iter()
+        .filter(|test| !test.also_registered_by.is_empty())
+        .map(|test| test.name.as_str())
+        .collect::<Vec<_>>();
+    duplicated.sort_unstable();
+    duplicated.dedup();
+    if duplicated.is_empty() {
+        return;
+    }
+
+    diagnostics::report(
+        Diagnostic::warning(
+            diagnostics::DUPLICATE_REGISTRATIONS,
+            match duplicated.len() {
+                1 => {
+                    "1 test is registered by several executables from the same source file, so it \
+                      runs once per executable:"
+                        .to_owned()
+                }
+                count => format!(
+                    "{count} tests are registered by several executables from the same source \
+                     file, so they run once per executable:"
+                ),
+            },
+        )
+        .details(duplicated)
+        .hint("Use --dedupe-by-source to run them once."),
+    );
+}
+
+// The fewest trailing components of the paths, without their extension, that tell the executables
+// apart, like `unit` and `integration`, or `first/unit` and `second/unit`
+fn distinguishing_suffixes(executables: &[&Path]) -> Vec<String> {
+    let stems = executables
+        .iter()
+        .map(|executable| executable.with_extension(""))
+        .collect::<Vec<_>>();
+    let components = stems
+        .iter()
+        .map(|stem| stem.components().rev().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let suffix = |components: &[Component], length: usize| {
+        components[..length.min(components.len())]
+            .iter()
+            .rev()
+            .collect::<PathBuf>()
+    };
+
+    let max_length = components.iter().map(Vec::len).max().unwrap_or_default();
+    let length = (1..=max_length).find(|&length| {
+        let suffixes = components
+            .iter()
+            .map(|components| suffix(components, length))
+            .collect::<HashSet<_>>();
+        suffixes.len() == executables.len()
+    });
+    match length {
+        Some(length) => components
+            .iter()
+            .map(|components| suffix(components, length).display().to_string())
+            .collect(),
+        // Only the extensions differ
+        None => executables
+            .iter()
+            .map(|executable| executable.display().to_string())
+            .collect(),
+    }
+}
+
+/// Gives every test an id that is unique among the tests: its name, prefixed with the end of the
+/// path of its executable, like `unit::Math.Adds`, when other executables have a test with the name
+pub fn assign_ids(tests: &mut [Test]) {
+    let mut executables = HashMap::<&str, Vec<&Path>>::new();
+    for test in tests.iter() {
+        let with_name = executables.entry(test.name.as_str()).or_default();
+        if !with_name.contains(&test.executable.path.as_path()) {
+            with_name.push(&test.executable.path);
+        }
+    }
+    let prefixes = executables
+        .into_iter()
+        .filter(|(_, executables)| executables.len() > 1)
+        .map(|(name, executables)| {
+            let suffixes = distinguishing_suffixes(&executables);
+            (name, executables.into_iter().zip(suffixes).collect())
+        })
+        .collect::<HashMap<_, HashMap<_, _>>>();
+
+    let ids = tests
+        .iter()
+        .map(|test| match prefixes.get(test.name.as_str()) {
+            Some(prefixes) => format!(
+                "{}::{}",
+                prefixes[&test.executable.path.as_path()],
+                test.name
+            ),
+            None => test.name.clone(),
+        })
+        .collect::<Vec<_>>();
+    for (test, id) in tests.iter_mut().zip(ids) {
+        test.id = id;
+    }
+}
+
+// The names of the tests of several executables, which were given prefixed ids
+fn duplicate_names(tests: &[Test]) -> Vec<&str> {
+    let mut names = tests
+        .iter()
+        .filter(|test| test.id != test.name)
+        .map(|test| test.name.as_str())
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+/// Warns about the different tests of several executables that have the same name, which can't be
+/// told apart in the plain list and in the launch.json. The same test registered by several
+/// executables is already warned about by `warn_about_duplicates`.
+pub fn warn_about_duplicate_names(tests: &[Test]) {
+    let mut registrations = HashMap::<&str, Vec<_>>::new();
+    for test in tests.iter().filter(|test| test.id != test.name) {
+        registrations
+            .entry(test.name.as_str())
+            .or_default()
+            .push(registration(test));
+    }
+    let mut clashing = registrations
+        .into_iter()
+        .filter(|(_, registrations)| {
+            registrations
+                .iter()
+                .any(|registration| registration.is_none() || *registration != registrations[0])
+        })
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    clashing.sort_unstable();
+    if clashing.is_empty() {
+        return;
+    }
+
+    diagnostics::report(
+        Diagnostic::warning(
+            diagnostics::DUPLICATE_NAMES,
+            match clashing.len() {
+                1 => "1 test name is used by different tests of several executables:".to_owned(),
+                count => {
+                    format!(
+                        "{count} test names are used by different tests of several executables:"
+                    )
+                }
+            },
+        )
+        .details(clashing)
+        .hint(
+            "Use --disambiguate-duplicates to name them by their id, prefixed with their \
+             executable, or --strict-duplicates to make it an error.",
+        ),
+    );
+}
+
+/// Fails when tests of several executables have the same name, for --strict-duplicates
+pub fn reject_duplicate_names(tests: &[Test]) -> Result<()> {
+    let names = duplicate_names(tests);
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    diagnostics::report(
+        Diagnostic::error(
+            diagnostics::DUPLICATE_NAMES,
+            match names.len() {
+                1 => "1 test name is used by tests of several executables:".to_owned(),
+                count => format!("{count} test names are used by tests of several executables:"),
+            },
+        )
+        .details(names)
+        .hint("Rename them, or use --dedupe-by-source for the tests of the same source file."),
+    );
+    bail!("Tests of several executables have the same name");
+}
@@ -0,0 +1,54 @@
+use anyhow::{bail, Result};
+use std::{env, path::Path, process::Command};
+
+// The editors that take the location as `file:line`, or after --goto for VS Code and its forks.
+// The others are given `+line file`, like vi, emacs and nano.
+const COLON_EDITORS: [&str; 4] = ["subl", "zed", "hx", "helix"];
+const GOTO_EDITORS: [&str; 4] = ["code", "code-insiders", "codium", "cursor"];
+
+/// The command that opens a file at a line: the --editor-command template with its {file} and
+/// {line} placeholders, or else $VISUAL or $EDITOR with the line syntax of the editor, or else VS
+/// Code. The template and the variables can contain arguments, separated by spaces.
+pub fn editor_command(template: Option<&str>, file: &Path, line: Option<u32>) -> Result<Command> {
+    let file_str = file.to_string_lossy();
+    let line_str = line.unwrap_or(1).to_string();
+
+    if let Some(template) = template {
+        let mut words = template.split_whitespace().map(|word| {
+            word.replace("{file}", &file_str)
+                .replace("{line}", &line_str)
+        });
+        let Some(program) = words.next() else {
+            bail!("The editor command is empty");
+        };
+        let mut command = Command::new(program);
+        command.args(words);
+        return Ok(command);
+    }
+
+    let editor = ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|variable| env::var(variable).ok())
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| String::from("code"));
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("code");
+    let mut command = Command::new(program);
+    command.args(words);
+
+    let name = Path::new(program)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    match line {
+        None => command.arg(file),
+        Some(_) if GOTO_EDITORS.contains(&name.as_ref()) => {
+            command.arg("--goto").arg(format!("{file_str}:{line_str}"))
+        }
+        Some(_) if COLON_EDITORS.contains(&name.as_ref()) => {
+            command.arg(format!("{file_str}:{line_str}"))
+        }
+        Some(_) => command.arg(format!("+{line_str}")).arg(file),
+    };
+    Ok(command)
+}
@@ -1,14 +1,29 @@
-use crate::types::{Executable, ExecutableType};
-use anyhow::{anyhow, bail, Result};
-use elf_parser::{Elf, Section, SectionHeaders};
-use faccess::PathExt;
-use ignore::WalkBuilder;
+use crate::{
+    binary_inspector::{inspect, TestBinaryInspector},
+    diagnostics::{self, Diagnostic},
+    discovery_deadline::DiscoveryDeadline,
+    discovery_progress::EXECUTABLES_FOUND,
+    framework_probe::{FrameworkProbe, PROBES},
+    types::{Executable, ExecutableMetadata, ExecutableType, Test},
+};
+use anyhow::{anyhow, bail, Error, Result};
+use clap::ValueEnum;
+use globset::GlobBuilder;
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    WalkBuilder,
+};
+use memchr::memmem;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use std::{
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::{atomic::Ordering, Arc},
     thread,
-    time::UNIX_EPOCH,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use walkdir::WalkDir;
 
 pub fn find_test_dir(cli_path: &str, cli_no_parent: bool) -> Result<Option<PathBuf>> {
     let cli_path = PathBuf::from(cli_path);
@@ -42,7 +57,9 @@ pub fn validate_executables(executables: &[PathBuf]) -> Result<Vec<Executable>>
     executables
         .par_iter()
         .map(|path| {
-            let Ok(Some(gtest_executable)) = parse_test_executable(path, true, true) else {
+            let Ok(Some(gtest_executable)) =
+                parse_test_executable(path, ExecutableType::value_variants())
+            else {
                 return Err(anyhow!(format!(
                     "{} is not a test executable",
                     path.display()
@@ -53,28 +70,243 @@ pub fn validate_executables(executables: &[PathBuf]) -> Result<Vec<Executable>>
         .collect::<Result<Vec<_>>>()
 }
 
+/// Expands paths and glob patterns into the executables they refer to. Only the directory
+/// before the first glob component of a pattern is walked, instead of the whole tree.
+pub fn expand_executable_patterns(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut executables = Vec::new();
+
+    for pattern in patterns {
+        let is_glob = pattern.contains(['*', '?', '[', '{']);
+        if !is_glob {
+            executables.push(PathBuf::from(pattern));
+            continue;
+        }
+
+        let components = Path::new(pattern).components().collect::<Vec<_>>();
+        let num_literal_components = components
+            .iter()
+            .take_while(|component| {
+                !component
+                    .as_os_str()
+                    .to_string_lossy()
+                    .contains(['*', '?', '[', '{'])
+            })
+            .count();
+
+        let base: PathBuf = components[..num_literal_components].iter().collect();
+        let base = if base.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            base
+        };
+
+        let mut walker = WalkDir::new(&base).sort_by_file_name();
+        if !pattern.contains("**") {
+            walker = walker.max_depth(components.len() - num_literal_components);
+        }
+
+        let matcher = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()?
+            .compile_matcher();
+        let num_executables = executables.len();
+        for entry in walker.into_iter().filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            // Walking from "." prefixes the paths with "./", which the pattern doesn't have
+            let relative_path = path.strip_prefix(".").unwrap_or(path);
+            if (matcher.is_match(path) || matcher.is_match(relative_path))
+                && path.is_file()
+                && is_executable(path)
+            {
+                executables.push(path.to_path_buf());
+            }
+        }
+
+        if executables.len() == num_executables {
+            bail!("No executable matches {pattern}");
+        }
+    }
+
+    Ok(executables)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    faccess::PathExt::executable(path)
+}
+
+// Windows has no executable permission, its executables are recognized by their extension
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("exe"))
+}
+
+// The shared libraries of an installed tree, like libgtest.so.1.14, contain the symbols of the
+// frameworks without being tests
+fn is_shared_library(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        let name = name.to_string_lossy();
+        name.ends_with(".so") || name.contains(".so.")
+    })
+}
+
+/// The cutoff of --newer-than: an age, like `30m` or `1h30m`, with the units `s`, `m`, `h` and
+/// `d`, or a file whose modification time it is, like a stamp touched after every run
+#[derive(Debug, Clone)]
+pub enum NewerThan {
+    Age(Duration),
+    File(PathBuf),
+}
+
+impl NewerThan {
+    /// The time after which the executables were modified
+    pub fn cutoff(&self) -> Result<SystemTime> {
+        match self {
+            NewerThan::Age(age) => Ok(SystemTime::now() - *age),
+            NewerThan::File(path) => std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .map_err(|error| anyhow!("Failed to read {}: {error}", path.display())),
+        }
+    }
+}
+
+// Like 1h30m, the total of every number and its unit
+fn parse_age(age: &str) -> Option<Duration> {
+    let mut seconds = 0;
+    let mut rest = age;
+    while !rest.is_empty() {
+        let digits = rest.find(|char: char| !char.is_ascii_digit())?;
+        let number = rest[..digits].parse::<u64>().ok()?;
+        let unit = match rest[digits..].chars().next()? {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return None,
+        };
+        seconds += number * unit;
+        rest = &rest[digits + 1..];
+    }
+    (!age.is_empty()).then(|| Duration::from_secs(seconds))
+}
+
+impl FromStr for NewerThan {
+    type Err = Error;
+
+    fn from_str(newer_than: &str) -> Result<Self> {
+        if let Some(age) = parse_age(newer_than) {
+            return Ok(NewerThan::Age(age));
+        }
+        let path = PathBuf::from(newer_than);
+        if !path.exists() {
+            bail!("{newer_than} is neither an age like 30m nor an existing file");
+        }
+        Ok(NewerThan::File(path))
+    }
+}
+
+/// The file of the paths the search for executables skips, in the syntax of a .gitignore, which
+/// applies to the directory it's in and below
+pub const TEST_IGNORE_FILE_NAME: &str = ".testignore";
+
+/// How the search for executables walks the directory. It skips the paths matching the globs of
+/// --exclude-path or not matching those of --include-path, which are matched like the lines of a
+/// .gitignore against the paths under the searched directory, and the paths ignored by the
+/// .testignore files and, with --use-gitignore, the .gitignore files. Once there is an included
+/// glob, only the files it matches are inspected, but the directories are still searched.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub use_gitignore: bool,
+    /// The depth of the deepest files inspected, where 1 is the files in the directory itself
+    pub max_depth: Option<usize>,
+    /// Whether the symlinks are followed, which an installed tree always does. The symlinks that
+    /// loop back to their parents are skipped.
+    pub follow_symlinks: bool,
+    /// Only the files modified since then are inspected, like the executables relinked by the
+    /// last build
+    pub newer_than: Option<SystemTime>,
+}
+
+impl WalkOptions {
+    fn overrides(&self, root: &Path) -> Result<Override> {
+        let mut builder = OverrideBuilder::new(root);
+        for glob in &self.include {
+            builder
+                .add(glob)
+                .map_err(|error| anyhow!("Invalid --include-path {glob}: {error}"))?;
+        }
+        for glob in &self.exclude {
+            builder
+                .add(&format!("!{glob}"))
+                .map_err(|error| anyhow!("Invalid --exclude-path {glob}: {error}"))?;
+        }
+        Ok(builder.build()?)
+    }
+}
+
+fn is_symlink_loop(error: &ignore::Error) -> bool {
+    match error {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => is_symlink_loop(err),
+        _ => false,
+    }
+}
+
+// The symlinks that loop are expected when they are followed, the other errors leave out the
+// executables under the path
+fn report_walk_error(error: &ignore::Error) {
+    let diagnostic = if is_symlink_loop(error) {
+        Diagnostic::info(
+            diagnostics::SYMLINK_LOOP,
+            "a symlink loops back to its parent, so it was skipped:",
+        )
+    } else {
+        Diagnostic::warning(
+            diagnostics::UNREADABLE_PATH,
+            "a path couldn't be read, so the executables under it are missing:",
+        )
+    };
+    tracing::debug!(%error, "skipped a path that couldn't be walked");
+    diagnostics::report(diagnostic.details([error]));
+}
+
+/// Searches the directory for test executables. An installed tree, like /opt/product, is searched
+/// through its symlinks, which packages use to point to their versioned files, and its shared
+/// libraries are skipped. An executable found through several symlinks is only kept once. Once the deadline passes, the directories left are only marked as
+/// unfinished.
 pub fn find_test_executables(
     path: &Path,
     jobs: Option<usize>,
     executable_types: &[ExecutableType],
+    installed_tree: bool,
+    walk_options: &WalkOptions,
+    deadline: Option<&DiscoveryDeadline>,
 ) -> Result<Vec<Executable>> {
+    let start = Instant::now();
+    let follow_symlinks = installed_tree || walk_options.follow_symlinks;
     let walker = WalkBuilder::new(path)
+        .overrides(walk_options.overrides(path)?)
+        .add_custom_ignore_filename(TEST_IGNORE_FILE_NAME)
         .hidden(false)
         .ignore(false)
-        .parents(false)
-        .git_ignore(false)
+        // The .gitignore files of the repository above the build directory apply to it too
+        .parents(walk_options.use_gitignore)
+        .git_ignore(walk_options.use_gitignore)
         .git_global(false)
-        .git_exclude(false)
+        .git_exclude(walk_options.use_gitignore)
         .require_git(false)
-        .follow_links(false)
+        .follow_links(follow_symlinks)
+        .max_depth(walk_options.max_depth)
         .threads(jobs.unwrap_or_default())
         .build_parallel();
 
     let (tx, rx) = crossbeam::channel::bounded::<Executable>(100);
 
-    let is_gtest_enabled = executable_types.contains(&ExecutableType::Gtest);
-    let is_catch2_enabled = executable_types.contains(&ExecutableType::Catch2);
-
     let mut tests = Vec::<Executable>::default();
 
     thread::scope(|scope| {
@@ -92,13 +324,65 @@ pub fn find_test_executables(
         walker.run(|| {
             let tx = tx.clone();
             Box::new(move |result| {
-                let path = result.as_ref().unwrap().path();
-                if path.is_file() && path.executable() {
-                    if let Ok(Some(executable)) =
-                        parse_test_executable(path, is_gtest_enabled, is_catch2_enabled)
+                let entry = match result {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        report_walk_error(&error);
+                        return ignore::WalkState::Continue;
+                    }
+                };
+                let path = entry.path();
+                // The rest of the tree isn't searched, only reported
+                if let Some(deadline) = deadline.filter(|deadline| deadline.is_expired()) {
+                    tracing::debug!(path = %path.display(), "skipped, the discovery timed out");
+                    if entry
+                        .file_type()
+                        .is_some_and(|file_type| file_type.is_dir())
                     {
+                        deadline.mark_unfinished(path);
+                        return ignore::WalkState::Skip;
+                    }
+                    if let Some(dir) = path.parent() {
+                        deadline.mark_unfinished(dir);
+                    }
+                    return ignore::WalkState::Continue;
+                }
+                if installed_tree && is_shared_library(path) {
+                    tracing::debug!(path = %path.display(), "skipped, a shared library");
+                    return ignore::WalkState::Continue;
+                }
+                // The directories are still searched, their time is the one of their entries
+                let is_dir = entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_dir());
+                if let Some(newer_than) = walk_options.newer_than.filter(|_| !is_dir) {
+                    // The time of the target, for the symlinks to the executables
+                    let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+                    if modified.is_ok_and(|modified| modified <= newer_than) {
+                        tracing::debug!(
+                            path = %path.display(),
+                            "skipped, not modified since --newer-than"
+                        );
+                        return ignore::WalkState::Continue;
+                    }
+                }
+                if !path.is_file() {
+                    return ignore::WalkState::Continue;
+                }
+                if !is_executable(path) {
+                    tracing::trace!(path = %path.display(), "skipped, not executable");
+                    return ignore::WalkState::Continue;
+                }
+                match parse_test_executable(path, executable_types) {
+                    Ok(Some(executable)) => {
+                        EXECUTABLES_FOUND.fetch_add(1, Ordering::Relaxed);
                         tx.send(executable).unwrap();
                     }
+                    // The reason is logged by parse_test_executable
+                    Ok(None) => {}
+                    Err(error) => {
+                        tracing::debug!(path = %path.display(), %error, "skipped, unreadable");
+                    }
                 }
                 ignore::WalkState::Continue
             })
@@ -107,56 +391,190 @@ pub fn find_test_executables(
         drop(tx);
     });
 
+    // The symlinks make the same executable appear under several paths
+    if follow_symlinks {
+        let mut seen = HashSet::new();
+        tests.sort_by(|a, b| a.path.cmp(&b.path));
+        tests.retain(|executable| {
+            let is_new = seen.insert(
+                executable
+                    .path
+                    .canonicalize()
+                    .unwrap_or_else(|_| executable.path.clone()),
+            );
+            if !is_new {
+                tracing::debug!(
+                    path = %executable.path.display(),
+                    "skipped, already found through another symlink"
+                );
+            }
+            is_new
+        });
+    }
+
+    tracing::info!(
+        dir = %path.display(),
+        executables = tests.len(),
+        elapsed = ?start.elapsed(),
+        "searched for test executables"
+    );
     Ok(tests)
 }
 
-pub fn parse_test_executable(
-    path: &Path,
-    is_gtest_enabled: bool,
-    is_catch2_enabled: bool,
-) -> Result<Option<Executable>> {
-    let elf = Elf::new(path)?;
+// The frameworks are recognized by their shared library first, which is the fastest, then by
+// their symbols, and then, when only a part of the symbols are in the binary, by the strings that
+// only the framework embeds
+fn find_executable_type(
+    binary: &dyn TestBinaryInspector,
+    probes: &[&&dyn FrameworkProbe],
+) -> Result<Option<ExecutableType>> {
+    let needed_libraries = binary.needed_libraries()?;
+    let library_type = probes.iter().find_map(|probe| {
+        needed_libraries
+            .iter()
+            .any(|library| probe.is_framework_library(library))
+            .then(|| probe.executable_type())
+    });
+    if library_type.is_some() {
+        return Ok(library_type);
+    }
 
-    let elf_type = elf.header.e_type();
-    if elf_type != 0x02 && elf_type != 0x03 {
-        return Ok(None);
+    let mut symbol_type = None;
+    binary.find_symbol(&mut |symbol| {
+        symbol_type = probes
+            .iter()
+            .find(|probe| probe.is_framework_symbol(symbol))
+            .map(|probe| probe.executable_type());
+        symbol_type.is_some()
+    })?;
+    if symbol_type.is_some() || !binary.is_stripped() {
+        return Ok(symbol_type);
     }
 
-    let all_section_headers: SectionHeaders = elf.get_all_section_headers()?;
-    let Some(symbol_table_header) = all_section_headers.find_symbol_table_header() else {
-        return Ok(None);
-    };
+    for data in binary.string_sections()? {
+        let executable_type = probes.iter().find_map(|probe| {
+            probe
+                .fingerprints()
+                .iter()
+                .any(|fingerprint| memmem::find(&data, fingerprint).is_some())
+                .then(|| probe.executable_type())
+        });
+        if executable_type.is_some() {
+            return Ok(executable_type);
+        }
+    }
 
-    let Some(string_table_header) = all_section_headers
-        .headers
-        .get(symbol_table_header.sh_link() as usize)
-    else {
-        bail!("Invalid ELF");
-    };
+    Ok(None)
+}
 
-    let Section::Symbols(symbols) = elf.get_section(symbol_table_header)? else {
-        bail!("Invalid ELF");
-    };
+// The symbols that the runtimes of the sanitizers define
+const SANITIZER_SYMBOLS: &[(&str, &str)] = &[
+    ("__asan_init", "address"),
+    ("__hwasan_init", "hwaddress"),
+    ("__msan_init", "memory"),
+    ("__tsan_init", "thread"),
+];
 
-    let Section::Strings(strings) = elf.get_section(string_table_header)? else {
-        bail!("Invalid ELF");
+// The runtimes of the other sanitizers also contain the handlers of UndefinedBehaviorSanitizer, so
+// it's only told apart when it's alone
+const UBSAN_HANDLER_PREFIX: &str = "__ubsan_handle_";
+
+fn find_sanitizers(binary: &dyn TestBinaryInspector) -> Result<Vec<String>> {
+    let mut sanitizers = Vec::new();
+    let mut undefined = false;
+    // Never true, so that all the symbols are read
+    binary.find_symbol(&mut |symbol| {
+        if let Some((_, sanitizer)) = SANITIZER_SYMBOLS.iter().find(|(name, _)| symbol == *name) {
+            if !sanitizers.contains(sanitizer) {
+                sanitizers.push(*sanitizer);
+            }
+        }
+        undefined |= symbol.starts_with(UBSAN_HANDLER_PREFIX);
+        false
+    })?;
+    if undefined && sanitizers.is_empty() {
+        sanitizers.push("undefined");
+    }
+
+    sanitizers.sort_unstable();
+    Ok(sanitizers.into_iter().map(String::from).collect())
+}
+
+// What the discovery reads anyway while recognizing the framework
+fn read_metadata(binary: &dyn TestBinaryInspector) -> ExecutableMetadata {
+    ExecutableMetadata {
+        machine: binary.machine(),
+        kind: binary.kind(),
+        build_id: binary.build_id(),
+        ..Default::default()
+    }
+}
+
+fn read_all_metadata(path: &Path) -> Result<ExecutableMetadata> {
+    let Some(binary) = inspect(path)? else {
+        bail!("{} is neither an ELF nor a PE", path.display());
     };
 
-    let test_executable_type = symbols.iter().find_map(|symbol| {
-        strings
-            .get_symbol_name(symbol)
-            .map(|symbol_cstr| symbol_cstr.to_string_lossy())
-            .and_then(|symbol| {
-                if is_gtest_enabled && symbol.contains("InitGoogleTest") {
-                    Some(ExecutableType::Gtest)
-                } else if is_catch2_enabled && symbol.contains("Catch2") {
-                    Some(ExecutableType::Catch2)
-                } else {
-                    None
+    Ok(ExecutableMetadata {
+        stripped: Some(binary.is_stripped()),
+        debug_info: Some(binary.has_debug_info()),
+        sanitizers: find_sanitizers(binary.as_ref())?,
+        needed_libraries: binary.needed_libraries()?,
+        file_size: Some(path.metadata()?.len()),
+        ..read_metadata(binary.as_ref())
+    })
+}
+
+/// Reads all the metadata of the executables of the tests, which share it. The executables that
+/// can't be read anymore keep the metadata of the discovery.
+pub fn attach_executable_metadata(tests: &mut [Test]) {
+    let mut executables = HashMap::<PathBuf, Arc<Executable>>::new();
+    for test in tests {
+        let executable = executables
+            .entry(test.executable.path.clone())
+            .or_insert_with(|| {
+                let mut executable = (*test.executable).clone();
+                if let Ok(metadata) = read_all_metadata(&executable.path) {
+                    executable.metadata = metadata;
                 }
-            })
-    });
+                Arc::new(executable)
+            });
+        test.executable = Arc::clone(executable);
+    }
+}
+
+pub fn parse_test_executable(
+    path: &Path,
+    executable_types: &[ExecutableType],
+) -> Result<Option<Executable>> {
+    let probes = PROBES
+        .iter()
+        .filter(|probe| executable_types.contains(&probe.executable_type()))
+        .collect::<Vec<_>>();
 
+    let Some(binary) = inspect(path)? else {
+        tracing::debug!(path = %path.display(), "skipped, neither an ELF nor a PE");
+        return Ok(None);
+    };
+    if !binary.is_runnable() {
+        tracing::debug!(
+            path = %path.display(),
+            machine = %binary.machine(),
+            "skipped, not runnable on this machine"
+        );
+        return Ok(None);
+    }
+
+    let test_executable_type = find_executable_type(binary.as_ref(), &probes)?;
+    match &test_executable_type {
+        Some(executable_type) => {
+            tracing::debug!(path = %path.display(), ?executable_type, "found a test executable");
+        }
+        None => tracing::debug!(
+            path = %path.display(),
+            "skipped, not built with one of the --executable-types"
+        ),
+    }
     let gtest_executable = test_executable_type.map(|test_executable_type| Executable {
         path: path.to_path_buf(),
         modified: path
@@ -168,6 +586,9 @@ pub fn parse_test_executable(
             .unwrap()
             .as_nanos(),
         executable_type: test_executable_type,
+        metadata: read_metadata(binary.as_ref()),
+        environment: Default::default(),
+        base_arguments: Vec::new(),
     });
 
     Ok(gtest_executable)
@@ -0,0 +1,78 @@
+use crate::types::Executable;
+use anyhow::{anyhow, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const EXECUTABLE_RULES_FILE_NAME: &str = "tests.executables.json";
+
+#[derive(Debug, Deserialize)]
+struct ExecutableRule {
+    /// A glob matched against the path of the executables relative to the directory of the file,
+    /// like "integration/*_test"
+    executables: String,
+    #[serde(default)]
+    arguments: Vec<String>,
+}
+
+/// The arguments that the executables matching some globs need to start, even to list their
+/// tests, like:
+///
+/// ```json
+/// [
+///     { "executables": "integration/*", "arguments": ["--config", "integration.yaml"] },
+///     { "executables": "**/server_test", "arguments": ["--port=0"] }
+/// ]
+/// ```
+///
+/// When several rules match an executable, their arguments are added in the order of the file.
+pub struct ExecutableRules {
+    dir: PathBuf,
+    rules: Vec<(GlobMatcher, Vec<String>)>,
+}
+
+impl ExecutableRules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let rules: Vec<ExecutableRule> = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|error| anyhow!("Failed to parse {}: {error}", path.display()))?;
+
+        Ok(Self {
+            dir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            rules: rules
+                .into_iter()
+                .map(|rule| {
+                    let glob = GlobBuilder::new(&rule.executables)
+                        .literal_separator(true)
+                        .build()?;
+                    Ok((glob.compile_matcher(), rule.arguments))
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Looks for the rules file in the test directory, then in its parent directory
+    pub fn find(test_dir: &Path) -> Option<PathBuf> {
+        test_dir
+            .ancestors()
+            .take(2)
+            .map(|dir| dir.join(EXECUTABLE_RULES_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    pub fn apply(&self, executables: &mut [Executable]) {
+        for executable in executables {
+            let relative_path = executable
+                .path
+                .strip_prefix(&self.dir)
+                .unwrap_or(&executable.path);
+            for (glob, arguments) in &self.rules {
+                if glob.is_match(relative_path) {
+                    executable.base_arguments.extend(arguments.iter().cloned());
+                }
+            }
+        }
+    }
+}
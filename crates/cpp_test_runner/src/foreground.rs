@@ -0,0 +1,174 @@
+use std::{
+    io,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Once,
+    },
+};
+
+// The process group of the test running in the foreground, or 0
+static FOREGROUND_GROUP: AtomicI32 = AtomicI32::new(0);
+// Set by Ctrl-C, after which no other test is started
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+const FORWARDED_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTSTP, libc::SIGWINCH];
+
+/// Whether the run was interrupted, by a SIGINT sent to this process or to a test in the
+/// foreground
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+extern "C" fn forward_signal(signal: libc::c_int) {
+    if signal == libc::SIGINT {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+
+    let group = FOREGROUND_GROUP.load(Ordering::Relaxed);
+    // SAFETY: kill and raise are async-signal-safe
+    unsafe {
+        if group > 0 {
+            libc::kill(-group, signal);
+        } else if signal == libc::SIGTSTP {
+            // Between two tests, Ctrl-Z stops the runner like it would without the handler
+            libc::raise(libc::SIGSTOP);
+        }
+    }
+}
+
+fn install_signal_handlers() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        for signal in FORWARDED_SIGNALS {
+            // SAFETY: sigaction is a plain C struct, for which zeroes are valid, and the handler
+            // only uses async-signal-safe functions
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = forward_signal as *const () as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, std::ptr::null_mut());
+            }
+        }
+    });
+}
+
+// Whether this process is in the foreground of the terminal on stdin, and can give it to a test
+fn owns_terminal() -> bool {
+    // SAFETY: these functions only read the state of the process and of the terminal
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::tcgetpgrp(0) == libc::getpgrp() }
+}
+
+// Makes a process group the foreground of the terminal. A process in the background gets a
+// SIGTTOU when it does that, unless it blocks the signal.
+fn set_terminal_group(group: libc::pid_t) {
+    // SAFETY: the signal mask of the thread is restored right after
+    unsafe {
+        let mut ttou: libc::sigset_t = std::mem::zeroed();
+        let mut previous: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut ttou);
+        libc::sigaddset(&mut ttou, libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &ttou, &mut previous);
+        libc::tcsetpgrp(libc::STDIN_FILENO, group);
+        libc::pthread_sigmask(libc::SIG_SETMASK, &previous, std::ptr::null_mut());
+    }
+}
+
+/// Runs a process in its own process group with the terminal of this process, and waits for it,
+/// like `output_with_max_rss` without capturing the output. The process group is the foreground
+/// of the terminal while it runs, so that the process can read from it and gets the Ctrl-C, the
+/// Ctrl-Z and the resizes of the terminal like when it runs directly. SIGINT, SIGTSTP and
+/// SIGWINCH sent to this process, e.g. by an IDE, are forwarded to it.
+///
+/// When the process is stopped, this process stops too so that the shell gets the terminal back,
+/// and it continues the process when it is continued.
+pub fn run_in_foreground(command: &mut Command) -> io::Result<(Output, Option<u64>)> {
+    install_signal_handlers();
+
+    let gives_terminal = owns_terminal();
+    command
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .process_group(0);
+    // The process takes the terminal itself, so that it never reads from it in the background
+    // SAFETY: the closure only uses async-signal-safe functions
+    unsafe {
+        command.pre_exec(move || {
+            if gives_terminal {
+                set_terminal_group(libc::getpid());
+            }
+            Ok(())
+        });
+    }
+
+    let child = command.spawn()?;
+    let pid = child.id() as libc::pid_t;
+    FOREGROUND_GROUP.store(pid, Ordering::Relaxed);
+    if gives_terminal {
+        set_terminal_group(pid);
+    }
+
+    let result = wait_in_foreground(pid, gives_terminal);
+
+    FOREGROUND_GROUP.store(0, Ordering::Relaxed);
+    if gives_terminal {
+        // SAFETY: getpgrp can't fail
+        set_terminal_group(unsafe { libc::getpgrp() });
+    }
+
+    let (status, max_rss) = result?;
+    if status.signal() == Some(libc::SIGINT) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+    let output = Output {
+        status,
+        stdout: Vec::new(),
+        stderr: Vec::new(),
+    };
+    Ok((output, max_rss))
+}
+
+fn wait_in_foreground(
+    pid: libc::pid_t,
+    gives_terminal: bool,
+) -> io::Result<(ExitStatus, Option<u64>)> {
+    let mut status = 0;
+    // SAFETY: rusage is a plain C struct, for which zeroes are valid
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: the process is a child of this process that wasn't waited for yet
+        let waited = unsafe { libc::wait4(pid, &mut status, libc::WUNTRACED, &mut usage) };
+        if waited == -1 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+        if !libc::WIFSTOPPED(status) {
+            break;
+        }
+
+        // SAFETY: the process group of the test exists until it is waited for
+        unsafe {
+            if gives_terminal {
+                set_terminal_group(libc::getpgrp());
+            }
+            libc::raise(libc::SIGSTOP);
+            // Continued with fg or bg. With bg, the test stays in the background.
+            if gives_terminal && owns_terminal() {
+                set_terminal_group(pid);
+            }
+            libc::kill(-pid, libc::SIGCONT);
+        }
+    }
+
+    // In kilobytes on Linux
+    Ok((
+        ExitStatus::from_raw(status),
+        Some(usage.ru_maxrss as u64 * 1024),
+    ))
+}
@@ -0,0 +1,183 @@
+use crate::{cache_dirs::shared_dir, child_environment, discovery_cache::hash_of, types::Test};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{ffi::OsStrExt, process::ExitStatusExt},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Output, Stdio},
+    sync::Mutex,
+};
+
+const SHIM_SOURCE: &str = include_str!("fork_server_shim.c");
+
+// The tests sharing a server run from the same directory with the same environment, which the
+// static initialization may depend on
+type ServerKey = (PathBuf, Option<PathBuf>, BTreeMap<String, String>);
+
+/// A gtest executable stopped after its static initialization, which forks a process per test
+struct ForkServer {
+    process: Child,
+    requests: ChildStdin,
+    responses: BufReader<ChildStdout>,
+}
+
+impl ForkServer {
+    fn start(shim: &Path, test: &Test) -> io::Result<Self> {
+        let mut command = Command::new(&test.executable.path);
+        child_environment::apply(&mut command)
+            .envs(&test.environment)
+            .env("LD_PRELOAD", shim)
+            .env("CPP_TEST_RUNNER_FORK_SERVER", "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        if let Some(cwd) = &test.cwd {
+            command.current_dir(cwd);
+        }
+
+        let mut process = command.spawn()?;
+        let (Some(requests), Some(responses)) = (process.stdin.take(), process.stdout.take())
+        else {
+            return Err(io::Error::other("The pipes of the fork server are missing"));
+        };
+        Ok(Self {
+            process,
+            requests,
+            responses: BufReader::new(responses),
+        })
+    }
+
+    // Runs main in a new process with the arguments, and returns its output and its peak memory
+    fn run<'a>(
+        &mut self,
+        arguments: impl IntoIterator<Item = &'a [u8]>,
+    ) -> io::Result<(Output, Option<u64>)> {
+        let output_file = crate::test_runner::report_path("out");
+
+        let mut request = b"out=".to_vec();
+        request.extend(output_file.as_os_str().as_bytes());
+        request.push(0);
+        for argument in arguments {
+            request.extend(b"arg=");
+            request.extend(argument);
+            request.push(0);
+        }
+        request.extend(b"run\0");
+        self.requests.write_all(&request)?;
+        self.requests.flush()?;
+
+        let mut response = String::new();
+        self.responses.read_line(&mut response)?;
+        let stdout = fs::read(&output_file).unwrap_or_default();
+        let _ = fs::remove_file(&output_file);
+
+        let Some((status, max_rss)) = response.trim().split_once(' ') else {
+            return Err(io::Error::other("The fork server exited"));
+        };
+        let (Ok(status), Ok(max_rss)) = (status.parse(), max_rss.parse::<u64>()) else {
+            return Err(io::Error::other(format!(
+                "Invalid response from the fork server: {response}"
+            )));
+        };
+
+        let output = Output {
+            status: ExitStatus::from_raw(status),
+            stdout,
+            stderr: Vec::new(),
+        };
+        // In kilobytes on Linux
+        Ok((output, Some(max_rss * 1024)))
+    }
+}
+
+impl Drop for ForkServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// The fork servers of the gtest executables, which are started when they run their first test
+/// and reused by the next ones. A test still runs in its own process, forked from the server, but
+/// the static initialization of the executable only happens once per server.
+pub struct ForkServers {
+    shim: PathBuf,
+    idle: Mutex<HashMap<ServerKey, Vec<ForkServer>>>,
+}
+
+impl ForkServers {
+    /// Compiles the preloaded shim with the C compiler, once per version of the shim
+    pub fn new() -> Result<Self> {
+        let dir = shared_dir()
+            .ok_or_else(|| anyhow!("The fork servers need a cache directory for their shim"))?;
+        let shim = dir.join(format!("fork_server_shim-{:016x}.so", hash_of(SHIM_SOURCE)));
+
+        if !shim.exists() {
+            fs::create_dir_all(&dir)?;
+            let source = dir.join(format!("fork_server_shim-{}.c", std::process::id()));
+            let compiled = source.with_extension("so");
+            fs::write(&source, SHIM_SOURCE)?;
+
+            let compiler = std::env::var_os("CC").unwrap_or_else(|| "cc".into());
+            let output = Command::new(&compiler)
+                .args(["-shared", "-fPIC", "-O2", "-o"])
+                .arg(&compiled)
+                .arg(&source)
+                .arg("-ldl")
+                .output();
+            let _ = fs::remove_file(&source);
+            match output {
+                Ok(output) if output.status.success() => fs::rename(&compiled, &shim)?,
+                Ok(output) => bail!(
+                    "Failed to compile the shim of the fork servers:\n{}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Err(error) => bail!(
+                    "Failed to compile the shim of the fork servers with {}: {error}",
+                    compiler.to_string_lossy()
+                ),
+            }
+        }
+
+        Ok(Self {
+            shim,
+            idle: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Runs a test in a process forked from a server of its executable. A server that fails is
+    /// dropped, and the next test starts a new one.
+    pub fn run<'a>(
+        &self,
+        test: &Test,
+        arguments: impl IntoIterator<Item = &'a [u8]>,
+    ) -> io::Result<(Output, Option<u64>)> {
+        let key = (
+            test.executable.path.clone(),
+            test.cwd.clone(),
+            test.environment.clone(),
+        );
+
+        let idle_server = self
+            .idle
+            .lock()
+            .unwrap()
+            .get_mut(&key)
+            .and_then(|servers| servers.pop());
+        let mut server = match idle_server {
+            Some(server) => server,
+            None => ForkServer::start(&self.shim, test)?,
+        };
+
+        let output = server.run(arguments)?;
+        self.idle
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_default()
+            .push(server);
+        Ok(output)
+    }
+}
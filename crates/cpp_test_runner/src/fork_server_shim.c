@@ -0,0 +1,116 @@
+// Preloaded in gtest executables by `run --fork-server`. Once the static initialization of the
+// executable is done, instead of running its main, the process becomes a server reading requests
+// on stdin. For every request, it forks a child that runs main with the arguments of the request,
+// waits for it, and writes its wait status and max RSS on stdout.
+//
+// A server is started with the working directory and the environment of its tests. A request is
+// a list of NUL-terminated fields, ended by "run":
+//   out=<file>   the file receiving the stdout and stderr of the test
+//   arg=<arg>    an argument given to main, after the path of the executable
+#define _GNU_SOURCE
+#include <dlfcn.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+typedef int (*main_fn)(int, char **, char **);
+typedef int (*libc_start_main_fn)(main_fn, int, char **, void (*)(void), void (*)(void),
+                                  void (*)(void), void *);
+
+static main_fn real_main;
+
+#define MAX_FIELDS 4096
+
+// Reads a NUL-terminated field from stdin, or returns NULL at the end of the input
+static char *read_field(void) {
+    size_t capacity = 256, length = 0;
+    char *field = malloc(capacity);
+    int c;
+    while ((c = getchar()) != EOF) {
+        if (length + 1 == capacity) {
+            capacity *= 2;
+            field = realloc(field, capacity);
+        }
+        field[length++] = (char)c;
+        if (c == '\0') {
+            return field;
+        }
+    }
+    free(field);
+    return NULL;
+}
+
+static int serve(char *executable) {
+    char *fields[MAX_FIELDS];
+    for (;;) {
+        int num_fields = 0;
+        char *field;
+        while ((field = read_field()) != NULL && strcmp(field, "run") != 0) {
+            if (num_fields < MAX_FIELDS) {
+                fields[num_fields++] = field;
+            }
+        }
+        if (field == NULL) {
+            return 0;
+        }
+        free(field);
+
+        pid_t pid = fork();
+        if (pid == 0) {
+            char *argv[MAX_FIELDS + 2];
+            int argc = 0;
+            argv[argc++] = executable;
+            for (int i = 0; i < num_fields; i++) {
+                if (strncmp(fields[i], "out=", 4) == 0) {
+                    int out = open(fields[i] + 4, O_WRONLY | O_CREAT | O_TRUNC, 0600);
+                    dup2(out, STDOUT_FILENO);
+                    dup2(out, STDERR_FILENO);
+                    close(out);
+                } else if (strncmp(fields[i], "arg=", 4) == 0) {
+                    argv[argc++] = fields[i] + 4;
+                }
+            }
+            argv[argc] = NULL;
+
+            int null = open("/dev/null", O_RDONLY);
+            dup2(null, STDIN_FILENO);
+            close(null);
+
+            exit(real_main(argc, argv, environ));
+        }
+
+        int status = 0;
+        struct rusage usage = {0};
+        if (pid < 0 || wait4(pid, &status, 0, &usage) < 0) {
+            status = 127 << 8;
+        }
+        // ru_maxrss is in kilobytes
+        printf("%d %ld\n", status, usage.ru_maxrss);
+        fflush(stdout);
+
+        for (int i = 0; i < num_fields; i++) {
+            free(fields[i]);
+        }
+    }
+}
+
+static int server_main(int argc, char **argv, char **envp) {
+    // The programs run by the tests must not become servers themselves
+    unsetenv("LD_PRELOAD");
+    if (getenv("CPP_TEST_RUNNER_FORK_SERVER") == NULL) {
+        return real_main(argc, argv, envp);
+    }
+    unsetenv("CPP_TEST_RUNNER_FORK_SERVER");
+    return serve(argv[0]);
+}
+
+int __libc_start_main(main_fn main, int argc, char **argv, void (*init)(void),
+                      void (*fini)(void), void (*rtld_fini)(void), void *stack_end) {
+    real_main = main;
+    libc_start_main_fn next = (libc_start_main_fn)dlsym(RTLD_NEXT, "__libc_start_main");
+    return next(server_main, argc, argv, init, fini, rtld_fini, stack_end);
+}
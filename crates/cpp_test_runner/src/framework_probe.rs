@@ -0,0 +1,270 @@
+use crate::{
+    discovery_deadline::DiscoveryDeadline,
+    test_parser::{
+        catch2_test_spec, get_tests_from_catch2_executable, get_tests_from_gtest_executable,
+        gtest_filter_pattern, NameFilter,
+    },
+    test_results::{
+        parse_catch2_results, parse_gtest_results, parse_partial_catch2_results,
+        parse_partial_gtest_output, PartialResults, TestOutcome,
+    },
+    types::{Executable, ExecutableType, Test},
+};
+use anyhow::Result;
+use std::{collections::HashMap, ffi::OsString, path::Path};
+
+/// The options of the listing of the tests of an executable
+#[derive(Debug, Clone, Copy)]
+pub struct ListingOptions<'a> {
+    pub executables_only: bool,
+    pub gtest_extra_args: &'a [String],
+    pub catch2_extra_args: &'a [String],
+    pub include_hidden: bool,
+    pub include_disabled: bool,
+    /// Kills the listing processes when it passes
+    pub deadline: Option<&'a DiscoveryDeadline>,
+}
+
+/// Everything that depends on the test framework of an executable: how to recognize it, how to
+/// list and run its tests, and how to read the report of the tests it ran.
+///
+/// To support a new framework, add its executable type and register its probe in `PROBES`.
+pub trait FrameworkProbe: Send + Sync {
+    fn executable_type(&self) -> ExecutableType;
+
+    /// Whether a symbol of an executable shows that it is built with the framework
+    fn is_framework_symbol(&self, symbol: &str) -> bool;
+
+    /// Whether a shared library the executable is linked to, like libgtest.so.1.14, is the one of
+    /// the framework
+    fn is_framework_library(&self, library: &str) -> bool;
+
+    /// Strings embedded in the executables built with the framework, which recognize the
+    /// stripped executables when none of their symbols does
+    fn fingerprints(&self) -> &'static [&'static [u8]];
+
+    /// Lists the tests of the executable, or fails if it doesn't use the framework
+    fn list_tests(
+        &self,
+        executable: &Executable,
+        options: &ListingOptions,
+        filter: NameFilter,
+    ) -> Result<Vec<Test>>;
+
+    fn color_arg(&self, use_color: bool) -> &'static str;
+
+    fn report_extension(&self) -> &'static str;
+
+    /// The arguments making the executable write the report of the tests it ran to `report`
+    fn report_args(&self, report: &Path) -> Vec<OsString>;
+
+    /// Reads the outcome of every test from a report, keyed by the full name of the tests
+    fn parse_report(&self, report: &str) -> Result<HashMap<String, TestOutcome>>;
+
+    /// What a process running several tests got through before it died, from the report it was
+    /// writing, if there is one, and from what it printed
+    fn parse_partial_results(&self, report: Option<&str>, stdout: &str) -> PartialResults;
+
+    /// The arguments running all the given tests of an executable with a single process, which
+    /// replace the arguments of the tests
+    fn group_args(&self, tests: &[&Test]) -> Vec<String>;
+
+    /// The output of a test, from what the executable printed and from its report
+    fn test_output(&self, stdout: String, reported_output: String) -> String;
+}
+
+pub struct GtestProbe;
+
+impl FrameworkProbe for GtestProbe {
+    fn executable_type(&self) -> ExecutableType {
+        ExecutableType::Gtest
+    }
+
+    fn is_framework_symbol(&self, symbol: &str) -> bool {
+        symbol.contains("InitGoogleTest")
+    }
+
+    // gmock bundles gtest. The DLLs of Windows have no lib prefix, like gtest.dll
+    fn is_framework_library(&self, library: &str) -> bool {
+        let library = library.strip_prefix("lib").unwrap_or(library);
+        library.starts_with("gtest") || library.starts_with("gmock")
+    }
+
+    // From the usage message of gtest
+    fn fingerprints(&self) -> &'static [&'static [u8]] {
+        &[b"gtest_list_tests"]
+    }
+
+    fn list_tests(
+        &self,
+        executable: &Executable,
+        options: &ListingOptions,
+        filter: NameFilter,
+    ) -> Result<Vec<Test>> {
+        get_tests_from_gtest_executable(
+            executable,
+            options.executables_only,
+            options.gtest_extra_args,
+            filter,
+            options.include_disabled,
+            options.deadline,
+        )
+    }
+
+    fn color_arg(&self, use_color: bool) -> &'static str {
+        if use_color {
+            "--gtest_color=yes"
+        } else {
+            "--gtest_color=no"
+        }
+    }
+
+    fn report_extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn report_args(&self, report: &Path) -> Vec<OsString> {
+        vec![format!("--gtest_output=json:{}", report.display()).into()]
+    }
+
+    fn parse_report(&self, report: &str) -> Result<HashMap<String, TestOutcome>> {
+        parse_gtest_results(report)
+    }
+
+    // The report is only written once all the tests ran
+    fn parse_partial_results(&self, _report: Option<&str>, stdout: &str) -> PartialResults {
+        parse_partial_gtest_output(stdout)
+    }
+
+    fn group_args(&self, tests: &[&Test]) -> Vec<String> {
+        let filter = tests
+            .iter()
+            .map(|test| gtest_filter_pattern(&test.name))
+            .collect::<Vec<_>>()
+            .join(":");
+
+        // The extra arguments are the same for all the tests of an executable
+        let extra_args = tests[0]
+            .arguments
+            .iter()
+            .filter(|arg| !arg.starts_with("--gtest_filter="))
+            .map(|arg| arg.to_string());
+
+        std::iter::once(format!("--gtest_filter={filter}"))
+            .chain(extra_args)
+            .collect()
+    }
+
+    // gtest already prints the failures on stdout
+    fn test_output(&self, stdout: String, _reported_output: String) -> String {
+        stdout
+    }
+}
+
+pub struct Catch2Probe;
+
+impl FrameworkProbe for Catch2Probe {
+    fn executable_type(&self) -> ExecutableType {
+        ExecutableType::Catch2
+    }
+
+    fn is_framework_symbol(&self, symbol: &str) -> bool {
+        symbol.contains("Catch2")
+    }
+
+    // Also libCatch2Main, and Catch2.dll on Windows
+    fn is_framework_library(&self, library: &str) -> bool {
+        let library = library.strip_prefix("lib").unwrap_or(library);
+        library.starts_with("Catch2")
+    }
+
+    // From the version banner of Catch2 v3, and the --list-reporters option of v2 and v3
+    fn fingerprints(&self) -> &'static [&'static [u8]] {
+        &[b"Catch2", b"--list-reporters"]
+    }
+
+    fn list_tests(
+        &self,
+        executable: &Executable,
+        options: &ListingOptions,
+        filter: NameFilter,
+    ) -> Result<Vec<Test>> {
+        get_tests_from_catch2_executable(
+            executable,
+            options.executables_only,
+            options.catch2_extra_args,
+            filter,
+            options.include_hidden,
+            options.deadline,
+        )
+    }
+
+    fn color_arg(&self, use_color: bool) -> &'static str {
+        if use_color {
+            "--colour-mode=ansi"
+        } else {
+            "--colour-mode=none"
+        }
+    }
+
+    // The JSON reporter only exists since Catch2 v3.5, the XML one is always available
+    fn report_extension(&self) -> &'static str {
+        "xml"
+    }
+
+    fn report_args(&self, report: &Path) -> Vec<OsString> {
+        vec![
+            "--reporter".into(),
+            "xml".into(),
+            "--out".into(),
+            report.as_os_str().to_owned(),
+        ]
+    }
+
+    fn parse_report(&self, report: &str) -> Result<HashMap<String, TestOutcome>> {
+        parse_catch2_results(report)
+    }
+
+    // Only the report has the names of the tests
+    fn parse_partial_results(&self, report: Option<&str>, _stdout: &str) -> PartialResults {
+        report.map(parse_partial_catch2_results).unwrap_or_default()
+    }
+
+    fn group_args(&self, tests: &[&Test]) -> Vec<String> {
+        let spec = tests
+            .iter()
+            .map(|test| catch2_test_spec(&test.name))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let first_spec = catch2_test_spec(&tests[0].name);
+        let extra_args = tests[0]
+            .arguments
+            .iter()
+            .filter(|arg| ***arg != *first_spec)
+            .map(|arg| arg.to_string());
+
+        std::iter::once(spec).chain(extra_args).collect()
+    }
+
+    // Catch2 writes the failures in the report instead of the console
+    fn test_output(&self, stdout: String, reported_output: String) -> String {
+        if stdout.trim().is_empty() {
+            reported_output
+        } else {
+            format!("{reported_output}\n\n{}", stdout.trim())
+        }
+    }
+}
+
+/// The probes of all the supported frameworks, in the order in which they are tried
+pub static PROBES: &[&dyn FrameworkProbe] = &[&GtestProbe, &Catch2Probe];
+
+impl ExecutableType {
+    pub fn probe(&self) -> &'static dyn FrameworkProbe {
+        *PROBES
+            .iter()
+            .find(|probe| probe.executable_type() == *self)
+            .expect("every executable type has a probe")
+    }
+}
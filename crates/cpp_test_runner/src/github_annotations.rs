@@ -0,0 +1,123 @@
+use crate::quickfix;
+use cpp_test_runner::{run_archive::ArchivedRun, test_results::TestResult, types::TestStatus};
+use std::{
+    borrow::Cow,
+    env,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Whether the runner runs in a job of GitHub Actions, where the annotations are written after
+/// the plain output
+pub fn in_github_actions() -> bool {
+    env::var("GITHUB_ACTIONS").is_ok_and(|value| value == "true")
+}
+
+// The message of a workflow command, which ends at the end of the line
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+// The properties of a workflow command, which are also separated by commas
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+// The annotations are only shown in the diffs of the pull requests for the files relative to the
+// checkout of the repository
+fn relative_to_workspace(file: &Path) -> Cow<'_, Path> {
+    let workspace = env::var_os("GITHUB_WORKSPACE")
+        .map(Into::into)
+        .or_else(|| env::current_dir().ok());
+    match workspace.and_then(|workspace| file.strip_prefix(workspace).ok().map(Path::to_owned)) {
+        Some(relative) => Cow::Owned(relative),
+        None => Cow::Borrowed(file),
+    }
+}
+
+fn write_command(
+    writer: &mut impl Write,
+    command: &str,
+    file: Option<&Path>,
+    line: Option<u32>,
+    title: &str,
+    message: &str,
+) -> io::Result<()> {
+    let mut properties = Vec::new();
+    if let Some(file) = file {
+        let file = relative_to_workspace(file);
+        properties.push(format!("file={}", escape_property(&file.to_string_lossy())));
+        if let Some(line) = line {
+            properties.push(format!("line={line}"));
+        }
+    }
+    properties.push(format!("title={}", escape_property(title)));
+    writeln!(
+        writer,
+        "::{command} {}::{}",
+        properties.join(","),
+        escape_data(message)
+    )
+}
+
+/// Writes the workflow commands of GitHub Actions that annotate the sources: an `::error` per
+/// failed assertion of the failed tests, located like with --output quickfix, and a `::notice`
+/// on the tests that passed but failed in some of `recent_runs`, as they may be flaky.
+pub fn write_annotations(
+    writer: &mut impl Write,
+    results: &[TestResult],
+    recent_runs: &[ArchivedRun],
+) -> io::Result<()> {
+    for result in results {
+        let name = result.test.display_name();
+        match result.status {
+            Some(status) if status.is_failure() => {
+                for failure in quickfix::failures(result) {
+                    let message = if failure.message.is_empty() {
+                        String::from("failed")
+                    } else {
+                        failure.message.join("\n")
+                    };
+                    write_command(
+                        writer,
+                        "error",
+                        failure.file.as_deref(),
+                        failure.line,
+                        &format!("{name} failed"),
+                        &message,
+                    )?;
+                }
+            }
+            Some(TestStatus::Passed) => {
+                let num_failed_runs = recent_runs
+                    .iter()
+                    .filter(|run| {
+                        run.results.iter().any(|archived| {
+                            archived.test.display_name() == name
+                                && archived.status.is_some_and(|status| status.is_failure())
+                        })
+                    })
+                    .count();
+                if num_failed_runs > 0 {
+                    write_command(
+                        writer,
+                        "notice",
+                        result.test.file.as_deref(),
+                        result.test.line,
+                        &format!("{name} may be flaky"),
+                        &format!(
+                            "Passed, but failed in {num_failed_runs} of the last {} runs",
+                            recent_runs.len()
+                        ),
+                    )?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use crate::types::TestStatus;
+use std::env;
+
+/// The characters of the statuses, the durations and the progress. The unicode glyphs are only
+/// used when the terminal can show them, and the ASCII ones are the text printed without glyphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Glyphs {
+    #[default]
+    Ascii,
+    Unicode,
+}
+
+impl Glyphs {
+    /// Unicode when stdout is a terminal with a UTF-8 locale, as told by LC_ALL, LC_CTYPE or
+    /// LANG, which isn't the Linux console, whose fonts lack most symbols
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .into_iter()
+            .filter_map(|variable| env::var(variable).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let is_utf8 = locale.contains("utf-8") || locale.contains("utf8");
+        let term = env::var("TERM").unwrap_or_default();
+
+        if is_utf8 && !matches!(term.as_str(), "linux" | "dumb") && atty::is(atty::Stream::Stdout) {
+            Self::Unicode
+        } else {
+            Self::Ascii
+        }
+    }
+
+    /// The glyph printed before a status, if any
+    pub fn status(self, status: TestStatus) -> Option<&'static str> {
+        match self {
+            Self::Ascii => None,
+            Self::Unicode => Some(match status {
+                TestStatus::Passed => "✓",
+                TestStatus::Failed | TestStatus::NotRunnable => "✗",
+                TestStatus::Skipped => "⊘",
+            }),
+        }
+    }
+
+    /// The glyph printed before a duration, if any
+    pub fn duration(self) -> Option<&'static str> {
+        match self {
+            Self::Ascii => None,
+            Self::Unicode => Some("⏱"),
+        }
+    }
+
+    /// The frames of the spinner shown while something is in progress
+    pub fn spinner_frames(self) -> &'static [char] {
+        match self {
+            Self::Ascii => &['|', '/', '-', '\\'],
+            Self::Unicode => &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'],
+        }
+    }
+
+    /// The done and the remaining parts of a progress bar
+    pub fn progress_bar(self) -> (char, char) {
+        match self {
+            Self::Ascii => ('#', '-'),
+            Self::Unicode => ('█', '░'),
+        }
+    }
+
+    /// Prefixes the text with a glyph, if there is one
+    pub fn prefix(glyph: Option<&str>, text: &str) -> String {
+        match glyph {
+            Some(glyph) => format!("{glyph} {text}"),
+            None => text.to_string(),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+const SIZE_UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+/// Formats a duration for people, with a precision that decreases as it grows:
+///
+/// ```
+/// # use cpp_test_runner::humanize;
+/// # use std::time::Duration;
+/// assert_eq!(humanize::duration(Duration::from_millis(42)), "42 ms");
+/// assert_eq!(humanize::duration(Duration::from_millis(1_234)), "1.23 s");
+/// assert_eq!(humanize::duration(Duration::from_millis(92_400)), "1m 32.4s");
+/// assert_eq!(humanize::duration(Duration::from_secs(3_723)), "1h 02m 03s");
+/// ```
+pub fn duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis < 1000 {
+        return format!("{millis} ms");
+    }
+
+    // Rounded before being split, so that 59.999 s is 1m 0.0s rather than 60.00 s
+    let seconds = duration.as_secs_f64();
+    let hundredths = (seconds * 100.0).round() as u64;
+    if hundredths < 60 * 100 {
+        return format!("{}.{:02} s", hundredths / 100, hundredths % 100);
+    }
+    let tenths = (seconds * 10.0).round() as u64;
+    if tenths < 60 * 60 * 10 {
+        return format!("{}m {}.{}s", tenths / 600, tenths % 600 / 10, tenths % 10);
+    }
+    let seconds = seconds.round() as u64;
+    format!(
+        "{}h {:02}m {:02}s",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Formats a number of bytes with binary units, like the memory of the tests or the size of the
+/// caches:
+///
+/// ```
+/// # use cpp_test_runner::humanize;
+/// assert_eq!(humanize::size(512), "512 B");
+/// assert_eq!(humanize::size(1536), "1.5 KiB");
+/// assert_eq!(humanize::size(42 * 1024 * 1024), "42.0 MiB");
+/// ```
+pub fn size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = SIZE_UNITS[0];
+    for next_unit in &SIZE_UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{value:.1} {unit}")
+}
+
+/// Formats the number of things done per second, or per minute when that is less than one per
+/// second. None when no time has passed yet.
+///
+/// ```
+/// # use cpp_test_runner::humanize;
+/// # use std::time::Duration;
+/// assert_eq!(humanize::rate(42, Duration::from_secs(10), "tests").unwrap(), "4.2 tests/s");
+/// assert_eq!(humanize::rate(3, Duration::from_secs(60), "tests").unwrap(), "3.0 tests/min");
+/// ```
+pub fn rate(count: usize, duration: Duration, unit: &str) -> Option<String> {
+    let seconds = duration.as_secs_f64();
+    if seconds == 0.0 {
+        return None;
+    }
+
+    let per_second = count as f64 / seconds;
+    Some(if per_second >= 1.0 || count == 0 {
+        format!("{per_second:.1} {unit}/s")
+    } else {
+        format!("{:.1} {unit}/min", per_second * 60.0)
+    })
+}
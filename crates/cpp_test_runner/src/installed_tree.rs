@@ -0,0 +1,79 @@
+use crate::types::Executable;
+use elf_parser::Elf;
+use std::path::{Path, PathBuf};
+
+const LIBRARY_DIR_NAMES: &[&str] = &["lib64", "lib"];
+
+// Replaces $ORIGIN and ${ORIGIN}, the directory of the executable, in a directory of the RUNPATH
+fn expand_origin(dir: &str, origin: &Path) -> PathBuf {
+    let origin = origin.to_string_lossy();
+    PathBuf::from(
+        dir.replace("${ORIGIN}", &origin)
+            .replace("$ORIGIN", &origin),
+    )
+}
+
+/// The LD_LIBRARY_PATH to run an executable of a tree installed under `prefix`, like
+/// /opt/product/bin/tests, which finds the libraries installed next to it:
+///
+/// - The directories of its RUNPATH, since LD_LIBRARY_PATH would take precedence over them. Its
+///   RPATH is searched before LD_LIBRARY_PATH, so it is honored without being added.
+/// - The lib64 and lib directories of its directory and of its parents, up to the prefix
+/// - The LD_LIBRARY_PATH of the runner
+///
+/// Returns None when there is nothing to add to the LD_LIBRARY_PATH of the runner.
+pub fn library_path(executable: &Path, prefix: &Path) -> Option<String> {
+    // The loader expands $ORIGIN to the directory of the executable once its symlinks are resolved
+    let executable = executable
+        .canonicalize()
+        .unwrap_or_else(|_| executable.to_path_buf());
+    let origin = executable.parent()?;
+
+    let mut dirs = Elf::new(&executable)
+        .and_then(|elf| Ok(elf.get_runpath()?))
+        .unwrap_or_default()
+        .iter()
+        .map(|dir| expand_origin(dir, origin))
+        .collect::<Vec<_>>();
+
+    let prefix = prefix
+        .canonicalize()
+        .unwrap_or_else(|_| prefix.to_path_buf());
+    for dir in origin.ancestors() {
+        if !dir.starts_with(&prefix) {
+            break;
+        }
+        dirs.extend(
+            LIBRARY_DIR_NAMES
+                .iter()
+                .map(|name| dir.join(name))
+                .filter(|dir| dir.is_dir()),
+        );
+    }
+    if dirs.is_empty() {
+        return None;
+    }
+
+    if let Some(inherited) = std::env::var_os("LD_LIBRARY_PATH") {
+        dirs.extend(std::env::split_paths(&inherited));
+    }
+    let mut unique_dirs = Vec::new();
+    for dir in dirs {
+        if !unique_dirs.contains(&dir) {
+            unique_dirs.push(dir);
+        }
+    }
+    let path = std::env::join_paths(unique_dirs).ok()?;
+    Some(path.to_string_lossy().into_owned())
+}
+
+/// Sets the LD_LIBRARY_PATH of the executables of an installed tree, see `library_path`
+pub fn set_library_paths(executables: &mut [Executable], prefix: &Path) {
+    for executable in executables {
+        if let Some(library_path) = library_path(&executable.path, prefix) {
+            executable
+                .environment
+                .insert(String::from("LD_LIBRARY_PATH"), library_path);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use crate::types::Test;
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InventoryEntry<'a> {
+    /// The path of the executable, relative to the root of its tree
+    pub executable: PathBuf,
+    pub name: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InventoryDiff<'a> {
+    pub only_in_test_dir: Vec<InventoryEntry<'a>>,
+    pub only_in_other: Vec<InventoryEntry<'a>>,
+}
+
+fn inventory<'a>(tests: &'a [Test], root: &Path) -> BTreeSet<InventoryEntry<'a>> {
+    tests
+        .iter()
+        .map(|test| InventoryEntry {
+            executable: test
+                .executable
+                .path
+                .strip_prefix(root)
+                .unwrap_or(&test.executable.path)
+                .to_path_buf(),
+            name: &test.name,
+        })
+        .collect()
+}
+
+/// Compares the tests of two build trees. Tests are identified by their name and the path of
+/// their executable relative to the root of their tree.
+pub fn diff_inventories<'a>(
+    tests: &'a [Test],
+    root: &Path,
+    other_tests: &'a [Test],
+    other_root: &Path,
+) -> InventoryDiff<'a> {
+    let inventory = inventory(tests, root);
+    let other_inventory = self::inventory(other_tests, other_root);
+
+    InventoryDiff {
+        only_in_test_dir: inventory.difference(&other_inventory).cloned().collect(),
+        only_in_other: other_inventory.difference(&inventory).cloned().collect(),
+    }
+}
@@ -0,0 +1,71 @@
+//! The discovery, the running and the formatting of gtest and Catch2 tests, on which the
+//! cpp_test_runner binary is built, so that other tools, like editor plugins, can link against
+//! them instead of running the binary.
+//!
+//! `TestDiscovery` finds the tests, and `TestRun` runs them in the background and streams their
+//! results as they finish:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use cpp_test_runner::{TestDiscovery, TestRun};
+//!
+//! let tests = TestDiscovery::in_dir("build").discover()?;
+//! for result in TestRun::new(tests).start() {
+//!     let (test, outcome) = result?;
+//!     println!("{}: {:?}", test.display_name(), outcome.status);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Their types, `types::Test`, `types::Executable` and `test_results::TestOutcome`, are the ones
+//! that the JSON output of the binary is made of. Embedders can run tests with their own
+//! scheduling with `TestRun::scheduler` and a `scheduler::Scheduler` implementation, and generate
+//! the launch.json of tests with `vscode_launch_json_formatter`, and keep the results of the runs
+//! elsewhere than in the cache directory by implementing `run_archive::RunStore`.
+
+pub mod binary_inspector;
+pub mod break_artifacts;
+pub mod cache_dirs;
+pub mod child_environment;
+pub mod compile_commands;
+pub mod crash_report;
+pub mod cwd_template;
+pub mod diagnostics;
+pub mod discovery_cache;
+pub mod discovery_deadline;
+pub mod discovery_progress;
+pub mod executable_finder;
+pub mod executable_rules;
+#[cfg(unix)]
+pub mod foreground;
+#[cfg(unix)]
+pub mod fork_server;
+pub mod framework_probe;
+pub mod glyphs;
+pub mod humanize;
+pub mod installed_tree;
+pub mod loader_check;
+pub mod memory_history;
+pub mod results_service;
+pub mod run_archive;
+pub mod scheduler;
+// The tests are picked with skim, which only builds on Unix
+#[cfg(unix)]
+mod skim_item;
+pub mod source_files;
+pub mod test_attributes;
+pub mod test_discovery;
+pub mod test_gates;
+pub mod test_labels;
+pub mod test_parser;
+pub mod test_pattern;
+pub mod test_results;
+pub mod test_run;
+pub mod test_runner;
+pub mod test_tokens;
+pub mod types;
+pub mod vscode_launch_json_formatter;
+
+pub use test_discovery::TestDiscovery;
+pub use test_run::{TestResults, TestRun};
@@ -0,0 +1,186 @@
+use crate::types::Test;
+use elf_parser::Elf;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+};
+
+// Searched by the loader after the ld.so.cache
+const DEFAULT_DIRS: &[&str] = &["/lib64", "/usr/lib64", "/lib", "/usr/lib"];
+
+const LD_SO_CACHE_MAGIC: &[u8] = b"glibc-ld.so.cache1.1";
+// The magic, the number of entries, the size of the strings, the flags, the offset of the
+// extensions, and 3 unused words
+const LD_SO_CACHE_HEADER_SIZE: usize = 48;
+// The flags, the name, the path, the OS version and the hardware capabilities
+const LD_SO_CACHE_ENTRY_SIZE: usize = 24;
+
+/// The names of the libraries of /etc/ld.so.cache, like libstdc++.so.6 in /lib/x86_64-linux-gnu,
+/// which the loader searches when the library isn't in the RPATH, LD_LIBRARY_PATH or RUNPATH
+static LD_SO_CACHE: LazyLock<HashSet<String>> = LazyLock::new(|| {
+    let Ok(data) = fs::read("/etc/ld.so.cache") else {
+        return HashSet::new();
+    };
+    // The old format can come first, the offsets of the strings are relative to the new format
+    let Some(start) = data
+        .windows(LD_SO_CACHE_MAGIC.len())
+        .position(|window| window == LD_SO_CACHE_MAGIC)
+    else {
+        return HashSet::new();
+    };
+    let cache = &data[start..];
+
+    let read_u32 = |offset: usize| {
+        cache
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()) as usize)
+    };
+    let read_string = |offset: usize| {
+        let bytes = cache.get(offset..)?;
+        let end = bytes.iter().position(|byte| *byte == 0)?;
+        Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    };
+
+    let num_entries = read_u32(LD_SO_CACHE_MAGIC.len()).unwrap_or_default();
+    (0..num_entries)
+        .map(|index| LD_SO_CACHE_HEADER_SIZE + index * LD_SO_CACHE_ENTRY_SIZE)
+        .filter_map(|entry| read_string(read_u32(entry + 4)?))
+        .collect()
+});
+
+// The directories of a RPATH or a RUNPATH, with $ORIGIN replaced by the directory of the object
+fn expand_search_path(dirs: Vec<String>, origin: &Path) -> Vec<PathBuf> {
+    let origin = origin.to_string_lossy();
+    dirs.iter()
+        .map(|dir| {
+            PathBuf::from(
+                dir.replace("${ORIGIN}", &origin)
+                    .replace("$ORIGIN", &origin),
+            )
+        })
+        .collect()
+}
+
+/// Why the dynamic loader can't start the executable with this LD_LIBRARY_PATH, which would
+/// otherwise only fail with a message of the loader on stderr: its interpreter (PT_INTERP) doesn't
+/// exist, or some libraries it needs, directly or through the libraries next to it, aren't found
+/// in their RPATH, the LD_LIBRARY_PATH, their RUNPATH, the ld.so.cache nor the default
+/// directories. The libraries of the system are trusted to have their own dependencies.
+pub fn loader_problem(executable: &Path, library_path: Option<&str>) -> Option<String> {
+    let executable = executable.canonicalize().ok()?;
+    let elf = Elf::new(&executable).ok()?;
+    if let Some(interpreter) = elf.get_interpreter().ok()? {
+        if !Path::new(&interpreter).exists() {
+            return Some(format!(
+                "{} can't be started, its dynamic loader {interpreter} doesn't exist",
+                executable.display()
+            ));
+        }
+    }
+
+    let library_path = library_path
+        .map(|library_path| std::env::split_paths(library_path).collect::<Vec<_>>())
+        .unwrap_or_default();
+    // The RPATH of the executable applies to all the libraries without a RUNPATH
+    let executable_rpath = match elf.get_runpath() {
+        Ok(runpath) if runpath.is_empty() => {
+            expand_search_path(elf.get_rpath().unwrap_or_default(), executable.parent()?)
+        }
+        _ => Vec::new(),
+    };
+
+    // Like `libfoo.so, needed by libbar.so`
+    let mut missing = BTreeSet::new();
+    let mut visited = HashSet::from([executable.clone()]);
+    let mut objects = vec![executable.clone()];
+    while let Some(object) = objects.pop() {
+        let Ok(elf) = Elf::new(&object) else {
+            continue;
+        };
+        let Some(origin) = object.parent() else {
+            continue;
+        };
+        let runpath = expand_search_path(elf.get_runpath().unwrap_or_default(), origin);
+        let mut dirs = Vec::new();
+        if runpath.is_empty() {
+            dirs.extend(expand_search_path(
+                elf.get_rpath().unwrap_or_default(),
+                origin,
+            ));
+            dirs.extend(executable_rpath.iter().cloned());
+        }
+        dirs.extend(library_path.iter().cloned());
+        dirs.extend(runpath);
+        // $LIB and $PLATFORM depend on the loader, so nothing is reported rather than a guess
+        if dirs.iter().any(|dir| dir.to_string_lossy().contains('$')) {
+            continue;
+        }
+
+        for library in elf.get_needed_libraries().unwrap_or_default() {
+            let found = if library.contains('/') {
+                Some(PathBuf::from(&library)).filter(|path| path.exists())
+            } else {
+                dirs.iter()
+                    .map(|dir| dir.join(&library))
+                    .find(|path| path.exists())
+            };
+
+            match found {
+                Some(path) => {
+                    if let Ok(path) = path.canonicalize() {
+                        if visited.insert(path.clone()) {
+                            objects.push(path);
+                        }
+                    }
+                }
+                None => {
+                    let is_system_library = LD_SO_CACHE.contains(&library)
+                        || DEFAULT_DIRS
+                            .iter()
+                            .any(|dir| Path::new(dir).join(&library).exists());
+                    if !is_system_library {
+                        let needed_by = object.file_name().unwrap_or_default().to_string_lossy();
+                        missing.insert(format!("{library}, needed by {needed_by}"));
+                    }
+                }
+            }
+        }
+    }
+
+    if missing.is_empty() {
+        return None;
+    }
+    let missing = missing
+        .iter()
+        .map(|library| format!("    {library}"))
+        .collect::<Vec<_>>();
+    Some(format!(
+        "{} can't be started, the dynamic loader doesn't find these libraries:\n{}",
+        executable.display(),
+        missing.join("\n")
+    ))
+}
+
+type LoaderProblems = HashMap<(PathBuf, Option<String>), Option<Arc<str>>>;
+
+/// `loader_problem` for the executable and the LD_LIBRARY_PATH of the test, which is only checked
+/// once per run for all the tests of the executable
+pub fn test_loader_problem(test: &Test) -> Option<Arc<str>> {
+    static PROBLEMS: LazyLock<Mutex<LoaderProblems>> = LazyLock::new(Default::default);
+
+    let library_path = test
+        .environment
+        .get("LD_LIBRARY_PATH")
+        .cloned()
+        .or_else(|| std::env::var("LD_LIBRARY_PATH").ok());
+    let key = (test.executable.path.clone(), library_path);
+    if let Some(problem) = PROBLEMS.lock().unwrap().get(&key) {
+        return problem.clone();
+    }
+
+    let problem = loader_problem(&key.0, key.1.as_deref()).map(Arc::from);
+    PROBLEMS.lock().unwrap().insert(key, problem.clone());
+    problem
+}
@@ -0,0 +1,68 @@
+use clap::ValueEnum;
+use std::io;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    Layer,
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    /// The timings of the discovery
+    Info,
+    /// The paths skipped by the discovery and why, and the processes started
+    Debug,
+    /// The files skipped because they aren't executable
+    Trace,
+}
+
+impl LogLevel {
+    // -v is info, -vv debug and -vvv trace
+    fn from_verbosity(verbose: u8) -> Self {
+        match verbose {
+            0 => Self::Off,
+            1 => Self::Info,
+            2 => Self::Debug,
+            _ => Self::Trace,
+        }
+    }
+
+    fn filter(self) -> LevelFilter {
+        match self {
+            Self::Off => LevelFilter::OFF,
+            Self::Error => LevelFilter::ERROR,
+            Self::Warn => LevelFilter::WARN,
+            Self::Info => LevelFilter::INFO,
+            Self::Debug => LevelFilter::DEBUG,
+            Self::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Writes the logs of the runner and of its library on stderr, as text or as a JSON object per
+/// line. The logs of the dependencies, like skim, are left out.
+pub fn init_logging(level: Option<LogLevel>, verbose: u8, json: bool) {
+    let level = level.unwrap_or_else(|| LogLevel::from_verbosity(verbose));
+    if matches!(level, LogLevel::Off) {
+        return;
+    }
+
+    // The binary and the library share the name of the crate
+    let targets = Targets::new().with_target("cpp_test_runner", level.filter());
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(atty::is(atty::Stream::Stderr));
+    if json {
+        tracing_subscriber::registry()
+            .with(layer.json().with_filter(targets))
+            .init();
+    } else {
+        tracing_subscriber::registry()
+            .with(layer.with_filter(targets))
+            .init();
+    }
+}
@@ -1,18 +1,100 @@
-mod executable_finder;
-mod test_parser;
-mod test_runner;
-mod types;
-mod vscode_launch_json_formatter;
+mod bisect;
+mod coverage;
+mod duplicates;
+mod editor;
+mod github_annotations;
+mod inventory_diff;
+mod logging;
+mod ordering;
+mod output_search;
+mod path_filter;
+mod porcelain;
+mod preload;
+mod quickfix;
+mod reports;
+#[cfg(unix)]
+mod server;
+mod source_locations;
+mod spinner;
+mod suggestions;
+mod tag_expression;
+mod test_matrix;
+#[cfg(unix)]
+mod tui;
+mod vscode_test_adapter;
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+use bisect::run_bisect;
+use cache_dirs::{collect_garbage, WorkspaceCache};
+use child_environment::EnvironmentPolicy;
 use clap::{Args, Parser, Subcommand, ValueEnum};
-use executable_finder::{find_test_dir, find_test_executables, validate_executables};
+use compile_commands::CompilationDatabase;
+use coverage::run_coverage;
+use cpp_test_runner::{
+    cache_dirs, child_environment, compile_commands, cwd_template, diagnostics, discovery_cache,
+    discovery_deadline, executable_finder, executable_rules, glyphs, humanize, installed_tree,
+    results_service, run_archive, source_files, test_attributes, test_gates, test_labels,
+    test_parser, test_pattern, test_results, test_runner, test_tokens, types,
+    vscode_launch_json_formatter,
+};
+use cwd_template::resolve_cwd_template;
+use diagnostics::{Diagnostic, Severity};
+use discovery_cache::DiscoveryCache;
+use discovery_deadline::DiscoveryDeadline;
+use duplicates::{
+    assign_ids, dedupe_by_source, mark_duplicates, reject_duplicate_names,
+    warn_about_duplicate_names, warn_about_duplicates,
+};
+use executable_finder::{
+    attach_executable_metadata, expand_executable_patterns, find_test_dir, find_test_executables,
+    validate_executables, NewerThan, WalkOptions,
+};
+use executable_rules::ExecutableRules;
+use glyphs::Glyphs;
+use installed_tree::set_library_paths;
+use inventory_diff::diff_inventories;
+use logging::{init_logging, LogLevel};
+use ordering::order_tests;
+use path_filter::PathFilter;
+use preload::add_preloads;
+use reports::{write_reports, ReportSink};
+use results_service::{HttpRunStore, RESULTS_URL_VARIABLE};
+use run_archive::{LocalRunArchive, RunStore};
+use serde::Serialize;
+#[cfg(unix)]
+use server::serve;
+#[cfg(unix)]
 use skim::{options::SkimOptionsBuilder, Skim, SkimItemReceiver, SkimItemSender};
-use std::{borrow::Cow, path::PathBuf, sync::Arc};
-use test_parser::get_tests_from_executables;
-use test_runner::run_all;
-use types::{ExecutableType, Test};
-use vscode_launch_json_formatter::format_tests_to_vscode_launch_json;
+use source_files::{workspace_root, SourceFileIndex};
+use source_locations::{backfill_locations, warn_about_missing_locations};
+use spinner::DiscoverySpinner;
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fs,
+    io::{self, BufWriter, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use suggestions::closest_test_names;
+use tag_expression::TagExpression;
+use test_attributes::AttributeRules;
+use test_gates::Gates;
+use test_labels::label_test_kinds;
+use test_matrix::{expand_matrix, MatrixDimension};
+use test_parser::{get_tests_from_executables, NameFilter};
+use test_pattern::TestPattern;
+use test_results::TestResult;
+use test_runner::{run_all, run_all_reporting, RunOptions};
+use test_tokens::TokenQuery;
+#[cfg(unix)]
+use tui::run_all_tui;
+use types::{Executable, ExecutableType, Test, TestStatus};
+use vscode_launch_json_formatter::{
+    format_tests_to_vscode_launch_json, format_vscode_tasks_json, update_vscode_launch_json,
+    update_vscode_tasks_json, LaunchJsonOptions, VscodeFileUpdate,
+};
 
 /// A test runner that works with Gtest and Catch2
 #[derive(Debug, Parser)]
@@ -20,11 +102,35 @@ use vscode_launch_json_formatter::format_tests_to_vscode_launch_json;
 struct Cli {
     #[command(subcommand)]
     command: Command,
+
+    /// Log what the discovery and the runs do on stderr: the timings of the discovery with -v, also
+    /// the paths it skipped with the reason and the processes started with -vv, and the files that
+    /// aren't executable with -vvv.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// The level of the logs, instead of -v.
+    #[arg(long, value_enum, value_name = "LEVEL", global = true)]
+    log_level: Option<LogLevel>,
+
+    /// Write the logs as a JSON object per line, with the fields of the events, like the path and
+    /// the reason of a skipped path.
+    #[arg(long, global = true)]
+    log_json: bool,
 }
 
 impl Cli {
-    fn common_flags(&self) -> &CommonFlags {
-        self.command.common_flags()
+    // --porcelain only prints the tests or the results
+    fn quiet(&self) -> bool {
+        self.command.common_flags().is_some_and(|flags| flags.quiet) || self.command.porcelain()
+    }
+
+    fn glyphs(&self) -> Glyphs {
+        if self.command.common_flags().is_some_and(|flags| flags.ascii) {
+            Glyphs::Ascii
+        } else {
+            Glyphs::detect()
+        }
     }
 }
 
@@ -41,22 +147,165 @@ struct CommonFlags {
     #[arg(short, long)]
     jobs: Option<usize>,
 
+    /// Limit the number of executables whose tests are listed at the same time. [default: the
+    /// number of threads]
+    #[arg(long, value_name = "N")]
+    list_jobs: Option<usize>,
+
+    /// Only inspect the files under the test directory matching this glob, like `*_test` or
+    /// `unit/**`. The globs are matched like the lines of a .gitignore, and the flag can be
+    /// repeated.
+    #[arg(long, value_name = "GLOB")]
+    include_path: Vec<String>,
+
+    /// Don't search the files and directories under the test directory matching this glob, like
+    /// `CMakeFiles`, `.git` or `third_party/**`. The globs are matched like the lines of a
+    /// .gitignore, and the flag can be repeated.
+    #[arg(long, value_name = "GLOB")]
+    exclude_path: Vec<String>,
+
+    /// Skip the paths ignored by the .gitignore files of the test directory and of the
+    /// repository above it, and by .git/info/exclude. The .testignore files are always used.
+    #[arg(long)]
+    use_gitignore: bool,
+
+    /// Only inspect the files up to this depth under the test directory, where 1 is the files in
+    /// the test directory itself.
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Follow the symlinks under the test directory, like the symlink farms of the output trees
+    /// of Bazel. The symlinks that loop back to their parents are skipped, and an executable
+    /// found through several symlinks is only listed once.
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Only consider the executables modified in this time, like 30m or 1h30m, or since this
+    /// file was, like a stamp touched after every run, such as the executables relinked by an
+    /// incremental build.
+    #[arg(long, value_name = "DURATION|PATH")]
+    newer_than: Option<NewerThan>,
+
+    /// Stop the discovery after this many seconds and use the tests found so far, like in trees
+    /// with a slow file system or executables that hang while listing their tests. The
+    /// directories that weren't searched and the executables that weren't listed are printed in
+    /// a warning.
+    #[arg(long, value_name = "SECONDS")]
+    discovery_timeout: Option<f64>,
+
     /// If set to true, the individual tests won't be parsed from the executables.
     #[arg(long)]
     executables_only: bool,
 
-    /// Filter tests by their name with a regex
+    /// Filter tests by their name with a regex. Can be repeated to select the tests matching any
+    /// of the regexes.
+    #[arg(long)]
+    filter: Vec<regex::Regex>,
+
+    /// Leave out the tests whose name matches this regex. Can be repeated, and is applied after
+    /// --filter.
+    #[arg(long)]
+    exclude: Vec<regex::Regex>,
+
+    /// Only select the tests whose name has all the words of this query, in any order, like
+    /// "http timeout" for HttpClientTest.RequestTimesOutAfterDeadline. The names are split at
+    /// their separators and case changes, and a word of the query matches the start of a word of
+    /// the name, or of it and the next ones, like timeout for TimesOut.
+    #[arg(long, value_name = "QUERY")]
+    fuzzy: Option<TokenQuery>,
+
+    /// Filter tests by their Catch2 tags, like "[integration]~[slow]". Consecutive tags must all
+    /// match, ~ negates a tag and , separates alternatives. gtest tests have no tags.
+    #[arg(long)]
+    tags: Option<TagExpression>,
+
+    /// Only select the tests with this label, like unit or integration. Can be repeated to select
+    /// the tests with any of the labels.
     #[arg(long)]
-    filter: Option<regex::Regex>,
+    label: Vec<String>,
+
+    /// Only select the tests declared in a source file matching this glob, like
+    /// "tests/network/*.cpp". Relative globs are matched from the current directory. Can be
+    /// repeated.
+    #[arg(long, value_name = "GLOB")]
+    file: Vec<String>,
+
+    /// Only select the tests of an executable matching this glob, like "build/**/unit_*".
+    /// Relative globs are matched from the current directory. Can be repeated.
+    #[arg(long, value_name = "GLOB")]
+    exec: Vec<String>,
 
     /// Interactive mode to fuzzy-find tests using Skim. Ctrl-A to toggle all selection.
     #[arg(short, long)]
     interactive: bool,
 
+    /// Also list and run the hidden Catch2 tests, which are tagged with [.] or [!hide].
+    #[arg(long)]
+    include_hidden: bool,
+
+    /// List and run the disabled gtest tests, whose suite or name starts with DISABLED_. This is
+    /// the default.
+    #[arg(long, overrides_with = "exclude_disabled")]
+    include_disabled: bool,
+
+    /// Leave out the disabled gtest tests, whose suite or name starts with DISABLED_.
+    #[arg(long, overrides_with = "include_disabled")]
+    exclude_disabled: bool,
+
+    /// Keep the tests registered by several executables from the same source location, like the
+    /// tests of an object library linked into several executables, only once, in the first
+    /// executable.
+    #[arg(long)]
+    dedupe_by_source: bool,
+
+    /// Name the tests that have the same name as tests of other executables by their id, which is
+    /// prefixed with the end of the path of their executable, like unit::Math.Adds, in the plain
+    /// list and in the launch.json configurations.
+    #[arg(long, conflicts_with = "strict_duplicates")]
+    disambiguate_duplicates: bool,
+
+    /// Fail when tests of several executables have the same name, instead of warning about them.
+    #[arg(long)]
+    strict_duplicates: bool,
+
+    /// Don't use the cache of the tests listed by every executable, and list them all again.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Don't show the progress of the search for the executables and of the listing of their
+    /// tests, nor the warnings about the tests. The progress is only shown when stdout and stderr
+    /// are terminals.
+    #[arg(long)]
+    quiet: bool,
+
+    /// Only print ASCII characters, instead of the unicode glyphs of the statuses, the durations
+    /// and the progress, which are used when stdout is a terminal with a UTF-8 locale.
+    #[arg(long)]
+    ascii: bool,
+
+    /// Search for the source file of the tests that don't report one, in the debug info of the
+    /// executables and in the sources of the repository.
+    #[arg(long)]
+    backfill_locations: bool,
+
+    /// A compile_commands.json, or the directory of one, which resolves the source files of the
+    /// tests from the directories they were compiled from, and gives the tests their compile
+    /// command and target in the JSON output.
+    #[arg(long, value_name = "PATH")]
+    compile_commands: Option<PathBuf>,
+
     /// Comma-separated list of the enabled executable types during the search.
     #[arg(long, value_delimiter = ',', default_value = "gtest,catch2")]
     executable_types: Vec<ExecutableType>,
 
+    /// The order in which the tests are listed and run.
+    #[arg(long, value_enum, default_value = "declaration")]
+    order: TestOrder,
+
+    /// The seed used to shuffle the tests with --order=random. A random seed is used if not set.
+    #[arg(long)]
+    seed: Option<u64>,
+
     /// Comma-separated list of extra arguments to pass to gtest executables.
     #[arg(long, value_delimiter = ',')]
     gtest_extra_args: Vec<String>,
@@ -64,6 +313,102 @@ struct CommonFlags {
     /// Comma-separated list of extra arguments to pass to catch2 executables.
     #[arg(long, value_delimiter = ',')]
     catch2_extra_args: Vec<String>,
+
+    /// Also pass this variable of the environment to the test executables, like ASAN_OPTIONS, or
+    /// the variables starting with a prefix, like QT_*. They only get PATH, HOME, the locale, TZ,
+    /// LD_LIBRARY_PATH and a few others by default. Can be repeated.
+    #[arg(long, value_name = "NAME", conflicts_with = "inherit_env")]
+    pass_env: Vec<String>,
+
+    /// Pass the whole environment to the test executables, which makes their runs depend on the
+    /// shell they are started from.
+    #[arg(long)]
+    inherit_env: bool,
+}
+
+impl CommonFlags {
+    fn discovery_cache(&self, workspace: &Path) -> Option<DiscoveryCache> {
+        if self.no_cache {
+            None
+        } else {
+            let cache = WorkspaceCache::for_workspace(workspace)?;
+            Some(DiscoveryCache::new(cache.discovery_dir()))
+        }
+    }
+
+    // The saved listing of every executable, for the suggestions when the filters match nothing.
+    // It's read even with --no-cache, but only updated without it.
+    fn saved_listing(&self, workspace: &Path) -> Option<DiscoveryCache> {
+        let cache = DiscoveryCache::new(WorkspaceCache::for_workspace(workspace)?.discovery_dir());
+        Some(if self.no_cache {
+            cache.read_only()
+        } else {
+            cache
+        })
+    }
+
+    fn list_tests(
+        &self,
+        executables: &[Executable],
+        filter: NameFilter,
+        cache: Option<&DiscoveryCache>,
+        source_files: Option<&SourceFileIndex>,
+        deadline: Option<&DiscoveryDeadline>,
+    ) -> Result<Vec<Test>> {
+        let list = || {
+            get_tests_from_executables(
+                executables,
+                self.executables_only,
+                &self.gtest_extra_args,
+                &self.catch2_extra_args,
+                filter,
+                self.include_hidden,
+                !self.exclude_disabled,
+                cache,
+                source_files,
+                deadline,
+            )
+        };
+
+        match self.list_jobs {
+            Some(list_jobs) => Ok(rayon::ThreadPoolBuilder::new()
+                .num_threads(list_jobs)
+                .build()?
+                .install(list)),
+            None => Ok(list()),
+        }
+    }
+
+    fn walk_options(&self) -> Result<WalkOptions> {
+        Ok(WalkOptions {
+            include: self.include_path.clone(),
+            exclude: self.exclude_path.clone(),
+            use_gitignore: self.use_gitignore,
+            max_depth: self.max_depth,
+            follow_symlinks: self.follow_symlinks,
+            newer_than: self
+                .newer_than
+                .as_ref()
+                .map(NewerThan::cutoff)
+                .transpose()?,
+        })
+    }
+
+    fn environment_policy(&self) -> EnvironmentPolicy {
+        if self.inherit_env {
+            EnvironmentPolicy::Inherit
+        } else {
+            EnvironmentPolicy::allow(&self.pass_env)
+        }
+    }
+
+    fn name_filter(&self) -> NameFilter<'_> {
+        NameFilter {
+            filters: &self.filter,
+            excludes: &self.exclude,
+            fuzzy: self.fuzzy.as_ref(),
+        }
+    }
 }
 
 #[derive(Args, Debug)]
@@ -79,6 +424,13 @@ struct Input {
     /// search for other executables. Mutually exclusive with --test-dir
     #[arg(long, value_delimiter = ',')]
     executables: Vec<PathBuf>,
+
+    /// Search a tree where the tests were installed, like /opt/product, instead of a build
+    /// directory. The symlinks are followed, the shared libraries are skipped, and the tests run
+    /// with the lib directories next to their executable and their RUNPATH in LD_LIBRARY_PATH.
+    /// Mutually exclusive with --test-dir and --executables
+    #[arg(long, value_name = "PREFIX")]
+    system_scan: Option<PathBuf>,
 }
 
 #[derive(ValueEnum, Debug, Clone, Default)]
@@ -89,12 +441,40 @@ enum ColorOption {
     No,
 }
 
+impl ColorOption {
+    fn use_color(&self) -> bool {
+        match self {
+            Self::No => false,
+            Self::Yes => true,
+            Self::Auto => atty::is(atty::Stream::Stdout),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Default)]
+pub enum TestOrder {
+    Alphabetical,
+    Random,
+    #[default]
+    Declaration,
+}
+
 #[derive(ValueEnum, Debug, Clone, Default)]
 enum OutputFormat {
     Plain,
     #[default]
     Json,
     PrettyJson,
+    /// The tree of test items of the Test Explorer of VS Code for list, and a JSON event per line
+    /// for run, as the tests finish
+    VscodeTestAdapter,
+    /// A `file:line: message` line per failed assertion once the tests have all run, for the
+    /// quickfix list of Vim and Neovim and the compilation mode of Emacs. Only for run.
+    Quickfix,
+    /// The workflow commands of GitHub Actions that annotate the failed assertions, and the tests
+    /// that passed but failed in the last runs. Written after the plain output when
+    /// GITHUB_ACTIONS=true. Only for run.
+    Github,
 }
 
 #[derive(Subcommand, Debug)]
@@ -105,16 +485,84 @@ enum Command {
     /// Print a vscode-compatible launch.json.
     LaunchJson(LaunchJsonCommand),
 
+    /// Print a vscode-compatible tasks.json with the task that builds the tests before debugging
+    /// them, run by the configurations of launch-json --build-command.
+    TasksJson(TasksJsonCommand),
+
     /// Run tests.
     Run(RunCommand),
+
+    /// Open the source file of a test in an editor, at the line of the test.
+    Open(OpenCommand),
+
+    /// Run tests with coverage instrumentation and merge the results into a report.
+    Coverage(CoverageCommand),
+
+    /// Search the outputs of the last runs for a regex, and print the tests that matched with
+    /// the matching lines.
+    Grep(GrepCommand),
+
+    /// Print the last result of a test in the last runs, with its output.
+    Show(ShowCommand),
+
+    /// Find the first version in which a test fails, by running it in the build directories or
+    /// the git revisions between a version in which it passes and one in which it fails.
+    Bisect(BisectCommand),
+
+    /// Remove the cached data of the workspaces that weren't used for a while.
+    Gc(GcCommand),
+
+    /// Keep the tests in memory, list them again when their executables change, and answer list,
+    /// run and cancel requests in line-delimited JSON-RPC on a unix socket.
+    Serve(ServeCommand),
 }
 
 impl Command {
-    fn common_flags(&self) -> &CommonFlags {
+    /// The flags of the commands that look for tests. Bisect looks for the tests of every version
+    /// with its own flags.
+    fn common_flags(&self) -> Option<&CommonFlags> {
+        match self {
+            Self::List(cmd) => Some(&cmd.common_flags),
+            Self::Run(cmd) => Some(&cmd.common_flags),
+            Self::Open(cmd) => Some(&cmd.common_flags),
+            Self::LaunchJson(cmd) => Some(&cmd.common_flags),
+            Self::Coverage(cmd) => Some(&cmd.common_flags),
+            Self::Serve(cmd) => Some(&cmd.common_flags),
+            Self::Gc(_) | Self::Grep(_) | Self::Show(_) | Self::Bisect(_) | Self::TasksJson(_) => {
+                None
+            }
+        }
+    }
+
+    fn porcelain(&self) -> bool {
         match self {
-            Self::List(cmd) => &cmd.common_flags,
-            Self::Run(cmd) => &cmd.common_flags,
-            Self::LaunchJson(cmd) => &cmd.common_flags,
+            Self::List(cmd) => cmd.porcelain,
+            Self::Run(cmd) => cmd.porcelain,
+            Self::LaunchJson(_)
+            | Self::Open(_)
+            | Self::Coverage(_)
+            | Self::Serve(_)
+            | Self::Gc(_)
+            | Self::Grep(_)
+            | Self::Show(_)
+            | Self::Bisect(_)
+            | Self::TasksJson(_) => false,
+        }
+    }
+
+    fn reads_stdin(&self) -> bool {
+        match self {
+            Self::Run(cmd) => cmd.stdin,
+            Self::LaunchJson(cmd) => cmd.stdin,
+            Self::List(_)
+            | Self::Open(_)
+            | Self::Coverage(_)
+            | Self::Serve(_)
+            | Self::Gc(_)
+            | Self::Grep(_)
+            | Self::Show(_)
+            | Self::Bisect(_)
+            | Self::TasksJson(_) => false,
         }
     }
 }
@@ -127,14 +575,114 @@ struct ListCommand {
     /// Choose the output format of the list.
     #[arg(long, value_enum, default_value = "json")]
     output: OutputFormat,
+
+    /// Print a line per test with its name, executable, file and line separated by tabs, without
+    /// progress nor warnings. Unlike the other outputs, this format is kept stable for scripts.
+    #[arg(long, conflicts_with_all = ["output", "compare_with"])]
+    porcelain: bool,
+
+    /// Instead of listing the tests, report the tests that are only present in the test
+    /// directory or in this other test directory.
+    #[arg(long)]
+    compare_with: Option<String>,
+
+    /// Read all the metadata of the executables for the JSON output, like whether they are
+    /// stripped, their sanitizers and the shared libraries they need.
+    #[arg(long, alias = "elf-metadata")]
+    executable_metadata: bool,
 }
 
-#[derive(ValueEnum, Debug, Clone, Default)]
-pub enum CwdRelativeTo {
-    #[default]
-    Executable,
-    CppFile,
-    None,
+#[derive(Debug, Args)]
+struct ServeCommand {
+    #[clap(flatten)]
+    common_flags: CommonFlags,
+
+    /// The unix socket to listen on. [default: serve.sock in the cache directory of the
+    /// workspace]
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    /// Search the test directory again after this many seconds, to find the new executables. The
+    /// executables that were found are checked for changes every second. 0 disables it.
+    #[arg(long, value_name = "SECONDS", default_value = "60")]
+    rescan_interval: u64,
+}
+
+#[derive(Debug, Args)]
+struct GcCommand {
+    /// Remove the data of the workspaces that weren't used for this many days.
+    #[arg(long, value_name = "DAYS", default_value = "30")]
+    max_age_days: u64,
+
+    /// Only print what would be removed.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+struct RunStoreFlags {
+    /// Store the results of the runs in this results service, shared by the runners of an
+    /// organization, instead of the cache directory, and read them from it for grep, show and
+    /// launch-json --failed. Like
+    /// http://results.example.com/cpp-tests. [default: $CPP_TEST_RUNNER_RESULTS_URL]
+    #[arg(long, value_name = "URL")]
+    results_url: Option<String>,
+}
+
+impl RunStoreFlags {
+    fn run_store(
+        &self,
+        workspace: &Path,
+        cache: Option<&WorkspaceCache>,
+    ) -> Result<Box<dyn RunStore>> {
+        let url = self.results_url.clone().or_else(|| {
+            std::env::var(RESULTS_URL_VARIABLE)
+                .ok()
+                .filter(|url| !url.is_empty())
+        });
+        if let Some(url) = url {
+            // The same repository has the same name on every machine
+            let root = workspace_root(workspace);
+            let name = root.file_name().unwrap_or(root.as_os_str());
+            return Ok(Box::new(HttpRunStore::new(&url, &name.to_string_lossy())?));
+        }
+
+        let cache = cache
+            .ok_or_else(|| anyhow!("There is no cache directory for the outputs of the runs"))?;
+        Ok(Box::new(LocalRunArchive {
+            dir: cache.runs_dir(),
+        }))
+    }
+}
+
+#[derive(Debug, Args)]
+struct GrepCommand {
+    /// The regex searched in the outputs of the tests, line by line.
+    pattern: String,
+
+    /// Search the outputs of this many of the last runs, from the latest. Only the last 10 runs
+    /// are kept.
+    #[arg(long, default_value = "1")]
+    runs: usize,
+
+    /// Print this many lines before and after the matching lines.
+    #[arg(short = 'C', long, default_value = "2")]
+    context: usize,
+
+    /// Match the pattern regardless of case.
+    #[arg(short, long)]
+    ignore_case: bool,
+
+    /// The test directory of the runs, which tells their workspace. [default: .]
+    #[arg(long, default_value = ".")]
+    test_dir: String,
+
+    /// Enable or disable colored output.
+    #[arg(long, value_enum, default_value = "auto")]
+    color: ColorOption,
+
+    #[clap(flatten)]
+    run_store: RunStoreFlags,
 }
 
 #[derive(Debug, Args)]
@@ -142,37 +690,113 @@ struct LaunchJsonCommand {
     #[clap(flatten)]
     common_flags: CommonFlags,
 
-    /// The type of debugger of the launch configuration.
-    #[arg(long, default_value = "cppdbg")]
-    launch_type: String,
+    #[clap(flatten)]
+    options: LaunchJsonOptions,
 
-    /// The request type of the launch configuration.
-    #[arg(long, default_value = "launch")]
-    launch_request: String,
+    /// Preload this shared library in the tests, like a fault injection or a time mocking
+    /// library, with the LD_PRELOAD of the configurations. Can be repeated.
+    #[arg(long, value_name = "LIBRARY")]
+    preload: Vec<PathBuf>,
 
-    /// The cwd of the tests. Change launch-cwd-relative-to to modify to what the cwd is relative to.
-    #[arg(long, value_enum, default_value = ".")]
-    launch_cwd: PathBuf,
+    /// Read the tests from stdin, either as the JSON printed by list, which skips the search for
+    /// tests, or as one test name per line.
+    #[arg(long, conflicts_with = "interactive")]
+    stdin: bool,
 
-    /// Controls to what the cwd is relative to.
-    #[arg(long, value_enum, default_value = "executable")]
-    launch_cwd_relative_to: CwdRelativeTo,
+    /// Write the configurations to the launch.json of the nearest .vscode directory, in the
+    /// current directory or its parents, instead of printing them. The configurations with the
+    /// name of a test are replaced, the others are kept, and what changed is printed.
+    #[arg(long, conflicts_with = "configurations_only")]
+    write: bool,
 
-    /// Appends the executable path to the test name. Useful for distinguishing between tests with duplicate names.
+    /// Only generate the configurations of the tests that failed in the last run, which are the
+    /// ones being debugged, instead of a configuration for every test.
     #[arg(long)]
-    add_exec_path_to_name: bool,
+    failed: bool,
 
-    /// Only print the list of configurations.
-    #[arg(long)]
-    configurations_only: bool,
+    #[clap(flatten)]
+    run_store: RunStoreFlags,
+}
 
-    /// Add the stopAtEntry option to the config.
+#[derive(Debug, Args)]
+struct ShowCommand {
+    /// The name of the test, like Suite.Test, or a glob pattern like 'Suite.*'.
+    #[arg(value_name = "TEST")]
+    test: TestPattern,
+
+    /// Show the last run in which the test failed, instead of the last run of the test.
     #[arg(long)]
-    stop_at_entry: bool,
+    last_failure: bool,
+
+    /// The test directory of the runs, which tells their workspace. [default: .]
+    #[arg(long, default_value = ".")]
+    test_dir: String,
+
+    #[clap(flatten)]
+    run_store: RunStoreFlags,
+}
+
+#[derive(Debug, Args)]
+struct BisectCommand {
+    /// The name of the test, like Suite.Test, or a glob pattern like 'Suite.*'. A version is bad
+    /// when one of the matching tests fails.
+    #[arg(value_name = "TEST")]
+    test: TestPattern,
+
+    /// A version in which the test passes: a build directory, or a git revision with
+    /// --build-command.
+    #[arg(long, value_name = "VERSION")]
+    good: String,
+
+    /// A later version in which the test fails. Without --build-command, the versions are the
+    /// directories between --good and --bad in their parent directory, in the order of their
+    /// names, like builds/1042 to builds/1100.
+    #[arg(long, value_name = "VERSION")]
+    bad: String,
+
+    /// Bisect the git revisions between --good and --bad instead, on the first-parent history,
+    /// checking out and building every revision with this shell command, like
+    /// `cmake --build build`. The working tree must have no changes, and HEAD is restored at the
+    /// end. The revisions that don't build are skipped.
+    #[arg(long, value_name = "COMMAND")]
+    build_command: Option<String>,
 
-    /// Enable pretty printing in the debugger.
+    /// The test directory of the revisions, searched after they are built. [default: .]
+    #[arg(long, default_value = ".", requires = "build_command")]
+    test_dir: String,
+
+    /// Comma-separated list of the enabled executable types during the search.
+    #[arg(long, value_delimiter = ',', default_value = "gtest,catch2")]
+    executable_types: Vec<ExecutableType>,
+}
+
+#[derive(Debug, Args)]
+struct OpenCommand {
+    #[clap(flatten)]
+    common_flags: CommonFlags,
+
+    /// The name of the test, like Suite.Test, or a glob pattern like 'Suite.*' that matches tests
+    /// of the same location.
+    #[arg(value_name = "TEST")]
+    test: TestPattern,
+
+    /// The command that opens the file, with the {file} and {line} placeholders, like
+    /// `vim +{line} {file}`. [default: $VISUAL or $EDITOR with the line syntax of the editor, or
+    /// `code --goto {file}:{line}`]
+    #[arg(long, value_name = "COMMAND")]
+    editor_command: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct TasksJsonCommand {
+    /// The shell command that builds the tests, like `cmake --build build`.
+    #[arg(long, value_name = "COMMAND")]
+    build_command: String,
+
+    /// Add the task to the tasks.json of the nearest .vscode directory, in the current directory
+    /// or its parents, instead of printing it. The other tasks are kept.
     #[arg(long)]
-    pretty_printing: bool,
+    write: bool,
 }
 
 #[derive(Debug, Args)]
@@ -180,118 +804,1280 @@ struct RunCommand {
     #[clap(flatten)]
     common_flags: CommonFlags,
 
+    #[clap(flatten)]
+    run_store: RunStoreFlags,
+
+    /// The names of the tests to run, like Suite.Test. Glob patterns like 'Suite.*' are also
+    /// accepted. [default: all the tests]
+    #[arg(value_name = "TEST")]
+    tests: Vec<TestPattern>,
+
+    /// Read the tests to run from stdin, either as the JSON printed by list, which skips the
+    /// search for tests, or as one test name per line.
+    #[arg(long, conflicts_with_all = ["tests", "interactive", "session"])]
+    stdin: bool,
+
+    /// Only run the tests of this executable, given as a path or a glob pattern. Can be repeated.
+    /// The test directory isn't searched, so this is the fastest way to rerun the tests of a
+    /// rebuilt executable. Mutually exclusive with --test-dir and --executables.
+    #[arg(long, value_name = "PATH_OR_GLOB", conflicts_with = "Input")]
+    exe: Vec<String>,
+
     /// Enable or disable colored output.
     #[arg(long, value_enum, default_value = "auto")]
     color: ColorOption,
+
+    /// The working directory of the tests. Supports the {exe_dir}, {src_dir}, {workspace} and
+    /// {tmp} placeholders, which are resolved for every test. [default: the current directory]
+    #[arg(long)]
+    run_cwd: Option<String>,
+
+    /// Preload this shared library in the processes of the tests, like a fault injection or a
+    /// time mocking library, with LD_PRELOAD. Can be repeated.
+    #[arg(long, value_name = "LIBRARY")]
+    preload: Vec<PathBuf>,
+
+    /// Run every test once per combination of the values of the matrix, like
+    /// env:TZ=UTC,America/New_York to run it with two values of TZ, or
+    /// arg:--backend=sqlite,postgres to append --backend=sqlite and then --backend=postgres to its
+    /// arguments. Can be repeated to add dimensions to the matrix. The results are labeled with
+    /// the values they were run with.
+    #[arg(long, value_name = "DIMENSION")]
+    matrix: Vec<MatrixDimension>,
+
+    /// Stop scheduling new tests as soon as a test fails.
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Let the tests use the terminal instead of capturing their output, to debug them
+    /// interactively. The tests run one at a time in the foreground, and get the Ctrl-C, Ctrl-Z
+    /// and resizes of the terminal like when they run directly. SIGINT, SIGTSTP and SIGWINCH sent
+    /// to the runner are forwarded to them. No other test is started after a Ctrl-C.
+    #[arg(long, conflicts_with_all = [
+        "output", "porcelain", "session", "tui", "group_by_executable", "chunk_size", "fork_server"
+    ])]
+    no_capture: bool,
+
+    /// Run all the tests of an executable with a single process instead of one process per test.
+    /// The result of every test is read from the report of the test framework.
+    #[arg(long)]
+    group_by_executable: bool,
+
+    /// Run the tests of an executable in chunks of at most N tests per process, like
+    /// --gtest_filter=a:b:c, which run in parallel, instead of one process per test. For
+    /// executables with many tests, this saves most of the processes, but the tests of a chunk
+    /// can affect each other. The result of every test is read from the report of the test
+    /// framework.
+    #[arg(long, value_name = "N")]
+    chunk_size: Option<NonZeroUsize>,
+
+    /// After a failure is printed, hold back the results of passed tests for this many
+    /// milliseconds, so that the failure isn't immediately buried by other results. The held back
+    /// results are then printed in the order of the tests.
+    #[arg(long, value_name = "MS")]
+    log_quiesce: Option<u64>,
+
+    /// Rerun the failed gtest tests with --gtest_break_on_failure under gdb, or catchsegv if gdb
+    /// isn't installed, and store the backtrace at their first failing assertion in this
+    /// directory, or in the artifacts directory of the workspace in the cache.
+    #[arg(long, value_name = "DIR", num_args = 0..=1)]
+    break_artifacts: Option<Option<PathBuf>>,
+
+    /// Hold back tests while the peak memory that the running tests used in their last run, with
+    /// the one of the next test, would exceed this fraction of the RAM. 0 disables it.
+    #[arg(long, value_name = "FRACTION", default_value = "0.8")]
+    max_memory_fraction: f64,
+
+    /// Experimental: start every gtest executable once, and fork a process per test after its
+    /// static initialization, which is then only done once. Tests that depend on state changed by
+    /// main before the tests, or on threads started during the initialization, may misbehave.
+    #[arg(long)]
+    fork_server: bool,
+
+    /// Repeatedly pick tests using Skim and run them, showing the result of their last run in
+    /// the picker. Press Esc in the picker to exit.
+    #[arg(long, conflicts_with = "tui")]
+    session: bool,
+
+    /// Run the tests in a full-screen interface showing the progress, the running tests and the failures.
+    #[arg(long)]
+    tui: bool,
+
+    /// Choose the output format of the results. With json and pretty-json, the tests are printed
+    /// with their status, duration and output once they have all run, like the list subcommand.
+    #[arg(long, value_enum, default_value = "plain", conflicts_with_all = ["session", "tui"])]
+    output: OutputFormat,
+
+    /// Print a line per test once they have all run, with its status, name, duration in
+    /// milliseconds, executable and matrix cell separated by tabs, without colors, progress nor
+    /// warnings. Unlike the other outputs, this format is kept stable for scripts.
+    #[arg(long, conflicts_with_all = ["output", "session", "tui"])]
+    porcelain: bool,
+
+    /// Write reports for the CI once the tests have all run, besides the output, like
+    /// junit=report.xml,codequality=sanitizers.json: a JUnit XML test report, a code quality
+    /// report of GitLab with the findings of the sanitizers, or an SVG badge.
+    #[arg(long, value_name = "KIND=PATH", value_delimiter = ',', conflicts_with_all = ["session", "tui"])]
+    report: Vec<ReportSink>,
+
+    /// Write an SVG badge with the numbers of passed and failed tests and the pass rate once the
+    /// tests have all run, like --report badge=PATH, to publish from the CI.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["session", "tui"])]
+    badge: Option<PathBuf>,
+
+    /// Write the durations of the tests as zero in the output once they have all run and in the
+    /// reports, so that identical runs write byte-identical files, like for golden files.
+    #[arg(long)]
+    normalize_durations: bool,
 }
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
+#[derive(ValueEnum, Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CoverageTool {
+    /// Source-based coverage (-fprofile-instr-generate -fcoverage-mapping).
+    #[default]
+    Llvm,
+    /// gcc's gcov coverage (--coverage).
+    Gcov,
+}
 
-    if let Some(jobs) = &args.common_flags().jobs {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(*jobs)
-            .build_global()?;
+#[derive(ValueEnum, Debug, Clone, Default)]
+pub enum CoverageFormat {
+    #[default]
+    Text,
+    Lcov,
+    Json,
+    Html,
+}
+
+#[derive(Debug, Args)]
+struct CoverageCommand {
+    #[clap(flatten)]
+    common_flags: CommonFlags,
+
+    /// The directory where the per-test profiles, the manifest and the reports are written.
+    #[arg(long, default_value = "coverage")]
+    coverage_dir: PathBuf,
+
+    /// The coverage instrumentation the executables were built with.
+    #[arg(long, value_enum, default_value = "llvm")]
+    coverage_tool: CoverageTool,
+
+    /// The format of the merged report. Only used with the llvm coverage tool.
+    #[arg(long, value_enum, default_value = "text")]
+    report_format: CoverageFormat,
+
+    /// The working directory of the tests. Supports the {exe_dir}, {src_dir}, {workspace} and
+    /// {tmp} placeholders, which are resolved for every test. [default: the current directory]
+    #[arg(long)]
+    run_cwd: Option<String>,
+
+    /// Preload this shared library in the processes of the tests, like a fault injection or a
+    /// time mocking library, with LD_PRELOAD. Can be repeated.
+    #[arg(long, value_name = "LIBRARY")]
+    preload: Vec<PathBuf>,
+
+    /// Path to the llvm-profdata executable.
+    #[arg(long, default_value = "llvm-profdata")]
+    llvm_profdata: PathBuf,
+
+    /// Path to the llvm-cov executable.
+    #[arg(long, default_value = "llvm-cov")]
+    llvm_cov: PathBuf,
+
+    /// Enable or disable colored output.
+    #[arg(long, value_enum, default_value = "auto")]
+    color: ColorOption,
+}
+
+/// Opens skim to pick tests. Returns None if skim was aborted.
+#[cfg(unix)]
+fn select_tests(tests: &[Test]) -> Result<Option<Vec<Test>>> {
+    let options = SkimOptionsBuilder::default()
+        .multi(true)
+        .bind(vec![String::from("ctrl-a:toggle-all")])
+        .preview(Some(String::new()))
+        .build()
+        .unwrap();
+
+    let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = skim::prelude::unbounded();
+
+    for (index, test) in tests.iter().enumerate() {
+        tx_item
+            .send(std::sync::Arc::new(test.clone_with_index(index)))
+            .unwrap();
+    }
+    drop(tx_item);
+
+    Ok(Skim::run_with(&options, Some(rx_item))
+        .filter(|out| !out.is_abort)
+        .map(|out| out.selected_items)
+        .map(|selected_items| {
+            selected_items
+                .into_iter()
+                .map(|item| item.as_any().downcast_ref::<Test>().unwrap().clone())
+                .collect::<Vec<_>>()
+        }))
+}
+
+// skim, tuikit and the unix sockets of serve only build on Unix
+#[cfg(not(unix))]
+fn select_tests(_tests: &[Test]) -> Result<Option<Vec<Test>>> {
+    bail!("The picker of --interactive and --session isn't supported on this platform")
+}
+
+#[cfg(not(unix))]
+fn run_all_tui(
+    _tests: &[Test],
+    _options: &RunOptions,
+) -> Result<Vec<Option<test_results::TestOutcome>>> {
+    bail!("--tui isn't supported on this platform")
+}
+
+#[cfg(not(unix))]
+fn serve(
+    _command: &ServeCommand,
+    _discover: &dyn Fn() -> Result<(Vec<Test>, PathBuf)>,
+) -> Result<()> {
+    bail!("serve listens on a unix socket, which isn't supported on this platform")
+}
+
+// Serializes directly into the output instead of building the whole string first
+fn write_json(output: &mut impl Write, value: &impl Serialize, pretty: bool) -> Result<()> {
+    if pretty {
+        serde_json::to_writer_pretty(&mut *output, value)?;
+    } else {
+        serde_json::to_writer(&mut *output, value)?;
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+/// Repeatedly picks tests with skim and runs them, until skim is aborted.
+fn run_session(mut tests: Vec<Test>, options: &RunOptions) -> Result<()> {
+    while let Some(selected_tests) = select_tests(&tests)? {
+        if selected_tests.is_empty() {
+            continue;
+        }
+
+        let outcomes = run_all(&selected_tests, options)?;
+        for (test, outcome) in selected_tests.iter().zip(outcomes) {
+            if let (Some(index), Some(outcome)) = (test.index, outcome) {
+                tests[index].last_status = Some(outcome.status);
+            }
+        }
+
+        // Leave the results on screen until the user is ready to pick again
+        println!("Press Enter to pick tests again");
+        std::io::stdin().read_line(&mut String::new())?;
     }
 
-    let input = args.common_flags().input.as_ref();
+    Ok(())
+}
+
+/// Searches for the test executables and lists their tests. `workspace` is set to the test
+/// directory if it was searched.
+fn discover_tests(args: &Cli, flags: &CommonFlags, workspace: &mut PathBuf) -> Result<Vec<Test>> {
+    let input = flags.input.as_ref();
+    let deadline = match flags.discovery_timeout {
+        Some(timeout) => Some(DiscoveryDeadline::after(
+            Duration::try_from_secs_f64(timeout)
+                .map_err(|_| anyhow!("Invalid discovery timeout {timeout}"))?,
+        )),
+        None => None,
+    };
 
-    let executables = {
-        let cli_executables = input
-            .map(|input| input.executables.clone())
-            .unwrap_or(Default::default());
+    let show_progress =
+        !args.quiet() && atty::is(atty::Stream::Stdout) && atty::is(atty::Stream::Stderr);
+    let spinner = show_progress.then(|| DiscoverySpinner::start(args.glyphs()));
+
+    let mut source_files = None;
+    let mut executables = {
+        let cli_executables = match &args.command {
+            Command::Run(command) if !command.exe.is_empty() => {
+                expand_executable_patterns(&command.exe)?
+            }
+            _ => input
+                .map(|input| input.executables.clone())
+                .unwrap_or_default(),
+        };
 
         if !cli_executables.is_empty() {
             validate_executables(&cli_executables)
+        } else if let Some(prefix) = input.and_then(|input| input.system_scan.as_ref()) {
+            if !prefix.is_dir() {
+                bail!("{} is not a directory", prefix.display());
+            }
+            let prefix = prefix.canonicalize()?;
+            workspace.clone_from(&prefix);
+            source_files = Some(SourceFileIndex::build_in_background(prefix.clone()));
+
+            find_test_executables(
+                &prefix,
+                flags.jobs,
+                &flags.executable_types,
+                true,
+                &flags.walk_options()?,
+                deadline.as_ref(),
+            )
+            .map(|mut executables| {
+                set_library_paths(&mut executables, &prefix);
+                executables
+            })
         } else {
             let test_dir = input
                 .and_then(|input| input.test_dir.clone())
                 .unwrap_or_else(|| String::from("."));
 
-            let Some(test_dir) = find_test_dir(&test_dir, args.common_flags().no_parent)? else {
+            let Some(test_dir) = find_test_dir(&test_dir, flags.no_parent)? else {
                 bail!("test_dir {test_dir} not found");
             };
+            workspace.clone_from(&test_dir);
+            source_files = Some(SourceFileIndex::build_in_background(
+                workspace_root(&test_dir).to_path_buf(),
+            ));
 
             find_test_executables(
                 &test_dir,
-                args.common_flags().jobs,
-                &args.common_flags().executable_types,
+                flags.jobs,
+                &flags.executable_types,
+                false,
+                &flags.walk_options()?,
+                deadline.as_ref(),
             )
         }
     }?;
+    if let Some(rules_file) = ExecutableRules::find(workspace) {
+        ExecutableRules::load(&rules_file)?.apply(&mut executables);
+    }
+
+    if let Some(compile_commands) = &flags.compile_commands {
+        let database = CompilationDatabase::load(compile_commands)?;
+        source_files = Some(
+            source_files
+                .unwrap_or_else(SourceFileIndex::empty)
+                .with_compilation_database(database),
+        );
+    }
 
-    let tests = get_tests_from_executables(
+    let mut tests = flags.list_tests(
         &executables,
-        args.common_flags().executables_only,
-        &args.common_flags().gtest_extra_args,
-        &args.common_flags().catch2_extra_args,
-        args.common_flags().filter.as_ref(),
+        flags.name_filter(),
+        flags.discovery_cache(workspace).as_ref(),
+        source_files.as_ref(),
+        deadline.as_ref(),
+    )?;
+
+    if let Some(attributes_file) = AttributeRules::find(workspace) {
+        AttributeRules::load(&attributes_file)?.apply(&mut tests);
+    }
+    label_test_kinds(&mut tests);
+    drop(spinner);
+    if let Some(deadline) = &deadline {
+        warn_about_unfinished_discovery(deadline);
+    }
+
+    let fuzzy = flags.fuzzy.as_ref();
+    if tests.is_empty() && (!flags.filter.is_empty() || fuzzy.is_some()) {
+        // Only the executables that changed since their listing was saved are listed again
+        let all_tests = flags.list_tests(
+            &executables,
+            NameFilter::default(),
+            flags.saved_listing(workspace).as_ref(),
+            source_files.as_ref(),
+            deadline.as_ref(),
+        )?;
+
+        for filter in &flags.filter {
+            let suggestions = closest_test_names(filter.as_str(), &all_tests);
+            if !suggestions.is_empty() {
+                diagnostics::report(
+                    Diagnostic::error(
+                        diagnostics::NO_MATCHING_TEST,
+                        format!("no test matches the filter \"{filter}\". Did you mean:"),
+                    )
+                    .details(suggestions),
+                );
+            }
+        }
+        if let Some(fuzzy) = fuzzy {
+            let suggestions = closest_test_names(fuzzy.as_str(), &all_tests);
+            if !suggestions.is_empty() {
+                diagnostics::report(
+                    Diagnostic::error(
+                        diagnostics::NO_MATCHING_TEST,
+                        format!(
+                            "no test has the words \"{}\". Did you mean:",
+                            fuzzy.as_str()
+                        ),
+                    )
+                    .details(suggestions),
+                );
+            }
+        }
+
+        bail!("No tests selected");
+    }
+
+    Ok(tests)
+}
+
+/// Warns that the discovery timed out, with what it left unfinished, as the tests there are missing
+fn warn_about_unfinished_discovery(deadline: &DiscoveryDeadline) {
+    let unfinished = deadline.unfinished();
+    if unfinished.is_empty() {
+        return;
+    }
+
+    diagnostics::report(
+        Diagnostic::warning(
+            diagnostics::DISCOVERY_TIMED_OUT,
+            match unfinished.len() {
+                1 => "the discovery timed out before finishing 1 path, so its tests are missing:"
+                    .to_owned(),
+                count => format!(
+                    "the discovery timed out before finishing {count} paths, so their tests are \
+                     missing:"
+                ),
+            },
+        )
+        .details(unfinished.iter().map(|path| path.display())),
     );
+}
 
-    let tests = if args.common_flags().interactive && !tests.is_empty() {
-        let options = SkimOptionsBuilder::default()
-            .multi(true)
-            .bind(vec![String::from("ctrl-a:toggle-all")])
-            .preview(Some(String::new()))
-            .build()
-            .unwrap();
+/// Keeps the tests matching any of the patterns. Fails if a pattern matches no test, and suggests
+/// the closest test names.
+fn retain_named_tests(tests: &mut Vec<Test>, patterns: &[TestPattern]) -> Result<()> {
+    let unknown_patterns = patterns
+        .iter()
+        .filter(|pattern| !tests.iter().any(|test| pattern.is_match(&test.name)))
+        .map(TestPattern::as_str)
+        .collect::<Vec<_>>();
+
+    if !unknown_patterns.is_empty() {
+        for pattern in &unknown_patterns {
+            // The wildcards would only make the suggestions worse
+            let query = pattern.replace(['*', '?'], "");
+            let suggestions = closest_test_names(&query, tests);
+            if !suggestions.is_empty() {
+                diagnostics::report(
+                    Diagnostic::error(
+                        diagnostics::NO_MATCHING_TEST,
+                        format!("no test matches \"{pattern}\". Did you mean:"),
+                    )
+                    .details(suggestions),
+                );
+            }
+        }
+        bail!("No test matches {}", unknown_patterns.join(", "));
+    }
+
+    tests.retain(|test| patterns.iter().any(|pattern| pattern.is_match(&test.name)));
+    Ok(())
+}
+
+enum StdinTests {
+    /// The JSON printed by list
+    Tests(Vec<Test>),
+    Names(Vec<TestPattern>),
+}
+
+fn read_stdin_tests() -> Result<StdinTests> {
+    let input = io::read_to_string(io::stdin())?;
+    if input.trim_start().starts_with('[') {
+        let tests = serde_json::from_str(&input)
+            .map_err(|error| anyhow!("Failed to parse the tests of stdin: {error}"))?;
+        return Ok(StdinTests::Tests(tests));
+    }
+
+    let names = input
+        .lines()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(TestPattern::exact)
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        bail!("No test names on stdin");
+    }
+    Ok(StdinTests::Names(names))
+}
+
+fn run_gc(command: &GcCommand) -> Result<()> {
+    let max_age = Duration::from_secs(command.max_age_days * 24 * 60 * 60);
+    let garbage = collect_garbage(max_age, command.dry_run)?;
+    for path in &garbage.paths {
+        println!("{}", path.display());
+    }
+    println!(
+        "{} {}",
+        if command.dry_run {
+            "Would free"
+        } else {
+            "Freed"
+        },
+        humanize::size(garbage.bytes)
+    );
+    Ok(())
+}
 
-        let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = skim::prelude::unbounded();
+// The cache of the workspace of the test directory, with the archived runs
+fn runs_store(test_dir: &str, flags: &RunStoreFlags) -> Result<Box<dyn RunStore>> {
+    let Some(test_dir) = find_test_dir(test_dir, false)? else {
+        bail!("test_dir {test_dir} not found");
+    };
+    flags.run_store(&test_dir, WorkspaceCache::for_workspace(&test_dir).as_ref())
+}
 
-        for (index, test) in tests.iter().enumerate() {
-            tx_item
-                .send(Arc::new(test.clone_with_index(index)))
-                .unwrap();
+// Which archived run this is, from the latest one
+fn describe_run(age: usize) -> String {
+    match age {
+        0 => String::from("the latest run"),
+        1 => String::from("the run before"),
+        _ => format!("the run {age} runs before the latest"),
+    }
+}
+
+/// Keeps the tests that failed in the latest archived run, for launch-json --failed
+fn retain_last_failed_tests(
+    tests: &mut Vec<Test>,
+    flags: &RunStoreFlags,
+    workspace: &Path,
+) -> Result<()> {
+    let store = flags.run_store(workspace, WorkspaceCache::for_workspace(workspace).as_ref())?;
+    let Some(run) = store.recent_runs(1)?.into_iter().next() else {
+        bail!("No run was archived yet in {}", store.location());
+    };
+
+    let failed = run
+        .results
+        .iter()
+        .filter(|result| result.status.is_some_and(|status| status.is_failure()))
+        .map(|result| result.test.name.as_str())
+        .collect::<HashSet<_>>();
+    tests.retain(|test| failed.contains(test.name.as_str()));
+    if tests.is_empty() {
+        bail!("None of the tests failed in the latest run");
+    }
+    Ok(())
+}
+
+/// Prints the tests of the last runs whose output matches, and exits with 1 when none does, like
+/// grep
+fn run_grep(command: &GrepCommand) -> Result<()> {
+    let pattern = regex::RegexBuilder::new(&command.pattern)
+        .case_insensitive(command.ignore_case)
+        .build()?;
+    let store = runs_store(&command.test_dir, &command.run_store)?;
+    let runs = store.recent_runs(command.runs)?;
+    if runs.is_empty() {
+        bail!("No run was archived yet in {}", store.location());
+    }
+
+    let use_color = command.color.use_color();
+    // colored only checks whether stdout is a terminal, which --color=yes overrides
+    colored::control::set_override(use_color);
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    let mut num_matches = 0;
+    for (age, run) in runs.iter().enumerate() {
+        let mut run_matches = Vec::new();
+        let matches = output_search::write_matches(
+            &mut run_matches,
+            &run.results,
+            &pattern,
+            command.context,
+            use_color,
+        )?;
+        if matches == 0 {
+            continue;
         }
-        drop(tx_item);
 
-        let selected_items = Skim::run_with(&options, Some(rx_item))
-            .map(|out| out.selected_items)
-            .map(|selected_items| {
-                selected_items
-                    .into_iter()
-                    .map(|item| item.as_any().downcast_ref::<Test>().unwrap().clone())
-                    .collect::<Vec<_>>()
+        if num_matches > 0 {
+            writeln!(stdout)?;
+        }
+        // Which run matched only needs to be told when several were searched
+        if runs.len() > 1 {
+            writeln!(stdout, "In {}:\n", describe_run(age))?;
+        }
+        stdout.write_all(&run_matches)?;
+        num_matches += matches;
+    }
+    stdout.flush()?;
+
+    if num_matches == 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn find_vscode_dir() -> Result<PathBuf> {
+    match find_test_dir(".vscode", false)? {
+        Some(vscode_dir) => Ok(vscode_dir),
+        None => bail!("No .vscode directory in the current directory or its parents"),
+    }
+}
+
+/// Updates a file of the .vscode directory with its generated entries, and prints the entries that
+/// were added or changed
+fn write_vscode_file(
+    vscode_dir: &Path,
+    file_name: &str,
+    update: impl FnOnce(Option<&str>) -> Result<VscodeFileUpdate>,
+) -> Result<()> {
+    let path = vscode_dir.join(file_name);
+    let existing = match fs::read_to_string(&path) {
+        Ok(existing) => Some(existing),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => bail!("Failed to read {}: {error}", path.display()),
+    };
+
+    let update = update(existing.as_deref())?;
+    if update.is_empty() && existing.is_some() {
+        println!("{} is up to date", path.display());
+        return Ok(());
+    }
+
+    // Renamed over the file, so that VS Code never reads half of it
+    let temporary = vscode_dir.join(format!(".{file_name}.{}.tmp", std::process::id()));
+    fs::write(&temporary, &update.contents)?;
+    if let Err(error) = fs::rename(&temporary, &path) {
+        let _ = fs::remove_file(&temporary);
+        bail!("Failed to write {}: {error}", path.display());
+    }
+
+    println!("Updated {}", path.display());
+    for name in &update.added {
+        println!("+ {name}");
+    }
+    for (name, fields) in &update.changed {
+        println!("~ {name} ({})", fields.join(", "));
+    }
+    Ok(())
+}
+
+/// Prints the result of the test in the latest archived run that has one, or that has a failure
+/// of the test with --last-failure
+fn run_show(command: &ShowCommand) -> Result<()> {
+    let store = runs_store(&command.test_dir, &command.run_store)?;
+    let runs = store.recent_runs(run_archive::KEPT_RUNS)?;
+    if runs.is_empty() {
+        bail!("No run was archived yet in {}", store.location());
+    }
+
+    let found = runs.iter().enumerate().find_map(|(age, run)| {
+        let results = run
+            .results
+            .iter()
+            .filter(|result| command.test.is_match(&result.test.name))
+            .filter(|result| match result.status {
+                Some(status) => !command.last_failure || status.is_failure(),
+                None => false,
             })
-            .unwrap_or_default();
+            .collect::<Vec<_>>();
+        (!results.is_empty()).then_some((age, run, results))
+    });
+    let Some((age, run, results)) = found else {
+        bail!(
+            "{} has no {} in the last {} runs",
+            command.test.as_str(),
+            if command.last_failure {
+                "failure"
+            } else {
+                "result"
+            },
+            runs.len()
+        );
+    };
+
+    let ago = run
+        .time
+        .and_then(|time| time.elapsed().ok())
+        .map(|elapsed| format!(", {} ago", humanize::duration(elapsed)))
+        .unwrap_or_default();
+    for (index, result) in results.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        let test = &result.test;
+        println!("{}", test.display_name());
+        println!(
+            "  Status:     {}",
+            output_search::status_name(result.status)
+        );
+        println!("  Run:        {}{ago}", describe_run(age));
+        if let Some(seconds) = result.duration_seconds {
+            println!(
+                "  Duration:   {}",
+                humanize::duration(Duration::from_secs_f64(seconds))
+            );
+        }
+        if let Some(seed) = run.seed {
+            println!("  Seed:       {seed}, reproduced with --order=random --seed={seed}");
+        }
+        println!("  Executable: {}", test.executable.path.display());
+        if let Some(file) = &test.file {
+            match test.line {
+                Some(line) => println!("  Location:   {}:{line}", file.display()),
+                None => println!("  Location:   {}", file.display()),
+            }
+        }
+        if !result.output.trim().is_empty() {
+            println!("\n{}", result.output.trim_end());
+        }
+    }
+    Ok(())
+}
+
+/// Adds the configurations of the tests to the launch.json of the nearest .vscode directory, and
+/// the task that they run before launching to its tasks.json
+fn write_launch_json(tests: &[Test], options: &LaunchJsonOptions, workspace: &Path) -> Result<()> {
+    let vscode_dir = find_vscode_dir()?;
+    write_vscode_file(&vscode_dir, "launch.json", |existing| {
+        update_vscode_launch_json(existing, tests, options, workspace)
+    })?;
+    if let Some(build_command) = &options.build_command {
+        write_vscode_file(&vscode_dir, "tasks.json", |existing| {
+            update_vscode_tasks_json(existing, build_command)
+        })?;
+    }
+    Ok(())
+}
+
+/// Opens the location of the tests in an editor, and waits for it, which a terminal editor needs
+fn open_in_editor(tests: &[Test], command: &OpenCommand) -> Result<()> {
+    let mut locations = tests
+        .iter()
+        .map(|test| (test.file.as_deref(), test.line))
+        .collect::<Vec<_>>();
+    locations.dedup();
+    let [(file, line)] = locations[..] else {
+        let names = tests
+            .iter()
+            .map(|test| test.name.as_str())
+            .collect::<Vec<_>>();
+        bail!(
+            "{} matches tests in several locations: {}",
+            command.test.as_str(),
+            names.join(", ")
+        );
+    };
+    let Some(file) = file else {
+        bail!(
+            "The source file of {} is unknown. --backfill-locations searches for it in the debug \
+             info and the sources.",
+            tests[0].name
+        );
+    };
+
+    let mut editor = editor::editor_command(command.editor_command.as_deref(), file, line)?;
+    let program = editor.get_program().to_string_lossy().into_owned();
+    let status = editor.status().map_err(|error| {
+        anyhow!("Failed to start {program}: {error}. Set $EDITOR or use --editor-command")
+    })?;
+    if !status.success() {
+        bail!("{program} exited with {status}");
+    }
+    Ok(())
+}
+
+fn run_tasks_json(command: &TasksJsonCommand) -> Result<()> {
+    if command.write {
+        write_vscode_file(&find_vscode_dir()?, "tasks.json", |existing| {
+            update_vscode_tasks_json(existing, &command.build_command)
+        })
+    } else {
+        println!("{}", format_vscode_tasks_json(&command.build_command));
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    child_environment::capture();
+    let args = Cli::parse();
+    init_logging(args.log_level, args.verbose, args.log_json);
+
+    let result = match &args.command {
+        Command::Gc(command) => return run_gc(command),
+        Command::Grep(command) => return run_grep(command),
+        Command::Show(command) => return run_show(command),
+        Command::TasksJson(command) => return run_tasks_json(command),
+        Command::Bisect(command) => return run_bisect(command),
+        Command::List(ListCommand { common_flags, .. })
+        | Command::Run(RunCommand { common_flags, .. })
+        | Command::Open(OpenCommand { common_flags, .. })
+        | Command::LaunchJson(LaunchJsonCommand { common_flags, .. })
+        | Command::Coverage(CoverageCommand { common_flags, .. })
+        | Command::Serve(ServeCommand { common_flags, .. }) => run_command(&args, common_flags),
+    };
+    // Even when the command failed, like when no test matches a filter
+    print_diagnostics(args.quiet());
+    result
+}
+
+/// Prints the diagnostics reported by the command in a section after its output, without the
+/// warnings and the infos with --quiet
+fn print_diagnostics(quiet: bool) {
+    let diagnostics = diagnostics::take()
+        .into_iter()
+        .filter(|diagnostic| !quiet || diagnostic.severity == Severity::Error)
+        .collect::<Vec<_>>();
+    if diagnostics.is_empty() {
+        return;
+    }
+
+    for diagnostic in &diagnostics {
+        eprintln!("{diagnostic}");
+    }
+    let count = |severity: Severity, noun: &str| {
+        let count = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count();
+        (count > 0).then(|| format!("{count} {noun}{}", if count > 1 { "s" } else { "" }))
+    };
+    let counts = [
+        count(Severity::Error, "error"),
+        count(Severity::Warning, "warning"),
+        count(Severity::Info, "info"),
+    ];
+    eprintln!(
+        "Diagnostics: {}",
+        counts.into_iter().flatten().collect::<Vec<_>>().join(", ")
+    );
+}
+
+/// Discovers the tests and runs the command on them
+fn run_command(args: &Cli, flags: &CommonFlags) -> Result<()> {
+    if let Some(jobs) = &flags.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(*jobs)
+            .build_global()?;
+    }
+    child_environment::set_policy(flags.environment_policy());
+
+    // Selections that aren't based on the name of the tests
+    let current_dir = std::env::current_dir()?;
+    let file_filter = PathFilter::new(&flags.file, &current_dir)?;
+    let exec_filter = PathFilter::new(&flags.exec, &current_dir)?;
+    let is_selected = |test: &Test| {
+        let tags_match = flags
+            .tags
+            .as_ref()
+            .is_none_or(|tags| tags.matches(&test.tags));
+        let label_matches = flags.label.is_empty()
+            || test
+                .attributes
+                .labels
+                .iter()
+                .any(|label| flags.label.contains(label));
+        let file_matches = file_filter.as_ref().is_none_or(|file_filter| {
+            test.file
+                .as_deref()
+                .is_some_and(|file| file_filter.is_match(file))
+        });
+        let exec_matches = exec_filter
+            .as_ref()
+            .is_none_or(|exec_filter| exec_filter.is_match(&test.executable.path));
+        tags_match && label_matches && file_matches && exec_matches
+    };
+
+    if let Command::Serve(command) = &args.command {
+        return serve(command, &|| {
+            let mut workspace = std::env::current_dir()?;
+            let mut tests = discover_tests(args, flags, &mut workspace)?;
+            assign_ids(&mut tests);
+            tests.retain(is_selected);
+            Ok((tests, workspace))
+        });
+    }
+
+    // The root of the search, used to resolve {workspace} in working directories
+    let mut workspace = std::env::current_dir()?;
 
+    let (stdin_tests, stdin_names) = if args.command.reads_stdin() {
+        match read_stdin_tests()? {
+            StdinTests::Tests(tests) => (Some(tests), Vec::new()),
+            StdinTests::Names(names) => (None, names),
+        }
+    } else {
+        (None, Vec::new())
+    };
+
+    let mut tests = match stdin_tests {
+        // The tests were already discovered by list, only the filters remain to be applied
+        Some(tests) => {
+            let name_filter = flags.name_filter();
+            let exclude_disabled = flags.exclude_disabled;
+            tests
+                .into_iter()
+                .filter(|test| name_filter.is_match(&test.name))
+                .filter(|test| !(exclude_disabled && test.disabled))
+                .collect()
+        }
+        None => discover_tests(args, flags, &mut workspace)?,
+    };
+
+    if flags.backfill_locations {
+        backfill_locations(&mut tests, &workspace);
+    }
+    // Reported even with --quiet, for the diagnostics report
+    warn_about_missing_locations(&tests, flags.backfill_locations);
+    mark_duplicates(&mut tests);
+    if flags.dedupe_by_source {
+        dedupe_by_source(&mut tests);
+    } else {
+        warn_about_duplicates(&tests);
+    }
+    // Before the selection, so that the ids don't depend on the filters
+    assign_ids(&mut tests);
+    if flags.strict_duplicates {
+        reject_duplicate_names(&tests)?;
+    } else if !flags.disambiguate_duplicates {
+        warn_about_duplicate_names(&tests);
+    }
+
+    let num_tests = tests.len();
+    tests.retain(is_selected);
+    if num_tests != 0 && tests.is_empty() {
+        bail!("No tests selected");
+    }
+
+    match &args.command {
+        Command::Run(command) if !command.tests.is_empty() => {
+            retain_named_tests(&mut tests, &command.tests)?;
+        }
+        Command::Open(command) => {
+            retain_named_tests(&mut tests, std::slice::from_ref(&command.test))?;
+        }
+        Command::LaunchJson(command) if command.failed => {
+            retain_last_failed_tests(&mut tests, &command.run_store, &workspace)?;
+        }
+        _ => {}
+    }
+    if !stdin_names.is_empty() {
+        retain_named_tests(&mut tests, &stdin_names)?;
+    }
+
+    let seed = order_tests(&mut tests, &flags.order, flags.seed);
+    if let Some(seed) = seed {
+        diagnostics::report(Diagnostic::info(
+            diagnostics::SHUFFLED,
+            format!("shuffled the tests using --seed={seed}"),
+        ));
+    }
+
+    let tests = if flags.interactive && !tests.is_empty() {
+        let selected_items = select_tests(&tests)?.unwrap_or_default();
         Cow::Owned(selected_items)
     } else {
         Cow::Borrowed(&tests)
     };
 
-    match args.command {
-        Command::List(command) => match command.output {
-            OutputFormat::Json => {
-                println!("{}", serde_json::to_string(&tests)?);
+    let run_cwd = match &args.command {
+        Command::Run(command) => command.run_cwd.as_ref(),
+        Command::Coverage(command) => command.run_cwd.as_ref(),
+        _ => None,
+    };
+    let tests = match run_cwd {
+        Some(run_cwd) => Cow::Owned(
+            tests
+                .iter()
+                .map(|test| {
+                    let cwd = resolve_cwd_template(run_cwd, test, &workspace);
+                    if !cwd.is_dir() {
+                        bail!(
+                            "The cwd {} of {} is not a directory",
+                            cwd.display(),
+                            test.name
+                        );
+                    }
+
+                    let mut test = test.clone();
+                    test.cwd = Some(cwd);
+                    Ok(test)
+                })
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        None => tests,
+    };
+
+    let preloads = match &args.command {
+        Command::Run(command) => command.preload.as_slice(),
+        Command::Coverage(command) => command.preload.as_slice(),
+        Command::LaunchJson(command) => command.preload.as_slice(),
+        _ => &[],
+    };
+    let tests = if preloads.is_empty() {
+        tests
+    } else {
+        let mut tests = tests.into_owned();
+        add_preloads(&mut tests, preloads)?;
+        Cow::Owned(tests)
+    };
+
+    let tests = match &args.command {
+        Command::Run(command) if !command.matrix.is_empty() => {
+            Cow::Owned(expand_matrix(&tests, &command.matrix))
+        }
+        _ => tests,
+    };
+
+    match &args.command {
+        Command::List(command) => {
+            if let Some(other_test_dir) = &command.compare_with {
+                let Some(other_test_dir) = find_test_dir(other_test_dir, flags.no_parent)? else {
+                    bail!("test_dir {other_test_dir} not found");
+                };
+
+                let other_source_files = SourceFileIndex::build_in_background(
+                    workspace_root(&other_test_dir).to_path_buf(),
+                );
+                let mut other_executables = find_test_executables(
+                    &other_test_dir,
+                    flags.jobs,
+                    &flags.executable_types,
+                    false,
+                    &flags.walk_options()?,
+                    None,
+                )?;
+                if let Some(rules_file) = ExecutableRules::find(&other_test_dir) {
+                    ExecutableRules::load(&rules_file)?.apply(&mut other_executables);
+                }
+
+                let mut other_tests = flags.list_tests(
+                    &other_executables,
+                    flags.name_filter(),
+                    flags.discovery_cache(&other_test_dir).as_ref(),
+                    Some(&other_source_files),
+                    None,
+                )?;
+
+                other_tests.retain(is_selected);
+
+                let diff = diff_inventories(&tests, &workspace, &other_tests, &other_test_dir);
+                let mut stdout = BufWriter::new(io::stdout().lock());
+                match command.output {
+                    OutputFormat::Json => write_json(&mut stdout, &diff, false)?,
+                    OutputFormat::PrettyJson => write_json(&mut stdout, &diff, true)?,
+                    OutputFormat::VscodeTestAdapter => {
+                        bail!("--compare-with doesn't support --output vscode-test-adapter")
+                    }
+                    OutputFormat::Quickfix | OutputFormat::Github => {
+                        bail!("--output quickfix and github are only supported by run")
+                    }
+                    OutputFormat::Plain => {
+                        for entry in &diff.only_in_test_dir {
+                            writeln!(stdout, "- {}: {}", entry.executable.display(), entry.name)?;
+                        }
+                        for entry in &diff.only_in_other {
+                            writeln!(stdout, "+ {}: {}", entry.executable.display(), entry.name)?;
+                        }
+                    }
+                }
+                stdout.flush()?;
+
+                return Ok(());
             }
-            OutputFormat::PrettyJson => {
-                println!("{}", serde_json::to_string_pretty(&tests)?);
+
+            let mut tests = tests;
+            if command.executable_metadata {
+                attach_executable_metadata(tests.to_mut());
             }
-            OutputFormat::Plain => {
-                let all_test_names =
-                    tests
-                        .iter()
-                        .map(|test| &test.name)
-                        .fold(String::new(), |mut list, name| {
-                            list.push_str(&format!("{name}\n"));
-                            list
-                        });
-                print!("{all_test_names}");
+
+            let mut stdout = BufWriter::new(io::stdout().lock());
+            match command.output {
+                _ if command.porcelain => porcelain::write_tests(&mut stdout, &tests)?,
+                OutputFormat::Json => write_json(&mut stdout, &tests, false)?,
+                OutputFormat::PrettyJson => write_json(&mut stdout, &tests, true)?,
+                OutputFormat::VscodeTestAdapter => {
+                    write_json(&mut stdout, &vscode_test_adapter::test_items(&tests), false)?
+                }
+                OutputFormat::Quickfix | OutputFormat::Github => {
+                    bail!("--output quickfix and github are only supported by run")
+                }
+                OutputFormat::Plain => {
+                    for test in tests.iter() {
+                        if flags.disambiguate_duplicates {
+                            write!(stdout, "{}", test.id)?;
+                        } else {
+                            write!(stdout, "{}", test.name)?;
+                        }
+                        if !test.also_registered_by.is_empty() {
+                            let executables = test
+                                .also_registered_by
+                                .iter()
+                                .map(|executable| executable.display().to_string())
+                                .collect::<Vec<_>>();
+                            write!(stdout, " (also in {})", executables.join(", "))?;
+                        }
+                        writeln!(stdout)?;
+                    }
+                }
             }
-        },
+            stdout.flush()?;
+        }
         Command::LaunchJson(command) => {
-            println!("{}", format_tests_to_vscode_launch_json(&tests, &command));
+            let options = LaunchJsonOptions {
+                name_by_id: flags.disambiguate_duplicates,
+                ..command.options.clone()
+            };
+            if command.write {
+                write_launch_json(&tests, &options, &workspace)?;
+            } else {
+                println!(
+                    "{}",
+                    format_tests_to_vscode_launch_json(&tests, &options, &workspace)
+                );
+            }
         }
         Command::Run(command) => {
-            let use_color = match command.color {
-                ColorOption::No => false,
-                ColorOption::Yes => true,
-                ColorOption::Auto => atty::is(atty::Stream::Stdout),
+            let cache = WorkspaceCache::for_workspace(&workspace);
+            let options = RunOptions {
+                use_color: command.color.use_color() && !command.porcelain,
+                fail_fast: command.fail_fast,
+                group_by_executable: command.group_by_executable,
+                chunk_size: command.chunk_size.map(NonZeroUsize::get),
+                log_quiesce: command.log_quiesce.map(Duration::from_millis),
+                quiet: command.porcelain || !matches!(command.output, OutputFormat::Plain),
+                break_artifacts: match &command.break_artifacts {
+                    Some(Some(dir)) => Some(dir.clone()),
+                    Some(None) => Some(
+                        cache
+                            .as_ref()
+                            .map(WorkspaceCache::artifacts_dir)
+                            .ok_or_else(|| {
+                                anyhow!("There is no cache directory for the artifacts")
+                            })?,
+                    ),
+                    None => None,
+                },
+                memory_fraction: Some(command.max_memory_fraction)
+                    .filter(|fraction| *fraction > 0.0),
+                fork_server: command.fork_server,
+                cache,
+                cancel: None,
+                no_capture: command.no_capture,
+                glyphs: args.glyphs(),
             };
 
-            run_all(&tests, use_color)?;
+            if command.session {
+                run_session(tests.into_owned(), &options)?;
+            } else {
+                // Loaded before running, so that a malformed file doesn't waste the run
+                let gates = Gates::find(&workspace)
+                    .map(|gates_file| Gates::load(&gates_file))
+                    .transpose()?;
+
+                let outcomes = match command.output {
+                    _ if command.tui => run_all_tui(&tests, &options)?,
+                    OutputFormat::VscodeTestAdapter => {
+                        vscode_test_adapter::write_enqueued(&mut io::stdout().lock(), &tests)?;
+                        let outcomes = run_all_reporting(&tests, &options, &|id, outcome| {
+                            let _ = vscode_test_adapter::write_outcome(
+                                &mut io::stdout().lock(),
+                                &tests[id],
+                                outcome,
+                            );
+                        })?;
+                        vscode_test_adapter::write_end(&mut io::stdout().lock())?;
+                        outcomes
+                    }
+                    _ => run_all(&tests, &options)?,
+                };
+                let mut results = tests
+                    .iter()
+                    .zip(outcomes)
+                    .map(|(test, outcome)| TestResult::new(test, outcome))
+                    .collect::<Vec<_>>();
+
+                let annotate = match command.output {
+                    _ if command.porcelain => false,
+                    OutputFormat::Github => true,
+                    OutputFormat::Plain => github_annotations::in_github_actions(),
+                    _ => false,
+                };
+                let store = command
+                    .run_store
+                    .run_store(&workspace, options.cache.as_ref());
+                // The runs before this one tell which of the passed tests may be flaky
+                let recent_runs = match &store {
+                    Ok(store) if annotate => store
+                        .recent_runs(run_archive::KEPT_RUNS)
+                        .unwrap_or_default(),
+                    _ => Vec::new(),
+                };
+
+                // Searched by grep and show. A run that can't be archived is still a run.
+                if let Ok(store) = store {
+                    if let Err(error) = store.archive_run(&results, seed) {
+                        diagnostics::report(Diagnostic::warning(
+                            diagnostics::RUN_NOT_ARCHIVED,
+                            format!("the results of the run weren't archived: {error}"),
+                        ));
+                    }
+                }
+
+                // Only once archived, which keeps the durations for the next runs
+                if command.normalize_durations {
+                    results.iter_mut().for_each(TestResult::normalize_durations);
+                }
+
+                let mut stdout = BufWriter::new(io::stdout().lock());
+                match command.output {
+                    _ if command.porcelain => porcelain::write_results(&mut stdout, &results)?,
+                    OutputFormat::Json => write_json(&mut stdout, &results, false)?,
+                    OutputFormat::PrettyJson => write_json(&mut stdout, &results, true)?,
+                    OutputFormat::Quickfix => quickfix::write_failures(&mut stdout, &results)?,
+                    // Already written as the tests finished
+                    OutputFormat::Plain | OutputFormat::VscodeTestAdapter => {}
+                    OutputFormat::Github => {}
+                }
+                if annotate {
+                    github_annotations::write_annotations(&mut stdout, &results, &recent_runs)?;
+                }
+                stdout.flush()?;
+                // Before the reports, which have the diagnostics of the gates
+                let mut exit_code = gates.map_or(0, |gates| gates.evaluate(&results));
+                // The TUI is gone once it's closed, so its exit code tells whether the run failed
+                // or was aborted
+                let all_passed = results.iter().all(|result| {
+                    matches!(
+                        result.status,
+                        Some(TestStatus::Passed | TestStatus::Skipped)
+                    )
+                });
+                if command.tui && exit_code == 0 && !all_passed {
+                    exit_code = 1;
+                }
+                let badge = command.badge.clone().map(ReportSink::badge);
+                write_reports(
+                    &command
+                        .report
+                        .iter()
+                        .cloned()
+                        .chain(badge)
+                        .collect::<Vec<_>>(),
+                    &results,
+                )?;
+
+                if exit_code != 0 {
+                    print_diagnostics(args.quiet());
+                    std::process::exit(exit_code);
+                }
+            }
+        }
+        Command::Open(command) => {
+            open_in_editor(&tests, command)?;
+        }
+        Command::Coverage(command) => {
+            run_coverage(&tests, command, command.color.use_color())?;
         }
+        // Before looking for the tests
+        Command::Gc(_)
+        | Command::Grep(_)
+        | Command::Show(_)
+        | Command::Bisect(_)
+        | Command::TasksJson(_)
+        | Command::Serve(_) => {}
     }
 
     Ok(())
@@ -0,0 +1,68 @@
+use crate::types::Test;
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+/// The peak memory (max RSS) of every test in its last run, in bytes, which lets the runner hold
+/// back the tests that wouldn't fit in the memory left by the running tests
+#[derive(Debug, Default)]
+pub struct MemoryHistory {
+    path: PathBuf,
+    max_rss: HashMap<String, u64>,
+}
+
+fn key(test: &Test) -> String {
+    format!("{}:{}", test.executable.path.display(), test.display_name())
+}
+
+impl MemoryHistory {
+    /// Loads the history of a workspace, which is empty until tests were run
+    pub fn load(path: PathBuf) -> Self {
+        let max_rss = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default();
+        Self { path, max_rss }
+    }
+
+    pub fn max_rss(&self, test: &Test) -> Option<u64> {
+        self.max_rss.get(&key(test)).copied()
+    }
+
+    pub fn record(&mut self, test: &Test, max_rss: u64) {
+        self.max_rss.insert(key(test), max_rss);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written to a temporary file first, so that concurrent runs never read a partial history
+        let temporary_path = self
+            .path
+            .with_extension(format!("{}.tmp", std::process::id()));
+        let mut writer = BufWriter::new(File::create(&temporary_path)?);
+        serde_json::to_writer(&mut writer, &self.max_rss)?;
+        writer.flush()?;
+        fs::rename(&temporary_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// The RAM of the machine in bytes, from /proc/meminfo
+pub fn total_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let kilobytes = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
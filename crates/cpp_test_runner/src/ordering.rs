@@ -0,0 +1,30 @@
+use crate::{types::Test, TestOrder};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+/// Orders the tests before they are dispatched. Returns the seed that was used if the order
+/// is random, so that it can be printed and the order replayed.
+pub fn order_tests(tests: &mut [Test], order: &TestOrder, seed: Option<u64>) -> Option<u64> {
+    match order {
+        TestOrder::Declaration => {
+            // Executables are discovered in parallel, so their order isn't stable. The tests of
+            // an executable are already in their order of declaration.
+            tests.sort_by(|lhs, rhs| lhs.executable.path.cmp(&rhs.executable.path));
+            None
+        }
+        TestOrder::Alphabetical => {
+            tests.sort_by(|lhs, rhs| {
+                lhs.name
+                    .cmp(&rhs.name)
+                    .then_with(|| lhs.executable.path.cmp(&rhs.executable.path))
+            });
+            None
+        }
+        TestOrder::Random => {
+            let seed = seed.unwrap_or_else(rand::random);
+            // Shuffle from a stable order, so that the same seed always gives the same order
+            tests.sort_by(|lhs, rhs| lhs.executable.path.cmp(&rhs.executable.path));
+            tests.shuffle(&mut StdRng::seed_from_u64(seed));
+            Some(seed)
+        }
+    }
+}
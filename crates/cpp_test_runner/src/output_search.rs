@@ -0,0 +1,94 @@
+use colored::Colorize;
+use cpp_test_runner::{run_archive::ArchivedResult, types::TestStatus};
+use regex::Regex;
+use std::io::{self, Write};
+
+/// The status of an archived result, as printed by grep and show
+pub fn status_name(status: Option<TestStatus>) -> &'static str {
+    match status {
+        Some(TestStatus::Passed) => "passed",
+        Some(TestStatus::Failed) => "failed",
+        Some(TestStatus::Skipped) => "skipped",
+        Some(TestStatus::NotRunnable) => "not runnable",
+        None => "not run",
+    }
+}
+
+fn highlight(line: &str, pattern: &Regex) -> String {
+    pattern
+        .replace_all(line, |captures: &regex::Captures| {
+            captures[0].red().bold().to_string()
+        })
+        .into_owned()
+}
+
+/// Writes the tests whose output matches the pattern, each followed by the matching lines of its
+/// output and `context` lines around them, like grep: the line numbers are followed by `:` on the
+/// matching lines and by `-` on the others, and `--` separates the groups of lines that aren't
+/// adjacent. Returns the number of tests that matched.
+pub fn write_matches(
+    writer: &mut impl Write,
+    results: &[ArchivedResult],
+    pattern: &Regex,
+    context: usize,
+    use_color: bool,
+) -> io::Result<usize> {
+    let mut num_matches = 0;
+    for result in results {
+        let lines = result.output.lines().collect::<Vec<_>>();
+        let matching = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| pattern.is_match(line))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            continue;
+        }
+
+        if num_matches > 0 {
+            writeln!(writer)?;
+        }
+        num_matches += 1;
+        let name = result.test.display_name();
+        writeln!(
+            writer,
+            "{} ({}) in {}",
+            if use_color {
+                name.bold().to_string()
+            } else {
+                name.into_owned()
+            },
+            status_name(result.status),
+            result.test.executable.path.display()
+        )?;
+
+        // The end of the last group of lines that was written
+        let mut written_until = None;
+        for (group_start, &index) in matching.iter().enumerate() {
+            let start = index.saturating_sub(context);
+            let start = written_until.map_or(start, |end: usize| start.max(end));
+            if written_until.is_some_and(|end| start > end) {
+                writeln!(writer, "--")?;
+            }
+            let end = (index + context + 1).min(lines.len());
+            // The lines after the next match are written with it
+            let end = matching
+                .get(group_start + 1)
+                .map_or(end, |next| end.min(*next));
+
+            for (line_index, line) in lines.iter().enumerate().take(end).skip(start) {
+                let is_match = line_index == index;
+                let line = if is_match && use_color {
+                    highlight(line, pattern)
+                } else {
+                    line.to_string()
+                };
+                let separator = if is_match { ':' } else { '-' };
+                writeln!(writer, "{}{separator}{line}", line_index + 1)?;
+            }
+            written_until = Some(end);
+        }
+    }
+    Ok(num_matches)
+}
@@ -0,0 +1,35 @@
+use anyhow::Result;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::{Path, PathBuf};
+
+/// Selects paths matching any of a list of glob patterns. Relative patterns are matched against
+/// the paths relative to the base directory, and absolute patterns against the absolute paths.
+pub struct PathFilter {
+    globs: GlobSet,
+    base: PathBuf,
+}
+
+impl PathFilter {
+    /// Returns None if there are no patterns, so that nothing is filtered out
+    pub fn new(patterns: &[String], base: &Path) -> Result<Option<Self>> {
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+
+        let mut globs = GlobSetBuilder::new();
+        for pattern in patterns {
+            globs.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+        }
+
+        Ok(Some(Self {
+            globs: globs.build()?,
+            base: base.to_path_buf(),
+        }))
+    }
+
+    pub fn is_match(&self, path: &Path) -> bool {
+        let absolute_path = self.base.join(path);
+        let relative_path = absolute_path.strip_prefix(&self.base).unwrap_or(path);
+        self.globs.is_match(&absolute_path) || self.globs.is_match(relative_path)
+    }
+}
@@ -0,0 +1,75 @@
+use cpp_test_runner::{
+    test_results::TestResult,
+    types::{Test, TestStatus},
+};
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+// The value of the fields that a test doesn't have, which `read` in a shell doesn't skip like an
+// empty field
+const NONE: &str = "-";
+
+/// Writes a line per test, with tab-separated fields in this order:
+///
+/// ```text
+/// <name>  <executable>  <file>  <line>
+/// ```
+///
+/// The format doesn't change between versions, new fields are only added at the end of the lines.
+pub fn write_tests(writer: &mut impl Write, tests: &[Test]) -> io::Result<()> {
+    for test in tests {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            test.name,
+            path_field(&test.executable.path),
+            test.file.as_deref().map_or(NONE.into(), path_field),
+            test.line.map_or(NONE.into(), |line| line.to_string()),
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes a line per test once they have all run, in the order of the tests, with tab-separated
+/// fields in this order:
+///
+/// ```text
+/// <status>  <name>  <duration in ms>  <executable>  <matrix cell>
+/// ```
+///
+/// The status is passed, failed, skipped, not-runnable or not-run, and the matrix cell is the
+/// comma-separated values of --matrix the test was run with. The format doesn't change between
+/// versions, new fields are only added at the end of the lines.
+pub fn write_results(writer: &mut impl Write, results: &[TestResult]) -> io::Result<()> {
+    for result in results {
+        let status = match result.status {
+            Some(TestStatus::Passed) => "passed",
+            Some(TestStatus::Failed) => "failed",
+            Some(TestStatus::Skipped) => "skipped",
+            Some(TestStatus::NotRunnable) => "not-runnable",
+            None => "not-run",
+        };
+        let duration = result.duration_seconds.map_or(NONE.into(), |seconds| {
+            ((seconds * 1000.0).round() as u64).to_string()
+        });
+        let matrix = if result.test.matrix.is_empty() {
+            NONE.into()
+        } else {
+            result.test.matrix.join(",")
+        };
+
+        writeln!(
+            writer,
+            "{status}\t{}\t{duration}\t{}\t{matrix}",
+            result.test.name,
+            path_field(&result.test.executable.path),
+        )?;
+    }
+    Ok(())
+}
+
+fn path_field(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
@@ -0,0 +1,47 @@
+use crate::types::Test;
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+/// The LD_PRELOAD of the tests for the libraries of --preload, which are loaded before the ones of
+/// the LD_PRELOAD that the tests would otherwise have. The paths are made absolute, since the tests
+/// may run in another directory, and the names without a slash, like libfaketime.so.1, are left to
+/// the search of the loader.
+fn preload_environment(preloads: &[PathBuf]) -> Result<String> {
+    let preloads = preloads
+        .iter()
+        .map(|preload| {
+            if !preload.to_string_lossy().contains('/') {
+                return Ok(preload.clone());
+            }
+            match preload.canonicalize() {
+                Ok(preload) => Ok(preload),
+                Err(_) => bail!("The library to preload {} doesn't exist", preload.display()),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(preloads
+        .iter()
+        .map(|preload| preload.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(":"))
+}
+
+/// Adds the libraries to the LD_PRELOAD of every test, see `preload_environment`
+pub fn add_preloads(tests: &mut [Test], preloads: &[PathBuf]) -> Result<()> {
+    let preload = preload_environment(preloads)?;
+    let inherited = std::env::var("LD_PRELOAD").ok();
+    for test in tests {
+        let previous = test
+            .environment
+            .get("LD_PRELOAD")
+            .or(inherited.as_ref())
+            .filter(|previous| !previous.is_empty());
+        let value = match previous {
+            Some(previous) => format!("{preload}:{previous}"),
+            None => preload.clone(),
+        };
+        test.environment.insert(String::from("LD_PRELOAD"), value);
+    }
+    Ok(())
+}
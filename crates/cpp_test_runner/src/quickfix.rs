@@ -0,0 +1,119 @@
+use cpp_test_runner::{source_files::find_file, test_results::TestResult, types::Test};
+use regex::Regex;
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+// The failed assertions in the output of the tests: `file:46: Failure` for gtest,
+// `file:42: FAILED:` for Catch2, and `file(46): error: message` for both with MSVC
+static FAILED_ASSERTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(.+?)(?::(\d+):|\((\d+)\):) (?:Failure|FAILED:|error:)\s*(.*)$").unwrap()
+});
+
+// Like `[  FAILED  ]`, which gtest prints after the assertions
+fn is_gtest_marker(line: &str) -> bool {
+    line.get(..12)
+        .is_some_and(|marker| marker.starts_with('[') && marker.ends_with(']'))
+}
+
+/// The executables report the files of the assertions like the files of the tests, relative to
+/// where they were compiled, so the file of the test is used when it is the same
+pub fn resolve_file(reported: &str, test: &Test) -> PathBuf {
+    let reported = Path::new(reported);
+    if reported.is_absolute() {
+        return reported.to_path_buf();
+    }
+    if let Some(file) = test.file.as_ref().filter(|file| file.ends_with(reported)) {
+        return file.clone();
+    }
+    let search_start = test.executable.path.parent().unwrap_or(Path::new("."));
+    find_file(search_start, reported).unwrap_or_else(|| reported.to_path_buf())
+}
+
+/// Where a failed test failed
+pub struct Failure {
+    /// The file of the test itself when it failed without an assertion, like when it crashed, and
+    /// None when it has no file either
+    pub file: Option<PathBuf>,
+    pub line: Option<u32>,
+    /// The lines after the location of the assertion, until an empty line or the status of the
+    /// test, or else the first line of the output of the test
+    pub message: Vec<String>,
+}
+
+/// The failed assertions of a failed test, or the test itself when there are none
+pub fn failures(result: &TestResult) -> Vec<Failure> {
+    let lines = result.output.lines().collect::<Vec<_>>();
+    let mut failures = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let Some(captures) = FAILED_ASSERTION.captures(line) else {
+            continue;
+        };
+        let line_number = captures.get(2).or(captures.get(3)).unwrap().as_str();
+        let message = if captures[4].is_empty() {
+            lines[index + 1..]
+                .iter()
+                .map(|line| line.trim_end())
+                .take_while(|line| !line.is_empty() && !is_gtest_marker(line))
+                .map(str::to_string)
+                .collect()
+        } else {
+            vec![captures[4].to_string()]
+        };
+        failures.push(Failure {
+            file: Some(resolve_file(&captures[1], result.test)),
+            line: line_number.parse().ok(),
+            message,
+        });
+    }
+
+    if failures.is_empty() {
+        let message = lines
+            .iter()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && !is_gtest_marker(line));
+        failures.push(Failure {
+            file: result.test.file.clone(),
+            line: result.test.line,
+            message: message.map(str::to_string).into_iter().collect(),
+        });
+    }
+    failures
+}
+
+/// Writes a `file:line: message` line per failed assertion of the failed tests, like compilers
+/// write their errors, for the quickfix list of Vim and Neovim and the compilation mode of Emacs.
+/// The message is the name of the test and the lines of the failure. A failed test without
+/// assertions is located at the test, or at its executable when it has no file.
+pub fn write_failures(writer: &mut impl Write, results: &[TestResult]) -> io::Result<()> {
+    let failed = results
+        .iter()
+        .filter(|result| result.status.is_some_and(|status| status.is_failure()));
+    for result in failed {
+        let name = result.test.display_name();
+        for failure in failures(result) {
+            let file = failure
+                .file
+                .as_deref()
+                .unwrap_or(&result.test.executable.path);
+            let location = match failure.line {
+                Some(line) => format!("{}:{line}", file.display()),
+                None => file.display().to_string(),
+            };
+            let message = if failure.message.is_empty() {
+                String::from("failed")
+            } else {
+                failure
+                    .message
+                    .iter()
+                    .map(|line| line.trim())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            writeln!(writer, "{location}: {name}: {message}")?;
+        }
+    }
+    Ok(())
+}
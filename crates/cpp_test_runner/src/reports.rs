@@ -0,0 +1,519 @@
+use crate::quickfix;
+use anyhow::{anyhow, bail, Error, Result};
+use cpp_test_runner::{diagnostics, test_results::TestResult, types::TestStatus};
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::LazyLock,
+};
+
+// The last line of the reports of the sanitizers, like
+// `SUMMARY: AddressSanitizer: heap-use-after-free /src/math.cpp:12:5 in Math_Adds_Test::TestBody()`
+static SANITIZER_SUMMARY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^SUMMARY: (\w+Sanitizer): (.+)$").unwrap());
+
+// The location in a summary, which is the executable and an offset when it isn't symbolized
+static SUMMARY_LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\S+) (.+?):(\d+)(?::\d+)? in ").unwrap());
+
+// UndefinedBehaviorSanitizer reports every error on a line, like
+// `/src/math.cpp:12:5: runtime error: signed integer overflow`
+static RUNTIME_ERROR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(.+?):(\d+):\d+: runtime error: (.+)$").unwrap());
+
+// The frames of the sanitizer reports that the runner symbolized, like `    #3 /src/math.cpp:12 f`
+static SYMBOLIZED_FRAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s+#\d+ (.+?):(\d+) ").unwrap());
+
+/// Writes a report of the results of a run, for the CI
+pub trait Reporter {
+    fn write_report(&self, writer: &mut dyn Write, results: &[TestResult]) -> io::Result<()>;
+}
+
+/// A JUnit XML test report, like GitLab shows in the merge requests with `artifacts:reports:junit`
+/// and most CI systems read. The tests are grouped in a test suite per executable.
+pub struct JunitReporter;
+
+/// A code quality report of GitLab, `artifacts:reports:codequality`, with an issue per finding of
+/// the sanitizers in the outputs of the tests, located at the frame that caused it when the
+/// sanitizer or the runner symbolized it, and at the test otherwise
+pub struct CodeQualityReporter;
+
+/// An SVG badge like those of shields.io, with the numbers of passed and failed tests and the
+/// pass rate, which the CI can publish to a dashboard or a README
+pub struct BadgeReporter;
+
+/// The diagnostics of the command in JSON, with their severity, code, message, details and hint,
+/// including the warnings hidden by --quiet
+pub struct DiagnosticsReporter;
+
+/// The kinds of report of --report
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReportKind {
+    Junit,
+    CodeQuality,
+    Badge,
+    Diagnostics,
+}
+
+impl ReportKind {
+    fn reporter(self) -> &'static dyn Reporter {
+        match self {
+            ReportKind::Junit => &JunitReporter,
+            ReportKind::CodeQuality => &CodeQualityReporter,
+            ReportKind::Badge => &BadgeReporter,
+            ReportKind::Diagnostics => &DiagnosticsReporter,
+        }
+    }
+}
+
+/// A report of --report and the file it is written to, like `junit=report.xml`
+#[derive(Debug, Clone)]
+pub struct ReportSink {
+    kind: ReportKind,
+    path: PathBuf,
+}
+
+impl FromStr for ReportSink {
+    type Err = Error;
+
+    fn from_str(sink: &str) -> Result<Self> {
+        let Some((kind, path)) = sink.split_once('=') else {
+            bail!("The report {sink} has no file, like junit=report.xml");
+        };
+        let kind = match kind {
+            "junit" => ReportKind::Junit,
+            "codequality" => ReportKind::CodeQuality,
+            "badge" => ReportKind::Badge,
+            "diagnostics" => ReportKind::Diagnostics,
+            _ => bail!("Unknown report {kind}, expected junit, codequality, badge or diagnostics"),
+        };
+        if path.is_empty() {
+            bail!("The report {sink} has no file");
+        }
+        Ok(Self {
+            kind,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl ReportSink {
+    /// The badge of --badge, which is `--report badge=PATH`
+    pub fn badge(path: PathBuf) -> Self {
+        Self {
+            kind: ReportKind::Badge,
+            path,
+        }
+    }
+}
+
+/// Writes every report to its file, once the tests have all run
+pub fn write_reports(sinks: &[ReportSink], results: &[TestResult]) -> Result<()> {
+    for sink in sinks {
+        let write = || -> io::Result<()> {
+            let mut writer = BufWriter::new(File::create(&sink.path)?);
+            sink.kind.reporter().write_report(&mut writer, results)?;
+            writer.flush()
+        };
+        write().map_err(|error| {
+            anyhow!(
+                "Failed to write the report {}: {error}",
+                sink.path.display()
+            )
+        })?;
+    }
+    Ok(())
+}
+
+// The reports are read from the checkout of the repository, where the CI shows the files
+// relative to it
+fn relative_to_project(file: &Path) -> Cow<'_, Path> {
+    let project = env::var_os("CI_PROJECT_DIR")
+        .map(Into::into)
+        .or_else(|| env::current_dir().ok());
+    match project.and_then(|project| file.strip_prefix(project).ok().map(Path::to_owned)) {
+        Some(relative) => Cow::Owned(relative),
+        None => Cow::Borrowed(file),
+    }
+}
+
+// The outputs can have colors and other control characters, which XML doesn't allow
+fn escape_xml(text: &str) -> String {
+    let text = text
+        .chars()
+        .filter(|char| !char.is_control() || matches!(char, '\t' | '\n' | '\r'))
+        .collect::<String>();
+    quick_xml::escape::escape(&text).into_owned()
+}
+
+// Through a Value, whose objects have their keys sorted, so that the reports don't depend on the
+// order of the fields of the structs
+fn write_json(writer: &mut dyn Write, value: &impl Serialize) -> io::Result<()> {
+    let value = serde_json::to_value(value)?;
+    serde_json::to_writer_pretty(&mut *writer, &value)?;
+    writeln!(writer)
+}
+
+// With a fixed number of decimals, which doesn't depend on the locale
+fn seconds(result: &TestResult) -> String {
+    format!("{:.3}", result.duration_seconds.unwrap_or_default())
+}
+
+impl Reporter for JunitReporter {
+    fn write_report(&self, writer: &mut dyn Write, results: &[TestResult]) -> io::Result<()> {
+        let mut executables = Vec::<(&Path, Vec<&TestResult>)>::new();
+        for result in results {
+            let path = result.test.executable.path.as_path();
+            match executables
+                .iter_mut()
+                .find(|(executable, _)| *executable == path)
+            {
+                Some((_, results)) => results.push(result),
+                None => executables.push((path, vec![result])),
+            }
+        }
+
+        let count = |results: &[&TestResult], status: Option<TestStatus>| {
+            results
+                .iter()
+                .filter(|result| result.status == status)
+                .count()
+        };
+        let totals = |results: &[&TestResult]| {
+            let time = results
+                .iter()
+                .filter_map(|result| result.duration_seconds)
+                .sum::<f64>();
+            format!(
+                "tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{time:.3}\"",
+                results.len(),
+                count(results, Some(TestStatus::Failed)),
+                count(results, Some(TestStatus::NotRunnable)),
+                count(results, Some(TestStatus::Skipped)) + count(results, None)
+            )
+        };
+
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        let all = results.iter().collect::<Vec<_>>();
+        writeln!(
+            writer,
+            r#"<testsuites name="cpp_test_runner" {}>"#,
+            totals(&all)
+        )?;
+        for (executable, results) in &executables {
+            let executable = relative_to_project(executable);
+            let executable = escape_xml(&executable.to_string_lossy());
+            writeln!(
+                writer,
+                r#"  <testsuite name="{executable}" {}>"#,
+                totals(results)
+            )?;
+            for result in results {
+                write!(
+                    writer,
+                    r#"    <testcase name="{}" classname="{executable}""#,
+                    escape_xml(&result.test.display_name())
+                )?;
+                if let Some(file) = &result.test.file {
+                    let file = relative_to_project(file);
+                    write!(writer, r#" file="{}""#, escape_xml(&file.to_string_lossy()))?;
+                    if let Some(line) = result.test.line {
+                        write!(writer, r#" line="{line}""#)?;
+                    }
+                }
+                write!(writer, r#" time="{}""#, seconds(result))?;
+
+                let message = || {
+                    let failures = quickfix::failures(result);
+                    let message = failures
+                        .first()
+                        .and_then(|failure| failure.message.first())
+                        .map(|line| line.trim().to_string());
+                    escape_xml(&message.unwrap_or_else(|| String::from("failed")))
+                };
+                match result.status {
+                    Some(TestStatus::Passed) => writeln!(writer, "/>")?,
+                    Some(TestStatus::Failed) => writeln!(
+                        writer,
+                        ">\n      <failure message=\"{}\" type=\"failure\">{}</failure>\n    \
+                         </testcase>",
+                        message(),
+                        escape_xml(&result.output)
+                    )?,
+                    Some(TestStatus::NotRunnable) => writeln!(
+                        writer,
+                        ">\n      <error message=\"{}\" type=\"not runnable\">{}</error>\n    \
+                         </testcase>",
+                        message(),
+                        escape_xml(&result.output)
+                    )?,
+                    Some(TestStatus::Skipped) => {
+                        writeln!(writer, ">\n      <skipped/>\n    </testcase>")?
+                    }
+                    None => writeln!(
+                        writer,
+                        ">\n      <skipped message=\"not run because of --fail-fast\"/>\n    \
+                         </testcase>"
+                    )?,
+                }
+            }
+            writeln!(writer, "  </testsuite>")?;
+        }
+        writeln!(writer, "</testsuites>")
+    }
+}
+
+/// A finding of a sanitizer in the output of a test
+#[derive(Debug, PartialEq)]
+struct SanitizerFinding {
+    sanitizer: String,
+    /// Like heap-use-after-free or data-race
+    check: String,
+    description: String,
+    file: Option<PathBuf>,
+    line: Option<u32>,
+}
+
+fn sanitizer_findings(result: &TestResult) -> Vec<SanitizerFinding> {
+    let lines = result.output.lines().collect::<Vec<_>>();
+    let mut findings = Vec::<SanitizerFinding>::new();
+    for (index, line) in lines.iter().enumerate() {
+        let line = line.trim_end();
+        let finding = if let Some(captures) = RUNTIME_ERROR.captures(line) {
+            SanitizerFinding {
+                sanitizer: String::from("UndefinedBehaviorSanitizer"),
+                check: String::from("undefined-behavior"),
+                description: format!("runtime error: {}", &captures[3]),
+                file: Some(quickfix::resolve_file(&captures[1], result.test)),
+                line: captures[2].parse().ok(),
+            }
+        } else if let Some(captures) = SANITIZER_SUMMARY.captures(line) {
+            summary_finding(&captures[1], &captures[2], &lines[index..], result)
+        } else {
+            continue;
+        };
+
+        // UndefinedBehaviorSanitizer summarizes the runtime errors it already reported
+        let reported = findings.iter().any(|reported| {
+            reported.sanitizer == finding.sanitizer
+                && reported.file == finding.file
+                && reported.line == finding.line
+        });
+        if !reported {
+            findings.push(finding);
+        }
+    }
+    findings
+}
+
+// The finding of a `SUMMARY: <sanitizer>: <summary>` line, followed by the rest of the output
+fn summary_finding(
+    sanitizer: &str,
+    summary: &str,
+    following: &[&str],
+    result: &TestResult,
+) -> SanitizerFinding {
+    let (check, mut location) = match SUMMARY_LOCATION.captures(summary) {
+        Some(location) => (
+            location[1].to_string(),
+            Some((
+                quickfix::resolve_file(&location[2], result.test),
+                location[3].parse().ok(),
+            )),
+        ),
+        // Like `40 byte(s) leaked in 1 allocation(s).`
+        None if summary.contains("leaked") => (String::from("memory-leak"), None),
+        None => (
+            summary
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            None,
+        ),
+    };
+    // The sanitizer couldn't symbolize the frames, but the runner did after the output
+    if location.is_none() {
+        location = following.iter().find_map(|line| {
+            let captures = SYMBOLIZED_FRAME.captures(line)?;
+            Some((
+                quickfix::resolve_file(&captures[1], result.test),
+                captures[2].parse().ok(),
+            ))
+        });
+    }
+    let (file, line) = match location {
+        Some((file, line)) => (Some(file), line),
+        None => (None, None),
+    };
+    SanitizerFinding {
+        sanitizer: sanitizer.to_string(),
+        check,
+        description: format!("{sanitizer}: {summary}"),
+        file,
+        line,
+    }
+}
+
+#[derive(Serialize)]
+struct CodeQualityLines {
+    begin: u32,
+}
+
+#[derive(Serialize)]
+struct CodeQualityLocation {
+    path: String,
+    lines: CodeQualityLines,
+}
+
+#[derive(Serialize)]
+struct CodeQualityIssue {
+    description: String,
+    check_name: String,
+    fingerprint: String,
+    severity: &'static str,
+    categories: [&'static str; 1],
+    location: CodeQualityLocation,
+}
+
+// Identifies the issue across the runs, for GitLab to tell the new issues from the fixed ones
+fn fingerprint(parts: &[&str]) -> String {
+    let key = parts.join("\0");
+    let hash = |seed| {
+        let mut hasher = crc32fast::Hasher::new_with_initial(seed);
+        hasher.update(key.as_bytes());
+        hasher.finalize()
+    };
+    format!("{:08x}{:08x}", hash(0), hash(0x5f3759df))
+}
+
+impl Reporter for CodeQualityReporter {
+    fn write_report(&self, writer: &mut dyn Write, results: &[TestResult]) -> io::Result<()> {
+        let mut issues = Vec::new();
+        for result in results {
+            let name = result.test.display_name();
+            for finding in sanitizer_findings(result) {
+                let (file, line) = match finding.file {
+                    Some(file) => (file, finding.line),
+                    None => match &result.test.file {
+                        Some(file) => (file.clone(), result.test.line),
+                        None => (result.test.executable.path.clone(), None),
+                    },
+                };
+                let path = relative_to_project(&file).to_string_lossy().into_owned();
+                let line = line.unwrap_or(1);
+                let check_name = format!("{}/{}", finding.sanitizer, finding.check);
+                let severity = match finding.sanitizer.as_str() {
+                    "AddressSanitizer" | "MemorySanitizer" if finding.check != "memory-leak" => {
+                        "critical"
+                    }
+                    _ => "major",
+                };
+                issues.push(CodeQualityIssue {
+                    description: format!("{name}: {}", finding.description),
+                    fingerprint: fingerprint(&[&check_name, &path, &line.to_string(), &name]),
+                    check_name,
+                    severity,
+                    categories: ["Bug Risk"],
+                    location: CodeQualityLocation {
+                        path,
+                        lines: CodeQualityLines { begin: line },
+                    },
+                });
+            }
+        }
+        write_json(writer, &issues)
+    }
+}
+
+// The width of the texts of the badges in Verdana 11px, which the viewers don't measure, with
+// the average width of its characters
+fn badge_text_width(text: &str) -> usize {
+    text.chars().count() * 7
+}
+
+impl Reporter for BadgeReporter {
+    fn write_report(&self, writer: &mut dyn Write, results: &[TestResult]) -> io::Result<()> {
+        let count = |is_counted: fn(&TestStatus) -> bool| {
+            results
+                .iter()
+                .filter(|result| result.status.as_ref().is_some_and(is_counted))
+                .count()
+        };
+        let passed = count(|status| *status == TestStatus::Passed);
+        let failed = count(TestStatus::is_failure);
+
+        // Rounded down, so that a run with a failure is never at 100%
+        let (message, color) = match passed + failed {
+            0 => ("no tests".to_string(), "#9f9f9f"),
+            executed => (
+                format!(
+                    "{passed} passed, {failed} failed, {}%",
+                    passed * 100 / executed
+                ),
+                if failed == 0 { "#4c1" } else { "#e05d44" },
+            ),
+        };
+
+        let label = "tests";
+        let label_width = badge_text_width(label) + 10;
+        let message_width = badge_text_width(&message) + 10;
+        let width = label_width + message_width;
+        writeln!(
+            writer,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}">"##
+        )?;
+        writeln!(writer, "  <title>{label}: {message}</title>")?;
+        writeln!(
+            writer,
+            r##"  <linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>"##
+        )?;
+        writeln!(
+            writer,
+            r##"  <clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>"##
+        )?;
+        writeln!(writer, r##"  <g clip-path="url(#r)">"##)?;
+        writeln!(
+            writer,
+            r##"    <rect width="{label_width}" height="20" fill="#555"/>"##
+        )?;
+        writeln!(
+            writer,
+            r##"    <rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/>"##
+        )?;
+        writeln!(
+            writer,
+            r##"    <rect width="{width}" height="20" fill="url(#s)"/>"##
+        )?;
+        writeln!(writer, "  </g>")?;
+        writeln!(
+            writer,
+            r##"  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">"##
+        )?;
+        writeln!(
+            writer,
+            r##"    <text x="{}" y="14">{label}</text>"##,
+            label_width / 2
+        )?;
+        writeln!(
+            writer,
+            r##"    <text x="{}" y="14">{message}</text>"##,
+            label_width + message_width / 2
+        )?;
+        writeln!(writer, "  </g>")?;
+        writeln!(writer, "</svg>")
+    }
+}
+
+impl Reporter for DiagnosticsReporter {
+    fn write_report(&self, writer: &mut dyn Write, _results: &[TestResult]) -> io::Result<()> {
+        write_json(writer, &diagnostics::reported())
+    }
+}
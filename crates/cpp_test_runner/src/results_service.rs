@@ -0,0 +1,166 @@
+use crate::{
+    run_archive::{ArchivedRun, ArchivedRunRef, RunStore},
+    test_results::TestResult,
+};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+/// The environment variable with the URL of the results service, when --results-url isn't given
+pub const RESULTS_URL_VARIABLE: &str = "CPP_TEST_RUNNER_RESULTS_URL";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A results service shared by the runners of an organization, which keeps the runs of every
+/// developer and CI job, so that it can tell which tests are flaky across all of them. It is
+/// reached with plain HTTP/1.1, and answers:
+///
+/// - `POST <url>/runs` with a run, as JSON: `{"workspace", "seed", "results": [{"test",
+///   "status", "duration_seconds", "output"}]}`, where `workspace` is the name of the repository
+///   the tests were run in, and `test` is a test like printed by list
+/// - `GET <url>/runs?workspace=<workspace>&count=<count>` with the last runs of the workspace,
+///   as a JSON array of runs from the latest to the oldest, each with its `time` in milliseconds
+///   since the Unix epoch
+pub struct HttpRunStore {
+    url: String,
+    // Like host:8080, for the Host header
+    authority: String,
+    host: String,
+    port: u16,
+    path: String,
+    workspace: String,
+}
+
+impl HttpRunStore {
+    pub fn new(url: &str, workspace: &str) -> Result<Self> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            if url.starts_with("https://") {
+                bail!("Only http:// is supported, like a local TLS proxy to the service: {url}");
+            }
+            bail!("The URL of the results service must start with http://: {url}");
+        };
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (
+                host,
+                port.parse().map_err(|_| {
+                    anyhow!("Invalid port in the URL of the results service: {url}")
+                })?,
+            ),
+            _ => (authority, 80),
+        };
+        if host.is_empty() {
+            bail!("No host in the URL of the results service: {url}");
+        }
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            authority: authority.to_string(),
+            host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port,
+            path: path.trim_end_matches('/').to_string(),
+            workspace: workspace.to_string(),
+        })
+    }
+
+    fn request(&self, method: &str, target: &str, body: &[u8]) -> Result<Vec<u8>> {
+        let url = format!("{}{target}", self.url);
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))
+            .map_err(|error| anyhow!("Failed to connect to {url}: {error}"))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let head = format!(
+            "{method} {}{target} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.authority,
+            body.len()
+        );
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .map_err(|error| anyhow!("Failed to read the answer of {url}: {error}"))?;
+        let Some(head_end) = memchr::memmem::find(&response, b"\r\n\r\n") else {
+            bail!("Invalid answer from {url}");
+        };
+        let head = String::from_utf8_lossy(&response[..head_end]).to_lowercase();
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or_else(|| anyhow!("Invalid answer from {url}"))?;
+        let body = &response[head_end + 4..];
+        let body = if head.contains("transfer-encoding: chunked") {
+            dechunk(body).ok_or_else(|| anyhow!("Invalid chunked answer from {url}"))?
+        } else {
+            body.to_vec()
+        };
+
+        if !(200..300).contains(&status) {
+            let message = String::from_utf8_lossy(&body);
+            bail!("{method} {url} answered {status}: {}", message.trim());
+        }
+        Ok(body)
+    }
+}
+
+// The body of an answer sent in chunks, each after its size in hexadecimal
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut content = Vec::new();
+    loop {
+        let line_end = memchr::memmem::find(body, b"\r\n")?;
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        if size == 0 {
+            return Some(content);
+        }
+        let chunk = body.get(line_end + 2..line_end + 2 + size)?;
+        content.extend_from_slice(chunk);
+        body = body.get(line_end + 4 + size..)?;
+    }
+}
+
+// Encodes a query parameter, keeping the characters that don't need to be escaped
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+impl RunStore for HttpRunStore {
+    fn archive_run(&self, results: &[TestResult], seed: Option<u64>) -> Result<()> {
+        let run = ArchivedRunRef::new(results, seed, Some(&self.workspace));
+        self.request("POST", "/runs", &serde_json::to_vec(&run)?)?;
+        Ok(())
+    }
+
+    fn recent_runs(&self, count: usize) -> Result<Vec<ArchivedRun>> {
+        let target = format!(
+            "/runs?workspace={}&count={count}",
+            percent_encode(&self.workspace)
+        );
+        let body = self.request("GET", &target, &[])?;
+        serde_json::from_slice(&body)
+            .map_err(|error| anyhow!("Failed to parse the runs of {}{target}: {error}", self.url))
+    }
+
+    fn location(&self) -> String {
+        self.url.clone()
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    test_results::TestResult,
+    types::{Test, TestStatus},
+};
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The number of runs whose results are kept, the older ones are removed when a run is archived
+pub const KEPT_RUNS: usize = 10;
+
+/// Where the results of the runs are kept for grep and show: the files of `LocalRunArchive` in the
+/// cache directory of the workspace, or a shared results service with
+/// `results_service::HttpRunStore`
+pub trait RunStore {
+    /// Stores the results of a run once it finished
+    fn archive_run(&self, results: &[TestResult], seed: Option<u64>) -> Result<()>;
+
+    /// The last `count` stored runs, from the latest to the oldest
+    fn recent_runs(&self, count: usize) -> Result<Vec<ArchivedRun>>;
+
+    /// Where the runs are stored, for the messages
+    fn location(&self) -> String;
+}
+
+/// The runs archived in a directory, the last `KEPT_RUNS` of which are kept
+pub struct LocalRunArchive {
+    pub dir: PathBuf,
+}
+
+impl RunStore for LocalRunArchive {
+    fn archive_run(&self, results: &[TestResult], seed: Option<u64>) -> Result<()> {
+        archive_run(&self.dir, results, seed)
+    }
+
+    fn recent_runs(&self, count: usize) -> Result<Vec<ArchivedRun>> {
+        recent_runs(&self.dir, count)
+    }
+
+    fn location(&self) -> String {
+        self.dir.display().to_string()
+    }
+}
+
+/// A run read back from what `archive_run` wrote
+#[derive(Debug, Deserialize)]
+pub struct ArchivedRun {
+    /// When the run was archived, once it finished. Sent in milliseconds since the Unix epoch by
+    /// the results services, and taken from the name of the archived files.
+    #[serde(default, deserialize_with = "deserialize_millis")]
+    pub time: Option<SystemTime>,
+    /// The --seed that the tests were shuffled with
+    pub seed: Option<u64>,
+    pub results: Vec<ArchivedResult>,
+}
+
+/// A test with its outcome in an archived run
+#[derive(Debug, Deserialize)]
+pub struct ArchivedResult {
+    pub test: Test,
+    pub status: Option<TestStatus>,
+    pub duration_seconds: Option<f64>,
+    #[serde(default)]
+    pub output: String,
+}
+
+fn deserialize_millis<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<SystemTime>, D::Error> {
+    let millis = Option::<u64>::deserialize(deserializer)?;
+    Ok(millis.map(|millis| UNIX_EPOCH + Duration::from_millis(millis)))
+}
+
+/// The results of a run as they are archived, and sent to the results services with the
+/// workspace they were run in
+#[derive(Serialize)]
+pub struct ArchivedRunRef<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace: Option<&'a str>,
+    seed: Option<u64>,
+    results: Vec<ArchivedResultRef<'a>>,
+}
+
+impl<'a> ArchivedRunRef<'a> {
+    pub fn new(results: &'a [TestResult], seed: Option<u64>, workspace: Option<&'a str>) -> Self {
+        Self {
+            workspace,
+            seed,
+            results: results
+                .iter()
+                .map(|result| ArchivedResultRef {
+                    test: result.test,
+                    status: result.status,
+                    duration_seconds: result.duration_seconds,
+                    output: &result.output,
+                })
+                .collect(),
+        }
+    }
+}
+
+// The test isn't flattened like in the JSON output, because a flattened test can't be read back:
+// the modification time of its executable doesn't fit in the integers that serde buffers
+#[derive(Serialize)]
+struct ArchivedResultRef<'a> {
+    test: &'a Test,
+    status: Option<TestStatus>,
+    duration_seconds: Option<f64>,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    output: &'a str,
+}
+
+// The archived runs, from the oldest to the latest. They are named after the time they were
+// archived at, in milliseconds, so that their names sort like their times.
+fn archived_runs(dir: &Path) -> Vec<PathBuf> {
+    let mut runs = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect::<Vec<_>>();
+    runs.sort();
+    runs
+}
+
+/// Writes the results of a run in the directory, and removes the runs before the last `KEPT_RUNS`
+pub fn archive_run(dir: &Path, results: &[TestResult], seed: Option<u64>) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default();
+    let path = dir.join(format!("{millis:016}.json"));
+
+    // Written to a temporary file first, so that grep never reads a partial run
+    let temporary_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    let mut writer = BufWriter::new(File::create(&temporary_path)?);
+    serde_json::to_writer(&mut writer, &ArchivedRunRef::new(results, seed, None))?;
+    writer.flush()?;
+    fs::rename(&temporary_path, &path)?;
+
+    let runs = archived_runs(dir);
+    for old_run in &runs[..runs.len().saturating_sub(KEPT_RUNS)] {
+        let _ = fs::remove_file(old_run);
+    }
+    Ok(())
+}
+
+/// The last `count` archived runs, from the latest to the oldest
+pub fn recent_runs(dir: &Path, count: usize) -> Result<Vec<ArchivedRun>> {
+    archived_runs(dir)
+        .iter()
+        .rev()
+        .take(count)
+        .map(|path| {
+            let mut run: ArchivedRun = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+            run.time = path
+                .file_stem()
+                .and_then(|millis| millis.to_str()?.parse().ok())
+                .map(|millis| UNIX_EPOCH + Duration::from_millis(millis));
+            Ok(run)
+        })
+        .collect()
+}
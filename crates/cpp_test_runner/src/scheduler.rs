@@ -0,0 +1,103 @@
+use crate::types::Test;
+use std::collections::BTreeSet;
+
+/// The index of a test in the tests given to the runner
+pub type TestId = usize;
+
+/// What a scheduler knows about a run when it picks the next batch of tests
+pub struct SchedulerState<'a> {
+    pub tests: &'a [Test],
+    /// The tests that haven't been scheduled yet
+    pub pending: &'a BTreeSet<TestId>,
+    /// The tests that are currently running
+    pub running: &'a BTreeSet<TestId>,
+    pub has_failed: bool,
+}
+
+/// Decides which tests are run, in which order, and which ones share a process. The runner asks
+/// for a batch whenever one of its threads is free, and runs the tests of a batch with a single
+/// process if there are several of them. Tests of a batch must come from the same executable.
+///
+/// A thread that gets an empty batch while other tests are running waits for one of them to finish
+/// and asks again, so that a scheduler can hold back tests. It stops when no test is running, and
+/// the tests that are still pending once all the threads have stopped are reported as not run.
+pub trait Scheduler: Sync {
+    fn next_batch(&self, state: &SchedulerState) -> Vec<TestId>;
+}
+
+/// Runs the tests in their order, either one test per process, or the tests of an executable
+/// with a single process: all of them, or chunks of at most `chunk_size` tests, which run in
+/// parallel.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultScheduler {
+    pub group_by_executable: bool,
+    pub chunk_size: Option<usize>,
+}
+
+impl Scheduler for DefaultScheduler {
+    fn next_batch(&self, state: &SchedulerState) -> Vec<TestId> {
+        let Some(&first) = state.pending.first() else {
+            return Vec::new();
+        };
+        if !self.group_by_executable && self.chunk_size.is_none() {
+            return vec![first];
+        }
+
+        // The tests can only share a process if they run in the same environment, with the same
+        // arguments of --matrix
+        let first_test = &state.tests[first];
+        state
+            .pending
+            .iter()
+            .copied()
+            .filter(|id| {
+                let test = &state.tests[*id];
+                test.executable.path == first_test.executable.path
+                    && test.cwd == first_test.cwd
+                    && test.environment == first_test.environment
+                    && test.matrix == first_test.matrix
+            })
+            .take(self.chunk_size.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// Holds back the batches of another scheduler while the memory they used in their last run,
+/// added to the one of the running tests, exceeds a budget. A batch is never held back when no
+/// test is running, so a test using more than the budget still runs, alone.
+pub struct MemoryBudgetScheduler<S> {
+    pub inner: S,
+    /// The max RSS of every test in its last run, in bytes, by TestId
+    pub max_rss: Vec<Option<u64>>,
+    pub budget: u64,
+}
+
+impl<S: Scheduler> MemoryBudgetScheduler<S> {
+    // The tests of a batch share a process, which uses about as much memory as the heaviest one
+    fn batch_rss(&self, batch: impl IntoIterator<Item = TestId>) -> u64 {
+        batch
+            .into_iter()
+            .filter_map(|id| self.max_rss[id])
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl<S: Scheduler> Scheduler for MemoryBudgetScheduler<S> {
+    fn next_batch(&self, state: &SchedulerState) -> Vec<TestId> {
+        let batch = self.inner.next_batch(state);
+        if state.running.is_empty() {
+            return batch;
+        }
+
+        let running_rss = state
+            .running
+            .iter()
+            .map(|id| self.batch_rss([*id]))
+            .sum::<u64>();
+        if running_rss + self.batch_rss(batch.iter().copied()) > self.budget {
+            return Vec::new();
+        }
+        batch
+    }
+}
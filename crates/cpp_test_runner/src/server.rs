@@ -0,0 +1,394 @@
+use crate::{
+    cache_dirs::WorkspaceCache,
+    test_pattern::TestPattern,
+    test_results::TestResult,
+    types::{Test, TestStatus},
+    ServeCommand,
+};
+use anyhow::{anyhow, bail, Result};
+use cpp_test_runner::TestRun;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+// The errors of JSON-RPC 2.0
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+// How often the executables are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Finds the tests with the flags of serve, and returns them with the directory that was searched
+pub type Discover<'a> = dyn Fn() -> Result<(Vec<Test>, PathBuf)> + Sync + 'a;
+
+struct Discovery {
+    tests: Arc<Vec<Test>>,
+    // The modification time of every executable when its tests were listed
+    executables: HashMap<PathBuf, Option<SystemTime>>,
+    at: Instant,
+}
+
+impl Discovery {
+    fn new(tests: Vec<Test>) -> Self {
+        let executables = tests
+            .iter()
+            .map(|test| test.executable.path.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|path| {
+                let modified = modification_time(&path);
+                (path, modified)
+            })
+            .collect();
+        Self {
+            tests: Arc::new(tests),
+            executables,
+            at: Instant::now(),
+        }
+    }
+
+    fn has_changed_executables(&self) -> bool {
+        self.executables
+            .iter()
+            .any(|(path, modified)| modification_time(path) != *modified)
+    }
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+struct Server<'a> {
+    discover: &'a Discover<'a>,
+    discovery: RwLock<Discovery>,
+    // The runs in progress, by the id of the request that started them
+    runs: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl Server<'_> {
+    fn rediscover(&self) -> Result<usize> {
+        let (tests, _) = (self.discover)()?;
+        let num_tests = tests.len();
+        *self.discovery.write().unwrap() = Discovery::new(tests);
+        Ok(num_tests)
+    }
+
+    // Lists the tests again when an executable was rebuilt or removed, and every rescan interval
+    // to find the new executables
+    fn watch(&self, rescan_interval: Option<Duration>) {
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let is_outdated = {
+                let discovery = self.discovery.read().unwrap();
+                discovery.has_changed_executables()
+                    || rescan_interval.is_some_and(|interval| discovery.at.elapsed() >= interval)
+            };
+            if !is_outdated {
+                continue;
+            }
+            // The tests of the previous discovery stay available
+            match self.rediscover() {
+                Ok(num_tests) => eprintln!("Listed {num_tests} tests again"),
+                Err(error) => {
+                    eprintln!("Failed to list the tests again: {error}");
+                    self.discovery.write().unwrap().at = Instant::now();
+                }
+            }
+        }
+    }
+
+    fn tests(&self) -> Arc<Vec<Test>> {
+        Arc::clone(&self.discovery.read().unwrap().tests)
+    }
+
+    fn handle_connection(&self, stream: UnixStream) -> Result<()> {
+        let writer = Mutex::new(stream.try_clone()?);
+        thread::scope(|scope| {
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let request = match serde_json::from_str::<Request>(&line) {
+                    Ok(request) => request,
+                    Err(error) => {
+                        let response = error_response(&Value::Null, PARSE_ERROR, error);
+                        send(&writer, &response);
+                        continue;
+                    }
+                };
+
+                // Runs take a while, and can be cancelled by the next requests, so they are
+                // registered before reading them
+                if request.method == "run" {
+                    let run_id = id_key(&request.id);
+                    let cancel = Arc::new(AtomicBool::new(false));
+                    self.runs
+                        .lock()
+                        .unwrap()
+                        .insert(run_id.clone(), Arc::clone(&cancel));
+                    let writer = &writer;
+                    scope.spawn(move || {
+                        let response = match self.run(&request, cancel, writer) {
+                            Ok(result) => success_response(&request.id, result),
+                            Err(error) => error_response(&request.id, INVALID_PARAMS, error),
+                        };
+                        self.runs.lock().unwrap().remove(&run_id);
+                        send(writer, &response);
+                    });
+                } else {
+                    send(&writer, &self.handle(&request));
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn handle(&self, request: &Request) -> Value {
+        match request.method.as_str() {
+            "list" => match self.select(&request.params) {
+                Ok(tests) => success_response(&request.id, json!(tests)),
+                Err(error) => error_response(&request.id, INVALID_PARAMS, error),
+            },
+            "cancel" => {
+                let run = request.params.get("run").map(id_key).unwrap_or_default();
+                let cancelled = match self.runs.lock().unwrap().get(&run) {
+                    Some(cancel) => {
+                        cancel.store(true, Ordering::Relaxed);
+                        true
+                    }
+                    None => false,
+                };
+                success_response(&request.id, json!(cancelled))
+            }
+            "refresh" => match self.rediscover() {
+                Ok(num_tests) => success_response(&request.id, json!({ "tests": num_tests })),
+                Err(error) => error_response(&request.id, INTERNAL_ERROR, error),
+            },
+            method => error_response(
+                &request.id,
+                METHOD_NOT_FOUND,
+                anyhow!("Unknown method {method}, expected list, run, cancel or refresh"),
+            ),
+        }
+    }
+
+    // The tests with a name matching one of the "tests" patterns and the "filter" regex
+    fn select(&self, params: &Value) -> Result<Vec<Test>> {
+        let params = parse_params::<SelectParams>(params)?;
+        let patterns = params
+            .tests
+            .iter()
+            .map(|pattern| pattern.parse::<TestPattern>())
+            .collect::<Result<Vec<_>>>()?;
+        let filter = params
+            .filter
+            .as_deref()
+            .map(regex::Regex::new)
+            .transpose()?;
+
+        Ok(self
+            .tests()
+            .iter()
+            .filter(|test| {
+                patterns.is_empty() || patterns.iter().any(|pattern| pattern.is_match(&test.name))
+            })
+            .filter(|test| {
+                filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.is_match(&test.name))
+            })
+            .cloned()
+            .collect())
+    }
+
+    // Sends the result of every test as it finishes, and returns the counts of the statuses
+    fn run(
+        &self,
+        request: &Request,
+        cancel: Arc<AtomicBool>,
+        writer: &Mutex<UnixStream>,
+    ) -> Result<Value> {
+        let tests = self.select(&request.params)?;
+        if tests.is_empty() {
+            bail!("No tests selected");
+        }
+        let params = parse_params::<RunParams>(&request.params)?;
+
+        let num_tests = tests.len();
+        let mut counts = HashMap::<&str, usize>::new();
+        let results = TestRun::new(tests)
+            .fail_fast(params.fail_fast)
+            .group_by_executable(params.group_by_executable)
+            .cancel_with(Arc::clone(&cancel))
+            .start();
+        let mut run_error = None;
+        for result in results {
+            let (test, outcome) = match result {
+                Ok(result) => result,
+                Err(error) => {
+                    run_error = Some(error);
+                    break;
+                }
+            };
+            *counts.entry(status_name(outcome.status)).or_default() += 1;
+            send(
+                writer,
+                &json!({
+                    "jsonrpc": "2.0",
+                    "method": "result",
+                    "params": { "run": request.id, "test": TestResult::new(&test, Some(outcome)) },
+                }),
+            );
+        }
+        if let Some(error) = run_error {
+            return Err(error);
+        }
+
+        let num_run = counts.values().sum::<usize>();
+        Ok(json!({
+            "passed": counts.get("passed").copied().unwrap_or_default(),
+            "failed": counts.get("failed").copied().unwrap_or_default(),
+            "skipped": counts.get("skipped").copied().unwrap_or_default(),
+            "not_runnable": counts.get("not_runnable").copied().unwrap_or_default(),
+            "not_run": num_tests - num_run,
+            "cancelled": cancel.load(Ordering::Relaxed),
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SelectParams {
+    tests: Vec<String>,
+    filter: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RunParams {
+    fail_fast: bool,
+    group_by_executable: bool,
+}
+
+// The params are optional
+fn parse_params<T: DeserializeOwned + Default>(params: &Value) -> Result<T> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+    Ok(serde_json::from_value(params.clone())?)
+}
+
+fn status_name(status: TestStatus) -> &'static str {
+    match status {
+        TestStatus::Passed => "passed",
+        TestStatus::Failed => "failed",
+        TestStatus::Skipped => "skipped",
+        TestStatus::NotRunnable => "not_runnable",
+    }
+}
+
+// The ids of the requests are numbers or strings
+fn id_key(id: &Value) -> String {
+    match id {
+        Value::String(id) => id.clone(),
+        id => id.to_string(),
+    }
+}
+
+fn success_response(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error_response(id: &Value, code: i64, error: impl std::fmt::Display) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": error.to_string() },
+    })
+}
+
+// One message per line. A client that went away only stops receiving them.
+fn send(writer: &Mutex<UnixStream>, message: &Value) {
+    let mut writer = writer.lock().unwrap();
+    let _ = writeln!(writer, "{message}");
+}
+
+/// Lists the tests once and keeps them in memory, lists them again when their executables change,
+/// and answers the requests of clients on a unix socket, in line-delimited JSON-RPC 2.0:
+///
+/// - `list`, with optional `tests` patterns and a `filter` regex, returns the tests like the list
+///   subcommand
+/// - `run`, with the same params and `fail_fast` and `group_by_executable`, sends a `result`
+///   notification per test as it finishes, then returns the counts of the statuses
+/// - `cancel`, with the id of the `run` request as `run`, stops starting the tests of the run
+/// - `refresh` lists the tests again, and returns their number
+pub fn serve(command: &ServeCommand, discover: &Discover) -> Result<()> {
+    let (tests, workspace) = discover()?;
+
+    let socket = match &command.socket {
+        Some(socket) => socket.clone(),
+        None => WorkspaceCache::for_workspace(&workspace)
+            .ok_or_else(|| anyhow!("There is no cache directory for the socket, use --socket"))?
+            .server_socket(),
+    };
+    if socket.exists() {
+        if UnixStream::connect(&socket).is_ok() {
+            bail!("A server is already listening on {}", socket.display());
+        }
+        // Left by a server that didn't stop cleanly
+        fs::remove_file(&socket)?;
+    }
+    let listener = UnixListener::bind(&socket)?;
+    eprintln!("Serving {} tests on {}", tests.len(), socket.display());
+
+    let server = Server {
+        discover,
+        discovery: RwLock::new(Discovery::new(tests)),
+        runs: Mutex::new(HashMap::new()),
+    };
+    let rescan_interval =
+        Some(Duration::from_secs(command.rescan_interval)).filter(|interval| !interval.is_zero());
+
+    thread::scope(|scope| {
+        scope.spawn(|| server.watch(rescan_interval));
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = &server;
+                    scope.spawn(move || server.handle_connection(stream));
+                }
+                Err(error) => eprintln!("Failed to accept a connection: {error}"),
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use crate::{
+    test_tokens::tokenize,
+    types::{Test, TestStatus},
+};
+use skim::{AnsiString, DisplayContext, ItemPreview, Matches, PreviewPosition, SkimItem};
+use std::{
+    borrow::Cow,
+    io::{BufRead, Cursor},
+    sync::LazyLock,
+};
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    parsing::SyntaxSet,
+    util::as_24_bit_terminal_escaped,
+};
+
+static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    let theme = include_str!("Catppuccin Macchiato.tmTheme");
+    let mut reader = Cursor::new(theme);
+    ThemeSet::load_from_reader(&mut reader).unwrap()
+});
+
+impl SkimItem for Test {
+    // The name followed by its words, so that skim also matches the words, like `times out` in
+    // `RequestTimesOutAfterDeadline`
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Owned(format!("{} {}", self.name, tokenize(&self.name).join(" ")))
+    }
+
+    // Only the name, with the matches in the words left out
+    fn display<'a>(&'a self, context: DisplayContext<'a>) -> AnsiString<'a> {
+        let name_length = self.name.chars().count();
+        let mut ranges = match context.matches {
+            Matches::CharIndices(indices) => {
+                indices.iter().map(|&index| (index, index + 1)).collect()
+            }
+            Matches::CharRange(start, end) => vec![(start, end)],
+            Matches::ByteRange(start, end) => {
+                let start_char = context.text[..start].chars().count();
+                vec![(
+                    start_char,
+                    start_char + context.text[start..end].chars().count(),
+                )]
+            }
+            Matches::None => Vec::new(),
+        };
+        ranges.retain(|(start, _)| *start < name_length);
+        let fragments = ranges
+            .into_iter()
+            .map(|(start, end)| {
+                (
+                    context.highlight_attr,
+                    (start as u32, end.min(name_length) as u32),
+                )
+            })
+            .collect();
+        let name = AnsiString::new_str(&self.name, fragments);
+
+        let Some(status) = self.last_status else {
+            return name;
+        };
+
+        let (marker, color) = match status {
+            TestStatus::Passed => ("[PASSED] ", tuikit::prelude::Color::GREEN),
+            TestStatus::Failed => ("[FAILED] ", tuikit::prelude::Color::RED),
+            TestStatus::Skipped => ("[SKIPPED] ", tuikit::prelude::Color::YELLOW),
+            TestStatus::NotRunnable => ("[NOT_RUNNABLE] ", tuikit::prelude::Color::RED),
+        };
+        let attr = tuikit::prelude::Attr::default().fg(color);
+
+        AnsiString::new_str(marker, vec![(attr, (0, marker.len() as u32))]) + name
+    }
+
+    fn get_index(&self) -> usize {
+        self.index.unwrap_or_default()
+    }
+
+    fn set_index(&mut self, index: usize) {
+        self.index = Some(index);
+    }
+
+    fn preview(&self, _context: skim::prelude::PreviewContext) -> ItemPreview {
+        if let Some(file) = &self.file {
+            let mut highlighter =
+                syntect::easy::HighlightFile::new(file, &SYNTAX_SET, &THEME).unwrap();
+
+            let mut content = String::default();
+            let mut line = String::default();
+            while highlighter.reader.read_line(&mut line).unwrap_or(0) > 0 {
+                let regions: Vec<_> = highlighter
+                    .highlight_lines
+                    .highlight_line(&line, &SYNTAX_SET)
+                    .unwrap();
+
+                content.push_str(&as_24_bit_terminal_escaped(&regions[..], false));
+                line.clear();
+            }
+
+            ItemPreview::AnsiWithPos(
+                content,
+                PreviewPosition {
+                    v_scroll: tuikit::prelude::Size::Fixed(self.line.unwrap_or(0) as usize),
+                    ..Default::default()
+                },
+            )
+        } else {
+            ItemPreview::Global
+        }
+    }
+}
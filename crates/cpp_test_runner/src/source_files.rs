@@ -0,0 +1,184 @@
+use crate::{compile_commands::CompilationDatabase, types::Test};
+use ignore::WalkBuilder;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    thread::{self, JoinHandle},
+};
+
+pub const SOURCE_EXTENSIONS: &[&str] = &["cpp", "cc", "cxx", "c++", "h", "hh", "hpp", "hxx", "ipp"];
+
+/// The root of the repository containing the directory, or the directory itself
+pub fn workspace_root(dir: &Path) -> &Path {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .unwrap_or(dir)
+}
+
+type FilesByName = HashMap<OsString, Vec<PathBuf>>;
+
+/// The source files under a directory, by file name, which resolves the relative paths reported by
+/// the executables without walking up the directories of every test. It is built in the
+/// background while the executables are searched and listed, and skips the files ignored by
+/// .gitignore. A compilation database, when given, resolves the files before the index.
+pub struct SourceFileIndex {
+    builder: Mutex<Option<JoinHandle<FilesByName>>>,
+    files: OnceLock<FilesByName>,
+    compilation_database: Option<CompilationDatabase>,
+}
+
+impl SourceFileIndex {
+    pub fn build_in_background(root: PathBuf) -> Self {
+        let builder = thread::spawn(move || {
+            let mut files = FilesByName::new();
+            let sources = WalkBuilder::new(root)
+                .build()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    entry.path().extension().is_some_and(|extension| {
+                        SOURCE_EXTENSIONS.contains(&extension.to_string_lossy().as_ref())
+                    })
+                });
+            for source in sources {
+                if let Some(file_name) = source.path().file_name() {
+                    files
+                        .entry(file_name.to_owned())
+                        .or_default()
+                        .push(source.path().to_path_buf());
+                }
+            }
+            files
+        });
+
+        Self {
+            builder: Mutex::new(Some(builder)),
+            files: OnceLock::new(),
+            compilation_database: None,
+        }
+    }
+
+    /// An index without files, for the executables given on the command line, which only
+    /// resolves with its compilation database
+    pub fn empty() -> Self {
+        Self {
+            builder: Mutex::new(None),
+            files: OnceLock::new(),
+            compilation_database: None,
+        }
+    }
+
+    pub fn with_compilation_database(mut self, database: CompilationDatabase) -> Self {
+        self.compilation_database = Some(database);
+        self
+    }
+
+    fn files(&self) -> &FilesByName {
+        self.files.get_or_init(|| {
+            self.builder
+                .lock()
+                .unwrap()
+                .take()
+                .and_then(|builder| builder.join().ok())
+                .unwrap_or_default()
+        })
+    }
+
+    fn candidates<'a>(&'a self, to_find: &'a Path) -> impl Iterator<Item = &'a PathBuf> {
+        to_find
+            .file_name()
+            .and_then(|file_name| self.files().get(file_name))
+            .into_iter()
+            .flatten()
+            .filter(move |file| file.ends_with(to_find))
+    }
+
+    /// Finds `to_find` in `search_start` or in its closest parent, like `find_file`
+    pub fn find_in_parents(&self, search_start: &Path, to_find: &Path) -> Option<PathBuf> {
+        self.candidates(to_find)
+            .filter(|file| {
+                file.ancestors()
+                    .nth(to_find.components().count())
+                    .is_some_and(|dir| search_start.starts_with(dir))
+            })
+            .max_by_key(|file| file.components().count())
+            .and_then(|file| file.canonicalize().ok())
+    }
+
+    /// Finds the file ending with `to_find` that shares the most parents with `search_start`
+    pub fn find_closest(&self, search_start: &Path, to_find: &Path) -> Option<PathBuf> {
+        self.candidates(to_find)
+            .max_by_key(|file| {
+                file.components()
+                    .zip(search_start.components())
+                    .take_while(|(file, search_start)| file == search_start)
+                    .count()
+            })
+            .and_then(|file| file.canonicalize().ok())
+    }
+}
+
+/// Finds a file by looking in `search_start` and in its parents
+pub fn find_file(search_start: &Path, to_find: &Path) -> Option<PathBuf> {
+    let file = if to_find.is_absolute() {
+        Some(Cow::Borrowed(to_find))
+    } else {
+        let mut current_dir = search_start;
+        loop {
+            let absolute_file_path = current_dir.join(to_find);
+            if absolute_file_path.exists() && absolute_file_path.is_file() {
+                break Some(Cow::Owned(absolute_file_path));
+            }
+
+            let Some(parent_dir) = current_dir.parent() else {
+                break None;
+            };
+
+            current_dir = parent_dir;
+        }
+    };
+
+    file.and_then(|file| file.canonicalize().ok())
+}
+
+/// Resolves the source files of the tests, which the executables report relative to the directory
+/// they were compiled from. Like before the index, the file closest to the executable in its
+/// parents wins, and the files that aren't in its parents are only used when there is none. The
+/// compilation database of the index knows the directories the files were compiled from, so it
+/// resolves them first, and gives the tests how their files were compiled.
+pub fn resolve_source_files(tests: &mut [Test], index: Option<&SourceFileIndex>) {
+    let database = index.and_then(|index| index.compilation_database.as_ref());
+    let mut resolved = HashMap::<(PathBuf, PathBuf), Option<PathBuf>>::new();
+    for test in tests {
+        let Some(file) = test.file.take() else {
+            continue;
+        };
+
+        let search_start = test
+            .executable
+            .path
+            .parent()
+            .unwrap_or(&test.executable.path);
+        test.file = resolved
+            .entry((search_start.to_path_buf(), file))
+            .or_insert_with_key(|(search_start, file)| {
+                if file.is_absolute() {
+                    return file.canonicalize().ok();
+                }
+                if let Some(file) = database.and_then(|database| database.resolve(file)) {
+                    return Some(file);
+                }
+                // The ignored directories aren't indexed, so the parents may still have the file
+                index
+                    .and_then(|index| index.find_in_parents(search_start, file))
+                    .or_else(|| find_file(search_start, file))
+                    .or_else(|| index.and_then(|index| index.find_closest(search_start, file)))
+            })
+            .clone();
+        test.compile_command = database
+            .zip(test.file.as_ref())
+            .and_then(|(database, file)| database.command(file));
+    }
+}
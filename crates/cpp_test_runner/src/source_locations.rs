@@ -0,0 +1,242 @@
+use cpp_test_runner::{
+    diagnostics::{self, Diagnostic},
+    source_files::{workspace_root, SOURCE_EXTENSIONS},
+    types::{ExecutableType, Test},
+};
+use elf_parser::{Elf, Section};
+use ignore::WalkBuilder;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Where the tests are defined in the sources of a workspace, keyed by `gtest_key` or `catch2_key`
+struct SourceIndex {
+    locations: HashMap<String, (PathBuf, u32)>,
+}
+
+// TEST(Suite, Name), TEST_F, TEST_P, TYPED_TEST and TYPED_TEST_P
+fn gtest_key(suite: &str, name: &str) -> String {
+    format!("gtest:{suite}.{name}")
+}
+
+fn catch2_key(name: &str) -> String {
+    format!("catch2:{name}")
+}
+
+impl SourceIndex {
+    fn build(root: &Path) -> Self {
+        let gtest_macro =
+            Regex::new(r"\b(?:TYPED_)?TEST(?:_F|_P)?\s*\(\s*(\w+)\s*,\s*(\w+)\s*\)").unwrap();
+        let catch2_macro = Regex::new(
+            r#"\b(TEST_CASE|TEST_CASE_METHOD|SCENARIO)\s*\(\s*(?:\w+\s*,\s*)?"((?:[^"\\]|\\.)*)""#,
+        )
+        .unwrap();
+
+        let mut locations = HashMap::new();
+        let sources = WalkBuilder::new(root)
+            .build()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry.path().extension().is_some_and(|extension| {
+                    SOURCE_EXTENSIONS.contains(&extension.to_string_lossy().as_ref())
+                })
+            });
+
+        for source in sources {
+            let Ok(content) = fs::read_to_string(source.path()) else {
+                continue;
+            };
+
+            for (index, line) in content.lines().enumerate() {
+                let key = if let Some(captures) = gtest_macro.captures(line) {
+                    gtest_key(&captures[1], &captures[2])
+                } else if let Some(captures) = catch2_macro.captures(line) {
+                    let name = captures[2].replace("\\\"", "\"").replace("\\\\", "\\");
+                    match &captures[1] {
+                        "SCENARIO" => catch2_key(&format!("Scenario: {name}")),
+                        _ => catch2_key(&name),
+                    }
+                } else {
+                    continue;
+                };
+
+                locations
+                    .entry(key)
+                    .or_insert_with(|| (source.path().to_path_buf(), index as u32 + 1));
+            }
+        }
+
+        Self { locations }
+    }
+
+    fn find(&self, test: &Test) -> Option<&(PathBuf, u32)> {
+        match test.executable.executable_type {
+            ExecutableType::Catch2 => self.locations.get(&catch2_key(&test.name)),
+            ExecutableType::Gtest => {
+                let (suite, name) = gtest_suite_and_name(&test.name)?;
+                // Parameterized suites are named like Prefix/Suite or Suite/0
+                suite
+                    .split('/')
+                    .find_map(|suite| self.locations.get(&gtest_key(suite, name)))
+            }
+        }
+    }
+}
+
+// The suite and the name of a gtest test, without the index of its parameter
+fn gtest_suite_and_name(test_name: &str) -> Option<(&str, &str)> {
+    let (suite, name) = test_name.split_once('.')?;
+    let name = name.split('/').next().unwrap_or(name);
+    Some((suite, name))
+}
+
+/// Finds the source location of the gtest tests in the debug info of their executable, with
+/// addr2line. The TestBody of TEST(Suite, Name) is a method of the class Suite_Name_Test.
+fn locations_from_debug_info(
+    executable: &Path,
+    tests: &[&Test],
+) -> HashMap<String, (PathBuf, u32)> {
+    let mut locations = HashMap::new();
+
+    let Ok(elf) = Elf::new(executable) else {
+        return locations;
+    };
+    let Ok(headers) = elf.get_all_section_headers() else {
+        return locations;
+    };
+    let Some(symbol_table_header) = headers.find_symbol_table_header() else {
+        return locations;
+    };
+    let Some(string_table_header) = headers.find_linked_header(symbol_table_header) else {
+        return locations;
+    };
+    let (Ok(Section::Symbols(symbols)), Ok(Section::Strings(strings))) = (
+        elf.get_section(symbol_table_header),
+        elf.get_section(string_table_header),
+    ) else {
+        return locations;
+    };
+
+    let mangled_names = tests
+        .iter()
+        .filter_map(|test| {
+            let (suite, name) = gtest_suite_and_name(&test.name)?;
+            let suite = suite
+                .rsplit('/')
+                .find(|suite| !suite.chars().all(|c| c.is_ascii_digit()))?;
+            let class = format!("{suite}_{name}_Test");
+            Some((
+                format!("{}{class}8TestBodyEv", class.len()),
+                test.name.clone(),
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    let addresses = symbols
+        .iter()
+        .filter(|symbol| symbol.st_value != 0)
+        .filter_map(|symbol| {
+            let symbol_name = strings.get_symbol_name(symbol)?.to_str().ok()?;
+            let (_, test_name) = mangled_names
+                .iter()
+                .find(|(mangled_name, _)| symbol_name.ends_with(mangled_name.as_str()))?;
+            Some((format!("{:#x}", symbol.st_value), test_name.clone()))
+        })
+        .collect::<Vec<_>>();
+    if addresses.is_empty() {
+        return locations;
+    }
+
+    let Ok(output) = Command::new("addr2line")
+        .arg("-e")
+        .arg(executable)
+        .args(addresses.iter().map(|(address, _)| address))
+        .output()
+    else {
+        return locations;
+    };
+
+    // One file:line per address, or ??:0 without debug info
+    for ((_, test_name), line) in addresses
+        .into_iter()
+        .zip(String::from_utf8_lossy(&output.stdout).lines())
+    {
+        let line = line.split(" (discriminator").next().unwrap_or(line);
+        let Some((file, line_number)) = line.rsplit_once(':') else {
+            continue;
+        };
+        let file = Path::new(file);
+        if let (true, Ok(line_number)) = (file.is_file(), line_number.parse()) {
+            locations.insert(test_name, (file.to_path_buf(), line_number));
+        }
+    }
+
+    locations
+}
+
+/// Fills in the source file and line of the tests that don't have one, from the debug info of the
+/// gtest executables, and otherwise from the test macros in the sources of the repository
+/// containing `workspace`.
+pub fn backfill_locations(tests: &mut [Test], workspace: &Path) {
+    let mut tests_by_executable: HashMap<&Path, Vec<&Test>> = HashMap::new();
+    for test in tests.iter().filter(|test| test.file.is_none()) {
+        if test.executable.executable_type == ExecutableType::Gtest {
+            tests_by_executable
+                .entry(&test.executable.path)
+                .or_default()
+                .push(test);
+        }
+    }
+    let debug_info_locations = tests_by_executable
+        .into_iter()
+        .flat_map(|(executable, tests)| locations_from_debug_info(executable, &tests))
+        .collect::<HashMap<_, _>>();
+
+    let mut source_index = None;
+    for test in tests.iter_mut().filter(|test| test.file.is_none()) {
+        let location = match debug_info_locations.get(&test.name) {
+            Some(location) => Some(location),
+            None => source_index
+                .get_or_insert_with(|| SourceIndex::build(workspace_root(workspace)))
+                .find(test),
+        };
+
+        if let Some((file, line)) = location {
+            test.file = Some(file.clone());
+            test.line = Some(*line);
+        }
+    }
+}
+
+/// Warns about the tests without a source file, which can't be previewed, run from a working
+/// directory relative to their file or opened in an editor
+pub fn warn_about_missing_locations(tests: &[Test], backfilled: bool) {
+    let missing = tests
+        .iter()
+        .filter(|test| test.file.is_none())
+        .map(|test| test.name.as_str())
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return;
+    }
+
+    let diagnostic = Diagnostic::warning(
+        diagnostics::MISSING_LOCATIONS,
+        format!(
+            "the source file of {} tests is unknown, so they can't be previewed, use {{src_dir}} \
+             or be opened in an editor:",
+            missing.len()
+        ),
+    )
+    .details(missing);
+    diagnostics::report(if backfilled {
+        diagnostic
+    } else {
+        diagnostic
+            .hint("Use --backfill-locations to search for them in the debug info and the sources.")
+    });
+}
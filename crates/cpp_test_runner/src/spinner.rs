@@ -0,0 +1,77 @@
+use cpp_test_runner::{
+    discovery_progress::{EXECUTABLES_FOUND, EXECUTABLES_LISTED, TESTS_LISTED},
+    glyphs::Glyphs,
+};
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+// Fast discoveries finish before the spinner shows up, instead of flickering
+const DELAY: Duration = Duration::from_millis(300);
+const FRAME_DURATION: Duration = Duration::from_millis(80);
+
+/// Shows the progress of the discovery on stderr until it is dropped.
+pub struct DiscoverySpinner {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DiscoverySpinner {
+    pub fn start(glyphs: Glyphs) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                thread::park_timeout(DELAY);
+                let mut has_drawn = false;
+                for frame in glyphs.spinner_frames().iter().cycle() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    let executables_found = EXECUTABLES_FOUND.load(Ordering::Relaxed);
+                    let executables_listed = EXECUTABLES_LISTED.load(Ordering::Relaxed);
+                    let tests_listed = TESTS_LISTED.load(Ordering::Relaxed);
+                    let status = if executables_listed == 0 {
+                        format!("Searching for test executables: {executables_found} found")
+                    } else {
+                        format!(
+                            "Listing tests: {executables_listed} executables listed, \
+                             {tests_listed} tests found"
+                        )
+                    };
+                    eprint!("\r\x1b[2K{frame} {status}");
+                    let _ = io::stderr().flush();
+                    has_drawn = true;
+
+                    thread::park_timeout(FRAME_DURATION);
+                }
+
+                if has_drawn {
+                    eprint!("\r\x1b[2K");
+                }
+            }
+        });
+
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for DiscoverySpinner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
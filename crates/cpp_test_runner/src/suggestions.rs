@@ -0,0 +1,40 @@
+use crate::types::Test;
+
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Returns the names of the tests that are the closest to `query`, from the closest to the
+/// furthest. Names containing the query are always considered the closest.
+pub fn closest_test_names<'a>(query: &str, tests: &'a [Test]) -> Vec<&'a str> {
+    let query = query.to_lowercase();
+
+    let mut scored_names = tests
+        .iter()
+        .map(|test| {
+            let name = test.name.to_lowercase();
+            let score = if name.contains(&query) {
+                0.0
+            } else {
+                // Also compare with the name of the test without its suite, since a typo
+                // in a test name would be drowned out by the length of the suite name
+                let short_name = name.rsplit_once('.').map(|(_, short)| short);
+                let similarity = short_name
+                    .map(|short_name| strsim::normalized_levenshtein(&query, short_name))
+                    .unwrap_or_default()
+                    .max(strsim::normalized_levenshtein(&query, &name));
+                1.0 - similarity
+            };
+            (score, test.name.as_str())
+        })
+        .filter(|(score, _)| *score < 0.6)
+        .collect::<Vec<_>>();
+
+    scored_names.sort_by(|(lhs, _), (rhs, _)| lhs.total_cmp(rhs));
+
+    let mut names = scored_names
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect::<Vec<_>>();
+    names.dedup();
+    names.truncate(MAX_SUGGESTIONS);
+    names
+}
@@ -0,0 +1,86 @@
+use anyhow::{bail, Error, Result};
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+struct TagTerm {
+    tag: String,
+    negated: bool,
+}
+
+/// A Catch2 tag expression, like `[integration]~[slow],[smoke]`. Consecutive tags must all
+/// match, `~` negates a tag and `,` separates alternatives. Tags are compared case-insensitively
+/// like Catch2 does.
+#[derive(Debug, Clone)]
+pub struct TagExpression {
+    alternatives: Vec<Vec<TagTerm>>,
+}
+
+impl FromStr for TagExpression {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        let mut alternatives = Vec::new();
+        let mut terms = Vec::new();
+        let mut negated = false;
+        let mut chars = expression.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '[' => {
+                    let mut tag = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        match c {
+                            ']' => {
+                                closed = true;
+                                break;
+                            }
+                            // The next tag starts before this one ends
+                            '[' => break,
+                            c => tag.push(c),
+                        }
+                    }
+                    if !closed {
+                        bail!("Unclosed tag in the tag expression {expression}");
+                    }
+                    if tag.is_empty() {
+                        bail!("Empty tag in the tag expression {expression}");
+                    }
+                    terms.push(TagTerm {
+                        tag: tag.to_lowercase(),
+                        negated,
+                    });
+                    negated = false;
+                }
+                '~' => negated = !negated,
+                ',' => {
+                    if terms.is_empty() {
+                        bail!("Empty alternative in the tag expression {expression}");
+                    }
+                    alternatives.push(std::mem::take(&mut terms));
+                }
+                c if c.is_whitespace() => {}
+                c => bail!("Unexpected '{c}' in the tag expression {expression}, expected a tag like [integration]"),
+            }
+        }
+
+        if negated || terms.is_empty() {
+            bail!("The tag expression {expression} must end with a tag");
+        }
+        alternatives.push(terms);
+
+        Ok(Self { alternatives })
+    }
+}
+
+impl TagExpression {
+    /// Tests without tags, like gtest tests, only match expressions made of negated tags
+    pub fn matches(&self, tags: &[String]) -> bool {
+        self.alternatives.iter().any(|terms| {
+            terms.iter().all(|term| {
+                let has_tag = tags.iter().any(|tag| tag.to_lowercase() == term.tag);
+                has_tag != term.negated
+            })
+        })
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{
+    test_pattern::TestPattern,
+    types::{Test, TestAttributes},
+};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+pub const ATTRIBUTES_FILE_NAME: &str = "tests.attributes.json";
+
+#[derive(Debug, Deserialize)]
+struct AttributeRule {
+    /// A test name or a glob pattern, like "Network.*"
+    tests: String,
+    #[serde(flatten)]
+    attributes: TestAttributes,
+}
+
+/// The attributes of the tests matching some patterns, like:
+///
+/// ```json
+/// [
+///     { "tests": "Network.*", "owner": "network-team", "labels": ["integration"] },
+///     { "tests": "Network.Reconnects", "timeout": 30, "serial": true, "xfail": true }
+/// ]
+/// ```
+///
+/// When several rules match a test, they are merged in the order of the file.
+pub struct AttributeRules {
+    rules: Vec<(TestPattern, TestAttributes)>,
+}
+
+impl AttributeRules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let rules: Vec<AttributeRule> = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|error| anyhow!("Failed to parse {}: {error}", path.display()))?;
+
+        Ok(Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| Ok((TestPattern::from_str(&rule.tests)?, rule.attributes)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Looks for the attributes file in the test directory, then in its parent directory
+    pub fn find(test_dir: &Path) -> Option<PathBuf> {
+        test_dir
+            .ancestors()
+            .take(2)
+            .map(|dir| dir.join(ATTRIBUTES_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    pub fn apply(&self, tests: &mut [Test]) {
+        for test in tests {
+            for (pattern, attributes) in &self.rules {
+                if pattern.is_match(&test.name) {
+                    test.attributes.merge(attributes);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{
+    cache_dirs::WorkspaceCache,
+    discovery_cache::DiscoveryCache,
+    executable_finder::{find_test_executables, validate_executables, WalkOptions},
+    executable_rules::ExecutableRules,
+    installed_tree::set_library_paths,
+    source_files::{workspace_root, SourceFileIndex},
+    test_attributes::AttributeRules,
+    test_labels::label_test_kinds,
+    test_parser::{get_tests_from_executables, NameFilter},
+    test_tokens::TokenQuery,
+    types::{ExecutableType, Test},
+};
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use regex::Regex;
+use std::{path::PathBuf, time::SystemTime};
+
+#[derive(Debug, Clone)]
+enum Source {
+    Dir(PathBuf),
+    InstalledTree(PathBuf),
+    Executables(Vec<PathBuf>),
+}
+
+/// Finds the tests of a build tree, an installed tree or some executables, like the list
+/// subcommand does, with the tests.attributes.json of the tree and the unit and integration
+/// labels applied:
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// use cpp_test_runner::TestDiscovery;
+///
+/// let tests = TestDiscovery::in_dir("build")
+///     .filter(regex::Regex::new("^Parser")?)
+///     .discover()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TestDiscovery {
+    source: Source,
+    executable_types: Vec<ExecutableType>,
+    jobs: Option<usize>,
+    walk_options: WalkOptions,
+    filters: Vec<Regex>,
+    excludes: Vec<Regex>,
+    fuzzy: Option<TokenQuery>,
+    include_hidden: bool,
+    include_disabled: bool,
+    gtest_extra_args: Vec<String>,
+    catch2_extra_args: Vec<String>,
+    use_cache: bool,
+}
+
+impl TestDiscovery {
+    fn new(source: Source) -> Self {
+        Self {
+            source,
+            executable_types: ExecutableType::value_variants().to_vec(),
+            jobs: None,
+            walk_options: WalkOptions::default(),
+            filters: Vec::new(),
+            excludes: Vec::new(),
+            fuzzy: None,
+            include_hidden: false,
+            include_disabled: true,
+            gtest_extra_args: Vec::new(),
+            catch2_extra_args: Vec::new(),
+            use_cache: true,
+        }
+    }
+
+    /// Searches the directory for test executables, like --test-dir, without looking in its
+    /// parents
+    pub fn in_dir(dir: impl Into<PathBuf>) -> Self {
+        Self::new(Source::Dir(dir.into()))
+    }
+
+    /// Searches a tree where the tests were installed, like --system-scan
+    pub fn in_installed_tree(prefix: impl Into<PathBuf>) -> Self {
+        Self::new(Source::InstalledTree(prefix.into()))
+    }
+
+    /// Lists the tests of these executables, like --executables, which fails if one of them
+    /// isn't a test executable
+    pub fn of_executables(executables: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        Self::new(Source::Executables(
+            executables.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    /// Only searches for these frameworks. [default: all of them]
+    pub fn executable_types(mut self, executable_types: &[ExecutableType]) -> Self {
+        self.executable_types = executable_types.to_vec();
+        self
+    }
+
+    /// The number of threads of the search for the executables. [default: the number of CPUs]
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Only inspects the files under the directory matching this glob, or any of the globs when
+    /// it's called several times, like --include-path
+    pub fn include_path(mut self, glob: &str) -> Self {
+        self.walk_options.include.push(glob.to_string());
+        self
+    }
+
+    /// Doesn't search the files and directories matching this glob, like --exclude-path
+    pub fn exclude_path(mut self, glob: &str) -> Self {
+        self.walk_options.exclude.push(glob.to_string());
+        self
+    }
+
+    /// Skips the paths ignored by the .gitignore files, like --use-gitignore. The .testignore
+    /// files are always used. [default: false]
+    pub fn use_gitignore(mut self, use_gitignore: bool) -> Self {
+        self.walk_options.use_gitignore = use_gitignore;
+        self
+    }
+
+    /// Only inspects the files up to this depth under the directory, where 1 is the files in the
+    /// directory itself, like --max-depth
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.walk_options.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Follows the symlinks of the directory, like --follow-symlinks. [default: false]
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.walk_options.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Only inspects the files modified since then, like --newer-than
+    pub fn newer_than(mut self, newer_than: SystemTime) -> Self {
+        self.walk_options.newer_than = Some(newer_than);
+        self
+    }
+
+    /// Only keeps the tests whose name matches this regex, or any of the regexes when it's called
+    /// several times, like --filter
+    pub fn filter(mut self, filter: Regex) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Leaves out the tests whose name matches this regex, like --exclude
+    pub fn exclude(mut self, exclude: Regex) -> Self {
+        self.excludes.push(exclude);
+        self
+    }
+
+    /// Only keeps the tests whose name has all the words of the query, like --fuzzy
+    pub fn fuzzy(mut self, query: &str) -> Self {
+        self.fuzzy = Some(TokenQuery::new(query));
+        self
+    }
+
+    /// Also lists the hidden Catch2 tests, tagged with [.] or [!hide]
+    pub fn include_hidden(mut self, include_hidden: bool) -> Self {
+        self.include_hidden = include_hidden;
+        self
+    }
+
+    /// Lists the disabled gtest tests, whose suite or name starts with DISABLED_. [default: true]
+    pub fn include_disabled(mut self, include_disabled: bool) -> Self {
+        self.include_disabled = include_disabled;
+        self
+    }
+
+    /// Arguments passed to the gtest executables, when listing and running their tests
+    pub fn gtest_extra_args(mut self, args: &[String]) -> Self {
+        self.gtest_extra_args = args.to_vec();
+        self
+    }
+
+    /// Arguments passed to the Catch2 executables, when listing and running their tests
+    pub fn catch2_extra_args(mut self, args: &[String]) -> Self {
+        self.catch2_extra_args = args.to_vec();
+        self
+    }
+
+    /// Uses the cache of the tests listed by every executable, shared with the command line.
+    /// [default: true]
+    pub fn use_cache(mut self, use_cache: bool) -> Self {
+        self.use_cache = use_cache;
+        self
+    }
+
+    /// The directory that was searched, or the current directory for executables, which is the
+    /// workspace of the cache and where tests.attributes.json is looked for
+    pub fn workspace(&self) -> Result<PathBuf> {
+        match &self.source {
+            Source::Dir(dir) | Source::InstalledTree(dir) => {
+                if !dir.is_dir() {
+                    bail!("{} is not a directory", dir.display());
+                }
+                Ok(dir.canonicalize()?)
+            }
+            Source::Executables(_) => Ok(std::env::current_dir()?),
+        }
+    }
+
+    pub fn discover(&self) -> Result<Vec<Test>> {
+        let workspace = self.workspace()?;
+        let (mut executables, source_files) = match &self.source {
+            Source::Dir(_) => (
+                find_test_executables(
+                    &workspace,
+                    self.jobs,
+                    &self.executable_types,
+                    false,
+                    &self.walk_options,
+                    None,
+                )?,
+                Some(SourceFileIndex::build_in_background(
+                    workspace_root(&workspace).to_path_buf(),
+                )),
+            ),
+            Source::InstalledTree(_) => {
+                let mut executables = find_test_executables(
+                    &workspace,
+                    self.jobs,
+                    &self.executable_types,
+                    true,
+                    &self.walk_options,
+                    None,
+                )?;
+                set_library_paths(&mut executables, &workspace);
+                (
+                    executables,
+                    Some(SourceFileIndex::build_in_background(workspace.clone())),
+                )
+            }
+            Source::Executables(executables) => (validate_executables(executables)?, None),
+        };
+        if let Some(rules_file) = ExecutableRules::find(&workspace) {
+            ExecutableRules::load(&rules_file)?.apply(&mut executables);
+        }
+
+        let cache = self
+            .use_cache
+            .then(|| WorkspaceCache::for_workspace(&workspace))
+            .flatten()
+            .map(|cache| DiscoveryCache::new(cache.discovery_dir()));
+        let mut tests = get_tests_from_executables(
+            &executables,
+            false,
+            &self.gtest_extra_args,
+            &self.catch2_extra_args,
+            NameFilter {
+                filters: &self.filters,
+                excludes: &self.excludes,
+                fuzzy: self.fuzzy.as_ref(),
+            },
+            self.include_hidden,
+            self.include_disabled,
+            cache.as_ref(),
+            source_files.as_ref(),
+            None,
+        );
+
+        if let Some(attributes_file) = AttributeRules::find(&workspace) {
+            AttributeRules::load(&attributes_file)?.apply(&mut tests);
+        }
+        label_test_kinds(&mut tests);
+        Ok(tests)
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    diagnostics::{self, Diagnostic},
+    test_results::TestResult,
+    types::TestStatus,
+};
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const GATES_FILE_NAME: &str = "tests.gates.json";
+
+#[derive(Debug, Deserialize)]
+struct Gate {
+    name: String,
+    /// The tests with any of these labels must pass
+    labels: Vec<String>,
+    exit_code: i32,
+}
+
+/// Gates that the tests with some labels must pass, evaluated once all the tests ran, like:
+///
+/// ```json
+/// [
+///     { "name": "smoke", "labels": ["smoke"], "exit_code": 2 },
+///     { "name": "integration", "labels": ["integration"], "exit_code": 3 }
+/// ]
+/// ```
+///
+/// The exit code is the one of the first gate that failed in the order of the file. The failures
+/// of the tests outside of the gates are only reported as warnings.
+pub struct Gates {
+    gates: Vec<Gate>,
+}
+
+impl Gates {
+    pub fn load(path: &Path) -> Result<Self> {
+        let gates: Vec<Gate> = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|error| anyhow!("Failed to parse {}: {error}", path.display()))?;
+
+        if let Some(gate) = gates.iter().find(|gate| gate.exit_code == 0) {
+            bail!(
+                "The gate {} of {} must have a non-zero exit code",
+                gate.name,
+                path.display()
+            );
+        }
+        Ok(Self { gates })
+    }
+
+    /// Looks for the gates file in the test directory, then in its parent directory
+    pub fn find(test_dir: &Path) -> Option<PathBuf> {
+        test_dir
+            .ancestors()
+            .take(2)
+            .map(|dir| dir.join(GATES_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    /// Prints the gates that failed and the failures outside of the gates, and returns the exit
+    /// code. The tests that weren't run, e.g. because of --fail-fast, didn't pass their gate.
+    pub fn evaluate(&self, results: &[TestResult]) -> i32 {
+        let passed = |result: &TestResult| {
+            matches!(
+                result.status,
+                Some(TestStatus::Passed | TestStatus::Skipped)
+            )
+        };
+        let in_gate = |result: &TestResult, gate: &Gate| {
+            result
+                .test
+                .attributes
+                .labels
+                .iter()
+                .any(|label| gate.labels.contains(label))
+        };
+
+        let mut exit_code = 0;
+        for gate in &self.gates {
+            let failed = results
+                .iter()
+                .filter(|result| in_gate(result, gate) && !passed(result))
+                .map(|result| result.test.display_name())
+                .collect::<Vec<_>>();
+            if failed.is_empty() {
+                continue;
+            }
+
+            eprintln!(
+                "Gate {} failed, {} of its tests didn't pass:",
+                gate.name,
+                failed.len()
+            );
+            for name in &failed {
+                eprintln!("    {name}");
+            }
+            if exit_code == 0 {
+                exit_code = gate.exit_code;
+            }
+        }
+
+        let num_failed_outside = results
+            .iter()
+            .filter(|result| result.status.is_some_and(|status| status.is_failure()))
+            .filter(|result| !self.gates.iter().any(|gate| in_gate(result, gate)))
+            .count();
+        if num_failed_outside > 0 {
+            diagnostics::report(Diagnostic::warning(
+                diagnostics::FAILED_OUTSIDE_GATES,
+                format!(
+                    "{num_failed_outside} tests failed outside of the gates of {GATES_FILE_NAME}"
+                ),
+            ));
+        }
+
+        exit_code
+    }
+}
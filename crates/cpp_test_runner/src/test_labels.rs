@@ -0,0 +1,105 @@
+use crate::types::Test;
+use elf_parser::Elf;
+use std::{collections::HashMap, path::Path};
+
+pub const UNIT_LABEL: &str = "unit";
+pub const INTEGRATION_LABEL: &str = "integration";
+
+// Tests using these libraries most likely talk to a real service
+const INTEGRATION_LIBRARIES: &[&str] = &[
+    "libpq",
+    "libgrpc",
+    "libmysqlclient",
+    "libmariadb",
+    "libmongoc",
+    "librdkafka",
+    "libcurl",
+    "libhiredis",
+    "librabbitmq",
+    "libzmq",
+    "libcassandra",
+    "libaws-cpp-sdk",
+];
+
+// Words like integration_tests, IntegrationTest, e2e, unit or unittests
+fn kind_from_words<'a>(words: impl IntoIterator<Item = &'a str>) -> Option<&'static str> {
+    words.into_iter().find_map(|word| {
+        let word = word.to_lowercase();
+        let tokens = word.split(['_', '-', ' ']).collect::<Vec<_>>();
+        if word.contains("integration") || tokens.contains(&"e2e") {
+            Some(INTEGRATION_LABEL)
+        } else if tokens
+            .iter()
+            .any(|token| *token == "unit" || token.starts_with("unittest"))
+        {
+            Some(UNIT_LABEL)
+        } else {
+            None
+        }
+    })
+}
+
+fn kind_from_path(path: &Path) -> Option<&'static str> {
+    kind_from_words(
+        path.iter()
+            .filter_map(|component| component.to_str())
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn links_integration_library(libraries: &[String]) -> bool {
+    libraries.iter().any(|library| {
+        INTEGRATION_LIBRARIES
+            .iter()
+            .any(|prefix| library.starts_with(prefix))
+    })
+}
+
+/// Labels the tests as unit or integration tests, unless they already have one of these labels.
+/// The first heuristic that gives an answer wins:
+/// - Catch2 tags like [integration] or [unit]
+/// - Suites and tests named like IntegrationTest
+/// - Executables and source files in directories like integration_tests/ or unit/
+/// - Executables linked to a client library, like libpq or gRPC, are integration tests
+///
+/// The other tests are unit tests.
+pub fn label_test_kinds(tests: &mut [Test]) {
+    let mut linked_integration_library = HashMap::new();
+
+    for test in tests {
+        let labels = &test.attributes.labels;
+        if labels
+            .iter()
+            .any(|label| label == UNIT_LABEL || label == INTEGRATION_LABEL)
+        {
+            continue;
+        }
+
+        let name_kind =
+            kind_from_words(test.name.split(['.', '/'])).filter(|kind| *kind == INTEGRATION_LABEL);
+        let path_kind = || {
+            test.file
+                .as_deref()
+                .and_then(kind_from_path)
+                .or_else(|| kind_from_path(&test.executable.path))
+        };
+        let library_kind = || {
+            let links_integration_library = *linked_integration_library
+                .entry(test.executable.path.clone())
+                .or_insert_with(|| {
+                    Elf::new(&test.executable.path)
+                        .ok()
+                        .and_then(|elf| elf.get_needed_libraries().ok())
+                        .is_some_and(|libraries| links_integration_library(&libraries))
+                });
+            links_integration_library.then_some(INTEGRATION_LABEL)
+        };
+
+        let kind = kind_from_words(test.tags.iter().map(String::as_str))
+            .or(name_kind)
+            .or_else(path_kind)
+            .or_else(library_kind)
+            .unwrap_or(UNIT_LABEL);
+        test.attributes.labels.push(kind.to_string());
+    }
+}
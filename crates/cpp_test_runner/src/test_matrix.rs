@@ -0,0 +1,118 @@
+use crate::types::Test;
+use anyhow::{bail, Error, Result};
+use std::{str::FromStr, sync::Arc};
+
+#[derive(Debug, Clone)]
+enum MatrixValue {
+    /// An environment variable, with one of its values
+    Env { variable: String, value: String },
+    /// An argument appended to the arguments of the test
+    Arg(String),
+}
+
+impl MatrixValue {
+    /// Like `TZ=UTC` or `--backend=sqlite`
+    fn label(&self) -> String {
+        match self {
+            Self::Env { variable, value } => format!("{variable}={value}"),
+            Self::Arg(argument) => argument.clone(),
+        }
+    }
+}
+
+/// A dimension of `--matrix`: an environment variable and the values that the tests are run with,
+/// like `env:TZ=UTC,America/New_York`, or an argument and the values it is appended to the tests
+/// with, like `arg:--backend=sqlite,postgres`
+#[derive(Debug, Clone)]
+pub struct MatrixDimension {
+    values: Vec<MatrixValue>,
+}
+
+impl FromStr for MatrixDimension {
+    type Err = Error;
+
+    fn from_str(dimension: &str) -> Result<Self> {
+        let Some((kind, definition)) = dimension.split_once(':') else {
+            bail!("The matrix dimension {dimension} doesn't start with env: or arg:");
+        };
+
+        let values = match kind {
+            "env" => {
+                let Some((variable, values)) = definition.split_once('=') else {
+                    bail!(
+                        "The matrix dimension {dimension} has no values, like env:TZ=UTC,Asia/Tokyo"
+                    );
+                };
+                if variable.is_empty() {
+                    bail!("The matrix dimension {dimension} has no variable");
+                }
+                values
+                    .split(',')
+                    .map(|value| MatrixValue::Env {
+                        variable: variable.to_string(),
+                        value: value.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+            }
+            // Like --backend=sqlite,postgres, or --fast,--thorough for arguments without a value
+            "arg" => {
+                let (prefix, values) = match definition.split_once('=') {
+                    Some((option, values)) => (format!("{option}="), values),
+                    None => (String::new(), definition),
+                };
+                values
+                    .split(',')
+                    .map(|value| MatrixValue::Arg(format!("{prefix}{value}")))
+                    .collect()
+            }
+            _ => bail!("Unknown kind of matrix dimension {kind}, expected env or arg"),
+        };
+        if values.iter().any(|value| value.label().is_empty()) {
+            bail!("The matrix dimension {dimension} has an empty value");
+        }
+
+        Ok(Self { values })
+    }
+}
+
+/// Runs every test once per cell of the matrix, which is a combination of a value of every
+/// dimension. The tests of a cell have its variables in their environment and its arguments after
+/// theirs, and are labeled with them, like `TZ=UTC, --backend=sqlite`, so that their results can
+/// be told apart. Their ids end with the label, like `Suite.Test [TZ=UTC]`.
+pub fn expand_matrix(tests: &[Test], dimensions: &[MatrixDimension]) -> Vec<Test> {
+    let mut cells = vec![Vec::new()];
+    for dimension in dimensions {
+        cells = cells
+            .iter()
+            .flat_map(|cell| {
+                dimension.values.iter().map(move |value| {
+                    let mut cell = cell.clone();
+                    cell.push(value);
+                    cell
+                })
+            })
+            .collect();
+    }
+
+    tests
+        .iter()
+        .flat_map(|test| {
+            cells.iter().map(|cell| {
+                let mut test = test.clone();
+                for value in cell {
+                    match value {
+                        MatrixValue::Env { variable, value } => {
+                            test.environment.insert(variable.clone(), value.clone());
+                        }
+                        MatrixValue::Arg(argument) => {
+                            test.arguments.push(Arc::from(argument.as_str()));
+                        }
+                    }
+                    test.matrix.push(value.label());
+                }
+                test.id = format!("{} [{}]", test.id, test.matrix.join(", "));
+                test
+            })
+        })
+        .collect()
+}
@@ -1,34 +1,188 @@
-use crate::types::{Executable, ExecutableType, Test};
+use crate::{
+    child_environment,
+    diagnostics::{self, Diagnostic},
+    discovery_cache::{hash_of, DiscoveryCache},
+    discovery_deadline::{output_until, DiscoveryDeadline},
+    discovery_progress::{EXECUTABLES_LISTED, TESTS_LISTED},
+    framework_probe::ListingOptions,
+    source_files::{resolve_source_files, SourceFileIndex},
+    test_runner::report_path,
+    test_tokens::TokenQuery,
+    types::{Executable, Test},
+};
 use anyhow::{bail, Result};
-use serde::Deserialize;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
 use std::{
-    borrow::Cow,
+    fmt,
+    fs::{self, File},
+    io::{BufReader, Read},
     ops::Deref,
-    path::{Path, PathBuf},
-    process::Command,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{atomic::Ordering, Arc},
+    time::Instant,
 };
 
-#[derive(Debug, Deserialize)]
-struct GtestJson {
-    // tests: u32,
-    // name: String,
-    testsuites: Vec<GtestTestSuite>,
-}
-
-#[derive(Debug, Deserialize)]
-struct GtestTestSuite {
-    name: String,
-    // tests: u32,
-    testsuite: Vec<GtestTest>,
-}
-
 #[derive(Debug, Deserialize)]
 struct GtestTest {
     name: String,
+    // Only present for value-parameterized tests
+    value_param: Option<String>,
+    // Only present for typed tests
+    type_param: Option<String>,
     file: PathBuf,
     line: u32,
 }
 
+// The listing of a gtest executable can be tens of MB for executables with many tests, so it is
+// deserialized one test at a time instead of materializing all its test suites.
+//
+// {"testsuites": [{"name": "Suite", "testsuite": [{"name": "Test", ...}, ...]}, ...], ...}
+type OnGtestTest<'a> = &'a mut dyn FnMut(&str, GtestTest);
+
+struct GtestListingSeed<'a>(OnGtestTest<'a>);
+
+impl<'de> DeserializeSeed<'de> for GtestListingSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for GtestListingSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a gtest listing")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "testsuites" {
+                map.next_value_seed(GtestTestSuitesSeed(&mut *self.0))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct GtestTestSuitesSeed<'a>(OnGtestTest<'a>);
+
+impl<'de> DeserializeSeed<'de> for GtestTestSuitesSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for GtestTestSuitesSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of gtest test suites")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq
+            .next_element_seed(GtestTestSuiteSeed(&mut *self.0))?
+            .is_some()
+        {}
+        Ok(())
+    }
+}
+
+struct GtestTestSuiteSeed<'a>(OnGtestTest<'a>);
+
+impl<'de> DeserializeSeed<'de> for GtestTestSuiteSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for GtestTestSuiteSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a gtest test suite")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        // gtest always writes the name of a test suite before its tests
+        let mut name: Option<String> = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "name" => name = Some(map.next_value()?),
+                "testsuite" => {
+                    let Some(name) = &name else {
+                        return Err(de::Error::custom("test suite without a name"));
+                    };
+                    map.next_value_seed(GtestTestsSeed {
+                        suite_name: name,
+                        on_test: &mut *self.0,
+                    })?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct GtestTestsSeed<'a> {
+    suite_name: &'a str,
+    on_test: OnGtestTest<'a>,
+}
+
+impl<'de> DeserializeSeed<'de> for GtestTestsSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for GtestTestsSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of gtest tests")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(test) = seq.next_element::<GtestTest>()? {
+            (self.on_test)(self.suite_name, test);
+        }
+        Ok(())
+    }
+}
+
+fn parse_gtest_listing(reader: impl Read, on_test: OnGtestTest) -> serde_json::Result<()> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    GtestListingSeed(on_test).deserialize(&mut deserializer)?;
+    deserializer.end()
+}
+
+// Gtest disables the tests whose suite or name starts with DISABLED_. The suite of a
+// parameterized test is prefixed by the name of its instantiation, like Instantiation/Suite.
+fn is_gtest_disabled(suite_name: &str, test_name: &str) -> bool {
+    suite_name
+        .split('/')
+        .chain(std::iter::once(test_name))
+        .any(|name| name.starts_with("DISABLED_"))
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct Catch2Json {
@@ -47,10 +201,20 @@ struct Catch2Listings {
 struct Catch2Test {
     name: String,
     // class_name: String,
-    // tags: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
     source_location: Catch2SourceLocation,
 }
 
+impl Catch2Test {
+    // Catch2 hides tests tagged with [.], [.tag] or [!hide]
+    fn is_hidden(&self) -> bool {
+        self.tags
+            .iter()
+            .any(|tag| tag.starts_with('.') || tag == "!hide")
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct Catch2SourceLocation {
@@ -58,151 +222,324 @@ struct Catch2SourceLocation {
     line: u32,
 }
 
-pub fn find_file(search_start: &Path, to_find: &Path) -> Option<PathBuf> {
-    let file = if to_find.is_absolute() {
-        Some(Cow::Borrowed(to_find))
-    } else {
-        let mut current_dir = search_start;
-        loop {
-            let absolute_file_path = current_dir.join(to_find);
-            if absolute_file_path.exists() && absolute_file_path.is_file() {
-                break Some(Cow::Owned(absolute_file_path));
-            }
-
-            let Some(parent_dir) = current_dir.parent() else {
-                break None;
-            };
+/// Gtest filters have no escape mechanism, so the characters that have a special meaning
+/// are replaced by the single character wildcard.
+pub fn gtest_filter_pattern(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            ':' | '*' | '?' | '-' => '?',
+            c => c,
+        })
+        .collect()
+}
 
-            current_dir = parent_dir;
+/// Escapes the characters that have a special meaning in a Catch2 test spec, so that it only
+/// matches the test with the exact given name.
+pub fn catch2_test_spec(name: &str) -> String {
+    let mut spec = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '\\' | ',' | '[' | ']' | '*' | '~' | '"') {
+            spec.push('\\');
         }
-    };
+        spec.push(c);
+    }
+    spec
+}
 
-    file.and_then(|file| file.canonicalize().ok())
+/// Selects tests by their full name. A test is selected if it matches any of the filters, or if
+/// there are no filters, if it matches none of the excludes, and if it has the words of the fuzzy
+/// query.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NameFilter<'a> {
+    pub filters: &'a [regex::Regex],
+    pub excludes: &'a [regex::Regex],
+    pub fuzzy: Option<&'a TokenQuery>,
 }
 
+impl NameFilter<'_> {
+    pub fn is_match(&self, name: &str) -> bool {
+        let is_included =
+            self.filters.is_empty() || self.filters.iter().any(|filter| filter.is_match(name));
+        is_included
+            && !self.excludes.iter().any(|exclude| exclude.is_match(name))
+            && self.fuzzy.is_none_or(|fuzzy| fuzzy.is_match(name))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn get_tests_from_executables(
     executables: &[Executable],
     exectuables_only: bool,
     gtest_extra_args: &[String],
     catch2_extra_args: &[String],
-    filter: Option<&regex::Regex>,
+    filter: NameFilter,
+    include_hidden: bool,
+    include_disabled: bool,
+    cache: Option<&DiscoveryCache>,
+    source_files: Option<&SourceFileIndex>,
+    deadline: Option<&DiscoveryDeadline>,
 ) -> Vec<Test> {
-    executables
-        .iter()
+    // Everything but the filter changes the listed tests, the filter is applied on the cached tests
+    let options_hash = hash_of((
+        env!("CARGO_PKG_VERSION"),
+        exectuables_only,
+        gtest_extra_args,
+        catch2_extra_args,
+        include_hidden,
+        include_disabled,
+    ));
+
+    // Listing an executable mostly waits for its process, so they are all listed in parallel.
+    // The order of the tests is kept.
+    let mut tests = executables
+        .par_iter()
         .filter_map(|exec| {
-            get_tests_from_executable(
-                exec,
-                exectuables_only,
-                gtest_extra_args,
-                catch2_extra_args,
-                filter,
-            )
-            .ok()
+            let start = Instant::now();
+            let list = |filter| {
+                let expired = || deadline.filter(|deadline| deadline.is_expired());
+                if let Some(deadline) = expired() {
+                    deadline.mark_unfinished(&exec.path);
+                    bail!("The discovery timed out");
+                }
+                let tests = get_tests_from_executable(
+                    exec,
+                    exectuables_only,
+                    gtest_extra_args,
+                    catch2_extra_args,
+                    filter,
+                    include_hidden,
+                    include_disabled,
+                    deadline,
+                );
+                // The executables that weren't listed in time are left out
+                match (&tests, expired()) {
+                    (Err(_), Some(deadline)) => deadline.mark_unfinished(&exec.path),
+                    (Err(error), None) => diagnostics::report(
+                        Diagnostic::warning(
+                            diagnostics::UNLISTED_EXECUTABLE,
+                            format!(
+                                "the tests of {} couldn't be listed, so they are missing:",
+                                exec.path.display()
+                            ),
+                        )
+                        .details(format!("{error:#}").lines()),
+                    ),
+                    (Ok(_), _) => {}
+                }
+                tests
+            };
+
+            let mut cached = false;
+            let mut tests = match cache {
+                None => list(filter).ok()?,
+                Some(cache) => {
+                    // The base arguments of the executable may change its tests too
+                    let options_hash = hash_of((options_hash, &exec.base_arguments));
+                    let tests = match cache.get(exec, options_hash) {
+                        Some(tests) => {
+                            cached = true;
+                            tests
+                        }
+                        None => {
+                            let tests = list(NameFilter::default()).ok()?;
+                            cache.insert(exec, options_hash, &tests);
+                            tests
+                        }
+                    };
+
+                    // When only listing executables, the filter isn't applied to their path
+                    tests
+                        .into_iter()
+                        .filter(|test| exectuables_only || filter.is_match(&test.name))
+                        .collect::<Vec<_>>()
+                }
+            };
+
+            // Not cached, since it may come from the environment of the runner
+            for test in &mut tests {
+                for (name, value) in &exec.environment {
+                    test.environment
+                        .entry(name.clone())
+                        .or_insert_with(|| value.clone());
+                }
+            }
+
+            tracing::info!(
+                executable = %exec.path.display(),
+                tests = tests.len(),
+                cached,
+                elapsed = ?start.elapsed(),
+                "listed the tests"
+            );
+            EXECUTABLES_LISTED.fetch_add(1, Ordering::Relaxed);
+            TESTS_LISTED.fetch_add(tests.len(), Ordering::Relaxed);
+            Some(tests)
         })
-        .flatten()
-        .collect::<Vec<Test>>()
+        .flatten_iter()
+        .collect::<Vec<Test>>();
+
+    // Resolved after the cache, which keeps working when the sources move
+    resolve_source_files(&mut tests, source_files);
+    tests
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn get_tests_from_executable(
     executable: &Executable,
     exectuables_only: bool,
     gtest_extra_args: &[String],
     catch2_extra_args: &[String],
-    filter: Option<&regex::Regex>,
+    filter: NameFilter,
+    include_hidden: bool,
+    include_disabled: bool,
+    deadline: Option<&DiscoveryDeadline>,
 ) -> Result<Vec<Test>> {
-    match executable.executable_type {
-        ExecutableType::Gtest => {
-            get_tests_from_gtest_executable(executable, exectuables_only, gtest_extra_args, filter)
-        }
-        ExecutableType::Catch2 => get_tests_from_catch2_executable(
-            executable,
-            exectuables_only,
-            catch2_extra_args,
-            filter,
-        ),
-    }
+    let options = ListingOptions {
+        executables_only: exectuables_only,
+        gtest_extra_args,
+        catch2_extra_args,
+        include_hidden,
+        include_disabled,
+        deadline,
+    };
+    executable
+        .executable_type
+        .probe()
+        .list_tests(executable, &options, filter)
 }
 
 pub fn get_tests_from_gtest_executable(
     executable: &Executable,
     executable_only: bool,
     extra_args: &[String],
-    filter: Option<&regex::Regex>,
+    filter: NameFilter,
+    include_disabled: bool,
+    deadline: Option<&DiscoveryDeadline>,
 ) -> Result<Vec<Test>> {
-    let args = vec![
-        String::from("--gtest_list_tests"),
-        String::from("--gtest_output=json:/dev/stderr"),
-    ];
-
-    let output = Command::new(&executable.path).args(args).output()?;
+    // The listing is written to a file instead of a pipe, so that it can be parsed while it is
+    // read instead of being buffered
+    let listing = report_path("json");
+    let output = output_until(
+        child_environment::apply(&mut Command::new(&executable.path))
+            .envs(&executable.environment)
+            .args(&executable.base_arguments)
+            .arg("--gtest_list_tests")
+            .arg(format!("--gtest_output=json:{}", listing.display()))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null()),
+        deadline,
+    )
+    .inspect_err(|_| {
+        let _ = fs::remove_file(&listing);
+    })?;
     if !output.status.success() {
+        let _ = fs::remove_file(&listing);
         bail!("{} is not a gtest executable!", executable.path.display());
     }
 
-    let Ok::<GtestJson, serde_json::Error>(json) =
-        serde_json::from_str(&String::from_utf8_lossy(&output.stderr))
-    else {
-        bail!("{} Failed to parse gtest json", executable.path.display());
-    };
-
     if executable_only {
+        let _ = fs::remove_file(&listing);
         return Ok(vec![Test {
+            id: executable.path.to_string_lossy().deref().to_string(),
             name: executable.path.to_string_lossy().deref().to_string(),
             file: None,
             line: None,
-            executable: executable.clone(),
-            arguments: extra_args.to_vec(),
+            executable: Arc::new(executable.clone()),
+            arguments: extra_args
+                .iter()
+                .map(|argument| Arc::from(argument.as_str()))
+                .collect(),
+            environment: Default::default(),
+            cwd: None,
+            hidden: false,
+            tags: Vec::new(),
+            disabled: false,
+            param: None,
+            type_param: None,
+            matrix: Vec::new(),
+            also_registered_by: Vec::new(),
+            attributes: Default::default(),
+            compile_command: None,
             index: None,
+            last_status: None,
         }]);
     }
 
-    Ok(json
-        .testsuites
+    // The executable and the extra arguments are shared by all the tests instead of being copied
+    let shared_executable = Arc::new(executable.clone());
+    let also_run_disabled_tests = Arc::<str>::from("--gtest_also_run_disabled_tests");
+    let extra_args = extra_args
         .iter()
-        .flat_map(|test_suite| {
-            test_suite
-                .testsuite
-                .iter()
-                .filter(|test| {
-                    filter
-                        .map(|filter| filter.is_match(&test.name))
-                        .unwrap_or(true)
-                })
-                .map(|test| {
-                    let name = test_suite.name.clone() + "." + &test.name;
-
-                    let mut arguments = vec![
-                        format!("--gtest_filter={name}"),
-                        String::from("--gtest_also_run_disabled_tests"),
-                    ];
-                    arguments.extend_from_slice(extra_args);
-
-                    Test {
-                        name: name.clone(),
-                        file: find_file(
-                            executable.path.parent().unwrap_or_else(|| &executable.path),
-                            &test.file,
-                        ),
-                        line: Some(test.line),
-                        executable: executable.clone(),
-                        arguments,
-                        index: None,
-                    }
-                })
-        })
-        .collect::<Vec<_>>())
+        .map(|argument| Arc::from(argument.as_str()))
+        .collect::<Vec<Arc<str>>>();
+
+    let mut tests = Vec::new();
+    let mut on_test = |suite_name: &str, test: GtestTest| {
+        let name = format!("{suite_name}.{}", test.name);
+        let disabled = is_gtest_disabled(suite_name, &test.name);
+        if !filter.is_match(&name) || (disabled && !include_disabled) {
+            return;
+        }
+
+        let mut arguments = vec![Arc::from(format!(
+            "--gtest_filter={}",
+            gtest_filter_pattern(&name)
+        ))];
+        if include_disabled {
+            arguments.push(Arc::clone(&also_run_disabled_tests));
+        }
+        arguments.extend(extra_args.iter().cloned());
+
+        tests.push(Test {
+            id: name.clone(),
+            name,
+            file: Some(test.file.clone()).filter(|file| !file.as_os_str().is_empty()),
+            line: Some(test.line),
+            executable: Arc::clone(&shared_executable),
+            arguments,
+            environment: Default::default(),
+            cwd: None,
+            hidden: false,
+            tags: Vec::new(),
+            disabled,
+            param: test.value_param,
+            type_param: test.type_param,
+            matrix: Vec::new(),
+            also_registered_by: Vec::new(),
+            attributes: Default::default(),
+            compile_command: None,
+            index: None,
+            last_status: None,
+        });
+    };
+
+    let parsed = File::open(&listing)
+        .map_err(serde_json::Error::io)
+        .and_then(|file| parse_gtest_listing(BufReader::new(file), &mut on_test));
+    let _ = fs::remove_file(&listing);
+    if parsed.is_err() {
+        bail!("{} Failed to parse gtest json", executable.path.display());
+    }
+
+    Ok(tests)
 }
 
 pub fn get_tests_from_catch2_executable(
     executable: &Executable,
     executable_only: bool,
     extra_args: &[String],
-    filter: Option<&regex::Regex>,
+    filter: NameFilter,
+    include_hidden: bool,
+    deadline: Option<&DiscoveryDeadline>,
 ) -> Result<Vec<Test>> {
     let is_catch2_executable = {
-        let output = Command::new(&executable.path)
-            .arg("--libidentify")
-            .output()?;
+        let output = output_until(
+            child_environment::apply(&mut Command::new(&executable.path))
+                .envs(&executable.environment)
+                .args(&executable.base_arguments)
+                .arg("--libidentify"),
+            deadline,
+        )?;
 
         if !output.status.success() {
             false
@@ -221,18 +558,47 @@ pub fn get_tests_from_catch2_executable(
 
     if executable_only {
         return Ok(vec![Test {
+            id: executable.path.to_string_lossy().deref().to_string(),
             name: executable.path.to_string_lossy().deref().to_string(),
             file: None,
             line: None,
-            executable: executable.clone(),
-            arguments: extra_args.to_vec(),
+            executable: Arc::new(executable.clone()),
+            arguments: extra_args
+                .iter()
+                .map(|argument| Arc::from(argument.as_str()))
+                .collect(),
+            environment: Default::default(),
+            cwd: None,
+            hidden: false,
+            tags: Vec::new(),
+            disabled: false,
+            param: None,
+            type_param: None,
+            matrix: Vec::new(),
+            also_registered_by: Vec::new(),
+            attributes: Default::default(),
+            compile_command: None,
             index: None,
+            last_status: None,
         }]);
     }
 
-    let output = Command::new(&executable.path)
-        .args(["--list-tests", "--reporter=JSON"])
-        .output()?;
+    let mut args = vec![
+        String::from("--list-tests"),
+        String::from("--reporter=JSON"),
+    ];
+    if include_hidden {
+        // Hidden tests are only listed when they are explicitly matched by a test spec
+        args.push(String::from("*,[.]"));
+    }
+
+    let output = output_until(
+        child_environment::apply(&mut Command::new(&executable.path))
+            .envs(&executable.environment)
+            .args(&executable.base_arguments)
+            .args(args),
+        deadline,
+    )?;
 
     if !output.status.success() {
         bail!("{} is not a catch2 executable!", executable.path.display());
@@ -244,25 +610,34 @@ pub fn get_tests_from_catch2_executable(
         bail!("{} Failed to parse catch2 json", executable.path.display());
     };
 
+    let shared_executable = Arc::new(executable.clone());
+
     Ok(json
         .listings
         .tests
         .iter()
-        .filter(|test| {
-            filter
-                .map(|filter| filter.is_match(&test.name))
-                .unwrap_or(true)
-        })
+        .filter(|test| filter.is_match(&test.name))
         .map(|test| Test {
+            id: test.name.clone(),
             name: test.name.clone(),
-            file: find_file(
-                executable.path.parent().unwrap_or_else(|| &executable.path),
-                &test.source_location.filename,
-            ),
+            file: Some(test.source_location.filename.clone())
+                .filter(|file| !file.as_os_str().is_empty()),
             line: Some(test.source_location.line),
-            executable: executable.clone(),
-            arguments: vec![test.name.clone()],
+            executable: Arc::clone(&shared_executable),
+            arguments: vec![Arc::from(catch2_test_spec(&test.name))],
+            environment: Default::default(),
+            cwd: None,
+            hidden: test.is_hidden(),
+            tags: test.tags.clone(),
+            disabled: false,
+            param: None,
+            type_param: None,
+            matrix: Vec::new(),
+            also_registered_by: Vec::new(),
+            attributes: Default::default(),
+            compile_command: None,
             index: None,
+            last_status: None,
         })
         .collect::<Vec<_>>())
 }
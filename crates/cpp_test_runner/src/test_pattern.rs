@@ -0,0 +1,41 @@
+use anyhow::{Error, Result};
+use globset::{Glob, GlobMatcher};
+use std::str::FromStr;
+
+/// A test name given on the command line, like `Suite.Test` or `'Suite.*'`. The name of a test
+/// matches if it is exactly the pattern, or if it matches the pattern as a glob.
+#[derive(Debug, Clone)]
+pub struct TestPattern {
+    pattern: String,
+    // None if the pattern isn't a valid glob, like a Catch2 name with an unclosed [
+    glob: Option<GlobMatcher>,
+}
+
+impl FromStr for TestPattern {
+    type Err = Error;
+
+    fn from_str(pattern: &str) -> Result<Self> {
+        Ok(Self {
+            pattern: pattern.to_string(),
+            glob: Glob::new(pattern).ok().map(|glob| glob.compile_matcher()),
+        })
+    }
+}
+
+impl TestPattern {
+    /// A pattern only matching the test with this exact name
+    pub fn exact(name: &str) -> Self {
+        Self {
+            pattern: name.to_string(),
+            glob: None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        name == self.pattern || self.glob.as_ref().is_some_and(|glob| glob.is_match(name))
+    }
+}
@@ -0,0 +1,375 @@
+use crate::{
+    humanize,
+    types::{Test, TestStatus},
+};
+use anyhow::Result;
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+
+#[derive(Debug, Clone)]
+pub struct TestOutcome {
+    pub status: TestStatus,
+    pub output: String,
+    pub duration: Option<Duration>,
+    /// The peak memory of the process that ran the test, in bytes
+    pub max_rss: Option<u64>,
+}
+
+/// A test with the outcome of its run, as printed by `run --output json`
+#[derive(Debug, Serialize)]
+pub struct TestResult<'a> {
+    #[serde(flatten)]
+    pub test: &'a Test,
+    /// None if the test wasn't run because of --fail-fast
+    pub status: Option<TestStatus>,
+    /// Rounded to the microsecond, so that it is printed without the noise of the floats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f64>,
+    /// The duration for people, e.g. `1m 32.4s`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+    /// The peak memory of the process that ran the test
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rss_bytes: Option<u64>,
+    /// The peak memory for people, e.g. `42.0 MiB`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rss: Option<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub output: String,
+}
+
+impl<'a> TestResult<'a> {
+    pub fn new(test: &'a Test, outcome: Option<TestOutcome>) -> Self {
+        match outcome {
+            Some(outcome) => Self {
+                test,
+                status: Some(outcome.status),
+                duration_seconds: outcome
+                    .duration
+                    .map(|duration| duration.as_micros() as f64 / 1e6),
+                duration: outcome.duration.map(humanize::duration),
+                max_rss_bytes: outcome.max_rss,
+                max_rss: outcome.max_rss.map(humanize::size),
+                output: outcome.output,
+            },
+            None => Self {
+                test,
+                status: None,
+                duration_seconds: None,
+                duration: None,
+                max_rss_bytes: None,
+                max_rss: None,
+                output: String::new(),
+            },
+        }
+    }
+
+    /// Sets the duration of a test that ran to zero, the only difference between the reports of
+    /// identical runs, for the golden files of --normalize-durations
+    pub fn normalize_durations(&mut self) {
+        if self.duration_seconds.is_some() {
+            self.duration_seconds = Some(0.0);
+            self.duration = Some(humanize::duration(Duration::ZERO));
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GtestResultJson {
+    #[serde(default)]
+    testsuites: Vec<GtestResultSuite>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GtestResultSuite {
+    name: String,
+    #[serde(default)]
+    testsuite: Vec<GtestResultTest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GtestResultTest {
+    name: String,
+    // RUN or NOTRUN
+    #[serde(default)]
+    status: String,
+    // COMPLETED, SKIPPED or SUPPRESSED
+    #[serde(default)]
+    result: String,
+    // Formatted like 0.123s
+    #[serde(default)]
+    time: String,
+    #[serde(default)]
+    failures: Vec<GtestFailure>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GtestFailure {
+    failure: String,
+}
+
+/// Parses the report written by `--gtest_output=json:<file>`. The outcomes are keyed by the
+/// full name of the tests.
+pub fn parse_gtest_results(json: &str) -> Result<HashMap<String, TestOutcome>> {
+    let json: GtestResultJson = serde_json::from_str(json)?;
+
+    Ok(json
+        .testsuites
+        .into_iter()
+        .flat_map(|test_suite| {
+            test_suite.testsuite.into_iter().map(move |test| {
+                let status = if !test.failures.is_empty() {
+                    TestStatus::Failed
+                } else if test.result == "SKIPPED" || test.status == "NOTRUN" {
+                    TestStatus::Skipped
+                } else {
+                    TestStatus::Passed
+                };
+
+                let duration = test
+                    .time
+                    .strip_suffix('s')
+                    .and_then(|time| time.parse::<f64>().ok())
+                    .map(Duration::from_secs_f64);
+
+                let output = test
+                    .failures
+                    .iter()
+                    .map(|failure| failure.failure.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+
+                (
+                    format!("{}.{}", test_suite.name, test.name),
+                    TestOutcome {
+                        status,
+                        output,
+                        duration,
+                        max_rss: None,
+                    },
+                )
+            })
+        })
+        .collect())
+}
+
+/// What a process running several tests got through before it died: the outcome of the tests that
+/// finished, and the test that was running, with what it printed so far
+#[derive(Debug, Default)]
+pub struct PartialResults {
+    pub outcomes: HashMap<String, TestOutcome>,
+    pub running: Option<(String, String)>,
+}
+
+// The lines that gtest prints around every test
+const GTEST_RUN: &str = "[ RUN      ] ";
+const GTEST_ENDS: [(&str, TestStatus); 3] = [
+    ("[       OK ] ", TestStatus::Passed),
+    ("[  FAILED  ] ", TestStatus::Failed),
+    ("[  SKIPPED ] ", TestStatus::Skipped),
+];
+
+/// Reads the results that gtest printed before its process died, as its report is only written
+/// at the end: a test with a `[ RUN      ]` line and a `[       OK ]`, `[  FAILED  ]` or
+/// `[  SKIPPED ]` line finished, and the test with only the first one was running.
+pub fn parse_partial_gtest_output(stdout: &str) -> PartialResults {
+    let mut results = PartialResults::default();
+    for line in stdout.lines() {
+        if let Some(name) = line.strip_prefix(GTEST_RUN) {
+            results.running = Some((name.trim().to_string(), format!("{line}\n")));
+            continue;
+        }
+        let Some((name, output)) = &mut results.running else {
+            continue;
+        };
+        output.push_str(line);
+        output.push('\n');
+
+        let end = GTEST_ENDS.iter().find_map(|(marker, status)| {
+            line.strip_prefix(marker)
+                .and_then(|rest| rest.strip_prefix(name.as_str()))
+                .map(|rest| (*status, rest))
+        });
+        let Some((status, rest)) = end else {
+            continue;
+        };
+        // Like ` (12 ms)`
+        let duration = rest
+            .trim()
+            .strip_prefix('(')
+            .and_then(|rest| rest.strip_suffix(" ms)"))
+            .and_then(|milliseconds| milliseconds.parse().ok())
+            .map(Duration::from_millis);
+        if let Some((name, output)) = results.running.take() {
+            results.outcomes.insert(
+                name,
+                TestOutcome {
+                    status,
+                    output,
+                    duration,
+                    max_rss: None,
+                },
+            );
+        }
+    }
+    results
+}
+
+/// Parses the report written by Catch2's XML reporter. The outcomes are keyed by the name of
+/// the tests, and the output of a failed test lists every failed assertion with the sections
+/// it was in.
+pub fn parse_catch2_results(xml: &str) -> Result<HashMap<String, TestOutcome>> {
+    let mut results = PartialResults::default();
+    read_catch2_results(xml, &mut results)?;
+    Ok(results.outcomes)
+}
+
+/// Parses the report that Catch2's XML reporter was writing when its process died, which is cut
+/// short in the test that was running, as the reporter writes the tests as they finish
+pub fn parse_partial_catch2_results(xml: &str) -> PartialResults {
+    let mut results = PartialResults::default();
+    // Fails where the report is cut short, with the results read until there
+    let _ = read_catch2_results(xml, &mut results);
+    if results
+        .running
+        .as_ref()
+        .is_some_and(|(name, _)| results.outcomes.contains_key(name))
+    {
+        results.running = None;
+    }
+    results
+}
+
+// Reads the outcomes of the tests into `results`, whose running test is the one being read
+fn read_catch2_results(xml: &str, results: &mut PartialResults) -> Result<()> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut sections: Vec<String> = Vec::new();
+    // The failed expression being parsed, with the macro used to check it
+    let mut failed_expression: Option<(String, String)> = None;
+    let mut current_element = Vec::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                match element.name().as_ref() {
+                    b"TestCase" => {
+                        let name = attribute(&element, "name")?.unwrap_or_default();
+                        let output = match attribute(&element, "tags")? {
+                            Some(tags) if !tags.is_empty() => format!("Tags: {tags}"),
+                            _ => String::new(),
+                        };
+                        results.running = Some((name, output));
+                        sections.clear();
+                    }
+                    b"Section" => sections.push(attribute(&element, "name")?.unwrap_or_default()),
+                    b"Expression" => {
+                        let is_failure = element
+                            .try_get_attribute("success")?
+                            .is_some_and(|success| success.value.as_ref() == b"false");
+                        if is_failure {
+                            let mut expression = String::new();
+                            if !sections.is_empty() {
+                                expression.push_str(&format!("In {}\n", sections.join(" > ")));
+                            }
+                            if let (Some(filename), Some(line)) = (
+                                attribute(&element, "filename")?,
+                                attribute(&element, "line")?,
+                            ) {
+                                expression.push_str(&format!("{filename}:{line}: "));
+                            }
+                            expression.push_str("FAILED:");
+                            let macro_name = attribute(&element, "type")?.unwrap_or_default();
+                            failed_expression = Some((expression, macro_name));
+                        }
+                    }
+                    _ => {}
+                }
+                current_element = element.name().as_ref().to_vec();
+            }
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                let text = text.trim();
+                if let Some((expression, macro_name)) = &mut failed_expression {
+                    match current_element.as_slice() {
+                        b"Original" if !macro_name.is_empty() => {
+                            expression.push_str(&format!("\n  {macro_name}( {text} )"))
+                        }
+                        b"Expanded" => expression.push_str(&format!("\nwith expansion:\n  {text}")),
+                        _ => expression.push_str(&format!("\n  {text}")),
+                    }
+                } else if let Some((_, output)) = &mut results.running {
+                    // Messages of failures and exceptions, or captured output
+                    if !output.is_empty() {
+                        output.push('\n');
+                    }
+                    output.push_str(text);
+                }
+            }
+            Event::End(element) => match element.name().as_ref() {
+                b"Expression" => {
+                    if let (Some((expression, _)), Some((_, output))) =
+                        (failed_expression.take(), &mut results.running)
+                    {
+                        if !output.is_empty() {
+                            output.push_str("\n\n");
+                        }
+                        output.push_str(&expression);
+                    }
+                }
+                b"Section" => {
+                    sections.pop();
+                }
+                b"TestCase" => results.running = None,
+                _ => {}
+            },
+            Event::Empty(element) if element.name().as_ref() == b"OverallResult" => {
+                let passed = element
+                    .try_get_attribute("success")?
+                    .is_some_and(|success| success.value.as_ref() == b"true");
+
+                let duration = attribute(&element, "durationInSeconds")?
+                    .and_then(|duration| duration.parse::<f64>().ok())
+                    .map(Duration::from_secs_f64);
+
+                // Catch2 reports a test that called SKIP() as successful
+                let skipped = attribute(&element, "skips")?
+                    .and_then(|skips| skips.parse::<u32>().ok())
+                    .is_some_and(|skips| skips > 0);
+
+                if let Some((name, output)) = &results.running {
+                    let status = match (passed, skipped) {
+                        (true, true) => TestStatus::Skipped,
+                        (true, false) => TestStatus::Passed,
+                        (false, _) => TestStatus::Failed,
+                    };
+                    results.outcomes.insert(
+                        name.clone(),
+                        TestOutcome {
+                            status,
+                            output: output.clone(),
+                            duration,
+                            max_rss: None,
+                        },
+                    );
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>> {
+    Ok(element
+        .try_get_attribute(name)?
+        .map(|attribute| attribute.unescape_value().map(|value| value.to_string()))
+        .transpose()?)
+}
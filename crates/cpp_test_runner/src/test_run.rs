@@ -0,0 +1,168 @@
+use crate::{
+    cache_dirs::WorkspaceCache,
+    scheduler::{Scheduler, TestId},
+    test_results::TestOutcome,
+    test_runner::{run_all_reporting, run_with_scheduler_reporting, OnOutcome, RunOptions},
+    types::Test,
+};
+use anyhow::Result;
+use crossbeam::channel::{self, Receiver};
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc},
+    thread,
+};
+
+/// Runs tests in the background, like the run subcommand without its output, and streams their
+/// results as they finish:
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// use cpp_test_runner::{TestDiscovery, TestRun};
+///
+/// let tests = TestDiscovery::in_dir("build").discover()?;
+/// for result in TestRun::new(tests).fail_fast(true).start() {
+///     let (test, outcome) = result?;
+///     println!("{}: {:?}", test.display_name(), outcome.status);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TestRun {
+    tests: Vec<Test>,
+    options: RunOptions,
+    jobs: Option<usize>,
+    scheduler: Option<Box<dyn Scheduler + Send>>,
+}
+
+impl TestRun {
+    pub fn new(tests: Vec<Test>) -> Self {
+        Self {
+            tests,
+            options: RunOptions {
+                quiet: true,
+                ..Default::default()
+            },
+            jobs: None,
+            scheduler: None,
+        }
+    }
+
+    /// The number of tests run at the same time. [default: the number of CPUs]
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Stops starting tests once one has failed. The tests that weren't run have no result.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.options.fail_fast = fail_fast;
+        self
+    }
+
+    /// Stops starting tests once `cancel` is set, from another thread. The running tests finish,
+    /// and the others have no result.
+    pub fn cancel_with(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.options.cancel = Some(cancel);
+        self
+    }
+
+    /// Runs all the tests of an executable with a single process
+    pub fn group_by_executable(mut self, group_by_executable: bool) -> Self {
+        self.options.group_by_executable = group_by_executable;
+        self
+    }
+
+    /// Runs the tests of an executable in chunks of at most this many tests per process, like
+    /// --chunk-size
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.options.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Forks the gtest tests from a server of their executable, like --fork-server
+    pub fn fork_server(mut self, fork_server: bool) -> Self {
+        self.options.fork_server = fork_server;
+        self
+    }
+
+    /// Reruns the failed gtest tests to capture the state at their first failing assertion in
+    /// this directory, like --break-artifacts
+    pub fn break_artifacts(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.break_artifacts = Some(dir.into());
+        self
+    }
+
+    /// Holds back tests while the peak memory that the running tests used in their last run would
+    /// exceed this fraction of the RAM, like --max-memory-fraction. The peak memory of the tests
+    /// is recorded in the cache of the workspace.
+    pub fn memory_budget(mut self, fraction: f64, cache: WorkspaceCache) -> Self {
+        self.options.memory_fraction = Some(fraction);
+        self.options.cache = Some(cache);
+        self
+    }
+
+    /// Picks the tests to run with this scheduler instead of running them in their order, which
+    /// disables the memory budget
+    pub fn scheduler(mut self, scheduler: impl Scheduler + Send + 'static) -> Self {
+        self.scheduler = Some(Box::new(scheduler));
+        self
+    }
+
+    // Runs the tests with the jobs and the scheduler of the run, in the current thread
+    fn execute(&self, on_outcome: OnOutcome) -> Result<Vec<Option<TestOutcome>>> {
+        let run = || match &self.scheduler {
+            Some(scheduler) => run_with_scheduler_reporting(
+                &self.tests,
+                &self.options,
+                scheduler.as_ref(),
+                on_outcome,
+            ),
+            None => run_all_reporting(&self.tests, &self.options, on_outcome),
+        };
+
+        match self.jobs {
+            Some(jobs) => rayon::ThreadPoolBuilder::new()
+                .num_threads(jobs)
+                .build()?
+                .install(run),
+            None => run(),
+        }
+    }
+
+    /// Starts running the tests in a background thread
+    pub fn start(self) -> TestResults {
+        let (sender, receiver) = channel::unbounded();
+        thread::spawn(move || {
+            let on_outcome = |id: TestId, outcome: &TestOutcome| {
+                let _ = sender.send(Ok((self.tests[id].clone(), outcome.clone())));
+            };
+            if let Err(error) = self.execute(&on_outcome) {
+                let _ = sender.send(Err(error));
+            }
+        });
+
+        TestResults { receiver }
+    }
+
+    /// Runs the tests and waits for all of them, returning their outcome in the same order as the
+    /// tests. The tests that weren't run because of `fail_fast` have no outcome.
+    pub fn run(self) -> Result<Vec<(Test, Option<TestOutcome>)>> {
+        let outcomes = self.execute(&|_, _| {})?;
+        Ok(self.tests.into_iter().zip(outcomes).collect())
+    }
+}
+
+/// The results of the tests of a `TestRun`, in the order in which they finish. The iteration
+/// ends when all the tests have run, or with an error if the run couldn't go on.
+pub struct TestResults {
+    receiver: Receiver<Result<(Test, TestOutcome)>>,
+}
+
+impl Iterator for TestResults {
+    type Item = Result<(Test, TestOutcome)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
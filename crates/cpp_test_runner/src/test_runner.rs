@@ -1,89 +1,923 @@
 use colored::Colorize;
 use std::{
-    process::Command,
-    sync::{atomic::AtomicUsize, Mutex},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
 };
+#[cfg(target_os = "linux")]
+use std::{fs::File, os::fd::AsRawFd};
+#[cfg(unix)]
+use std::{io::Read, os::unix::process::ExitStatusExt, process::ExitStatus, thread};
 
-use crate::types::{ExecutableType, Test};
-use anyhow::Result;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use crate::{
+    break_artifacts::capture_break_artifact,
+    cache_dirs::WorkspaceCache,
+    child_environment,
+    crash_report::crash_summary,
+    framework_probe::FrameworkProbe,
+    glyphs::Glyphs,
+    humanize,
+    loader_check::test_loader_problem,
+    memory_history::{total_memory, MemoryHistory},
+    scheduler::{DefaultScheduler, MemoryBudgetScheduler, Scheduler, SchedulerState, TestId},
+    test_results::TestOutcome,
+    types::{ExecutableType, Test, TestStatus},
+};
+#[cfg(unix)]
+use crate::{
+    foreground::{is_interrupted, run_in_foreground},
+    fork_server::ForkServers,
+};
+use anyhow::{anyhow, Result};
 
-pub fn run_all(tests: &[Test], use_color: bool) -> Result<()> {
-    let test_number = Mutex::<u32>::default(); // Use a mutex to lock during printing
-    let num_tests_passed = AtomicUsize::default();
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    pub use_color: bool,
+    pub fail_fast: bool,
+    pub group_by_executable: bool,
+    /// Run the tests of an executable in chunks of at most this many tests per process, in
+    /// parallel, whether they are grouped by executable or not
+    pub chunk_size: Option<usize>,
+    /// How long to hold back the results of passed tests after a failure is printed
+    pub log_quiesce: Option<Duration>,
+    /// Don't print the results and the summary
+    pub quiet: bool,
+    /// Where to store what is captured when rerunning the failed gtest tests with
+    /// --gtest_break_on_failure
+    pub break_artifacts: Option<PathBuf>,
+    /// Hold back tests while the peak memory of the running tests in their last run would exceed
+    /// this fraction of the RAM
+    pub memory_fraction: Option<f64>,
+    /// Run the gtest tests in processes forked from a server of their executable, which only does
+    /// the static initialization once
+    pub fork_server: bool,
+    /// Where the peak memory of the tests is recorded, from one run to the next
+    pub cache: Option<WorkspaceCache>,
+    /// Stops scheduling new tests once set from another thread, like --fail-fast does after a
+    /// failure
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Run the tests one at a time in the foreground of the terminal, with their output going to
+    /// it instead of being captured, and stop starting tests after a Ctrl-C. Tests run in a group
+    /// or from a fork server are still captured.
+    pub no_capture: bool,
+    /// The glyphs of the results and of the summary
+    pub glyphs: Glyphs,
+}
+
+struct PrintState<'a> {
+    tests: &'a [Test],
+    options: &'a RunOptions,
+    test_number: u32,
+    // Results held back while the output is quiesced after a failure. They are printed in the
+    // order of the tests, whatever order they finished in.
+    quiet_until: Option<Instant>,
+    pending: BTreeMap<TestId, TestOutcome>,
+}
+
+impl<'a> PrintState<'a> {
+    fn new(tests: &'a [Test], options: &'a RunOptions) -> Self {
+        Self {
+            tests,
+            options,
+            test_number: 0,
+            quiet_until: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    fn flush(&mut self) {
+        self.write_pending(&mut BufWriter::new(io::stdout().lock()));
+    }
+
+    fn write_pending(&mut self, stdout: &mut impl Write) {
+        for (id, outcome) in std::mem::take(&mut self.pending) {
+            self.write(stdout, id, &outcome);
+        }
+    }
+
+    // The results are numbered in the order they are printed
+    fn write(&mut self, stdout: &mut impl Write, id: TestId, outcome: &TestOutcome) {
+        self.test_number += 1;
+        let result = format_result(
+            &self.tests[id],
+            outcome,
+            self.test_number,
+            self.tests.len(),
+            self.options.use_color,
+            self.options.glyphs,
+        );
+        let _ = writeln!(stdout, "{result}");
+    }
+
+    fn print(&mut self, id: TestId, outcome: &TestOutcome) {
+        let test_passed = !outcome.status.is_failure();
+        let is_quiet = self
+            .quiet_until
+            .is_some_and(|quiet_until| Instant::now() < quiet_until);
+
+        if test_passed && is_quiet {
+            self.pending.insert(id, outcome.clone());
+            return;
+        }
+
+        // The lines are written at once when the writer is dropped
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        if !is_quiet {
+            self.write_pending(&mut stdout);
+        }
+
+        self.write(&mut stdout, id, outcome);
 
-    tests.par_iter().for_each(|test| {
-        let mut args = test.arguments.clone();
+        if !test_passed {
+            self.quiet_until = self
+                .options
+                .log_quiesce
+                .map(|log_quiesce| Instant::now() + log_quiesce);
+        }
+    }
+}
+
+fn test_command(test: &Test, use_color: bool) -> Command {
+    let mut command = Command::new(&test.executable.path);
+    child_environment::apply(&mut command)
+        .args(&test.executable.base_arguments)
+        .args(test.arguments.iter().map(|argument| &**argument))
+        .arg(test.executable.executable_type.probe().color_arg(use_color))
+        .envs(&test.environment);
+    if let Some(cwd) = &test.cwd {
+        command.current_dir(cwd);
+    }
+    command
+}
+
+pub fn run_single(test: &Test, use_color: bool) -> TestOutcome {
+    run_single_with(test, use_color, None, false)
+}
+
+fn run_single_with(
+    test: &Test,
+    use_color: bool,
+    fork_servers: Option<&ForkServers>,
+    no_capture: bool,
+) -> TestOutcome {
+    if let Some(problem) = test_loader_problem(test) {
+        return not_runnable(&problem);
+    }
+
+    let probe = test.executable.executable_type.probe();
+    let mut command = test_command(test, use_color);
+
+    // The report tells if the test was skipped, how long it took and why it failed
+    let report = report_path(probe.report_extension());
+    command.args(probe.report_args(&report));
 
-        match (use_color, &test.executable.executable_type) {
-            (true, ExecutableType::Gtest) => {
-                args.push("--gtest_color=yes".to_string());
+    tracing::debug!(test = %test.name, ?command, "starting the test");
+    let start = Instant::now();
+    let output = match fork_servers {
+        Some(fork_servers) if test.executable.executable_type == ExecutableType::Gtest => {
+            let arguments = command
+                .get_args()
+                .map(|argument| argument.as_encoded_bytes());
+            fork_servers.run(test, arguments)
+        }
+        _ if no_capture => run_in_foreground(&mut command),
+        _ => output_with_max_rss(&mut command),
+    };
+    let elapsed = start.elapsed();
+
+    // A gtest filter can't be escaped, so the filter of a test with a special character in its
+    // name can also match other tests, whose results are ignored
+    let mut reported_outcomes = read_report(&report, probe).unwrap_or_default();
+    let ran_other_tests = reported_outcomes.keys().any(|name| *name != test.name);
+    let reported_outcome = reported_outcomes.remove(&test.name);
+
+    match output {
+        Ok((output, max_rss)) => {
+            let exit_status = if output.status.success() {
+                TestStatus::Passed
+            } else {
+                TestStatus::Failed
+            };
+            let mut stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            if ran_other_tests {
+                if let Some(own_output) = probe
+                    .parse_partial_results(None, &stdout)
+                    .outcomes
+                    .remove(&test.name)
+                {
+                    stdout = own_output.output;
+                }
             }
-            (false, ExecutableType::Gtest) => {
-                args.push("--gtest_color=no".to_string());
+
+            // Sanitizers report on stderr
+            let crash_summary = crash_summary(
+                &test.executable.path,
+                output.status,
+                &format!("{stdout}\n{}", String::from_utf8_lossy(&output.stderr)),
+            );
+            let with_crash_summary = |test_output: String| match &crash_summary {
+                Some(crash_summary) => format!("{}\n\n{crash_summary}", test_output.trim_end()),
+                None => test_output,
+            };
+
+            let Some(outcome) = reported_outcome else {
+                return TestOutcome {
+                    status: exit_status,
+                    output: with_crash_summary(stdout),
+                    duration: Some(elapsed),
+                    max_rss,
+                };
+            };
+
+            // A test can pass and still make the process fail, e.g. with sanitizers. Catch2
+            // exits with an error when all the tests it ran were skipped, so skips are trusted.
+            // When other tests ran, they can be the ones that made the process fail.
+            let status = match outcome.status {
+                TestStatus::Passed if !ran_other_tests => exit_status,
+                status => status,
+            };
+
+            TestOutcome {
+                status,
+                output: with_crash_summary(probe.test_output(stdout, outcome.output)),
+                duration: outcome.duration,
+                max_rss,
             }
-            (true, ExecutableType::Catch2) => {
-                args.push("--colour-mode=ansi".to_string());
+        }
+        Err(error) => TestOutcome {
+            status: TestStatus::Failed,
+            output: format!(
+                "Failed to execute {}: {error}",
+                test.executable.path.display()
+            ),
+            duration: None,
+            max_rss: None,
+        },
+    }
+}
+
+fn not_runnable(problem: &str) -> TestOutcome {
+    TestOutcome {
+        status: TestStatus::NotRunnable,
+        output: problem.to_string(),
+        duration: None,
+        max_rss: None,
+    }
+}
+
+/// Like `Command::output`, also returning the peak memory of the process in bytes
+#[cfg(unix)]
+fn output_with_max_rss(command: &mut Command) -> io::Result<(Output, Option<u64>)> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Both pipes are read at the same time, so that the process never blocks on a full pipe
+    let (mut stdout_pipe, mut stderr_pipe) = (child.stdout.take(), child.stderr.take());
+    let (stdout, stderr) = thread::scope(|scope| {
+        let stderr = scope.spawn(|| {
+            let mut stderr = Vec::new();
+            if let Some(pipe) = &mut stderr_pipe {
+                let _ = pipe.read_to_end(&mut stderr);
+            }
+            stderr
+        });
+        let mut stdout = Vec::new();
+        if let Some(pipe) = &mut stdout_pipe {
+            let _ = pipe.read_to_end(&mut stdout);
+        }
+        (stdout, stderr.join().unwrap_or_default())
+    });
+
+    // Child::wait doesn't give the resource usage of the process, wait4 does
+    let mut status = 0;
+    // SAFETY: rusage is a plain C struct, for which zeroes are valid
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: the process is a child of this process that wasn't waited for yet
+        let pid = unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut usage) };
+        if pid != -1 {
+            break;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+
+    let output = Output {
+        status: ExitStatus::from_raw(status),
+        stdout,
+        stderr,
+    };
+    // In kilobytes on Linux
+    Ok((output, Some(usage.ru_maxrss as u64 * 1024)))
+}
+
+// Without wait4, the peak memory of the process isn't known
+#[cfg(not(unix))]
+fn output_with_max_rss(command: &mut Command) -> io::Result<(Output, Option<u64>)> {
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?
+        .wait_with_output()?;
+    Ok((output, None))
+}
+
+// The tests that aren't captured still share the terminal, but they can't be moved to its
+// foreground, nor be interrupted apart from the runner
+#[cfg(not(unix))]
+fn run_in_foreground(command: &mut Command) -> io::Result<(Output, Option<u64>)> {
+    let output = Output {
+        status: command.status()?,
+        stdout: Vec::new(),
+        stderr: Vec::new(),
+    };
+    Ok((output, None))
+}
+
+#[cfg(not(unix))]
+fn is_interrupted() -> bool {
+    false
+}
+
+/// Without fork, no test runs from a fork server: asking for them fails the run
+#[cfg(not(unix))]
+enum ForkServers {}
+
+#[cfg(not(unix))]
+impl ForkServers {
+    fn new() -> Result<Self> {
+        Err(anyhow!(
+            "--fork-server relies on fork, which this platform doesn't have"
+        ))
+    }
+
+    fn run<'a>(
+        &self,
+        _test: &Test,
+        _arguments: impl IntoIterator<Item = &'a [u8]>,
+    ) -> io::Result<(Output, Option<u64>)> {
+        match *self {}
+    }
+}
+
+// Reads the outcome of every test from a report, and deletes it
+fn read_report(report: &Path, probe: &dyn FrameworkProbe) -> Result<HashMap<String, TestOutcome>> {
+    let outcomes = fs::read_to_string(report)
+        .map_err(|error| anyhow!(error))
+        .and_then(|report| probe.parse_report(&report));
+    let _ = fs::remove_file(report);
+    outcomes
+}
+
+// A unique path in the temporary directory where an executable can write its report
+pub fn report_path(extension: &str) -> PathBuf {
+    static REPORT_NUMBER: AtomicUsize = AtomicUsize::new(0);
+    let report_number = REPORT_NUMBER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    std::env::temp_dir().join(format!(
+        "cpp_test_runner-{}-{report_number}.{extension}",
+        std::process::id()
+    ))
+}
+
+/// Runs all the tests of a single executable with one process, and reads the result of every
+/// test from the report written by the test framework. When the process dies partway, like on a
+/// crash, the tests that finished keep their result, the test that was running gets the crash, and
+/// the tests that didn't start are run again with a process each.
+fn run_group(tests: &[&Test]) -> Vec<TestOutcome> {
+    let first_test = tests[0];
+    let executable = &first_test.executable;
+    // The tests of a group share their executable and their environment
+    if let Some(problem) = test_loader_problem(first_test) {
+        return tests.iter().map(|_| not_runnable(&problem)).collect();
+    }
+
+    let probe = executable.executable_type.probe();
+    let report = report_path(probe.report_extension());
+
+    let mut command = Command::new(&executable.path);
+    child_environment::apply(&mut command)
+        .args(&executable.base_arguments)
+        .args(probe.group_args(tests))
+        .arg(probe.color_arg(false))
+        .args(probe.report_args(&report));
+
+    command.envs(&first_test.environment);
+    if let Some(cwd) = &first_test.cwd {
+        command.current_dir(cwd);
+    }
+
+    tracing::debug!(
+        tests = tests.len(),
+        ?command,
+        "starting the tests of a group"
+    );
+    let output = output_with_max_rss(&mut command);
+    // Kept for the partial results when the process died before finishing the report
+    let report_contents = fs::read_to_string(&report).ok();
+    let _ = fs::remove_file(&report);
+    let mut outcomes = report_contents
+        .as_deref()
+        .and_then(|report| probe.parse_report(report).ok())
+        .unwrap_or_default();
+
+    // The memory of the process is attributed to every test, which overestimates the one they
+    // would use alone
+    let max_rss = output.as_ref().ok().and_then(|(_, max_rss)| *max_rss);
+    let (stdout, crash_summary) = match &output {
+        Ok((output, _)) => {
+            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let crash_summary = crash_summary(
+                &executable.path,
+                output.status,
+                &format!("{stdout}\n{stderr}"),
+            );
+            (stdout, crash_summary)
+        }
+        Err(error) => (
+            String::new(),
+            Some(format!(
+                "Failed to execute {}: {error}",
+                executable.path.display()
+            )),
+        ),
+    };
+
+    let mut running = None;
+    if tests.iter().any(|test| !outcomes.contains_key(&test.name)) {
+        let partial = probe.parse_partial_results(report_contents.as_deref(), &stdout);
+        for (name, outcome) in partial.outcomes {
+            outcomes.entry(name).or_insert(outcome);
+        }
+        running = partial.running;
+        tracing::info!(
+            executable = %executable.path.display(),
+            running = running.as_ref().map(|(name, _)| name.as_str()),
+            "the process of a group died before running all its tests"
+        );
+    }
+
+    tests
+        .iter()
+        .map(|test| {
+            if let Some(outcome) = outcomes.remove(&test.name) {
+                return TestOutcome { max_rss, ..outcome };
             }
-            (false, ExecutableType::Catch2) => {
-                args.push("--colour-mode=none".to_string());
+            match &running {
+                Some((name, output)) if *name == test.name => TestOutcome {
+                    status: TestStatus::Failed,
+                    output: format!(
+                        "{}\n\n{} died while running {}{}",
+                        output.trim_end(),
+                        executable.path.display(),
+                        test.name,
+                        crash_summary
+                            .as_ref()
+                            .map(|crash_summary| format!("\n\n{crash_summary}"))
+                            .unwrap_or_default()
+                    ),
+                    duration: None,
+                    max_rss,
+                },
+                // Didn't start before the process died
+                _ => run_single(test, false),
             }
+        })
+        .collect()
+}
+
+fn format_result(
+    test: &Test,
+    outcome: &TestOutcome,
+    test_num: u32,
+    total: usize,
+    use_color: bool,
+    glyphs: Glyphs,
+) -> String {
+    let test_passed = !outcome.status.is_failure();
+
+    const DESIRED_LINE_LEN: usize = 120;
+    let to_print_first_part = std::format!("[{}/{}] {} ", test_num, total, test.display_name());
+    let status = match outcome.status {
+        TestStatus::Passed => "PASSED",
+        TestStatus::Failed => "FAILED",
+        TestStatus::Skipped => "SKIPPED",
+        TestStatus::NotRunnable => "NOT_RUNNABLE",
+    };
+    let status = Glyphs::prefix(glyphs.status(outcome.status), status);
+    let to_print_last_part = match outcome.duration {
+        Some(duration) => format!(
+            " {status} ({})",
+            Glyphs::prefix(glyphs.duration(), &humanize::duration(duration))
+        ),
+        None => format!(" {status}"),
+    };
+
+    // The glyphs and the names can have characters of several bytes
+    let number_of_chars_missing = DESIRED_LINE_LEN
+        .saturating_sub(to_print_first_part.chars().count())
+        .saturating_sub(to_print_last_part.chars().count());
+    let filling = ".".repeat(number_of_chars_missing);
+
+    let color_output = |output: &str| -> String {
+        match (use_color, &outcome.status) {
+            (true, TestStatus::Passed) => output.green().to_string(),
+            (true, TestStatus::Failed | TestStatus::NotRunnable) => output.red().to_string(),
+            (true, TestStatus::Skipped) => output.yellow().to_string(),
+            (false, _) => output.to_string(),
         }
+    };
+
+    let first_line = color_output(&format!(
+        "{to_print_first_part}{filling}{to_print_last_part}"
+    ));
+
+    if test_passed {
+        return first_line;
+    }
+    let reproduction = format!("Reproduce with: {}", reproduction_command(test));
+    // The output of the tests that aren't captured is already on the terminal
+    if outcome.output.trim().is_empty() {
+        format!("{first_line}\n{reproduction}\n")
+    } else {
+        format!(
+            "{first_line}\n\n{}\n\n{reproduction}\n",
+            outcome.output.trim()
+        )
+    }
+}
+
+// Quotes an argument for sh, unless it only has characters that sh takes literally
+fn shell_quote(argument: &str) -> Cow<'_, str> {
+    let is_literal = !argument.is_empty()
+        && argument
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c));
+    if is_literal {
+        Cow::Borrowed(argument)
+    } else {
+        Cow::Owned(format!("'{}'", argument.replace('\'', r"'\''")))
+    }
+}
+
+/// The shell command that runs the test again by itself, like
+/// `cd /src && env -i PATH="$PATH" HOME="$HOME" TZ=UTC build/unit_tests --gtest_filter=Math.Adds`.
+/// Like in the run, the test only gets the allowed variables of the environment, which are taken
+/// from the shell by name, and its own variables.
+pub fn reproduction_command(test: &Test) -> String {
+    let mut words = Vec::new();
+    if let Some(cwd) = &test.cwd {
+        words.push(format!("cd {} &&", shell_quote(&cwd.to_string_lossy())));
+    }
+    if let Some(names) = child_environment::passed_names() {
+        words.push(String::from("env -i"));
+        words.extend(
+            names
+                .iter()
+                .filter(|name| !test.environment.contains_key(*name))
+                .filter(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+                .map(|name| format!("{name}=\"${name}\"")),
+        );
+    }
+    words.extend(
+        test.environment
+            .iter()
+            .map(|(name, value)| format!("{name}={}", shell_quote(value))),
+    );
+    words.push(shell_quote(&test.executable.path.to_string_lossy()).into_owned());
+    let arguments = test
+        .executable
+        .base_arguments
+        .iter()
+        .map(String::as_str)
+        .chain(test.arguments.iter().map(|argument| &**argument));
+    words.extend(arguments.map(|argument| shell_quote(argument).into_owned()));
+    words.join(" ")
+}
+
+/// Called with every test that finishes and its outcome, while the other tests run
+pub type OnOutcome<'a> = &'a (dyn Fn(TestId, &TestOutcome) + Sync);
+
+/// Called with every test right before it starts, in the thread that runs it
+pub type OnStart<'a> = &'a (dyn Fn(TestId) + Sync);
 
-        let output = Command::new(&test.executable.path)
-            .args(args)
-            .output()
-            .unwrap();
+/// Runs the tests and returns their outcome, in the same order as the tests. Tests that were not
+/// run because of --fail-fast have no outcome.
+pub fn run_all(tests: &[Test], options: &RunOptions) -> Result<Vec<Option<TestOutcome>>> {
+    run_all_reporting(tests, options, &|_, _| {})
+}
+
+/// Like `run_all`, calling `on_outcome` as soon as every test finishes
+pub fn run_all_reporting(
+    tests: &[Test],
+    options: &RunOptions,
+    on_outcome: OnOutcome,
+) -> Result<Vec<Option<TestOutcome>>> {
+    run_all_observing(tests, options, &|_| {}, on_outcome)
+}
 
-        let test_passed = output.status.success();
+/// Like `run_all_reporting`, also calling `on_start` when every test starts
+pub fn run_all_observing(
+    tests: &[Test],
+    options: &RunOptions,
+    on_start: OnStart,
+    on_outcome: OnOutcome,
+) -> Result<Vec<Option<TestOutcome>>> {
+    let scheduler = DefaultScheduler {
+        group_by_executable: options.group_by_executable,
+        chunk_size: options.chunk_size,
+    };
 
-        if test_passed {
-            num_tests_passed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let Some(mut history) = options
+        .cache
+        .as_ref()
+        .map(|cache| MemoryHistory::load(cache.memory_history()))
+    else {
+        return run_with_scheduler_observing(tests, options, &scheduler, on_start, on_outcome);
+    };
+
+    let outcomes = match options.memory_fraction.zip(total_memory()) {
+        Some((memory_fraction, total_memory)) => {
+            let scheduler = MemoryBudgetScheduler {
+                inner: scheduler,
+                max_rss: tests.iter().map(|test| history.max_rss(test)).collect(),
+                budget: (total_memory as f64 * memory_fraction) as u64,
+            };
+            run_with_scheduler_observing(tests, options, &scheduler, on_start, on_outcome)?
         }
+        None => run_with_scheduler_observing(tests, options, &scheduler, on_start, on_outcome)?,
+    };
+
+    for (test, outcome) in tests.iter().zip(&outcomes) {
+        if let Some(max_rss) = outcome.as_ref().and_then(|outcome| outcome.max_rss) {
+            history.record(test, max_rss);
+        }
+    }
+    // The next runs only schedule less carefully without the history
+    let _ = history.save();
+
+    Ok(outcomes)
+}
+
+// How many of the next pending tests have their executable read ahead
+const PREFETCH_LOOKAHEAD: usize = 32;
 
-        let mut test_num = test_number.lock().unwrap();
-        *test_num += 1;
+/// Asks the kernel to read an executable in the background, so that it's loaded from the page
+/// cache when it runs, instead of from a slow disk or a network file system
+#[cfg(target_os = "linux")]
+fn prefetch_executable(path: &Path) {
+    let Ok(file) = File::open(path) else {
+        return;
+    };
+    // SAFETY: the file descriptor is open until the file is dropped
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_WILLNEED) };
+}
 
-        const DESIRED_LINE_LEN: usize = 120;
-        let to_print_first_part = std::format!("[{}/{}] {} ", test_num, tests.len(), test.name);
-        let to_print_last_part = if test_passed { " PASSED" } else { " FAILED" };
+// posix_fadvise is specific to Linux, and the executables are then loaded when they run
+#[cfg(not(target_os = "linux"))]
+fn prefetch_executable(_path: &Path) {}
 
-        let number_of_chars_missing =
-            DESIRED_LINE_LEN - to_print_first_part.len() - to_print_last_part.len();
-        let filling = ".".repeat(number_of_chars_missing);
+#[derive(Default)]
+struct Schedule {
+    pending: BTreeSet<TestId>,
+    running: BTreeSet<TestId>,
+}
 
-        let color_output = |output: &str| -> String {
-            match (use_color, test_passed) {
-                (true, true) => output.green().to_string(),
-                (true, false) => output.red().to_string(),
-                (false, _) => output.to_string(),
+/// Like `run_all`, with the tests picked by `scheduler`. Tests that the scheduler never picked
+/// have no outcome.
+pub fn run_all_with_scheduler(
+    tests: &[Test],
+    options: &RunOptions,
+    scheduler: &dyn Scheduler,
+) -> Result<Vec<Option<TestOutcome>>> {
+    run_with_scheduler_reporting(tests, options, scheduler, &|_, _| {})
+}
+
+/// Like `run_all_with_scheduler`, calling `on_outcome` as soon as every test finishes
+pub fn run_with_scheduler_reporting(
+    tests: &[Test],
+    options: &RunOptions,
+    scheduler: &dyn Scheduler,
+    on_outcome: OnOutcome,
+) -> Result<Vec<Option<TestOutcome>>> {
+    run_with_scheduler_observing(tests, options, scheduler, &|_| {}, on_outcome)
+}
+
+fn run_with_scheduler_observing(
+    tests: &[Test],
+    options: &RunOptions,
+    scheduler: &dyn Scheduler,
+    on_start: OnStart,
+    on_outcome: OnOutcome,
+) -> Result<Vec<Option<TestOutcome>>> {
+    let print_state = Mutex::new(PrintState::new(tests, options)); // Use a mutex to lock during printing
+    let num_tests_passed = AtomicUsize::default();
+    let num_tests_failed = AtomicUsize::default();
+    let num_tests_skipped = AtomicUsize::default();
+    let num_tests_not_runnable = AtomicUsize::default();
+    let has_failed = AtomicBool::default();
+
+    let schedule = Mutex::new(Schedule {
+        pending: (0..tests.len()).collect(),
+        running: BTreeSet::new(),
+    });
+    let outcomes = Mutex::new(vec![None; tests.len()]);
+
+    // Signaled whenever tests finish, for the threads waiting for the scheduler to pick a test
+    let tests_finished = Condvar::new();
+
+    let prefetched = Mutex::new(HashSet::new());
+
+    let fork_servers = options.fork_server.then(ForkServers::new).transpose()?;
+
+    let next_batch = || {
+        let mut schedule = schedule.lock().unwrap();
+        loop {
+            let has_failed = has_failed.load(std::sync::atomic::Ordering::Relaxed);
+            if options.fail_fast && has_failed {
+                return (Vec::new(), Vec::new());
+            }
+            let cancelled = options
+                .cancel
+                .as_ref()
+                .is_some_and(|cancel| cancel.load(std::sync::atomic::Ordering::Relaxed));
+            if cancelled || (options.no_capture && is_interrupted()) {
+                return (Vec::new(), Vec::new());
+            }
+
+            let Schedule { pending, running } = &mut *schedule;
+            let mut batch = scheduler.next_batch(&SchedulerState {
+                tests,
+                pending,
+                running,
+                has_failed,
+            });
+
+            // A test can't be run twice, even if the scheduler picks it again
+            batch.retain(|id| pending.remove(id));
+            if !batch.is_empty() || running.is_empty() || pending.is_empty() {
+                running.extend(&batch);
+
+                // The next pending tests likely run soon, so their executables are read while this
+                // batch runs
+                let mut prefetched = prefetched.lock().unwrap();
+                let upcoming = pending
+                    .iter()
+                    .take(PREFETCH_LOOKAHEAD)
+                    .map(|id| tests[*id].executable.path.as_path())
+                    .filter(|path| prefetched.insert(*path))
+                    .collect::<Vec<_>>();
+                return (batch, upcoming);
             }
-        };
 
-        let first_line = color_output(&format!(
-            "{to_print_first_part}{filling}{to_print_last_part}"
-        ));
+            schedule = tests_finished.wait(schedule).unwrap();
+        }
+    };
 
-        let to_print = if test_passed {
-            first_line
+    let run_batches = || loop {
+        let (batch, upcoming_executables) = next_batch();
+        if batch.is_empty() {
+            break;
+        }
+        for executable in upcoming_executables {
+            prefetch_executable(executable);
+        }
+        for id in &batch {
+            on_start(*id);
+        }
+
+        let mut batch_outcomes = if let [id] = batch[..] {
+            vec![run_single_with(
+                &tests[id],
+                options.use_color,
+                fork_servers.as_ref(),
+                options.no_capture,
+            )]
         } else {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            format!("{first_line}\n\n{}\n", stdout.trim())
+            run_group(&batch.iter().map(|id| &tests[*id]).collect::<Vec<_>>())
         };
 
-        println!("{to_print}");
+        if let Some(break_artifacts) = &options.break_artifacts {
+            for (id, outcome) in batch.iter().zip(&mut batch_outcomes) {
+                let test = &tests[*id];
+                if outcome.status != TestStatus::Failed
+                    || test.executable.executable_type != ExecutableType::Gtest
+                {
+                    continue;
+                }
+
+                let note = match capture_break_artifact(test, break_artifacts) {
+                    Ok(artifact) => format!(
+                        "The state at the first failing assertion was captured in {}",
+                        artifact.display()
+                    ),
+                    Err(error) => {
+                        format!(
+                            "Failed to capture the state at the first failing assertion: {error}"
+                        )
+                    }
+                };
+                outcome.output = format!("{}\n\n{note}", outcome.output.trim_end());
+            }
+        }
+
+        let mut print_state = print_state.lock().unwrap();
+        for (id, outcome) in batch.iter().zip(batch_outcomes) {
+            match outcome.status {
+                TestStatus::Passed => {
+                    num_tests_passed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                }
+                TestStatus::Skipped => {
+                    num_tests_skipped.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                }
+                TestStatus::Failed => {
+                    num_tests_failed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    has_failed.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+                TestStatus::NotRunnable => {
+                    num_tests_not_runnable.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    has_failed.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+            }
+
+            if !options.quiet {
+                print_state.print(*id, &outcome);
+            }
+
+            on_outcome(*id, &outcome);
+            outcomes.lock().unwrap()[*id] = Some(outcome);
+        }
+        drop(print_state);
+
+        let running = &mut schedule.lock().unwrap().running;
+        for id in &batch {
+            running.remove(id);
+        }
+        tests_finished.notify_all();
+    };
+
+    // Every thread of the pool pulls batches from the scheduler until there are none left. The
+    // tests that aren't captured share the terminal, so they run one at a time.
+    let num_threads = if options.no_capture {
+        1
+    } else {
+        rayon::current_num_threads()
+    };
+    rayon::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|_| run_batches());
+        }
     });
 
+    print_state.lock().unwrap().flush();
+
+    let outcomes = outcomes.into_inner().unwrap();
+    if options.quiet {
+        return Ok(outcomes);
+    }
+
     let num_tests_passed = num_tests_passed.load(std::sync::atomic::Ordering::Relaxed);
-    let num_tests_failed = tests.len() - num_tests_passed;
-    println!(
-        "{} {} passed, {} {} failed",
+    let num_tests_failed = num_tests_failed.load(std::sync::atomic::Ordering::Relaxed);
+    let num_tests_skipped = num_tests_skipped.load(std::sync::atomic::Ordering::Relaxed);
+    let num_tests_not_runnable = num_tests_not_runnable.load(std::sync::atomic::Ordering::Relaxed);
+    let glyphs = options.glyphs;
+    let mut skipped_summary = if num_tests_skipped > 0 {
+        let skipped = format!("{num_tests_skipped} skipped");
+        format!(
+            ", {}",
+            Glyphs::prefix(glyphs.status(TestStatus::Skipped), &skipped)
+        )
+    } else {
+        String::new()
+    };
+    if num_tests_not_runnable > 0 {
+        skipped_summary += &format!(", {num_tests_not_runnable} not runnable");
+    }
+    let passed = format!(
+        "{} {} passed",
         num_tests_passed,
         if num_tests_passed > 1 {
             "tests"
         } else {
             "test"
         },
+    );
+    let failed = format!(
+        "{} {} failed",
         num_tests_failed,
         if num_tests_failed > 1 {
             "tests"
@@ -91,6 +925,36 @@ pub fn run_all(tests: &[Test], use_color: bool) -> Result<()> {
             "test"
         },
     );
+    println!(
+        "{}, {}{}",
+        Glyphs::prefix(glyphs.status(TestStatus::Passed), &passed),
+        Glyphs::prefix(glyphs.status(TestStatus::Failed), &failed),
+        skipped_summary,
+    );
+
+    let num_tests_not_run = tests.len()
+        - num_tests_passed
+        - num_tests_failed
+        - num_tests_skipped
+        - num_tests_not_runnable;
+    if num_tests_not_run > 0 {
+        println!(
+            "{} {} not run{}",
+            num_tests_not_run,
+            if num_tests_not_run > 1 {
+                "tests were"
+            } else {
+                "test was"
+            },
+            if options.no_capture && is_interrupted() {
+                " because of Ctrl-C"
+            } else if options.fail_fast {
+                " because of --fail-fast"
+            } else {
+                ""
+            },
+        );
+    }
 
-    Ok(())
+    Ok(outcomes)
 }
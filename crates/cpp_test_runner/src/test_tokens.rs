@@ -0,0 +1,97 @@
+//! The words of the test names, for searches like "http timeout" that find
+//! `HttpClientTest.RequestTimesOutAfterDeadline`
+
+use std::{convert::Infallible, str::FromStr};
+
+/// Splits a test name into its lowercase words, at the separators like `.`, `_` and `/`, at the
+/// case changes of CamelCase, where `HTTPClient` is `http` and `client`, and around the numbers
+pub fn tokenize(name: &str) -> Vec<String> {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    for (index, &char) in chars.iter().enumerate() {
+        if !char.is_alphanumeric() {
+            if !token.is_empty() {
+                tokens.push(std::mem::take(&mut token));
+            }
+            continue;
+        }
+
+        if let Some(&previous) = index.checked_sub(1).map(|previous| &chars[previous]) {
+            let next = chars.get(index + 1);
+            let is_boundary = (previous.is_lowercase() && char.is_uppercase())
+                || (previous.is_uppercase()
+                    && char.is_uppercase()
+                    && next.is_some_and(|next| next.is_lowercase()))
+                || (previous.is_ascii_digit() != char.is_ascii_digit());
+            if is_boundary && !token.is_empty() {
+                tokens.push(std::mem::take(&mut token));
+            }
+        }
+        token.extend(char.to_lowercase());
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+// Whether the word is made of the starts of the tokens from `start`, like timeout of the tokens
+// times and out
+fn matches_from(word: &str, tokens: &[String], start: usize) -> bool {
+    let Some(token) = tokens.get(start) else {
+        return false;
+    };
+    let common = word
+        .char_indices()
+        .zip(token.chars())
+        .take_while(|((_, a), b)| a == b)
+        .map(|((index, char), _)| index + char.len_utf8())
+        .last()
+        .unwrap_or_default();
+    // The longest start first, so that timeout is matched by timeout before time and out
+    (1..=common)
+        .rev()
+        .filter(|&length| word.is_char_boundary(length))
+        .any(|length| length == word.len() || matches_from(&word[length..], tokens, start + 1))
+}
+
+/// A search of the tests by the words of their names, which matches the names having all its
+/// words, in any order. A word of the search matches the start of a word of the name, and can
+/// span the next ones, so that `timeout` matches `TimesOut`.
+#[derive(Debug, Clone)]
+pub struct TokenQuery {
+    query: String,
+    words: Vec<String>,
+}
+
+impl TokenQuery {
+    /// The words of the query are split like the test names, so that `HttpTimeout` is the same
+    /// as `http timeout`
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            words: tokenize(query),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.query
+    }
+
+    /// Whether the name has all the words of the query. A query without words matches every name.
+    pub fn is_match(&self, name: &str) -> bool {
+        let tokens = tokenize(name);
+        self.words
+            .iter()
+            .all(|word| (0..tokens.len()).any(|start| matches_from(word, &tokens, start)))
+    }
+}
+
+impl FromStr for TokenQuery {
+    type Err = Infallible;
+
+    fn from_str(query: &str) -> Result<Self, Infallible> {
+        Ok(Self::new(query))
+    }
+}
@@ -0,0 +1,387 @@
+use crate::{
+    glyphs::Glyphs,
+    humanize,
+    test_results::TestOutcome,
+    test_runner::{run_all_observing, RunOptions},
+    types::{Test, TestStatus},
+};
+use anyhow::{anyhow, Result};
+use cpp_test_runner::scheduler::TestId;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tuikit::prelude::*;
+
+const REFRESH_RATE: Duration = Duration::from_millis(100);
+
+struct Failure {
+    // The index of the test among all the tests of the TUI, to rerun it
+    id: TestId,
+    test: Test,
+    output: String,
+}
+
+#[derive(Default)]
+struct TuiState {
+    total: usize,
+    finished: usize,
+    passed: usize,
+    skipped: usize,
+    // Since the start of the run, when the last test finished
+    elapsed: Duration,
+    // Maps the running tests to the index of the rayon worker executing them and their name
+    running: BTreeMap<TestId, (usize, String)>,
+    failures: Vec<Failure>,
+    is_running: bool,
+    is_aborted: bool,
+    glyphs: Glyphs,
+    // Why the run stopped, when it couldn't go on
+    error: Option<anyhow::Error>,
+}
+
+struct Progress<'a>(&'a TuiState);
+
+impl Draw for Progress<'_> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        let (width, _) = canvas.size()?;
+        let state = self.0;
+
+        let status = if state.is_running && state.is_aborted {
+            "aborting"
+        } else if state.is_running {
+            "running"
+        } else if state.is_aborted {
+            "aborted"
+        } else {
+            "done"
+        };
+
+        let glyphs = state.glyphs;
+        let timing = match humanize::rate(state.finished, state.elapsed, "tests") {
+            Some(rate) => format!(", {}, {rate}", humanize::duration(state.elapsed)),
+            None => String::new(),
+        };
+        let counts = format!(
+            " {}/{} {} {} {} ({status}{timing})",
+            state.finished,
+            state.total,
+            Glyphs::prefix(
+                glyphs.status(TestStatus::Passed),
+                &format!("passed: {}", state.passed)
+            ),
+            Glyphs::prefix(
+                glyphs.status(TestStatus::Failed),
+                &format!("failed: {}", state.failures.len())
+            ),
+            Glyphs::prefix(
+                glyphs.status(TestStatus::Skipped),
+                &format!("skipped: {}", state.skipped)
+            ),
+        );
+
+        // The glyphs take a column but several bytes
+        let bar_width = width.saturating_sub(counts.chars().count() + 2);
+        let filled = (bar_width * state.finished)
+            .checked_div(state.total)
+            .unwrap_or(bar_width);
+
+        let bar_attr = if state.failures.is_empty() {
+            Attr::default().fg(Color::GREEN)
+        } else {
+            Attr::default().fg(Color::RED)
+        };
+
+        let (done, remaining) = glyphs.progress_bar();
+        canvas.print(0, 0, "[")?;
+        canvas.print_with_attr(0, 1, &done.to_string().repeat(filled), bar_attr)?;
+        canvas.print(
+            0,
+            1 + filled,
+            &remaining.to_string().repeat(bar_width - filled),
+        )?;
+        canvas.print(0, 1 + bar_width, "]")?;
+        canvas.print(0, 2 + bar_width, &counts)?;
+        Ok(())
+    }
+}
+
+impl Widget for Progress<'_> {}
+
+struct Workers<'a>(&'a TuiState);
+
+impl Draw for Workers<'_> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        for (row, (worker, test_name)) in self.0.running.values().enumerate() {
+            canvas.print(row, 0, &format!("#{worker}: {test_name}"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Widget for Workers<'_> {}
+
+struct Failures<'a> {
+    state: &'a TuiState,
+    selected: usize,
+}
+
+impl Draw for Failures<'_> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        let (_, height) = canvas.size()?;
+
+        // Keep the selected failure visible
+        let first_row = (self.selected + 1).saturating_sub(height);
+
+        for (row, failure) in self.state.failures.iter().enumerate().skip(first_row) {
+            let attr = if row == self.selected {
+                Attr::default().fg(Color::RED).effect(Effect::REVERSE)
+            } else {
+                Attr::default().fg(Color::RED)
+            };
+            let name = Glyphs::prefix(
+                self.state.glyphs.status(TestStatus::Failed),
+                &failure.test.display_name(),
+            );
+            canvas.print_with_attr(row - first_row, 0, &name, attr)?;
+        }
+        Ok(())
+    }
+}
+
+impl Widget for Failures<'_> {}
+
+struct FailureOutput<'a> {
+    failure: Option<&'a Failure>,
+    scroll: usize,
+}
+
+impl Draw for FailureOutput<'_> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        let Some(failure) = self.failure else {
+            return Ok(());
+        };
+
+        for (row, line) in failure.output.lines().skip(self.scroll).enumerate() {
+            canvas.print(row, 0, line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Widget for FailureOutput<'_> {}
+
+struct Help;
+
+impl Draw for Help {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        canvas.print_with_attr(
+            0,
+            0,
+            "q: quit  a: abort  r: rerun failures  j/k: select failure  PgUp/PgDn: scroll output",
+            Effect::DIM.into(),
+        )?;
+        Ok(())
+    }
+}
+
+impl Widget for Help {}
+
+// Runs the tests with these ids like the run subcommand does, with its scheduling, and records
+// their outcome by id
+fn execute(
+    tests: &[Test],
+    ids: &[TestId],
+    options: &RunOptions,
+    state: &Mutex<TuiState>,
+    outcomes: &Mutex<Vec<Option<TestOutcome>>>,
+) {
+    let selected_tests = ids.iter().map(|id| tests[*id].clone()).collect::<Vec<_>>();
+    let start = Instant::now();
+
+    let on_start = |id: TestId| {
+        let worker = rayon::current_thread_index().unwrap_or_default();
+        let name = selected_tests[id].display_name().into_owned();
+        state.lock().unwrap().running.insert(id, (worker, name));
+    };
+    let on_outcome = |id: TestId, outcome: &TestOutcome| {
+        let mut state = state.lock().unwrap();
+        state.running.remove(&id);
+        state.finished += 1;
+        state.elapsed = start.elapsed();
+        match outcome.status {
+            TestStatus::Passed => state.passed += 1,
+            TestStatus::Skipped => state.skipped += 1,
+            TestStatus::Failed | TestStatus::NotRunnable => state.failures.push(Failure {
+                id: ids[id],
+                test: selected_tests[id].clone(),
+                output: outcome.output.clone(),
+            }),
+        }
+        outcomes.lock().unwrap()[ids[id]] = Some(outcome.clone());
+    };
+    let result = run_all_observing(&selected_tests, options, &on_start, &on_outcome);
+
+    let mut state = state.lock().unwrap();
+    state.is_running = false;
+    state.error = result.err();
+}
+
+fn draw(term: &Term, state: &TuiState, selected: usize, scroll: usize) -> Result<()> {
+    let progress = Progress(state);
+    let workers = Workers(state);
+    let failures = Failures { state, selected };
+    let failure_output = FailureOutput {
+        failure: state.failures.get(selected),
+        scroll,
+    };
+
+    let layout = VSplit::default()
+        .split(Win::new(&progress).basis(1).grow(0))
+        .split(
+            HSplit::default()
+                .basis(Size::Percent(40))
+                .grow(0)
+                .split(Win::new(&workers).border(true).title("Running"))
+                .split(Win::new(&failures).border(true).title("Failures")),
+        )
+        .split(Win::new(&failure_output).border(true).title("Output"))
+        .split(Win::new(&Help).basis(1).grow(0));
+
+    term.draw(&layout).map_err(|error| anyhow!("{error}"))?;
+    term.present().map_err(|error| anyhow!("{error}"))?;
+    Ok(())
+}
+
+/// Runs the tests with the options of the run in a full-screen interface, and returns their
+/// outcome in the same order as the tests, the failed tests that were rerun with the outcome of
+/// their rerun. The tests that weren't run, like after an abort, have no outcome.
+pub fn run_all_tui(tests: &[Test], options: &RunOptions) -> Result<Vec<Option<TestOutcome>>> {
+    let glyphs = options.glyphs;
+    let state = Mutex::new(TuiState {
+        total: tests.len(),
+        is_running: true,
+        glyphs,
+        ..Default::default()
+    });
+    let outcomes = Mutex::new(vec![None; tests.len()]);
+    let abort = Arc::new(AtomicBool::new(false));
+    let options = RunOptions {
+        // Colors can't be displayed in the output pane, and the results are shown by the TUI
+        use_color: false,
+        quiet: true,
+        cancel: Some(abort.clone()),
+        ..options.clone()
+    };
+
+    let term: Term = Term::new().map_err(|error| anyhow!("{error}"))?;
+
+    thread::scope(|scope| -> Result<()> {
+        let (state, outcomes, options) = (&state, &outcomes, &options);
+        let ids = (0..tests.len()).collect::<Vec<_>>();
+        scope.spawn(move || execute(tests, &ids, options, state, outcomes));
+
+        let mut selected = 0;
+        let mut scroll = 0;
+
+        loop {
+            draw(&term, &state.lock().unwrap(), selected, scroll)?;
+
+            let Ok(Event::Key(key)) = term.peek_event(REFRESH_RATE) else {
+                continue;
+            };
+
+            match key {
+                Key::Char('q') | Key::Ctrl('c') | Key::ESC => {
+                    let mut state = state.lock().unwrap();
+                    state.is_aborted = state.is_running;
+                    abort.store(true, Ordering::Relaxed);
+                    break;
+                }
+                Key::Char('a') => {
+                    state.lock().unwrap().is_aborted = true;
+                    abort.store(true, Ordering::Relaxed);
+                }
+                Key::Char('r') => {
+                    let mut guard = state.lock().unwrap();
+                    if guard.is_running || guard.failures.is_empty() {
+                        continue;
+                    }
+
+                    let failed_ids = guard
+                        .failures
+                        .drain(..)
+                        .map(|failure| failure.id)
+                        .collect::<Vec<_>>();
+
+                    *guard = TuiState {
+                        total: failed_ids.len(),
+                        is_running: true,
+                        glyphs,
+                        ..Default::default()
+                    };
+                    drop(guard);
+
+                    selected = 0;
+                    scroll = 0;
+                    abort.store(false, Ordering::Relaxed);
+                    scope.spawn(move || execute(tests, &failed_ids, options, state, outcomes));
+                }
+                Key::Char('j') | Key::Down => {
+                    let num_failures = state.lock().unwrap().failures.len();
+                    if selected + 1 < num_failures {
+                        selected += 1;
+                        scroll = 0;
+                    }
+                }
+                Key::Char('k') | Key::Up if selected > 0 => {
+                    selected -= 1;
+                    scroll = 0;
+                }
+                Key::PageDown => scroll += 10,
+                Key::PageUp => scroll = scroll.saturating_sub(10),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    })?;
+
+    drop(term);
+
+    let state = state.into_inner().unwrap();
+    if let Some(error) = state.error {
+        return Err(error);
+    }
+    for failure in &state.failures {
+        println!(
+            "{} {}\n\n{}\n",
+            failure.test.display_name(),
+            Glyphs::prefix(glyphs.status(TestStatus::Failed), "FAILED"),
+            failure.output.trim()
+        );
+    }
+    println!(
+        "{}, {}, {}, {} not run",
+        Glyphs::prefix(
+            glyphs.status(TestStatus::Passed),
+            &format!("{} passed", state.passed)
+        ),
+        Glyphs::prefix(
+            glyphs.status(TestStatus::Failed),
+            &format!("{} failed", state.failures.len())
+        ),
+        Glyphs::prefix(
+            glyphs.status(TestStatus::Skipped),
+            &format!("{} skipped", state.skipped)
+        ),
+        state.total - state.finished
+    );
+
+    Ok(outcomes.into_inner().unwrap())
+}
@@ -1,100 +1,204 @@
 use clap::ValueEnum;
-use serde::Serialize;
-use skim::{ItemPreview, PreviewPosition, SkimItem};
-use std::{
-    borrow::Cow,
-    io::{BufRead, Cursor},
-    path::PathBuf,
-    sync::LazyLock,
-};
-use syntect::{
-    highlighting::{Theme, ThemeSet},
-    parsing::SyntaxSet,
-    util::as_24_bit_terminal_escaped,
-};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, collections::BTreeMap, path::PathBuf, sync::Arc};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Executable {
     pub path: PathBuf,
     pub modified: u128,
     pub executable_type: ExecutableType,
+    /// What was read from the binary, the same in the output of every command
+    #[serde(default)]
+    pub metadata: ExecutableMetadata,
+    /// What the executable needs in its environment to start, like the LD_LIBRARY_PATH of an
+    /// installed tree. Its tests are listed and run with it.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, String>,
+    /// The arguments the executable needs to start, like `--config tests.yaml`, from
+    /// tests.executables.json. Its tests are listed and run with them, before their own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub base_arguments: Vec<String>,
 }
 
-#[derive(ValueEnum, Debug, Clone, PartialEq, Serialize)]
+/// The metadata of a test executable. The machine, the type and the build-id are read by the
+/// discovery, the rest only by list --executable-metadata.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ExecutableMetadata {
+    /// Like x86-64 or aarch64, or the hexadecimal e_machine of the less common architectures
+    #[serde(default)]
+    pub machine: String,
+    /// executable, or shared-object for the position-independent executables
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    /// The GNU build-id, in hexadecimal, which matches the executable to its debug symbols
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+    /// Without a .symtab section
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stripped: Option<bool>,
+    /// With a .debug_info section
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_info: Option<bool>,
+    /// The sanitizers built into the executable, like address or undefined
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sanitizers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub needed_libraries: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+}
+
+#[derive(ValueEnum, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum ExecutableType {
     Gtest,
     Catch2,
 }
 
-#[derive(Debug, Serialize, Clone)]
+// Needed for skipping serializing false bools. Tests are also deserialized from the output of
+// list, so the skipped fields need a default.
+pub fn is_false(b: &bool) -> bool {
+    !b
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Test {
+    // Unique among the tests of a listing: the name, or the name prefixed with the end of the path
+    // of the executable, like `unit::Suite.Test`, when several executables have a test with the
+    // name. Empty in the output of list before it had ids.
+    #[serde(default)]
+    pub id: String,
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub file: Option<PathBuf>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub line: Option<u32>,
-    pub executable: Executable,
-    pub arguments: Vec<String>,
+    // Shared by all the tests of an executable, which can have hundreds of thousands of tests
+    pub executable: Arc<Executable>,
+    pub arguments: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub hidden: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub disabled: bool,
+    // The parameter of a value-parameterized gtest test
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub param: Option<String>,
+    // The type of a typed gtest test
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_param: Option<String>,
+    // The cell of --matrix the test is run in, like ["TZ=UTC", "LC_ALL=C"]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matrix: Vec<String>,
+    // The other executables that register the test from the same source location, like when an
+    // object library with the test is linked into several executables
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub also_registered_by: Vec<PathBuf>,
+
+    #[serde(default, skip_serializing_if = "TestAttributes::is_empty")]
+    pub attributes: TestAttributes,
+    // How the source of the test was compiled, from --compile-commands. Shared by the tests of a
+    // source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compile_command: Option<Arc<CompileCommand>>,
 
-    #[serde(skip_serializing)]
+    #[serde(skip)]
     pub index: Option<usize>,
+    #[serde(skip)]
+    pub last_status: Option<TestStatus>,
 }
 
-static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
-static THEME: LazyLock<Theme> = LazyLock::new(|| {
-    let theme = include_str!("Catppuccin Macchiato.tmTheme");
-    let mut reader = Cursor::new(theme);
-    ThemeSet::load_from_reader(&mut reader).unwrap()
-});
+/// An entry of a compile_commands.json
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CompileCommand {
+    /// The working directory of the compiler
+    pub directory: PathBuf,
+    pub arguments: Vec<String>,
+    /// The target triple, like aarch64-linux-gnu, from --target or from the name of a cross
+    /// compiler
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
 
-impl SkimItem for Test {
-    fn text(&self) -> Cow<str> {
-        Cow::Borrowed(&self.name)
-    }
+/// Annotations of a test that the test frameworks can't express, read from a
+/// tests.attributes.json file
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TestAttributes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// In seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+    /// The test must not run at the same time as other tests
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub serial: bool,
+    /// The test is expected to fail
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub xfail: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+}
 
-    fn get_index(&self) -> usize {
-        self.index.unwrap_or_default()
+impl TestAttributes {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
     }
 
-    fn set_index(&mut self, index: usize) {
-        self.index = Some(index);
+    /// The owner and the timeout of `other` replace the current ones, the other attributes are
+    /// added to the current ones
+    pub fn merge(&mut self, other: &TestAttributes) {
+        if other.owner.is_some() {
+            self.owner.clone_from(&other.owner);
+        }
+        if other.timeout.is_some() {
+            self.timeout = other.timeout;
+        }
+        self.serial |= other.serial;
+        self.xfail |= other.xfail;
+        for label in &other.labels {
+            if !self.labels.contains(label) {
+                self.labels.push(label.clone());
+            }
+        }
     }
+}
 
-    fn preview(&self, _context: skim::prelude::PreviewContext) -> ItemPreview {
-        if let Some(file) = &self.file {
-            let mut highlighter =
-                syntect::easy::HighlightFile::new(file, &SYNTAX_SET, &THEME).unwrap();
-
-            let mut content = String::default();
-            let mut line = String::default();
-            while highlighter.reader.read_line(&mut line).unwrap_or(0) > 0 {
-                let regions: Vec<_> = highlighter
-                    .highlight_lines
-                    .highlight_line(&line, &SYNTAX_SET)
-                    .unwrap();
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Skipped,
+    /// The dynamic loader can't start the executable of the test, e.g. because of a missing
+    /// library, so it wasn't run
+    NotRunnable,
+}
 
-                content.push_str(&as_24_bit_terminal_escaped(&regions[..], false));
-                line.clear();
-            }
+impl TestStatus {
+    pub fn is_failure(&self) -> bool {
+        matches!(self, TestStatus::Failed | TestStatus::NotRunnable)
+    }
+}
 
-            ItemPreview::AnsiWithPos(
-                content,
-                PreviewPosition {
-                    v_scroll: tuikit::prelude::Size::Fixed(self.line.unwrap_or(0) as usize),
-                    ..Default::default()
-                },
-            )
+impl Test {
+    /// The name of the test, followed by its cell of --matrix, like `Suite.Test [TZ=UTC]`, which
+    /// tells apart the runs of the test in the cells
+    pub fn display_name(&self) -> Cow<'_, str> {
+        if self.matrix.is_empty() {
+            Cow::Borrowed(&self.name)
         } else {
-            ItemPreview::Global
+            Cow::Owned(format!("{} [{}]", self.name, self.matrix.join(", ")))
         }
     }
-}
 
-impl Test {
     pub fn clone_with_index(&self, index: usize) -> Self {
         let mut clone = self.clone();
-        clone.set_index(index);
+        clone.index = Some(index);
         clone
     }
 }
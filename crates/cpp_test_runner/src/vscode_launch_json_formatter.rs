@@ -1,12 +1,166 @@
-use crate::{types::Test, CwdRelativeTo, LaunchJsonCommand};
+use crate::{
+    cwd_template::resolve_cwd_template,
+    types::{is_false, Test},
+};
+use anyhow::{anyhow, bail, Error, Result};
+use clap::{Args, ValueEnum};
 use serde::Serialize;
-use std::{ops::Deref, path::PathBuf};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    ops::Deref,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-// Needed for skipping serializing false bools
-fn is_false(b: &bool) -> bool {
-    !b
+/// The debugger extension that the configurations are for, which each have their own fields
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum LaunchType {
+    /// The debugger of the C/C++ extension, with gdb or lldb
+    #[default]
+    Cppdbg,
+    /// CodeLLDB
+    Lldb,
+    /// The debugger of the C/C++ extension for MSVC executables, on Windows
+    Cppvsdbg,
 }
 
+impl LaunchType {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Cppdbg => "cppdbg",
+            Self::Lldb => "lldb",
+            Self::Cppvsdbg => "cppvsdbg",
+        }
+    }
+}
+
+/// The debugger that cppdbg drives
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum MiMode {
+    #[default]
+    Gdb,
+    Lldb,
+}
+
+/// A variable of --launch-env, like `ASAN_OPTIONS=detect_leaks=0`
+#[derive(Debug, Clone)]
+pub struct LaunchEnv {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for LaunchEnv {
+    type Err = Error;
+
+    fn from_str(variable: &str) -> Result<Self> {
+        match variable.split_once('=') {
+            Some((name, value)) if !name.is_empty() => Ok(Self {
+                name: name.to_string(),
+                value: value.to_string(),
+            }),
+            _ => bail!("The variable {variable} isn't like KEY=VALUE"),
+        }
+    }
+}
+
+/// A mapping of --source-map, from the directory that the executables were built in to where the
+/// sources are on this machine
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    pub build: String,
+    pub source: String,
+}
+
+impl FromStr for SourceMap {
+    type Err = Error;
+
+    fn from_str(mapping: &str) -> Result<Self> {
+        match mapping.split_once('=') {
+            Some((build, source)) if !build.is_empty() && !source.is_empty() => Ok(Self {
+                build: build.to_string(),
+                source: source.to_string(),
+            }),
+            _ => bail!("The source map {mapping} isn't like /build/path=/src/path"),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Default)]
+pub enum CwdRelativeTo {
+    #[default]
+    Executable,
+    CppFile,
+    None,
+}
+
+/// How the launch configurations of the tests are generated, which are the options of the
+/// launch-json subcommand
+#[derive(Debug, Clone, Args)]
+pub struct LaunchJsonOptions {
+    /// The debugger extension of the launch configuration: cppdbg for the C/C++ extension, lldb
+    /// for CodeLLDB, or cppvsdbg for the C/C++ extension with MSVC executables.
+    #[arg(long, value_enum, default_value = "cppdbg")]
+    pub launch_type: LaunchType,
+
+    /// The debugger that cppdbg drives.
+    #[arg(long, value_enum, default_value = "gdb")]
+    pub mi_mode: MiMode,
+
+    /// The request type of the launch configuration.
+    #[arg(long, default_value = "launch")]
+    pub launch_request: String,
+
+    /// The cwd of the tests. Change launch-cwd-relative-to to modify to what the cwd is relative to.
+    /// Supports the {exe_dir}, {src_dir}, {workspace} and {tmp} placeholders, like --run-cwd.
+    #[arg(long, default_value = ".")]
+    pub launch_cwd: String,
+
+    /// Controls to what the cwd is relative to.
+    #[arg(long, value_enum, default_value = "executable")]
+    pub launch_cwd_relative_to: CwdRelativeTo,
+
+    /// Appends the executable path to the test name. Useful for distinguishing between tests with duplicate names.
+    #[arg(long)]
+    pub add_exec_path_to_name: bool,
+
+    /// Name the configurations by the id of the tests instead of their name, which tells apart the
+    /// tests with the same name in several executables. Set by --disambiguate-duplicates.
+    #[arg(skip)]
+    pub name_by_id: bool,
+
+    /// Only print the list of configurations.
+    #[arg(long)]
+    pub configurations_only: bool,
+
+    /// Add the stopAtEntry option to the config.
+    #[arg(long)]
+    pub stop_at_entry: bool,
+
+    /// Enable pretty printing in gdb. CodeLLDB always formats the standard library types.
+    #[arg(long)]
+    pub pretty_printing: bool,
+
+    /// Set this environment variable in the configurations, after the environment of the tests.
+    /// Can be repeated.
+    #[arg(long, value_name = "KEY=VALUE")]
+    pub launch_env: Vec<LaunchEnv>,
+
+    /// Make the debugger find the sources compiled in /build/path in /src/path, for executables
+    /// built in a container or on another machine. Can be repeated.
+    #[arg(long, value_name = "/build/path=/src/path")]
+    pub source_map: Vec<SourceMap>,
+
+    /// Rebuild the tests with this shell command before debugging them, with a task of the
+    /// tasks.json that the configurations run as their preLaunchTask. --write adds the task to the
+    /// tasks.json, and the tasks-json subcommand prints it.
+    #[arg(long, value_name = "COMMAND")]
+    pub build_command: Option<String>,
+}
+
+/// The label of the task of --build-command, which the configurations run before launching
+pub const BUILD_TASK_LABEL: &str = "cpp_test_runner: build";
+
 #[derive(Debug, Clone, Serialize)]
 struct VscodeLaunchJson {
     version: String,
@@ -14,12 +168,36 @@ struct VscodeLaunchJson {
 }
 
 #[derive(Debug, Clone, Serialize)]
+struct VscodeTasksJson {
+    version: String,
+    tasks: Vec<Task>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Task {
+    label: &'static str,
+    r#type: &'static str,
+    command: String,
+    group: &'static str,
+    /// Shows the errors of gcc and clang in the Problems panel
+    problem_matcher: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 struct SetupCommand {
     text: String,
     description: String,
     ignore_failures: bool,
 }
 
+#[derive(Debug, Clone, Serialize)]
+struct EnvironmentVariable {
+    name: String,
+    value: String,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct Configuration {
@@ -29,23 +207,55 @@ struct Configuration {
     program: String,
     args: Vec<String>,
     cwd: PathBuf,
+    /// Of cppdbg and cppvsdbg
     #[serde(skip_serializing_if = "is_false")]
     stop_at_entry: bool,
+    /// Of CodeLLDB
+    #[serde(skip_serializing_if = "is_false")]
+    stop_on_entry: bool,
+    /// Of cppdbg
+    #[serde(rename = "MIMode", skip_serializing_if = "Option::is_none")]
+    mi_mode: Option<&'static str>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     setup_commands: Vec<SetupCommand>,
+    /// The languages of the sources for CodeLLDB, which then stops on the C++ exceptions
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    source_languages: Vec<&'static str>,
+    /// The environment of the test, in the format of cppdbg and cppvsdbg
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    environment: Vec<EnvironmentVariable>,
+    /// The environment of the test, in the format of CodeLLDB
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    /// The directories of the build mapped to the ones of the sources, for cppdbg and cppvsdbg
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    source_file_map: BTreeMap<String, String>,
+    /// The same, for CodeLLDB
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    source_map: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pre_launch_task: Option<&'static str>,
 }
 
-pub fn format_tests_to_vscode_launch_json(tests: &[Test], command: &LaunchJsonCommand) -> String {
-    let configurations = tests
+fn launch_configurations(
+    tests: &[Test],
+    options: &LaunchJsonOptions,
+    workspace: &Path,
+) -> Vec<Configuration> {
+    tests
         .iter()
         .map(|test| {
-            let name = if command.add_exec_path_to_name {
+            let name = if options.add_exec_path_to_name {
                 format!("{}:{}", test.name, test.executable.path.display())
+            } else if options.name_by_id {
+                test.id.clone()
             } else {
                 test.name.clone()
             };
 
-            let cwd = match command.launch_cwd_relative_to {
+            let launch_cwd = resolve_cwd_template(&options.launch_cwd, test, workspace);
+
+            let cwd = match options.launch_cwd_relative_to {
                 CwdRelativeTo::Executable => {
                     let executable_directory = test
                         .executable
@@ -53,7 +263,7 @@ pub fn format_tests_to_vscode_launch_json(tests: &[Test], command: &LaunchJsonCo
                         .parent()
                         .unwrap_or_else(|| &test.executable.path);
 
-                    executable_directory.join(&command.launch_cwd)
+                    executable_directory.join(&launch_cwd)
                 }
                 CwdRelativeTo::CppFile => {
                     let cpp_file_path = if let Some(file) = &test.file {
@@ -64,14 +274,16 @@ pub fn format_tests_to_vscode_launch_json(tests: &[Test], command: &LaunchJsonCo
                     let cpp_file_directory =
                         cpp_file_path.parent().unwrap_or_else(|| &cpp_file_path);
 
-                    cpp_file_directory.join(&command.launch_cwd)
+                    cpp_file_directory.join(&launch_cwd)
                 }
-                CwdRelativeTo::None => command.launch_cwd.to_path_buf(),
+                CwdRelativeTo::None => launch_cwd,
             }
             .canonicalize()
             .unwrap();
 
-            let setup_commands = if command.pretty_printing {
+            let launch_type = options.launch_type;
+            let uses_gdb = launch_type == LaunchType::Cppdbg && options.mi_mode == MiMode::Gdb;
+            let setup_commands = if options.pretty_printing && uses_gdb {
                 vec![SetupCommand {
                     text: String::from("-enable-pretty-printing"),
                     description: String::from("Enable pretty printing"),
@@ -81,20 +293,76 @@ pub fn format_tests_to_vscode_launch_json(tests: &[Test], command: &LaunchJsonCo
                 Vec::default()
             };
 
+            let mut test_environment = test.environment.clone();
+            test_environment.extend(
+                options
+                    .launch_env
+                    .iter()
+                    .map(|variable| (variable.name.clone(), variable.value.clone())),
+            );
+            let (environment, env) = if launch_type != LaunchType::Lldb {
+                let environment = test_environment
+                    .into_iter()
+                    .map(|(name, value)| EnvironmentVariable { name, value })
+                    .collect();
+                (environment, BTreeMap::new())
+            } else {
+                (Vec::new(), test_environment)
+            };
+
+            let source_maps = options
+                .source_map
+                .iter()
+                .map(|mapping| (mapping.build.clone(), mapping.source.clone()))
+                .collect::<BTreeMap<_, _>>();
+            let (source_file_map, source_map) = if launch_type != LaunchType::Lldb {
+                (source_maps, BTreeMap::new())
+            } else {
+                (BTreeMap::new(), source_maps)
+            };
+
             Configuration {
                 name,
-                r#type: command.launch_type.to_string(),
-                request: command.launch_request.to_string(),
+                r#type: launch_type.as_str().to_string(),
+                request: options.launch_request.to_string(),
                 program: test.executable.path.to_string_lossy().deref().to_string(),
-                args: test.arguments.clone(),
-                stop_at_entry: command.stop_at_entry,
+                args: test
+                    .executable
+                    .base_arguments
+                    .iter()
+                    .cloned()
+                    .chain(test.arguments.iter().map(|argument| argument.to_string()))
+                    .collect(),
+                stop_at_entry: options.stop_at_entry && launch_type != LaunchType::Lldb,
+                stop_on_entry: options.stop_at_entry && launch_type == LaunchType::Lldb,
+                mi_mode: (launch_type == LaunchType::Cppdbg).then_some(match options.mi_mode {
+                    MiMode::Gdb => "gdb",
+                    MiMode::Lldb => "lldb",
+                }),
                 cwd,
                 setup_commands,
+                source_languages: match launch_type {
+                    LaunchType::Lldb => vec!["cpp"],
+                    LaunchType::Cppdbg | LaunchType::Cppvsdbg => Vec::new(),
+                },
+                environment,
+                env,
+                source_file_map,
+                source_map,
+                pre_launch_task: options.build_command.as_ref().map(|_| BUILD_TASK_LABEL),
             }
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
+
+pub fn format_tests_to_vscode_launch_json(
+    tests: &[Test],
+    options: &LaunchJsonOptions,
+    workspace: &Path,
+) -> String {
+    let configurations = launch_configurations(tests, options, workspace);
 
-    if command.configurations_only {
+    if options.configurations_only {
         serde_json::to_string_pretty(&configurations).unwrap()
     } else {
         let launch_json = VscodeLaunchJson {
@@ -104,3 +372,166 @@ pub fn format_tests_to_vscode_launch_json(tests: &[Test], command: &LaunchJsonCo
         serde_json::to_string_pretty(&launch_json).unwrap()
     }
 }
+
+fn build_task(build_command: &str) -> Task {
+    Task {
+        label: BUILD_TASK_LABEL,
+        r#type: "shell",
+        command: build_command.to_string(),
+        group: "build",
+        problem_matcher: vec!["$gcc"],
+    }
+}
+
+/// A tasks.json with the task of --build-command
+pub fn format_vscode_tasks_json(build_command: &str) -> String {
+    let tasks_json = VscodeTasksJson {
+        version: String::from("2.0.0"),
+        tasks: vec![build_task(build_command)],
+    };
+    serde_json::to_string_pretty(&tasks_json).unwrap()
+}
+
+/// A file of the .vscode directory with the generated entries, and what changed in it
+#[derive(Debug)]
+pub struct VscodeFileUpdate {
+    pub contents: String,
+    /// The names of the entries that weren't in the file
+    pub added: Vec<String>,
+    /// The names of the entries that were replaced, with their fields that changed
+    pub changed: Vec<(String, Vec<String>)>,
+}
+
+impl VscodeFileUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Adds the configurations of the tests to an existing launch.json. The configurations with the
+/// same name as a test are replaced, and the others are kept, like the ones written by hand.
+pub fn update_vscode_launch_json(
+    existing: Option<&str>,
+    tests: &[Test],
+    options: &LaunchJsonOptions,
+    workspace: &Path,
+) -> Result<VscodeFileUpdate> {
+    let configurations = launch_configurations(tests, options, workspace)
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    update_entries(
+        existing,
+        EntriesFile {
+            file_name: "launch.json",
+            version: "0.2.0",
+            entries_key: "configurations",
+            name_key: "name",
+        },
+        configurations,
+    )
+}
+
+/// Adds the task of --build-command to an existing tasks.json, replacing the task with its label
+/// and keeping the others
+pub fn update_vscode_tasks_json(
+    existing: Option<&str>,
+    build_command: &str,
+) -> Result<VscodeFileUpdate> {
+    let task = serde_json::to_value(build_task(build_command))?;
+    update_entries(
+        existing,
+        EntriesFile {
+            file_name: "tasks.json",
+            version: "2.0.0",
+            entries_key: "tasks",
+            name_key: "label",
+        },
+        vec![task],
+    )
+}
+
+// The layout of launch.json and tasks.json: an object with a version and an array of entries that
+// are identified by one of their fields
+struct EntriesFile {
+    file_name: &'static str,
+    version: &'static str,
+    entries_key: &'static str,
+    name_key: &'static str,
+}
+
+fn update_entries(
+    existing: Option<&str>,
+    file: EntriesFile,
+    new_entries: Vec<Value>,
+) -> Result<VscodeFileUpdate> {
+    let EntriesFile {
+        file_name,
+        version,
+        entries_key,
+        name_key,
+    } = file;
+    let mut json = match existing {
+        Some(existing) => serde_json::from_str::<Value>(existing).map_err(|error| {
+            anyhow!(
+                "The {file_name} isn't plain JSON, without comments nor trailing commas: {error}"
+            )
+        })?,
+        None => json!({ "version": version, entries_key: [] }),
+    };
+    let Some(json_object) = json.as_object_mut() else {
+        bail!("The {file_name} isn't a JSON object");
+    };
+    let Some(entries) = json_object
+        .entry(entries_key)
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+    else {
+        bail!("The {entries_key} of the {file_name} aren't an array");
+    };
+
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    // Tests with the same name in several executables all get a configuration
+    let num_existing = entries.len();
+    for entry in new_entries {
+        let name = entry[name_key].as_str().unwrap_or_default().to_string();
+
+        let existing = entries[..num_existing]
+            .iter_mut()
+            .find(|existing| existing[name_key] == entry[name_key]);
+        match existing {
+            Some(existing) if *existing == entry => {}
+            Some(existing) => {
+                let fields = changed_fields(existing, &entry);
+                *existing = entry;
+                changed.push((name, fields));
+            }
+            None => {
+                entries.push(entry);
+                added.push(name);
+            }
+        }
+    }
+
+    Ok(VscodeFileUpdate {
+        contents: serde_json::to_string_pretty(&json)? + "\n",
+        added,
+        changed,
+    })
+}
+
+fn changed_fields(old: &Value, new: &Value) -> Vec<String> {
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return Vec::new();
+    };
+    let mut fields = old
+        .keys()
+        .chain(new.keys())
+        .filter(|field| old.get(*field) != new.get(*field))
+        .cloned()
+        .collect::<Vec<_>>();
+    fields.sort();
+    fields.dedup();
+    fields
+}
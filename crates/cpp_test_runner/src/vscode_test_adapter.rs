@@ -0,0 +1,233 @@
+use cpp_test_runner::{
+    test_results::TestOutcome,
+    types::{ExecutableType, Test, TestStatus},
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::Path,
+};
+
+/// A position in a file, with lines and characters counted from 0
+#[derive(Debug, Serialize)]
+struct Position {
+    line: u32,
+    character: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct Range {
+    start: Position,
+    end: Position,
+}
+
+#[derive(Debug, Serialize)]
+struct Location {
+    uri: String,
+    range: Range,
+}
+
+/// An item of the tree of the Test Explorer: an executable, a gtest suite or a test
+#[derive(Debug, Serialize)]
+pub struct TestItem {
+    id: String,
+    label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<Range>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<TestItem>,
+}
+
+impl TestItem {
+    fn container(id: String, label: String, uri: Option<String>) -> Self {
+        Self {
+            id,
+            label,
+            uri,
+            range: None,
+            tags: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TestMessage {
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+}
+
+/// An event of a run, named after the method of the TestRun of VS Code that it maps to
+#[derive(Debug, Serialize)]
+struct RunEvent<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    test: Option<String>,
+    // The cell of --matrix, when the test is run several times
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    matrix: &'a [String],
+    /// In milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<TestMessage>,
+}
+
+// Only the characters that can't be in a URI are escaped
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                uri.push(*byte as char);
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+/// The id of the item of a test, which is the same in the list and in the events of the runs
+fn test_id(test: &Test) -> String {
+    format!("{}::{}", test.executable.path.display(), test.name)
+}
+
+fn test_location(test: &Test) -> Option<Location> {
+    let file = test.file.as_ref()?;
+    let line = test.line.unwrap_or(1).saturating_sub(1);
+    Some(Location {
+        uri: file_uri(file),
+        range: Range {
+            start: Position { line, character: 0 },
+            end: Position { line, character: 0 },
+        },
+    })
+}
+
+/// The tree of the Test Explorer: the executables, containing the gtest suites, which contain the
+/// tests, or directly the tests for Catch2. The items are in the order of the tests.
+pub fn test_items(tests: &[Test]) -> Vec<TestItem> {
+    let mut executables = Vec::<TestItem>::new();
+    // The index of every executable and suite in their parent
+    let mut indices = HashMap::<String, usize>::new();
+
+    for test in tests {
+        let executable_id = test.executable.path.display().to_string();
+        let executable_index = *indices.entry(executable_id.clone()).or_insert_with(|| {
+            let label = test
+                .executable
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            let uri = Some(file_uri(&test.executable.path));
+            executables.push(TestItem::container(executable_id.clone(), label, uri));
+            executables.len() - 1
+        });
+        let executable = &mut executables[executable_index];
+
+        let (parent, label) = match test.name.split_once('.') {
+            Some((suite, name)) if test.executable.executable_type == ExecutableType::Gtest => {
+                let suite_id = format!("{executable_id}::{suite}");
+                let suite_index = *indices.entry(suite_id.clone()).or_insert_with(|| {
+                    let uri = test.file.as_deref().map(file_uri);
+                    let suite = TestItem::container(suite_id, suite.to_string(), uri);
+                    executable.children.push(suite);
+                    executable.children.len() - 1
+                });
+                (&mut executable.children[suite_index], name)
+            }
+            _ => (executable, test.name.as_str()),
+        };
+
+        let location = test_location(test);
+        parent.children.push(TestItem {
+            id: test_id(test),
+            label: label.to_string(),
+            uri: location.as_ref().map(|location| location.uri.clone()),
+            range: location.map(|location| location.range),
+            tags: test.tags.clone(),
+            children: Vec::new(),
+        });
+    }
+
+    executables
+}
+
+fn write_event(writer: &mut impl Write, event: &RunEvent) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, event)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+/// Writes an `enqueued` event per test, before the run starts
+pub fn write_enqueued(writer: &mut impl Write, tests: &[Test]) -> io::Result<()> {
+    for test in tests {
+        write_event(
+            writer,
+            &RunEvent {
+                kind: "enqueued",
+                test: Some(test_id(test)),
+                matrix: &test.matrix,
+                duration: None,
+                message: None,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the `passed`, `failed`, `skipped` or `errored` event of a test that finished. The
+/// message of a failure is the output of the test, at the location of the test.
+pub fn write_outcome(
+    writer: &mut impl Write,
+    test: &Test,
+    outcome: &TestOutcome,
+) -> io::Result<()> {
+    let message = || TestMessage {
+        message: outcome.output.trim().to_string(),
+        location: test_location(test),
+    };
+    let (kind, message) = match outcome.status {
+        TestStatus::Passed => ("passed", None),
+        TestStatus::Failed => ("failed", Some(message())),
+        TestStatus::Skipped => ("skipped", None),
+        // The test couldn't start, which is an error rather than a failure of the test
+        TestStatus::NotRunnable => ("errored", Some(message())),
+    };
+
+    write_event(
+        writer,
+        &RunEvent {
+            kind,
+            test: Some(test_id(test)),
+            matrix: &test.matrix,
+            duration: outcome
+                .duration
+                .map(|duration| duration.as_secs_f64() * 1000.0),
+            message,
+        },
+    )
+}
+
+/// Writes the `end` event, once all the tests have run
+pub fn write_end(writer: &mut impl Write) -> io::Result<()> {
+    write_event(
+        writer,
+        &RunEvent {
+            kind: "end",
+            test: None,
+            matrix: &[],
+            duration: None,
+            message: None,
+        },
+    )
+}
@@ -0,0 +1,169 @@
+// A minimal imitation of a Catch2 v3 executable, implementing the parts of its command line
+// used by cpp_test_runner: --libidentify, --list-tests --reporter=JSON, test specs made of
+// escaped names, and --reporter xml --out <file>.
+#include <cstdio>
+#include <string>
+#include <vector>
+
+namespace Catch2 {
+// Catch2 executables are recognized by this symbol
+void init() {}
+}  // namespace Catch2
+
+enum class Result { Pass, Fail, Skip };
+
+struct TestCase {
+    std::string name;
+    std::vector<std::string> tags;
+    int line;
+    Result result;
+};
+
+static const std::vector<TestCase> tests = {
+    {"vector grows", {"unit", "vector"}, 30, Result::Pass},
+    {"parses: weird*name", {"unit"}, 35, Result::Pass},
+    {"db roundtrip", {"integration", "slow"}, 40, Result::Fail},
+    {"hidden check", {".", "unit"}, 45, Result::Pass},
+    {"not on this platform", {"unit"}, 50, Result::Skip},
+#ifdef UNUSUAL_NAMES
+    // Names with the special characters of the test specs, one of them starting like another
+    {"commas, [brackets] and \\backslashes", {"unit"}, 55, Result::Pass},
+    {"commas", {"unit"}, 55, Result::Fail},
+    {"stars * and ~tildes", {"unit"}, 55, Result::Pass},
+#endif
+};
+
+static std::string json_escaped(const std::string& text) {
+    std::string escaped;
+    for (char c : text) {
+        if (c == '\\' || c == '"') escaped += '\\';
+        escaped += c;
+    }
+    return escaped;
+}
+
+static bool is_hidden(const TestCase& test) { return test.tags[0] == "."; }
+
+// Splits a test spec on the unescaped commas, and removes the escapes
+static std::vector<std::string> parse_spec(const std::string& spec) {
+    std::vector<std::string> names(1);
+    for (size_t i = 0; i < spec.size(); ++i) {
+        if (spec[i] == '\\' && i + 1 < spec.size()) {
+            names.back() += spec[++i];
+        } else if (spec[i] == ',') {
+            names.emplace_back();
+        } else {
+            names.back() += spec[i];
+        }
+    }
+    return names;
+}
+
+static void write_listing(bool include_hidden) {
+    printf("{\"version\":1,\"metadata\":{},\"listings\":{\"tests\":[");
+    bool is_first = true;
+    for (const TestCase& test : tests) {
+        if (is_hidden(test) && !include_hidden) continue;
+        printf("%s{\"name\":\"%s\",\"class-name\":\"\",\"tags\":[", is_first ? "" : ",",
+               json_escaped(test.name).c_str());
+        for (size_t i = 0; i < test.tags.size(); ++i) {
+            printf("%s\"%s\"", i == 0 ? "" : ",", test.tags[i].c_str());
+        }
+        printf("],\"source-location\":{\"filename\":\"catch2_fixture.cpp\",\"line\":%d}}",
+               test.line);
+        is_first = false;
+    }
+    printf("]}}\n");
+}
+
+static void write_test_case(FILE* report, const TestCase& test) {
+    std::string tags;
+    for (const std::string& tag : test.tags) tags += "[" + tag + "]";
+    fprintf(report, "  <TestCase name=\"%s\" tags=\"%s\" filename=\"catch2_fixture.cpp\" line=\"%d\">\n",
+            test.name.c_str(), tags.c_str(), test.line);
+    if (test.result == Result::Skip) {
+        fprintf(report, "    <Skip filename=\"catch2_fixture.cpp\" line=\"%d\">\n      not today\n    </Skip>\n",
+                test.line + 1);
+    }
+    if (test.result == Result::Fail) {
+        fprintf(report,
+                "    <Section name=\"roundtrip\" filename=\"catch2_fixture.cpp\" line=\"%d\">\n"
+                "      <Expression success=\"false\" type=\"REQUIRE\" filename=\"catch2_fixture.cpp\" line=\"%d\">\n"
+                "        <Original>\n          a == b\n        </Original>\n"
+                "        <Expanded>\n          1 == 2\n        </Expanded>\n"
+                "      </Expression>\n"
+                "    </Section>\n",
+                test.line + 1, test.line + 2);
+    }
+    fprintf(report, "    <OverallResult success=\"%s\" skips=\"%d\" durationInSeconds=\"0.002\"/>\n  </TestCase>\n",
+            test.result == Result::Fail ? "false" : "true", test.result == Result::Skip ? 1 : 0);
+}
+
+int main(int argc, char** argv) {
+    Catch2::init();
+
+    bool list = false;
+    std::string reporter;
+    std::string output;
+    std::vector<std::string> names;
+    for (int i = 1; i < argc; ++i) {
+        std::string arg = argv[i];
+        if (arg == "--libidentify") {
+            printf("description:    A Catch2 test executable\ncategory:       testframework\n"
+                   "framework:      Catch2\nversion:        3.4.0\n");
+            return 0;
+        }
+        if (arg == "--list-tests") {
+            list = true;
+        } else if (arg == "--reporter" && i + 1 < argc) {
+            reporter = argv[++i];
+        } else if (arg.rfind("--reporter=", 0) == 0) {
+            reporter = arg.substr(11);
+        } else if (arg == "--out" && i + 1 < argc) {
+            output = argv[++i];
+        } else if (arg.rfind("--", 0) != 0) {
+            for (const std::string& name : parse_spec(arg)) names.push_back(name);
+        }
+    }
+
+    // Hidden tests are only listed when a spec explicitly matches them
+    bool include_hidden = false;
+    for (const std::string& name : names) include_hidden |= name == "*" || name == "[.]";
+
+    if (list) {
+        write_listing(include_hidden);
+        return 0;
+    }
+
+    FILE* report = reporter == "xml" && !output.empty() ? fopen(output.c_str(), "w") : nullptr;
+    if (report) fprintf(report, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Catch2TestRun name=\"catch2_fixture\">\n");
+
+    int ran = 0;
+    int failed = 0;
+    int skipped = 0;
+    for (const TestCase& test : tests) {
+        bool is_selected = names.empty() && !is_hidden(test);
+        for (const std::string& name : names) is_selected |= name == test.name;
+        if (!is_selected) continue;
+
+        ++ran;
+        failed += test.result == Result::Fail;
+        skipped += test.result == Result::Skip;
+
+        if (report) {
+            write_test_case(report, test);
+        } else if (test.result == Result::Fail) {
+            printf("catch2_fixture.cpp:%d: FAILED:\n  REQUIRE( a == b )\nwith expansion:\n  1 == 2\n",
+                   test.line + 2);
+        }
+    }
+
+    if (report) {
+        fprintf(report, "</Catch2TestRun>\n");
+        fclose(report);
+    }
+
+    // Like Catch2, fail when all the tests that ran were skipped
+    if (ran > 0 && skipped == ran) return 4;
+    return failed;
+}
@@ -0,0 +1,214 @@
+// A minimal imitation of a GoogleTest executable, implementing the parts of its command line
+// used by cpp_test_runner: --gtest_list_tests, --gtest_filter, --gtest_output=json:<file> and
+// --gtest_also_run_disabled_tests.
+#include <cstdio>
+#include <cstdlib>
+#include <string>
+#include <vector>
+
+namespace testing {
+// gtest executables are recognized by this symbol
+void InitGoogleTest(int*, char**) {}
+}  // namespace testing
+
+enum class Result { Pass, Fail, Skip };
+
+struct TestCase {
+    std::string suite;
+    std::string name;
+    int line;
+    Result result;
+    const char* value_param;
+};
+
+// Math.Subtracts passes when compiled with -DSUBTRACTS_RESULT=Result::Pass, for the versions of
+// bisect
+#ifndef SUBTRACTS_RESULT
+#define SUBTRACTS_RESULT Result::Fail
+#endif
+
+// Math.Subtracts also overflows a buffer when compiled with -DSUBTRACTS_OVERFLOWS and
+// -fsanitize=address, for the reports of the sanitizers
+static void overflow(const std::string& name) {
+#ifdef SUBTRACTS_OVERFLOWS
+    if (name == "Math.Subtracts") {
+        volatile char* buffer = new char[4];
+        buffer[4] = 0;
+        delete[] buffer;
+    }
+#endif
+    (void)name;
+}
+
+// Math.Subtracts aborts the process when compiled with -DSUBTRACTS_CRASHES, for the chunks whose
+// process dies partway
+static void crash(const std::string& name) {
+#ifdef SUBTRACTS_CRASHES
+    if (name == "Math.Subtracts") abort();
+#endif
+    (void)name;
+}
+
+// The tests that don't fail take this long when compiled with -DPASSES_WAIT_MS=<ms>, so that the
+// failure finishes first, for the results held back by --log-quiesce. Included here to keep the
+// lines of the functions above, which the reports of the sanitizers point to.
+#include <unistd.h>
+#ifndef PASSES_WAIT_MS
+#define PASSES_WAIT_MS 0
+#endif
+
+static const std::vector<TestCase> tests = {
+    {"Math", "Adds", 40, Result::Pass, nullptr},
+    {"Math", "Subtracts", 45, SUBTRACTS_RESULT, nullptr},
+    {"Math", "DISABLED_Divides", 50, Result::Pass, nullptr},
+    {"Network", "Skipped", 55, Result::Skip, nullptr},
+    {"Values/Param", "Works/0", 60, Result::Pass, "1"},
+#ifdef UNUSUAL_NAMES
+    // Names with the special characters of the filters, which a filter for one of them can't
+    // match without also matching the others
+    {"Names", "x:y", 65, Result::Pass, nullptr},
+    {"Names", "x_y", 65, Result::Fail, nullptr},
+    {"Names", "a-b", 65, Result::Pass, nullptr},
+    {"Names", "a*b", 65, Result::Pass, nullptr},
+    {"Names", "a?b", 65, Result::Pass, nullptr},
+    {"Names", "a\\b", 65, Result::Pass, nullptr},
+    {"Names", "a,b[0]", 65, Result::Pass, nullptr},
+#endif
+};
+
+static std::string json_escaped(const std::string& text) {
+    std::string escaped;
+    for (char c : text) {
+        if (c == '\\' || c == '"') escaped += '\\';
+        escaped += c;
+    }
+    return escaped;
+}
+
+static bool glob_match(const char* pattern, const char* name) {
+    if (*pattern == '\0') return *name == '\0';
+    if (*pattern == '*') {
+        return glob_match(pattern + 1, name) || (*name != '\0' && glob_match(pattern, name + 1));
+    }
+    if (*name == '\0') return false;
+    return (*pattern == '?' || *pattern == *name) && glob_match(pattern + 1, name + 1);
+}
+
+// Negative patterns aren't supported
+static bool matches_filter(const std::string& filter, const std::string& name) {
+    size_t start = 0;
+    while (true) {
+        size_t end = filter.find(':', start);
+        std::string pattern = filter.substr(start, end == std::string::npos ? end : end - start);
+        if (glob_match(pattern.c_str(), name.c_str())) return true;
+        if (end == std::string::npos) return false;
+        start = end + 1;
+    }
+}
+
+static void write_listing(FILE* file) {
+    fprintf(file, "{\"tests\":%zu,\"name\":\"AllTests\",\"testsuites\":[", tests.size());
+    for (size_t i = 0; i < tests.size(); ++i) {
+        const TestCase& test = tests[i];
+        bool is_first_of_suite = i == 0 || tests[i - 1].suite != test.suite;
+        if (is_first_of_suite) {
+            fprintf(file, "%s{\"name\":\"%s\",\"testsuite\":[", i == 0 ? "" : "]},",
+                    json_escaped(test.suite).c_str());
+        }
+        fprintf(file, "%s{\"name\":\"%s\",", is_first_of_suite ? "" : ",",
+                json_escaped(test.name).c_str());
+        if (test.value_param) fprintf(file, "\"value_param\":\"%s\",", test.value_param);
+        fprintf(file, "\"file\":\"gtest_fixture.cpp\",\"line\":%d}", test.line);
+    }
+    fprintf(file, "]}]}\n");
+}
+
+int main(int argc, char** argv) {
+    testing::InitGoogleTest(&argc, argv);
+
+    bool list = false;
+    bool run_disabled = false;
+    std::string filter = "*";
+    std::string output;
+    std::string config;
+    for (int i = 1; i < argc; ++i) {
+        std::string arg = argv[i];
+        if (arg == "--config" && i + 1 < argc) config = argv[++i];
+        if (arg == "--gtest_list_tests") list = true;
+        if (arg == "--gtest_also_run_disabled_tests") run_disabled = true;
+        if (arg.rfind("--gtest_filter=", 0) == 0) filter = arg.substr(15);
+        if (arg.rfind("--gtest_output=json:", 0) == 0) output = arg.substr(20);
+    }
+
+#ifdef REQUIRES_CONFIG
+    // Like the executables that can't start without their configuration
+    if (config.empty()) {
+        fprintf(stderr, "--config is required\n");
+        return 2;
+    }
+#endif
+
+    FILE* report = output.empty() ? nullptr : fopen(output.c_str(), "w");
+
+    if (list) {
+        for (const TestCase& test : tests) printf("%s.\n  %s\n", test.suite.c_str(), test.name.c_str());
+        if (report) {
+            write_listing(report);
+            fclose(report);
+        }
+        return 0;
+    }
+
+    int failed = 0;
+    std::string previous_suite;
+    if (report) fprintf(report, "{\"testsuites\":[");
+    for (const TestCase& test : tests) {
+        std::string name = test.suite + "." + test.name;
+        if (!matches_filter(filter, name)) continue;
+        if (test.name.rfind("DISABLED_", 0) == 0 && !run_disabled) continue;
+
+        // Flushed like gtest does, so that the lines are kept when the process dies
+        printf("[ RUN      ] %s\n", name.c_str());
+        fflush(stdout);
+        if (test.result != Result::Fail) usleep(PASSES_WAIT_MS * 1000);
+        switch (test.result) {
+            case Result::Pass: printf("[       OK ] %s\n", name.c_str()); break;
+            case Result::Skip: printf("[  SKIPPED ] %s\n", name.c_str()); break;
+            case Result::Fail:
+                overflow(name);
+                crash(name);
+                // Tells the tests which variables of the environment the test got
+                if (const char* value = getenv("FIXTURE_VARIABLE")) printf("FIXTURE_VARIABLE=%s\n", value);
+                printf("gtest_fixture.cpp:%d: Failure\nExpected equality\n[  FAILED  ] %s\n",
+                       test.line + 1, name.c_str());
+                ++failed;
+                break;
+        }
+        fflush(stdout);
+
+        if (!report) continue;
+        if (test.suite != previous_suite) {
+            fprintf(report, "%s{\"name\":\"%s\",\"testsuite\":[",
+                    previous_suite.empty() ? "" : "]},", json_escaped(test.suite).c_str());
+        } else {
+            fprintf(report, ",");
+        }
+        previous_suite = test.suite;
+        fprintf(report,
+                "{\"name\":\"%s\",\"status\":\"RUN\",\"result\":\"%s\",\"time\":\"0.001s\"",
+                json_escaped(test.name).c_str(),
+                test.result == Result::Skip ? "SKIPPED" : "COMPLETED");
+        if (test.result == Result::Fail) {
+            fprintf(report,
+                    ",\"failures\":[{\"failure\":\"gtest_fixture.cpp:%d\\nExpected equality\"}]",
+                    test.line + 1);
+        }
+        fprintf(report, "}");
+    }
+    if (report) {
+        fprintf(report, "%s]}\n", previous_suite.empty() ? "" : "]}");
+        fclose(report);
+    }
+
+    return failed ? 1 : 0;
+}
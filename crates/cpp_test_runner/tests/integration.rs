@@ -0,0 +1,1568 @@
+//! End-to-end tests of cpp_test_runner against small gtest and Catch2 imitations, which are
+//! compiled with the C++ compiler of the system ($CXX, or c++). The tests are skipped when no
+//! compiler is available.
+//!
+//! The outputs are compared with the golden files in tests/golden. Set UPDATE_GOLDEN=1 to
+//! rewrite them after an intended change of output.
+
+use regex::Regex;
+use serde_json::Value;
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+fn compile(source: &Path, output: &Path, args: &[&str]) -> bool {
+    let compiler = std::env::var("CXX").unwrap_or_else(|_| String::from("c++"));
+    Command::new(compiler)
+        .args(args)
+        .arg("-o")
+        .arg(output)
+        .arg(source)
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+// Compiles the fixtures once for all the tests. The build tree looks like:
+// <root>/gtest_fixture.cpp, <root>/catch2_fixture.cpp, <root>/tests.attributes.json,
+// <root>/build/gtest_fixture and <root>/build/catch2/catch2_fixture
+fn fixtures_root() -> Option<&'static Path> {
+    static ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fixtures");
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        fs::create_dir_all(root.join("build").join("catch2")).unwrap();
+
+        for (source, executable) in [
+            ("gtest_fixture.cpp", "build/gtest_fixture"),
+            ("catch2_fixture.cpp", "build/catch2/catch2_fixture"),
+        ] {
+            // The sources are next to the build tree so that the tests can find them
+            let source_copy = root.join(source);
+            fs::copy(Path::new(FIXTURES_DIR).join(source), &source_copy).unwrap();
+            if !compile(&source_copy, &root.join(executable), &[]) {
+                eprintln!("Skipping the integration tests, the fixtures can't be compiled");
+                return None;
+            }
+        }
+        fs::copy(
+            Path::new(FIXTURES_DIR).join("tests.attributes.json"),
+            root.join("tests.attributes.json"),
+        )
+        .unwrap();
+
+        Some(root.canonicalize().unwrap())
+    })
+    .as_deref()
+}
+
+// The runner on the executables of `test_dir`, a directory of the fixtures, with the cache of
+// the fixtures. The tests that need their own cache or environment set them on the command.
+fn runner_command(root: &Path, test_dir: &str, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cpp_test_runner"));
+    command
+        .args(args)
+        .args(["--test-dir", test_dir])
+        .current_dir(root)
+        .env("XDG_CACHE_HOME", root.join("cache"));
+    command
+}
+
+fn runner(root: &Path, test_dir: &str, args: &[&str]) -> Output {
+    runner_command(root, test_dir, args).output().unwrap()
+}
+
+fn cpp_test_runner(root: &Path, args: &[&str]) -> Output {
+    runner_command(root, "build", args)
+        // A single thread makes the order of the results deterministic
+        .args(["--jobs", "1"])
+        .output()
+        .unwrap()
+}
+
+fn cpp_test_runner_with_stdin(root: &Path, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = runner_command(root, "build", args)
+        .args(["--jobs", "1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+// Replaces what depends on the machine, like the location of the fixtures, the modification
+// time of the executables, what was read from their binary and the variables of the environment
+// passed to the tests
+fn normalize(output: &str, root: &Path) -> String {
+    let output = output.replace(&root.to_string_lossy().to_string(), "<root>");
+    let output = Regex::new(r#"env -i( \w+="\$\w+")*"#)
+        .unwrap()
+        .replace_all(&output, "env -i <environment>")
+        .into_owned();
+
+    let Ok(mut json) = serde_json::from_str::<Value>(&output) else {
+        return output;
+    };
+    remove_keys(
+        &mut json,
+        &[
+            "modified",
+            "metadata",
+            "duration_seconds",
+            "duration",
+            "max_rss_bytes",
+            "max_rss",
+        ],
+    );
+    serde_json::to_string_pretty(&json).unwrap() + "\n"
+}
+
+fn remove_keys(json: &mut Value, keys: &[&str]) {
+    match json {
+        Value::Object(object) => {
+            for key in keys {
+                object.remove(*key);
+            }
+            object
+                .values_mut()
+                .for_each(|value| remove_keys(value, keys));
+        }
+        Value::Array(array) => array.iter_mut().for_each(|value| remove_keys(value, keys)),
+        _ => {}
+    }
+}
+
+fn assert_golden(name: &str, output: &Output, root: &Path) {
+    let actual = normalize(&String::from_utf8_lossy(&output.stdout), root);
+    let golden_file = Path::new(GOLDEN_DIR).join(name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden_file, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&golden_file).unwrap_or_default();
+    assert!(
+        actual == expected,
+        "The output doesn't match {}. Rerun with UPDATE_GOLDEN=1 if the change is intended.\n\
+         Expected:\n{expected}\nActual:\n{actual}\nStderr:\n{}",
+        golden_file.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn list_plain() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["list", "--output", "plain"]);
+    assert!(output.status.success());
+    assert_golden("list_plain.txt", &output, root);
+}
+
+#[test]
+fn list_json() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["list", "--include-hidden"]);
+    assert!(output.status.success());
+    assert_golden("list_json.json", &output, root);
+}
+
+#[test]
+fn list_executable_metadata() {
+    let Some(root) = fixtures_root() else { return };
+    let metadata = |args: &[&str]| {
+        let output = cpp_test_runner(root, &[&["list"], args].concat());
+        assert!(output.status.success());
+        let tests = serde_json::from_slice::<Value>(&output.stdout).unwrap();
+        tests[0]["executable"]["metadata"].clone()
+    };
+
+    let metadata_of_discovery = metadata(&[]);
+    assert!(!metadata_of_discovery["machine"]
+        .as_str()
+        .unwrap()
+        .is_empty());
+    assert!(metadata_of_discovery.get("stripped").is_none());
+
+    let all_metadata = metadata(&["--executable-metadata"]);
+    assert_eq!(all_metadata["machine"], metadata_of_discovery["machine"]);
+    assert_eq!(all_metadata["stripped"], false);
+    assert!(all_metadata["needed_libraries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|library| library.as_str().unwrap().starts_with("libc.so")));
+}
+
+#[test]
+fn list_compile_commands() {
+    let Some(root) = fixtures_root() else { return };
+    let database_dir = root.join("compile_commands");
+    fs::create_dir_all(&database_dir).unwrap();
+    let database = serde_json::json!([{
+        "directory": root,
+        "file": "gtest_fixture.cpp",
+        "command": "/usr/bin/aarch64-linux-gnu-g++ -DNAME=\"a b\" -c gtest_fixture.cpp",
+    }]);
+    fs::write(
+        database_dir.join("compile_commands.json"),
+        database.to_string(),
+    )
+    .unwrap();
+
+    let output = cpp_test_runner(
+        root,
+        &[
+            "list",
+            "--filter",
+            "^Math.Adds$",
+            "--compile-commands",
+            database_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    let tests: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        tests[0]["compile_command"],
+        serde_json::json!({
+            "directory": root,
+            "arguments": [
+                "/usr/bin/aarch64-linux-gnu-g++",
+                "-DNAME=a b",
+                "-c",
+                "gtest_fixture.cpp",
+            ],
+            "target": "aarch64-linux-gnu",
+        })
+    );
+
+    // The sources that aren't in the database have no compile command
+    let output = cpp_test_runner(
+        root,
+        &["list", "--compile-commands", database_dir.to_str().unwrap()],
+    );
+    let tests: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(tests
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|test| test.get("compile_command").is_none()));
+}
+
+#[test]
+fn list_duplicates() {
+    let Some(root) = fixtures_root() else { return };
+    // Like an object library with the tests linked into two executables
+    for executable in ["duplicates/first", "duplicates/second"] {
+        fs::create_dir_all(root.join(executable)).unwrap();
+        fs::copy(
+            root.join("build").join("gtest_fixture"),
+            root.join(executable).join("gtest_fixture"),
+        )
+        .unwrap();
+    }
+    let cpp_test_runner = |args: &[&str]| {
+        runner(
+            root,
+            "duplicates",
+            &[args, &["--filter", "^Math.Adds$"]].concat(),
+        )
+    };
+
+    let output = cpp_test_runner(&["list", "--output", "plain"]);
+    assert_golden("list_duplicates.txt", &output, root);
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("1 test is registered by several executables"));
+
+    let output = cpp_test_runner(&["run", "--dedupe-by-source", "--output", "json"]);
+    let results: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let results = results.as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert!(results[0]["executable"]["path"]
+        .as_str()
+        .unwrap()
+        .ends_with("duplicates/first/gtest_fixture"));
+}
+
+#[test]
+fn list_duplicate_names() {
+    let Some(root) = fixtures_root() else { return };
+    // A different Math.Adds, declared at another line
+    let test_dir = root.join("duplicate_names");
+    fs::create_dir_all(test_dir.join("unit")).unwrap();
+    fs::copy(
+        root.join("build").join("gtest_fixture"),
+        test_dir.join("unit").join("gtest_fixture"),
+    )
+    .unwrap();
+    let source = root.join("other_fixture.cpp");
+    let fixture = fs::read_to_string(root.join("gtest_fixture.cpp")).unwrap();
+    fs::write(
+        &source,
+        fixture.replace(r#"{"Math", "Adds", 40,"#, r#"{"Math", "Adds", 41,"#),
+    )
+    .unwrap();
+    assert!(compile(&source, &test_dir.join("other_fixture"), &[]));
+    let cpp_test_runner = |args: &[&str]| {
+        runner(
+            root,
+            "duplicate_names",
+            &[args, &["--filter", "^Math.Adds$"]].concat(),
+        )
+    };
+
+    let output = cpp_test_runner(&["list"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("warning[CTR0205]: 1 test name is used by different tests"));
+    let tests: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut ids = tests
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|test| test["id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    assert_eq!(
+        ids,
+        ["gtest_fixture::Math.Adds", "other_fixture::Math.Adds"]
+    );
+
+    let output = cpp_test_runner(&["list", "--output", "plain", "--disambiguate-duplicates"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("CTR0205"));
+    let mut names = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    assert_eq!(
+        names,
+        ["gtest_fixture::Math.Adds", "other_fixture::Math.Adds"]
+    );
+
+    let output = cpp_test_runner(&["list", "--strict-duplicates"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error[CTR0205]"));
+}
+
+// -vv logs why the files of the test directory aren't test executables
+#[test]
+fn list_logs() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("logs");
+    fs::create_dir_all(&test_dir).unwrap();
+    fs::copy(
+        root.join("build").join("gtest_fixture"),
+        test_dir.join("gtest_fixture"),
+    )
+    .unwrap();
+    let script = test_dir.join("setup.sh");
+    fs::write(&script, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let list = |args: &[&str]| runner(root, "logs", &[&["list", "--no-cache"], args].concat());
+
+    let output = list(&["-vv", "--log-json"]);
+    assert!(output.status.success());
+    let logs = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .collect::<Vec<_>>();
+    let skipped = logs
+        .iter()
+        .find(|log| {
+            log["fields"]["path"]
+                .as_str()
+                .is_some_and(|path| path.ends_with("setup.sh"))
+        })
+        .unwrap();
+    assert_eq!(
+        skipped["fields"]["message"],
+        "skipped, neither an ELF nor a PE"
+    );
+    assert!(logs
+        .iter()
+        .any(|log| log["fields"]["message"] == "listed the tests"));
+
+    let output = list(&[]);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("setup.sh"));
+}
+
+// The suggestions for a filter matching nothing come from the saved listing of the executables,
+// and only the rebuilt ones are listed again
+#[test]
+fn list_suggestions_from_saved_listing() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("suggestions");
+    fs::create_dir_all(&test_dir).unwrap();
+    for executable in ["gtest_fixture", "catch2/catch2_fixture"] {
+        fs::copy(
+            root.join("build").join(executable),
+            test_dir.join(Path::new(executable).file_name().unwrap()),
+        )
+        .unwrap();
+    }
+    let list = |args: &[&str]| {
+        runner_command(root, "suggestions", &[&["list"], args].concat())
+            .env("XDG_CACHE_HOME", root.join("suggestions_cache"))
+            .output()
+            .unwrap()
+    };
+    assert!(list(&[]).status.success());
+
+    assert!(compile(
+        &root.join("gtest_fixture.cpp"),
+        &test_dir.join("gtest_fixture"),
+        &["-DSUBTRACTS_RESULT=Result::Pass"],
+    ));
+
+    // Twice, since --no-cache doesn't save the new listing
+    for _ in 0..2 {
+        let output = list(&["--no-cache", "--filter", "Math.Addz", "-vv", "--log-json"]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Did you mean:\n    Math.Adds"));
+
+        // (executable, cached) of every listing, the first ones with the filter
+        let listings = stderr
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .filter(|log| log["fields"]["message"] == "listed the tests")
+            .map(|log| {
+                let executable = log["fields"]["executable"].as_str().unwrap().to_string();
+                let cached = log["fields"]["cached"].as_bool().unwrap();
+                (executable.rsplit('/').next().unwrap().to_string(), cached)
+            })
+            .collect::<Vec<_>>();
+        let count = |executable: &str, cached: bool| {
+            listings
+                .iter()
+                .filter(|listing| **listing == (executable.to_string(), cached))
+                .count()
+        };
+        assert_eq!(count("gtest_fixture", false), 2, "{listings:?}");
+        assert_eq!(count("catch2_fixture", false), 1, "{listings:?}");
+        assert_eq!(count("catch2_fixture", true), 1, "{listings:?}");
+    }
+}
+
+// The listing of the executable that never ends always outlasts the timeout. The other one is
+// listed from the discovery cache, so that the machine being slow doesn't leave it unfinished too.
+#[test]
+fn list_discovery_timeout() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("timeout");
+    fs::create_dir_all(&test_dir).unwrap();
+    fs::copy(
+        root.join("build").join("gtest_fixture"),
+        test_dir.join("gtest_fixture"),
+    )
+    .unwrap();
+    let list = |args: &[&str]| {
+        runner_command(
+            root,
+            "timeout",
+            &[&["list", "--output", "plain"], args].concat(),
+        )
+        .env("XDG_CACHE_HOME", root.join("timeout_cache"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap()
+    };
+    assert!(list(&[]).wait_with_output().unwrap().status.success());
+
+    // A gtest executable that never lists its tests
+    let source = root.join("hangs.cpp");
+    fs::write(
+        &source,
+        "#include <unistd.h>\n\
+         namespace testing { void InitGoogleTest(int*, char**) {} }\n\
+         int main(int argc, char** argv) { testing::InitGoogleTest(&argc, argv); for (;;) pause(); }\n",
+    )
+    .unwrap();
+    assert!(compile(&source, &test_dir.join("hangs"), &[]));
+
+    // Only returns if the listing of hangs is killed
+    let mut child = list(&["--discovery-timeout", "1"]);
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > Duration::from_secs(120) {
+            child.kill().unwrap();
+            panic!("The discovery didn't time out");
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Math.Adds"));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("the discovery timed out before finishing 1 path,"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("timeout/hangs"));
+}
+
+#[test]
+fn run_diagnostics() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("diagnostics");
+    fs::create_dir_all(&test_dir).unwrap();
+    fs::copy(
+        root.join("build").join("gtest_fixture"),
+        test_dir.join("gtest_fixture"),
+    )
+    .unwrap();
+    // A gtest executable that fails to list its tests, which used to be left out silently
+    let source = root.join("fails_listing.cpp");
+    fs::write(
+        &source,
+        "namespace testing { void InitGoogleTest(int*, char**) {} }\n\
+         int main(int argc, char** argv) { testing::InitGoogleTest(&argc, argv); return 3; }\n",
+    )
+    .unwrap();
+    assert!(compile(&source, &test_dir.join("fails_listing"), &[]));
+
+    let run = |args: &[&str]| {
+        let options = ["run", "--no-cache", "--filter", "Adds"];
+        let report = ["--report", "diagnostics=diagnostics.json"];
+        runner(root, "diagnostics", &[&options[..], &report, args].concat())
+    };
+
+    let output = run(&[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!(
+        "warning[CTR0201]: the tests of {} couldn't be listed",
+        test_dir.join("fails_listing").display()
+    )));
+    assert!(stderr.contains("Diagnostics: 1 warning"));
+
+    // Hidden by --quiet, but still reported
+    let output = run(&["--quiet"]);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("CTR0201"));
+    let diagnostics: Value =
+        serde_json::from_slice(&fs::read(root.join("diagnostics.json")).unwrap()).unwrap();
+    assert_eq!(diagnostics[0]["severity"], "warning");
+    assert_eq!(diagnostics[0]["code"], "CTR0201");
+}
+
+// The test executables only get the allowed variables of the environment, so that a variable left
+// in the shell doesn't change the runs
+#[test]
+fn run_environment() {
+    let Some(root) = fixtures_root() else { return };
+    let output_of_failure = |args: &[&str]| {
+        let options = [
+            "run",
+            "--output",
+            "json",
+            "--filter",
+            "Math.Subtracts",
+            "--no-cache",
+        ];
+        let output = runner_command(root, "build", &[&options[..], args].concat())
+            .env("FIXTURE_VARIABLE", "from the shell")
+            .output()
+            .unwrap();
+        let results: Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(results[0]["status"], "failed");
+        results[0]["output"].as_str().unwrap().to_string()
+    };
+
+    assert!(!output_of_failure(&[]).contains("FIXTURE_VARIABLE"));
+    for args in [&["--pass-env", "FIXTURE_*"][..], &["--inherit-env"]] {
+        assert!(output_of_failure(args).contains("FIXTURE_VARIABLE=from the shell"));
+    }
+}
+
+#[test]
+fn list_filters() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(
+        root,
+        &[
+            "list",
+            "--output",
+            "plain",
+            "--filter",
+            "^Math",
+            "--filter",
+            "weird",
+            "--exclude",
+            "Subtracts",
+            "--exclude-disabled",
+        ],
+    );
+    assert!(output.status.success());
+    assert_golden("list_filters.txt", &output, root);
+}
+
+#[test]
+fn list_fuzzy() {
+    let Some(root) = fixtures_root() else { return };
+    let list = |query: &str| {
+        let output = cpp_test_runner(root, &["list", "--output", "plain", "--fuzzy", query]);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    // In any order, and a word can span the next words of the name
+    assert_eq!(list("divides math"), "Math.DISABLED_Divides\n");
+    assert_eq!(list("netskip"), "Network.Skipped\n");
+    assert_eq!(list("ParamWorks"), "Values/Param.Works/0\n");
+
+    let output = cpp_test_runner(root, &["list", "--fuzzy", "substracts"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("    Math.Subtracts"));
+}
+
+#[test]
+fn list_unclosed_tags() {
+    let Some(root) = fixtures_root() else { return };
+    for expression in ["[slow", "[a~[b]"] {
+        let output = cpp_test_runner(root, &["list", "--tags", expression]);
+        assert!(!output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("Unclosed tag in the tag expression")
+        );
+    }
+}
+
+#[test]
+fn list_path_globs() {
+    let Some(root) = fixtures_root() else { return };
+    let list = |args: &[&str]| {
+        let output = cpp_test_runner(root, &[&["list", "--output", "plain"], args].concat());
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let excluded = list(&["--exclude-path", "catch2"]);
+    assert!(excluded.contains("Math.Adds\n"));
+    assert!(!excluded.contains("db roundtrip"));
+
+    let included = list(&["--include-path", "catch2_*"]);
+    assert!(included.contains("db roundtrip"));
+    assert!(!included.contains("Math.Adds"));
+
+    let output = cpp_test_runner(root, &["list", "--exclude-path", "[catch2"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid --exclude-path [catch2"));
+}
+
+#[test]
+fn list_ignore_files() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("ignore_files");
+    for dir in ["unit", "data", "vendor"] {
+        fs::create_dir_all(test_dir.join(dir)).unwrap();
+        fs::copy(
+            root.join("build").join("gtest_fixture"),
+            test_dir.join(dir).join("gtest_fixture"),
+        )
+        .unwrap();
+    }
+    fs::write(test_dir.join(".testignore"), "data/\n").unwrap();
+    fs::write(test_dir.join(".gitignore"), "vendor/\n").unwrap();
+
+    let list = |args: &[&str]| {
+        let options = ["list", "--output", "plain", "--executables-only", "--quiet"];
+        let output = runner(
+            root,
+            "ignore_files",
+            &[&options[..], &["--no-cache"], args].concat(),
+        );
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let listed = list(&[]);
+    assert!(listed.contains("ignore_files/unit/gtest_fixture"));
+    assert!(!listed.contains("ignore_files/data/gtest_fixture"));
+    assert!(listed.contains("ignore_files/vendor/gtest_fixture"));
+
+    let listed = list(&["--use-gitignore"]);
+    assert!(listed.contains("ignore_files/unit/gtest_fixture"));
+    assert!(!listed.contains("ignore_files/data/gtest_fixture"));
+    assert!(!listed.contains("ignore_files/vendor/gtest_fixture"));
+}
+
+#[test]
+fn list_symlinks_and_depth() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("symlinks");
+    let outside = root.join("symlinks_outside");
+    let _ = fs::remove_dir_all(&test_dir);
+    fs::create_dir_all(test_dir.join("a/b/c")).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    for dir in [test_dir.join("a/b/c"), outside.clone()] {
+        fs::copy(
+            root.join("build").join("gtest_fixture"),
+            dir.join("gtest_fixture"),
+        )
+        .unwrap();
+    }
+    // Like the output trees of Bazel, which link the same directory several times
+    std::os::unix::fs::symlink(&outside, test_dir.join("out")).unwrap();
+    std::os::unix::fs::symlink(&outside, test_dir.join("out_again")).unwrap();
+    std::os::unix::fs::symlink(&test_dir, test_dir.join("loop")).unwrap();
+
+    let list = |args: &[&str]| {
+        let options = ["list", "--output", "plain", "--executables-only", "--quiet"];
+        let output = runner(
+            root,
+            "symlinks",
+            &[&options[..], &["--no-cache"], args].concat(),
+        );
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).replace(&root.display().to_string(), "<root>")
+    };
+
+    assert_eq!(list(&[]), "<root>/symlinks/a/b/c/gtest_fixture\n");
+    assert_eq!(
+        list(&["--follow-symlinks"]),
+        "<root>/symlinks/a/b/c/gtest_fixture\n<root>/symlinks/out/gtest_fixture\n"
+    );
+    assert_eq!(
+        list(&["--follow-symlinks", "--max-depth", "2"]),
+        "<root>/symlinks/out/gtest_fixture\n"
+    );
+}
+
+#[test]
+fn list_newer_than() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("newer_than");
+    let hours_ago = |hours: u64| SystemTime::now() - Duration::from_secs(hours * 60 * 60);
+    for (dir, modified) in [("relinked", SystemTime::now()), ("unchanged", hours_ago(2))] {
+        fs::create_dir_all(test_dir.join(dir)).unwrap();
+        let executable = test_dir.join(dir).join("gtest_fixture");
+        fs::copy(root.join("build").join("gtest_fixture"), &executable).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&executable)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+    // Like a stamp touched after the last run
+    let stamp = root.join("newer_than.stamp");
+    fs::write(&stamp, "").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&stamp)
+        .unwrap()
+        .set_modified(hours_ago(1))
+        .unwrap();
+
+    for newer_than in ["1h", "newer_than.stamp"] {
+        let output = runner(
+            root,
+            "newer_than",
+            &[
+                "list",
+                "--output",
+                "plain",
+                "--executables-only",
+                "--quiet",
+                "--no-cache",
+                "--newer-than",
+                newer_than,
+            ],
+        );
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("{}\n", test_dir.join("relinked/gtest_fixture").display())
+        );
+    }
+}
+
+// The format of --porcelain is kept stable for scripts, so its golden files are only updated
+// when fields are added at the end of the lines
+#[test]
+fn list_porcelain() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["list", "--porcelain", "--include-hidden"]);
+    assert!(output.status.success());
+    assert_golden("list_porcelain.txt", &output, root);
+}
+
+#[test]
+fn run_plain() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["run", "--color", "no"]);
+    assert!(output.status.success());
+    assert_golden("run_plain.txt", &output, root);
+}
+
+// The chunks of 2 tests split the executables, whose results are still read from their reports
+#[test]
+fn run_chunk_size() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["run", "--color", "no", "--chunk-size", "2"]);
+    assert!(output.status.success());
+    assert_golden("run_chunk_size.txt", &output, root);
+}
+
+// The process of the chunk dies in Math.Subtracts: the test before it keeps its result, and the
+// tests after it run with a process each
+#[test]
+fn run_chunk_crash() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("chunk_crash");
+    fs::create_dir_all(&test_dir).unwrap();
+    assert!(compile(
+        &root.join("gtest_fixture.cpp"),
+        &test_dir.join("gtest_fixture"),
+        &["-DSUBTRACTS_CRASHES"],
+    ));
+
+    let output = runner(
+        root,
+        "chunk_crash",
+        &[
+            "run",
+            "--output",
+            "json",
+            "--chunk-size",
+            "5",
+            "--jobs",
+            "1",
+        ],
+    );
+    let results: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let result = |name: &str| {
+        results
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|result| result["name"] == name)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(result("Math.Adds")["status"], "passed");
+    assert_eq!(result("Math.Subtracts")["status"], "failed");
+    assert!(result("Math.Subtracts")["output"]
+        .as_str()
+        .unwrap()
+        .contains("died while running Math.Subtracts"));
+    assert_eq!(result("Math.DISABLED_Divides")["status"], "passed");
+    assert_eq!(result("Network.Skipped")["status"], "skipped");
+    assert_eq!(result("Values/Param.Works/0")["status"], "passed");
+}
+
+// A gtest filter can't be escaped, so the filter of Names.x:y also matches Names.x_y, which fails
+// and mustn't change the result of Names.x:y. The Catch2 specs are escaped.
+#[test]
+fn run_unusual_names() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("unusual_names");
+    fs::create_dir_all(&test_dir).unwrap();
+    for source in ["gtest_fixture.cpp", "catch2_fixture.cpp"] {
+        assert!(compile(
+            &root.join(source),
+            &test_dir.join(source.trim_end_matches(".cpp")),
+            &["-DUNUSUAL_NAMES"],
+        ));
+    }
+
+    for args in [&[][..], &["--chunk-size", "20"]] {
+        let options = ["run", "--output", "json", "--jobs", "1", "--no-cache"];
+        let output = runner(root, "unusual_names", &[&options[..], args].concat());
+        let results: Value = serde_json::from_slice(&output.stdout).unwrap();
+        let result = |name: &str| {
+            results
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|result| result["name"] == name)
+                .unwrap_or_else(|| panic!("No result for {name} with {args:?}"))
+                .clone()
+        };
+        for name in [
+            "Names.x:y",
+            "Names.a-b",
+            "Names.a*b",
+            "Names.a?b",
+            "Names.a\\b",
+            "Names.a,b[0]",
+            "commas, [brackets] and \\backslashes",
+            "stars * and ~tildes",
+        ] {
+            assert_eq!(result(name)["status"], "passed", "{name} with {args:?}");
+            assert!(
+                !result(name)["output"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .contains("Failure"),
+                "{name} with {args:?}"
+            );
+        }
+        assert_eq!(result("Names.x_y")["status"], "failed");
+        assert_eq!(result("commas")["status"], "failed");
+    }
+}
+
+// The results held back after a failure are printed in the order of the tests, whatever order
+// the workers finished them in. The tests that don't fail wait for the failure to be printed.
+#[test]
+fn run_log_quiesce() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("log_quiesce");
+    fs::create_dir_all(&test_dir).unwrap();
+    assert!(compile(
+        &root.join("gtest_fixture.cpp"),
+        &test_dir.join("gtest_fixture"),
+        &["-DPASSES_WAIT_MS=1000"],
+    ));
+
+    let output = runner(
+        root,
+        "log_quiesce",
+        &[
+            "run",
+            "--color",
+            "no",
+            "--jobs",
+            "8",
+            "--log-quiesce",
+            "600000",
+            "--no-cache",
+        ],
+    );
+    assert_golden("run_log_quiesce.txt", &output, root);
+}
+
+#[test]
+fn run_badge() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["run", "--output", "json", "--badge", "badge.svg"]);
+    let badge = Output {
+        stdout: fs::read(root.join("badge.svg")).unwrap(),
+        ..output
+    };
+    assert_golden("run_badge.svg", &badge, root);
+}
+
+#[test]
+fn run_group_by_executable() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["run", "--color", "no", "--group-by-executable"]);
+    assert!(output.status.success());
+    assert_golden("run_group_by_executable.txt", &output, root);
+}
+
+#[test]
+fn run_positional() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["run", "--color", "no", "Math.*", "vector grows"]);
+    assert!(output.status.success());
+    assert_golden("run_positional.txt", &output, root);
+
+    let output = cpp_test_runner(root, &["run", "Mat.Ads"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Did you mean:\n    Math.Adds"));
+}
+
+#[test]
+fn run_stdin() {
+    let Some(root) = fixtures_root() else { return };
+    let listing = cpp_test_runner(root, &["list", "--filter", "^(Math|vector)"]);
+    assert!(listing.status.success());
+    let from_json =
+        cpp_test_runner_with_stdin(root, &["run", "--color", "no", "--stdin"], &listing.stdout);
+    assert_golden("run_stdin.txt", &from_json, root);
+
+    let names = cpp_test_runner(
+        root,
+        &["list", "--output", "plain", "--filter", "^(Math|vector)"],
+    );
+    let from_names =
+        cpp_test_runner_with_stdin(root, &["run", "--color", "no", "--stdin"], &names.stdout);
+    assert_golden("run_stdin.txt", &from_names, root);
+}
+
+#[test]
+fn run_json() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["run", "--output", "json"]);
+    assert!(output.status.success());
+    assert_golden("run_json.json", &output, root);
+}
+
+#[test]
+fn run_quickfix() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["run", "--output", "quickfix"]);
+    assert_golden("run_quickfix.txt", &output, root);
+}
+
+#[test]
+fn run_reports() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("reports");
+    fs::create_dir_all(&test_dir).unwrap();
+    if !compile(
+        &root.join("gtest_fixture.cpp"),
+        &test_dir.join("gtest_fixture"),
+        &["-g", "-fsanitize=address", "-DSUBTRACTS_OVERFLOWS"],
+    ) {
+        eprintln!("Skipping run_reports, AddressSanitizer isn't available");
+        return;
+    }
+
+    let run = || {
+        runner_command(
+            root,
+            "reports",
+            &[
+                "run",
+                "--filter",
+                "^Math",
+                "--output",
+                "json",
+                "--normalize-durations",
+                "--report",
+                "junit=report.xml,codequality=codequality.json",
+            ],
+        )
+        .env("XDG_CACHE_HOME", root.join("reports_cache"))
+        .output()
+        .unwrap()
+    };
+    let output = run();
+    let reports = || {
+        [
+            fs::read(root.join("report.xml")).unwrap(),
+            fs::read(root.join("codequality.json")).unwrap(),
+        ]
+    };
+    // Identical runs write byte-identical reports
+    let first_reports = reports();
+    run();
+    assert_eq!(reports(), first_reports);
+
+    let junit = Output {
+        stdout: fs::read(root.join("report.xml")).unwrap(),
+        ..output
+    };
+    assert_golden("run_report_junit.xml", &junit, root);
+
+    let issues: Value =
+        serde_json::from_slice(&fs::read(root.join("codequality.json")).unwrap()).unwrap();
+    let issues = issues.as_array().unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(
+        issues[0]["check_name"],
+        "AddressSanitizer/heap-buffer-overflow"
+    );
+    assert_eq!(issues[0]["location"]["path"], "gtest_fixture.cpp");
+    assert_eq!(issues[0]["location"]["lines"]["begin"], 36);
+    assert_eq!(issues[0]["severity"], "critical");
+}
+
+#[test]
+fn run_github() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("github");
+    fs::create_dir_all(&test_dir).unwrap();
+    let executable = test_dir.join("gtest_fixture");
+    fs::copy(root.join("build").join("gtest_fixture"), &executable).unwrap();
+    let cpp_test_runner = |args: &[&str]| {
+        runner_command(root, "github", &[args, &["--filter", "^Math"]].concat())
+            .env("XDG_CACHE_HOME", root.join("github_cache"))
+            .env("GITHUB_WORKSPACE", root)
+            .output()
+            .unwrap()
+    };
+
+    let output = cpp_test_runner(&["run", "--output", "github"]);
+    assert_golden("run_github.txt", &output, root);
+
+    // Math.Subtracts failed in the last run, so it may be flaky
+    assert!(compile(
+        &root.join("gtest_fixture.cpp"),
+        &executable,
+        &["-DSUBTRACTS_RESULT=Result::Pass"],
+    ));
+    let output = cpp_test_runner(&["run", "--output", "github"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "::notice file=gtest_fixture.cpp,line=45,title=Math.Subtracts may be flaky::Passed, but \
+         failed in 1 of the last 1 runs\n"
+    );
+}
+
+#[test]
+fn run_matrix() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(
+        root,
+        &[
+            "run",
+            "Math.Adds",
+            "--matrix",
+            "env:TZ=UTC,America/New_York",
+            "--matrix",
+            "env:LC_ALL=C,tr_TR.UTF-8",
+            "--output",
+            "json",
+        ],
+    );
+    assert!(output.status.success());
+    assert_golden("run_matrix.json", &output, root);
+}
+
+#[test]
+fn run_porcelain() {
+    let Some(root) = fixtures_root() else { return };
+    let mut output = cpp_test_runner(
+        root,
+        &["run", "--porcelain", "--matrix", "env:TZ=UTC,Asia/Tokyo"],
+    );
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    // The durations depend on the machine
+    let stdout = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            let mut fields = line.split('\t').collect::<Vec<_>>();
+            assert!(fields[2] == "-" || fields[2].parse::<u64>().is_ok());
+            fields[2] = "<ms>";
+            fields.join("\t") + "\n"
+        })
+        .collect::<String>();
+    output.stdout = stdout.into_bytes();
+    assert_golden("run_porcelain.txt", &output, root);
+}
+
+#[test]
+fn list_vscode_test_adapter() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(
+        root,
+        &[
+            "list",
+            "--output",
+            "vscode-test-adapter",
+            "--filter",
+            "^(Math|vector)",
+        ],
+    );
+    assert!(output.status.success());
+    assert_golden("list_vscode_test_adapter.json", &output, root);
+}
+
+#[test]
+fn run_vscode_test_adapter() {
+    let Some(root) = fixtures_root() else { return };
+    let mut output = cpp_test_runner(
+        root,
+        &[
+            "run",
+            "--output",
+            "vscode-test-adapter",
+            "Math.*",
+            "db roundtrip",
+        ],
+    );
+    assert!(output.status.success());
+
+    // An event per line, without the durations which depend on the machine
+    let events = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            let mut event = serde_json::from_str::<Value>(line).unwrap();
+            remove_keys(&mut event, &["duration"]);
+            event.to_string() + "\n"
+        })
+        .collect::<String>();
+    output.stdout = events.into_bytes();
+    assert_golden("run_vscode_test_adapter.jsonl", &output, root);
+}
+
+#[test]
+fn launch_json() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(root, &["launch-json", "--filter", "^(Math|vector)"]);
+    assert!(output.status.success());
+    assert_golden("launch_json.json", &output, root);
+}
+
+#[test]
+fn launch_json_failed() {
+    let Some(root) = fixtures_root() else { return };
+    let test_dir = root.join("launch_failed");
+    fs::create_dir_all(&test_dir).unwrap();
+    fs::copy(
+        root.join("build").join("gtest_fixture"),
+        test_dir.join("gtest_fixture"),
+    )
+    .unwrap();
+    let cpp_test_runner = |args: &[&str]| {
+        runner_command(root, "launch_failed", args)
+            .env("XDG_CACHE_HOME", root.join("launch_failed_cache"))
+            .output()
+            .unwrap()
+    };
+
+    let output = cpp_test_runner(&["launch-json", "--failed"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No run was archived yet"));
+
+    cpp_test_runner(&["run"]);
+    let output = cpp_test_runner(&["launch-json", "--failed"]);
+    assert!(output.status.success());
+    assert_golden("launch_json_failed.json", &output, root);
+}
+
+#[test]
+fn launch_json_launch_types() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(
+        root,
+        &[
+            "launch-json",
+            "--filter",
+            "^Math.Adds",
+            "--mi-mode",
+            "lldb",
+            "--stop-at-entry",
+            "--pretty-printing",
+        ],
+    );
+    assert!(output.status.success());
+    assert_golden("launch_json_mi_mode_lldb.json", &output, root);
+
+    // CodeLLDB names it stopOnEntry
+    let output = cpp_test_runner(
+        root,
+        &[
+            "launch-json",
+            "--filter",
+            "^Math.Adds",
+            "--launch-type",
+            "lldb",
+            "--stop-at-entry",
+        ],
+    );
+    assert!(output.status.success());
+    let launch_json = serde_json::from_slice::<Value>(&output.stdout).unwrap();
+    let configuration = &launch_json["configurations"][0];
+    assert_eq!(configuration["stopOnEntry"], true);
+    assert!(configuration.get("stopAtEntry").is_none());
+    assert!(configuration.get("MIMode").is_none());
+}
+
+#[test]
+fn grep() {
+    let Some(root) = fixtures_root() else { return };
+    // With its own cache, so that the runs of the other tests are never the latest one
+    let cache = root.join("grep_cache");
+    let cpp_test_runner = |args: &[&str]| {
+        runner_command(root, "build", args)
+            .env("XDG_CACHE_HOME", &cache)
+            .output()
+            .unwrap()
+    };
+
+    let output = cpp_test_runner(&["grep", "FAILED"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No run was archived yet"));
+
+    cpp_test_runner(&["run", "--jobs", "1", "--output", "json"]);
+    let output = cpp_test_runner(&["grep", "FAILED", "--context", "1", "--color", "no"]);
+    assert!(output.status.success());
+    assert_golden("grep.txt", &output, root);
+
+    let output = cpp_test_runner(&["grep", "nothing logs this"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn show() {
+    let Some(root) = fixtures_root() else { return };
+    let cache = root.join("show_cache");
+    let cpp_test_runner = |args: &[&str]| {
+        runner_command(root, "build", args)
+            .env("XDG_CACHE_HOME", &cache)
+            .output()
+            .unwrap()
+    };
+
+    cpp_test_runner(&[
+        "run", "--order", "random", "--seed", "42", "--output", "json",
+    ]);
+    cpp_test_runner(&["run", "--filter", "^Math.Adds$", "--output", "json"]);
+
+    // The last failure is in the first run, with the seed that shuffled it
+    let output = cpp_test_runner(&["show", "Math.*", "--last-failure"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Math.Subtracts\n  Status:     failed\n"));
+    assert!(stdout.contains("  Run:        the run before, "));
+    assert!(stdout.contains("  Seed:       42, "));
+    assert!(stdout.contains("gtest_fixture.cpp:46: Failure\nExpected equality"));
+
+    let output = cpp_test_runner(&["show", "Math.Adds"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("  Status:     passed\n  Run:        the latest run, "));
+
+    let output = cpp_test_runner(&["show", "Network.Skipped", "--last-failure"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn bisect() {
+    let Some(root) = fixtures_root() else { return };
+    let versions = root.join("bisect");
+    let passing = root.join("gtest_fixture_passing");
+    assert!(compile(
+        &root.join("gtest_fixture.cpp"),
+        &passing,
+        &["-DSUBTRACTS_RESULT=Result::Pass"],
+    ));
+    // Sorted by the value of their numbers, v9 is before v10
+    for version in 8..=14 {
+        let dir = versions.join(format!("v{version}"));
+        fs::create_dir_all(&dir).unwrap();
+        let executable = if version < 11 {
+            passing.clone()
+        } else {
+            root.join("build").join("gtest_fixture")
+        };
+        // v10 has no tests, so it is skipped
+        if version != 10 {
+            fs::copy(executable, dir.join("gtest_fixture")).unwrap();
+        }
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_cpp_test_runner"))
+        .args(["bisect", "Math.Subtracts", "--good", "bisect/v8"])
+        .args(["--bad", "bisect/v14"])
+        .current_dir(root)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_golden("bisect.txt", &output, root);
+}
+
+// A results service answering the runs it was sent, which returns the requests it received
+fn serve_results(listener: TcpListener, num_requests: usize) -> Vec<(String, String)> {
+    let mut runs = Vec::new();
+    let mut requests = Vec::new();
+    for stream in listener.incoming().take(num_requests) {
+        let mut stream = BufReader::new(stream.unwrap());
+        let mut request_line = String::new();
+        stream.read_line(&mut request_line).unwrap();
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            stream.read_line(&mut header).unwrap();
+            if header.trim().is_empty() {
+                break;
+            }
+            if let Some(length) = header.to_lowercase().strip_prefix("content-length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; content_length];
+        stream.read_exact(&mut body).unwrap();
+        let body = String::from_utf8(body).unwrap();
+
+        let answer = if request_line.starts_with("POST") {
+            let mut run: Value = serde_json::from_str(&body).unwrap();
+            run["time"] = Value::from(0);
+            runs.insert(0, run);
+            String::new()
+        } else {
+            Value::from(runs.clone()).to_string()
+        };
+        write!(
+            stream.get_mut(),
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{answer}",
+            answer.len()
+        )
+        .unwrap();
+        requests.push((request_line.trim().to_string(), body));
+    }
+    requests
+}
+
+#[test]
+fn results_service() {
+    let Some(root) = fixtures_root() else { return };
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/cpp-tests", listener.local_addr().unwrap());
+    let service = thread::spawn(move || serve_results(listener, 2));
+
+    cpp_test_runner(
+        root,
+        &[
+            "run",
+            "--filter",
+            "^Math",
+            "--output",
+            "json",
+            "--results-url",
+            &url,
+        ],
+    );
+    let output = runner(
+        root,
+        "build",
+        &["show", "Math.Subtracts", "--results-url", &url],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Math.Subtracts\n  Status:     failed\n"));
+    assert!(stdout.contains("gtest_fixture.cpp:46: Failure\nExpected equality"));
+
+    let requests = service.join().unwrap();
+    assert_eq!(requests[0].0, "POST /cpp-tests/runs HTTP/1.1");
+    // The name of the repository containing the test directory
+    let run: Value = serde_json::from_str(&requests[0].1).unwrap();
+    let workspace = run["workspace"].as_str().unwrap();
+    assert_eq!(run["results"].as_array().unwrap().len(), 3);
+    assert_eq!(
+        requests[1].0,
+        format!("GET /cpp-tests/runs?workspace={workspace}&count=10 HTTP/1.1")
+    );
+}
+
+#[test]
+fn open() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(
+        root,
+        &[
+            "open",
+            "Math.Adds",
+            "--editor-command",
+            "echo {file}:{line}",
+        ],
+    );
+    assert!(output.status.success());
+    assert_golden("open.txt", &output, root);
+
+    let output = cpp_test_runner(root, &["open", "Math.*", "--editor-command", "true"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("matches tests in several locations"));
+}
+
+#[test]
+fn launch_json_write() {
+    let Some(root) = fixtures_root() else { return };
+    let vscode_dir = root.join(".vscode");
+    fs::create_dir_all(&vscode_dir).unwrap();
+    fs::write(
+        vscode_dir.join("launch.json"),
+        r#"{"version": "0.2.0", "configurations": [{"name": "by hand"}, {"name": "Math.Adds"}]}"#,
+    )
+    .unwrap();
+
+    let output = cpp_test_runner(root, &["launch-json", "--filter", "^Math", "--write"]);
+    assert!(output.status.success());
+    assert_golden("launch_json_write.txt", &output, root);
+
+    let launch_json = fs::read_to_string(vscode_dir.join("launch.json")).unwrap();
+    let launch_json = serde_json::from_str::<Value>(&launch_json).unwrap();
+    let names = launch_json["configurations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|configuration| configuration["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "by hand",
+            "Math.Adds",
+            "Math.Subtracts",
+            "Math.DISABLED_Divides"
+        ]
+    );
+
+    let output = cpp_test_runner(root, &["launch-json", "--filter", "^Math", "--write"]);
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("is up to date\n"));
+
+    // The configurations run the build task, which is added to the tasks.json
+    let output = cpp_test_runner(
+        root,
+        &[
+            "launch-json",
+            "--filter",
+            "^Math",
+            "--write",
+            "--build-command",
+            "make -C build",
+        ],
+    );
+    assert!(output.status.success());
+    let launch_json = fs::read_to_string(vscode_dir.join("launch.json")).unwrap();
+    let launch_json = serde_json::from_str::<Value>(&launch_json).unwrap();
+    assert_eq!(
+        launch_json["configurations"][1]["preLaunchTask"],
+        "cpp_test_runner: build"
+    );
+    let tasks_json = fs::read_to_string(vscode_dir.join("tasks.json")).unwrap();
+    let tasks_json = serde_json::from_str::<Value>(&tasks_json).unwrap();
+    assert_eq!(tasks_json["tasks"][0]["label"], "cpp_test_runner: build");
+    assert_eq!(tasks_json["tasks"][0]["command"], "make -C build");
+}
+
+#[test]
+fn launch_json_env_and_source_map() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(
+        root,
+        &[
+            "launch-json",
+            "--filter",
+            "^Math",
+            "--launch-env",
+            "ASAN_OPTIONS=detect_leaks=0",
+            "--launch-env",
+            "TZ=UTC",
+            "--source-map",
+            "/build=/src",
+        ],
+    );
+    assert!(output.status.success());
+    assert_golden("launch_json_env_and_source_map.json", &output, root);
+}
+
+#[test]
+fn launch_json_preload() {
+    let Some(root) = fixtures_root() else { return };
+    let output = cpp_test_runner(
+        root,
+        &[
+            "launch-json",
+            "--filter",
+            "^Math",
+            "--launch-type",
+            "lldb",
+            "--preload",
+            "libfaketime.so.1",
+        ],
+    );
+    assert!(output.status.success());
+    assert_golden("launch_json_preload.json", &output, root);
+}
@@ -0,0 +1,671 @@
+//! The line number information of the DWARF debug info, in the .debug_line section, which maps the
+//! addresses of the code to their source file and line
+
+use crate::{Elf, FetchInteger, SectionHeaders};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+const DW_FORM_SDATA: u64 = 0x0d;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], position: usize, little_endian: bool) -> Self {
+        Self {
+            data,
+            position,
+            little_endian,
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let bytes = self.bytes(8)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    }
+
+    // Offsets are 8 bytes in the 64 bits DWARF format
+    fn offset(&mut self, is_64_bits: bool) -> Option<u64> {
+        if is_64_bits {
+            self.u64()
+        } else {
+            self.u32().map(u64::from)
+        }
+    }
+
+    fn uleb128(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> Option<i64> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= i64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+
+    fn cstr(&mut self) -> Option<&'a [u8]> {
+        let rest = self.data.get(self.position..)?;
+        let len = rest.iter().position(|byte| *byte == 0)?;
+        self.position += len + 1;
+        Some(&rest[..len])
+    }
+}
+
+fn string_at(strings: &[u8], offset: u64) -> Option<&[u8]> {
+    Reader::new(strings, usize::try_from(offset).ok()?, true).cstr()
+}
+
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+struct StringSections {
+    debug_str: Vec<u8>,
+    debug_line_str: Vec<u8>,
+}
+
+// Reads an attribute of a directory or a file entry of DWARF 5, which returns a string for the
+// string forms and an integer for the others
+enum FormValue<'a> {
+    String(&'a [u8]),
+    Integer(u64),
+}
+
+fn read_form<'a>(
+    reader: &mut Reader<'a>,
+    form: u64,
+    is_64_bits: bool,
+    strings: &'a StringSections,
+) -> Option<FormValue<'a>> {
+    Some(match form {
+        DW_FORM_STRING => FormValue::String(reader.cstr()?),
+        DW_FORM_LINE_STRP => FormValue::String(string_at(
+            &strings.debug_line_str,
+            reader.offset(is_64_bits)?,
+        )?),
+        DW_FORM_STRP => {
+            FormValue::String(string_at(&strings.debug_str, reader.offset(is_64_bits)?)?)
+        }
+        DW_FORM_UDATA => FormValue::Integer(reader.uleb128()?),
+        DW_FORM_SDATA => FormValue::Integer(reader.sleb128()? as u64),
+        DW_FORM_DATA1 => FormValue::Integer(reader.u8()?.into()),
+        DW_FORM_DATA2 => FormValue::Integer(reader.u16()?.into()),
+        DW_FORM_DATA4 => FormValue::Integer(reader.u32()?.into()),
+        DW_FORM_DATA8 => FormValue::Integer(reader.u64()?),
+        DW_FORM_DATA16 => {
+            reader.bytes(16)?;
+            FormValue::Integer(0)
+        }
+        DW_FORM_BLOCK => {
+            let len = reader.uleb128()?;
+            reader.bytes(usize::try_from(len).ok()?)?;
+            FormValue::Integer(0)
+        }
+        // The string index forms need .debug_str_offsets and the compilation unit
+        _ => return None,
+    })
+}
+
+// The directories or the files of a DWARF 5 header, as (path, directory index)
+fn read_entries<'a>(
+    reader: &mut Reader<'a>,
+    is_64_bits: bool,
+    strings: &'a StringSections,
+) -> Option<Vec<(&'a [u8], u64)>> {
+    let format_count = reader.u8()?;
+    let mut format = Vec::with_capacity(format_count.into());
+    for _ in 0..format_count {
+        format.push((reader.uleb128()?, reader.uleb128()?));
+    }
+
+    let count = reader.uleb128()?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let mut entry: (&[u8], u64) = (&[], 0);
+        for (content_type, form) in &format {
+            match (
+                *content_type,
+                read_form(reader, *form, is_64_bits, strings)?,
+            ) {
+                (DW_LNCT_PATH, FormValue::String(path)) => entry.0 = path,
+                (DW_LNCT_DIRECTORY_INDEX, FormValue::Integer(index)) => entry.1 = index,
+                _ => {}
+            }
+        }
+        entries.push(entry);
+    }
+    Some(entries)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    address: u64,
+    file: u32,
+    line: u32,
+    end_sequence: bool,
+}
+
+/// The source location of the addresses of an executable. Like addr2line, an address maps to the
+/// row of the closest lower address, within the same sequence of instructions.
+#[derive(Debug, Default)]
+pub struct LineTable {
+    rows: Vec<Row>,
+    files: Vec<PathBuf>,
+}
+
+impl LineTable {
+    /// Reads the line table of the .debug_line section, or returns None without debug info
+    pub fn read(elf: &Elf, headers: &SectionHeaders) -> Result<Option<Self>, io::Error> {
+        let Some(debug_line_header) = headers.find_by_name(".debug_line") else {
+            return Ok(None);
+        };
+        let debug_line = elf.get_section_data(debug_line_header)?;
+
+        let read_optional = |name| match headers.find_by_name(name) {
+            Some(header) => elf.get_section_data(header),
+            None => Ok(Vec::new()),
+        };
+        let strings = StringSections {
+            debug_str: read_optional(".debug_str")?,
+            debug_line_str: read_optional(".debug_line_str")?,
+        };
+
+        let mut table = LineTable::default();
+        let mut unit_offset = 0;
+        while unit_offset < debug_line.len() {
+            match table.read_unit(
+                &debug_line,
+                unit_offset,
+                &strings,
+                elf.header.is_little_endian(),
+            ) {
+                Some(next_unit_offset) => unit_offset = next_unit_offset,
+                // The units after a malformed one can't be found
+                None => break,
+            }
+        }
+
+        // At the same address, the end of a sequence comes before the start of the next one
+        table
+            .rows
+            .sort_by_key(|row| (row.address, !row.end_sequence));
+        Ok(Some(table))
+    }
+
+    // Runs the line number program of a unit, and returns the offset of the next unit
+    fn read_unit(
+        &mut self,
+        debug_line: &[u8],
+        unit_offset: usize,
+        strings: &StringSections,
+        little_endian: bool,
+    ) -> Option<usize> {
+        let mut reader = Reader::new(debug_line, unit_offset, little_endian);
+        let (unit_length, is_64_bits) = match reader.u32()? {
+            0xffff_ffff => (reader.u64()?, true),
+            unit_length => (u64::from(unit_length), false),
+        };
+        let unit_end = reader
+            .position
+            .checked_add(usize::try_from(unit_length).ok()?)?;
+        if unit_end > debug_line.len() {
+            return None;
+        }
+
+        let version = reader.u16()?;
+        if !(2..=5).contains(&version) {
+            return Some(unit_end);
+        }
+        if version >= 5 {
+            let _address_size = reader.u8()?;
+            let _segment_selector_size = reader.u8()?;
+        }
+        let header_length = reader.offset(is_64_bits)?;
+        let program_start = reader
+            .position
+            .checked_add(usize::try_from(header_length).ok()?)?;
+
+        let minimum_instruction_length = u64::from(reader.u8()?);
+        if version >= 4 {
+            let _maximum_operations_per_instruction = reader.u8()?;
+        }
+        let _default_is_stmt = reader.u8()?;
+        let line_base = reader.u8()? as i8;
+        let line_range = reader.u8()?;
+        let opcode_base = reader.u8()?;
+        if line_range == 0 || opcode_base == 0 {
+            return Some(unit_end);
+        }
+        let standard_opcode_lengths = reader.bytes(usize::from(opcode_base) - 1)?.to_vec();
+
+        // The file indexes start at 1 before DWARF 5, and at 0 since
+        let first_file = self.files.len() as u32;
+        let file_index_base = if version >= 5 {
+            let directories = read_entries(&mut reader, is_64_bits, strings)?;
+            let files = read_entries(&mut reader, is_64_bits, strings)?;
+            for (name, directory) in files {
+                let directory = directories
+                    .get(directory as usize)
+                    .map(|(directory, _)| *directory)
+                    .unwrap_or_default();
+                self.files
+                    .push(path_from_bytes(directory).join(path_from_bytes(name)));
+            }
+            0
+        } else {
+            // The directory 0 is the one of the compilation, which is only in .debug_info
+            let mut directories = vec![PathBuf::new()];
+            loop {
+                let directory = reader.cstr()?;
+                if directory.is_empty() {
+                    break;
+                }
+                directories.push(path_from_bytes(directory));
+            }
+            loop {
+                let name = reader.cstr()?;
+                if name.is_empty() {
+                    break;
+                }
+                let directory = reader.uleb128()?;
+                let _modification_time = reader.uleb128()?;
+                let _length = reader.uleb128()?;
+                let directory = directories
+                    .get(directory as usize)
+                    .map(PathBuf::as_path)
+                    .unwrap_or(Path::new(""));
+                self.files.push(directory.join(path_from_bytes(name)));
+            }
+            1
+        };
+
+        let mut reader = Reader::new(&debug_line[..unit_end], program_start, little_endian);
+        let mut address = 0u64;
+        let mut file = 1u64;
+        let mut line = 1i64;
+        let mut sequence_start = self.rows.len();
+
+        let emit = |rows: &mut Vec<Row>, address: u64, file: u64, line: i64, end: bool| {
+            rows.push(Row {
+                address,
+                file: first_file + (file.saturating_sub(file_index_base) as u32),
+                line: line.clamp(0, u32::MAX.into()) as u32,
+                end_sequence: end,
+            });
+        };
+
+        while reader.position < unit_end {
+            let opcode = reader.u8()?;
+            if opcode >= opcode_base {
+                let adjusted_opcode = opcode - opcode_base;
+                address = address.wrapping_add(
+                    u64::from(adjusted_opcode / line_range) * minimum_instruction_length,
+                );
+                line += i64::from(line_base) + i64::from(adjusted_opcode % line_range);
+                emit(&mut self.rows, address, file, line, false);
+                continue;
+            }
+
+            match opcode {
+                0 => {
+                    let len = usize::try_from(reader.uleb128()?).ok()?;
+                    let extended_end = reader.position.checked_add(len)?;
+                    match reader.u8()? {
+                        DW_LNE_END_SEQUENCE => {
+                            emit(&mut self.rows, address, file, line, true);
+                            // The code removed by the linker keeps its line information, at the
+                            // address 0
+                            if self.rows[sequence_start].address == 0 {
+                                self.rows.truncate(sequence_start);
+                            }
+                            sequence_start = self.rows.len();
+                            (address, file, line) = (0, 1, 1);
+                        }
+                        DW_LNE_SET_ADDRESS => {
+                            address = match len - 1 {
+                                4 => reader.u32()?.into(),
+                                8 => reader.u64()?,
+                                _ => return Some(unit_end),
+                            };
+                        }
+                        DW_LNE_DEFINE_FILE => {
+                            let name = reader.cstr()?;
+                            self.files.push(path_from_bytes(name));
+                        }
+                        _ => {}
+                    }
+                    reader.position = extended_end;
+                }
+                DW_LNS_COPY => emit(&mut self.rows, address, file, line, false),
+                DW_LNS_ADVANCE_PC => {
+                    address = address
+                        .wrapping_add(reader.uleb128()?.wrapping_mul(minimum_instruction_length));
+                }
+                DW_LNS_ADVANCE_LINE => line += reader.sleb128()?,
+                DW_LNS_SET_FILE => file = reader.uleb128()?,
+                DW_LNS_CONST_ADD_PC => {
+                    address = address.wrapping_add(
+                        u64::from((255 - opcode_base) / line_range) * minimum_instruction_length,
+                    );
+                }
+                DW_LNS_FIXED_ADVANCE_PC => address = address.wrapping_add(reader.u16()?.into()),
+                // The other standard opcodes only have ULEB128 operands, which don't matter here
+                _ => {
+                    for _ in 0..standard_opcode_lengths[usize::from(opcode) - 1] {
+                        reader.uleb128()?;
+                    }
+                }
+            }
+        }
+
+        // A sequence without its end can't be trusted
+        self.rows.truncate(sequence_start);
+        Some(unit_end)
+    }
+
+    /// The source file and line of an address
+    pub fn find(&self, address: u64) -> Option<(&Path, u32)> {
+        let index = self.rows.partition_point(|row| row.address <= address);
+        let row = self.rows.get(index.checked_sub(1)?)?;
+        if row.end_sequence {
+            return None;
+        }
+        let file = self.files.get(row.file as usize)?;
+        Some((file, row.line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STANDARD_OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+    // The bytes of a 32 bits DWARF unit, with the integers written in its endianness
+    struct UnitBuilder {
+        data: Vec<u8>,
+        little_endian: bool,
+    }
+
+    impl UnitBuilder {
+        fn new(version: u16, little_endian: bool) -> Self {
+            let mut unit = Self {
+                data: Vec::new(),
+                little_endian,
+            };
+            // The unit length is written once the unit is complete
+            unit.u32(0);
+            unit.u16(version);
+            unit
+        }
+
+        fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+            self.data.extend_from_slice(bytes);
+            self
+        }
+
+        fn u16(&mut self, value: u16) -> &mut Self {
+            if self.little_endian {
+                self.bytes(&value.to_le_bytes())
+            } else {
+                self.bytes(&value.to_be_bytes())
+            }
+        }
+
+        fn u32(&mut self, value: u32) -> &mut Self {
+            if self.little_endian {
+                self.bytes(&value.to_le_bytes())
+            } else {
+                self.bytes(&value.to_be_bytes())
+            }
+        }
+
+        fn set_address(&mut self, address: u64) -> &mut Self {
+            self.bytes(&[0, 9, DW_LNE_SET_ADDRESS]);
+            if self.little_endian {
+                self.bytes(&address.to_le_bytes())
+            } else {
+                self.bytes(&address.to_be_bytes())
+            }
+        }
+
+        fn end_sequence(&mut self) -> &mut Self {
+            self.bytes(&[0, 1, DW_LNE_END_SEQUENCE])
+        }
+
+        // The fields of the header that follow the header length, up to the directories: a
+        // minimum instruction length of 1, a line base of -5, a line range of 14 and an opcode
+        // base of 13
+        fn line_parameters(&mut self, version: u16) -> &mut Self {
+            self.bytes(&[1]);
+            if version >= 4 {
+                self.bytes(&[1]);
+            }
+            self.bytes(&[1, -5i8 as u8, 14, 13])
+                .bytes(&STANDARD_OPCODE_LENGTHS)
+        }
+
+        // Patches the unit length, and the header length that ends at program_start
+        fn finish(mut self, header_length_offset: usize, program_start: usize) -> Vec<u8> {
+            let unit_length = (self.data.len() - 4) as u32;
+            let header_length = (program_start - header_length_offset - 4) as u32;
+            for (offset, value) in [(0, unit_length), (header_length_offset, header_length)] {
+                let bytes = if self.little_endian {
+                    value.to_le_bytes()
+                } else {
+                    value.to_be_bytes()
+                };
+                self.data[offset..offset + 4].copy_from_slice(&bytes);
+            }
+            self.data
+        }
+    }
+
+    // The special opcode advancing the address and the line, with the parameters of the units
+    fn special_opcode(address_advance: u8, line_advance: i8) -> u8 {
+        (line_advance + 5) as u8 + 14 * address_advance + 13
+    }
+
+    fn no_strings() -> StringSections {
+        StringSections {
+            debug_str: Vec::new(),
+            debug_line_str: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dwarf_4_unit() {
+        let mut unit = UnitBuilder::new(4, true);
+        let header_length_offset = unit.data.len();
+        unit.u32(0)
+            .line_parameters(4)
+            // The directories, then the files with their directory, time and length
+            .bytes(b"src\0\0")
+            .bytes(b"a.cpp\0\x01\0\0b.h\0\0\0\0\0");
+        let program_start = unit.data.len();
+        unit.set_address(0x1000)
+            .bytes(&[DW_LNS_ADVANCE_LINE, 9, DW_LNS_COPY])
+            .bytes(&[special_opcode(4, 2)])
+            .bytes(&[DW_LNS_SET_FILE, 2, DW_LNS_ADVANCE_PC, 0x10])
+            // -7 in SLEB128
+            .bytes(&[DW_LNS_ADVANCE_LINE, 0x79, DW_LNS_COPY])
+            .bytes(&[DW_LNS_ADVANCE_PC, 4])
+            .end_sequence()
+            // A function removed by the linker
+            .set_address(0)
+            .bytes(&[DW_LNS_COPY, DW_LNS_ADVANCE_PC, 8])
+            .end_sequence();
+        let data = unit.finish(header_length_offset, program_start);
+
+        let mut table = LineTable::default();
+        assert_eq!(
+            table.read_unit(&data, 0, &no_strings(), true),
+            Some(data.len())
+        );
+
+        let a = Path::new("src/a.cpp");
+        assert_eq!(table.find(0x1000), Some((a, 10)));
+        assert_eq!(table.find(0x1003), Some((a, 10)));
+        assert_eq!(table.find(0x1004), Some((a, 12)));
+        assert_eq!(table.find(0x1014), Some((Path::new("b.h"), 5)));
+        assert_eq!(table.find(0x1018), None);
+        assert_eq!(table.find(0xfff), None);
+        assert_eq!(table.find(0), None);
+    }
+
+    #[test]
+    fn big_endian_dwarf_5_unit() {
+        let strings = StringSections {
+            debug_str: Vec::new(),
+            debug_line_str: b"/work\0include\0test.cpp\0util.h\0".to_vec(),
+        };
+
+        let mut unit = UnitBuilder::new(5, false);
+        // The address and the segment selector sizes
+        unit.bytes(&[8, 0]);
+        let header_length_offset = unit.data.len();
+        unit.u32(0).line_parameters(5);
+        // The directories are paths in .debug_line_str
+        unit.bytes(&[1, DW_LNCT_PATH as u8, DW_FORM_LINE_STRP as u8, 2])
+            .u32(0)
+            .u32(6);
+        // The files are paths in .debug_line_str with the index of their directory
+        unit.bytes(&[2, DW_LNCT_PATH as u8, DW_FORM_LINE_STRP as u8])
+            .bytes(&[DW_LNCT_DIRECTORY_INDEX as u8, DW_FORM_UDATA as u8, 2])
+            .u32(14)
+            .bytes(&[0])
+            .u32(23)
+            .bytes(&[1]);
+        let program_start = unit.data.len();
+        // The files are indexed from 0 in DWARF 5
+        unit.set_address(0x2000)
+            .bytes(&[DW_LNS_SET_FILE, 0, DW_LNS_ADVANCE_LINE, 41, DW_LNS_COPY])
+            .bytes(&[DW_LNS_SET_FILE, 1, special_opcode(2, 1)])
+            .bytes(&[DW_LNS_FIXED_ADVANCE_PC])
+            .u16(2)
+            .end_sequence();
+        let data = unit.finish(header_length_offset, program_start);
+
+        let mut table = LineTable::default();
+        assert_eq!(table.read_unit(&data, 0, &strings, false), Some(data.len()));
+        assert_eq!(table.find(0x2001), Some((Path::new("/work/test.cpp"), 42)));
+        assert_eq!(table.find(0x2002), Some((Path::new("include/util.h"), 43)));
+        assert_eq!(table.find(0x2004), None);
+    }
+
+    #[test]
+    fn truncated_unit() {
+        let mut unit = UnitBuilder::new(4, true);
+        let header_length_offset = unit.data.len();
+        unit.u32(0).line_parameters(4).bytes(b"\0a.cpp\0\0\0\0\0");
+        let program_start = unit.data.len();
+        unit.set_address(0x1000)
+            .bytes(&[DW_LNS_COPY, DW_LNS_ADVANCE_PC, 4])
+            .end_sequence();
+        let data = unit.finish(header_length_offset, program_start);
+
+        // Past the end of the section
+        let mut table = LineTable::default();
+        assert_eq!(
+            table.read_unit(&data[..data.len() - 1], 0, &no_strings(), true),
+            None
+        );
+        assert_eq!(table.find(0x1000), None);
+
+        // Without the end of its sequence
+        let mut without_end = data[..data.len() - 3].to_vec();
+        without_end[0] -= 3;
+        let mut table = LineTable::default();
+        assert_eq!(
+            table.read_unit(&without_end, 0, &no_strings(), true),
+            Some(without_end.len())
+        );
+        assert_eq!(table.find(0x1000), None);
+    }
+}
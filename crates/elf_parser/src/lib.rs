@@ -1,5 +1,8 @@
+pub mod debug_line;
+pub mod pe;
+
 use bytemuck::{Pod, Zeroable};
-use std::{ffi::CStr, io, os::unix::fs::FileExt, path::Path};
+use std::{ffi::CStr, fs::File, io, path::Path};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,10 +18,66 @@ pub enum Error {
 
     #[error("Elf is not little endian")]
     NotLittleEndian,
+
+    #[error("File is not a PE")]
+    NotAPe,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[cfg(unix)]
+fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buffer, offset)
+}
+
+// seek_read moves the cursor of the file and can read less than asked
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buffer: &mut [u8], mut offset: u64) -> io::Result<()> {
+    while !buffer.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buffer, offset) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(read) => {
+                buffer = &mut buffer[read..];
+                offset += read as u64;
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+// The offsets and the sizes come from the file, so a corrupted one could make a reader allocate
+// more memory than there is. Checks that the bytes are in the file before they are allocated.
+fn checked_size(file: &File, offset: u64, size: u64) -> io::Result<usize> {
+    let file_len = file.metadata()?.len();
+    offset
+        .checked_add(size)
+        .filter(|end| *end <= file_len)
+        .and_then(|_| usize::try_from(size).ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{size} bytes at offset {offset} are past the end of the file"),
+            )
+        })
+}
+
+fn read_data(file: &File, offset: u64, size: u64) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; checked_size(file, offset, size)?];
+    read_exact_at(file, &mut data, offset)?;
+    Ok(data)
+}
+
+// Reads the header of a format at the start of the file. A file shorter than the header, like a
+// small script, isn't in the format.
+fn read_header(file: &File, buffer: &mut [u8], not_in_format: Error) -> Result<()> {
+    match read_exact_at(file, buffer, 0) {
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Err(not_in_format),
+        result => Ok(result?),
+    }
+}
+
 pub trait FetchInteger {
     fn is_little_endian(&self) -> bool;
     fn data(&self) -> &[u8];
@@ -67,16 +126,16 @@ pub trait FetchInteger {
 #[derive(Debug)]
 pub struct Elf {
     pub header: Header,
-    file: std::fs::File,
+    file: File,
 }
 
 impl Elf {
     pub fn new(path: &Path) -> Result<Self> {
-        let file = std::fs::File::open(path)?;
+        let file = File::open(path)?;
 
         let header_buffer = {
             let mut header_buffer = [0u8; 64];
-            file.read_exact_at(&mut header_buffer, 0)?;
+            read_header(&file, &mut header_buffer, Error::NotAnElf)?;
             header_buffer
         };
 
@@ -98,20 +157,42 @@ impl Elf {
         Ok(executable)
     }
 
-    pub fn get_all_section_headers(&self) -> std::result::Result<SectionHeaders, io::Error> {
-        let mut all_section_headers: Vec<SectionHeader> =
-            std::iter::repeat(SectionHeader::zeroed())
-                .take(self.header.e_shnum() as usize)
-                .collect();
-
-        let all_section_headers_bytes: &mut [u8] =
-            bytemuck::cast_slice_mut(&mut all_section_headers);
+    // The symbols and the dynamic entries are read as native structs, which need their bytes
+    // swapped when the ELF was built for a target of the other endianness
+    fn has_foreign_endianness(&self) -> bool {
+        self.header.is_little_endian() != cfg!(target_endian = "little")
+    }
 
-        self.file
-            .read_exact_at(all_section_headers_bytes, self.header.e_shoff())?;
+    pub fn get_all_section_headers(&self) -> std::result::Result<SectionHeaders, io::Error> {
+        let all_section_headers_bytes = read_data(
+            &self.file,
+            self.header.e_shoff(),
+            self.header.e_shnum() as u64 * SECTION_HEADER_SIZE as u64,
+        )?;
+
+        let little_endian = self.header.is_little_endian();
+        let all_section_headers = all_section_headers_bytes
+            .chunks_exact(SECTION_HEADER_SIZE)
+            .map(|data| SectionHeader {
+                data: data.try_into().unwrap(),
+                little_endian,
+            })
+            .collect::<Vec<_>>();
+
+        // The names of the sections are in the section at index e_shstrndx
+        let names = match all_section_headers.get(self.header.e_shstrndx() as usize) {
+            Some(names_header) if names_header.sh_type() == 0x3 => {
+                match self.get_section(names_header)? {
+                    Section::Strings(names) => names,
+                    _ => StringTable::default(),
+                }
+            }
+            _ => StringTable::default(),
+        };
 
         Ok(SectionHeaders {
             headers: all_section_headers,
+            names,
         })
     }
 
@@ -121,31 +202,286 @@ impl Elf {
     ) -> std::result::Result<Section, io::Error> {
         let header_type = section_header.sh_type();
         Ok(match header_type {
-            0x2 => {
-                let mut symbols: Vec<Elf64Sym> = std::iter::repeat(Elf64Sym::zeroed())
-                    .take(section_header.sh_size() as usize / std::mem::size_of::<Elf64Sym>())
-                    .collect();
+            0x2 | 0xB => {
+                let size = checked_size(
+                    &self.file,
+                    section_header.sh_offset(),
+                    section_header.sh_size(),
+                )?;
+                let mut symbols: Vec<Elf64Sym> =
+                    std::iter::repeat_n(Elf64Sym::zeroed(), size / std::mem::size_of::<Elf64Sym>())
+                        .collect();
 
-                self.file.read_exact_at(
+                read_exact_at(
+                    &self.file,
                     bytemuck::cast_slice_mut(&mut symbols),
                     section_header.sh_offset(),
                 )?;
+                if self.has_foreign_endianness() {
+                    symbols.iter_mut().for_each(Elf64Sym::swap_bytes);
+                }
 
                 Section::Symbols(symbols)
             }
             0x3 => {
-                let mut data: Vec<u8> = std::iter::repeat(0u8)
-                    .take(section_header.sh_size() as usize)
-                    .collect();
-
-                self.file
-                    .read_exact_at(&mut data, section_header.sh_offset())?;
+                let data = read_data(
+                    &self.file,
+                    section_header.sh_offset(),
+                    section_header.sh_size(),
+                )?;
 
                 Section::Strings(StringTable { data })
             }
+            0x6 => {
+                let size = checked_size(
+                    &self.file,
+                    section_header.sh_offset(),
+                    section_header.sh_size(),
+                )?;
+                let mut entries: Vec<Elf64Dyn> =
+                    std::iter::repeat_n(Elf64Dyn::zeroed(), size / std::mem::size_of::<Elf64Dyn>())
+                        .collect();
+
+                read_exact_at(
+                    &self.file,
+                    bytemuck::cast_slice_mut(&mut entries),
+                    section_header.sh_offset(),
+                )?;
+                if self.has_foreign_endianness() {
+                    entries.iter_mut().for_each(Elf64Dyn::swap_bytes);
+                }
+
+                Section::Dynamic(entries)
+            }
             _ => Section::NotImplemented,
         })
     }
+
+    /// Returns the raw content of a section, whatever its type
+    pub fn get_section_data(
+        &self,
+        section_header: &SectionHeader,
+    ) -> std::result::Result<Vec<u8>, io::Error> {
+        // Sections like .bss take no space in the file
+        if section_header.sh_type() == 8 {
+            return Ok(Vec::new());
+        }
+
+        read_data(
+            &self.file,
+            section_header.sh_offset(),
+            section_header.sh_size(),
+        )
+    }
+
+    /// Iterates over the symbols of a symbol table, which are only decoded when they are reached,
+    /// so that a search can stop at the first match without decoding the whole table
+    pub fn symbols(
+        &self,
+        section_header: &SectionHeader,
+    ) -> std::result::Result<Symbols, io::Error> {
+        Ok(Symbols {
+            data: self.get_section_data(section_header)?,
+            position: 0,
+            foreign_endianness: self.has_foreign_endianness(),
+        })
+    }
+
+    pub fn get_all_program_headers(&self) -> std::result::Result<Vec<ElfProgramHeader>, io::Error> {
+        let program_headers_bytes = read_data(
+            &self.file,
+            self.header.e_phoff(),
+            self.header.e_phnum() as u64 * PROGRAM_HEADER_SIZE as u64,
+        )?;
+
+        let little_endian = self.header.is_little_endian();
+        Ok(program_headers_bytes
+            .chunks_exact(PROGRAM_HEADER_SIZE)
+            .map(|data| ElfProgramHeader {
+                data: data.try_into().unwrap(),
+                little_endian,
+            })
+            .collect())
+    }
+
+    fn read_segment(
+        &self,
+        program_header: &ElfProgramHeader,
+    ) -> std::result::Result<Vec<u8>, io::Error> {
+        read_data(
+            &self.file,
+            program_header.p_offset(),
+            program_header.p_filesz(),
+        )
+    }
+
+    /// Returns the dynamic loader requested by the ELF (its PT_INTERP segment), like
+    /// /lib64/ld-linux-x86-64.so.2. Static executables have none.
+    pub fn get_interpreter(&self) -> std::result::Result<Option<String>, io::Error> {
+        let program_headers = self.get_all_program_headers()?;
+        let Some(interp_header) = program_headers
+            .iter()
+            .find(|header| header.p_type() == PT_INTERP)
+        else {
+            return Ok(None);
+        };
+
+        let data = self.read_segment(interp_header)?;
+        Ok(CStr::from_bytes_until_nul(&data)
+            .ok()
+            .map(|interpreter| interpreter.to_string_lossy().into_owned()))
+    }
+
+    /// Returns the GNU build-id of the ELF, from its PT_NOTE segments, which identifies the build
+    /// it comes from, e.g. to find its separate debug symbols
+    pub fn get_build_id(&self) -> std::result::Result<Option<Vec<u8>>, io::Error> {
+        for note_header in self
+            .get_all_program_headers()?
+            .iter()
+            .filter(|header| header.p_type() == PT_NOTE)
+        {
+            let data = self.read_segment(note_header)?;
+            let mut notes = data.as_slice();
+            // Every note is a name size, a description size and a type, followed by the name and
+            // the description, both padded to 4 bytes
+            let read_u32 = |bytes: [u8; 4]| {
+                if self.header.is_little_endian() {
+                    u32::from_le_bytes(bytes)
+                } else {
+                    u32::from_be_bytes(bytes)
+                }
+            };
+            while let [n0, n1, n2, n3, d0, d1, d2, d3, t0, t1, t2, t3, rest @ ..] = notes {
+                let name_size = read_u32([*n0, *n1, *n2, *n3]) as usize;
+                let description_size = read_u32([*d0, *d1, *d2, *d3]) as usize;
+                let note_type = read_u32([*t0, *t1, *t2, *t3]);
+
+                let name_end = name_size.next_multiple_of(4);
+                let description_end = name_end + description_size.next_multiple_of(4);
+                let (Some(name), Some(description)) = (
+                    rest.get(..name_size),
+                    rest.get(name_end..name_end + description_size),
+                ) else {
+                    break;
+                };
+
+                if note_type == NT_GNU_BUILD_ID && name == b"GNU\0" {
+                    return Ok(Some(description.to_vec()));
+                }
+                notes = rest.get(description_end..).unwrap_or_default();
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the entries of the PT_DYNAMIC segment, which the loader reads, so they are there
+    /// even when the section headers are stripped
+    pub fn get_dynamic_entries(&self) -> std::result::Result<Vec<Elf64Dyn>, io::Error> {
+        let program_headers = self.get_all_program_headers()?;
+        let Some(dynamic_header) = program_headers
+            .iter()
+            .find(|header| header.p_type() == PT_DYNAMIC)
+        else {
+            return Ok(Vec::new());
+        };
+
+        let size = checked_size(
+            &self.file,
+            dynamic_header.p_offset(),
+            dynamic_header.p_filesz(),
+        )?;
+        let mut entries: Vec<Elf64Dyn> =
+            std::iter::repeat_n(Elf64Dyn::zeroed(), size / std::mem::size_of::<Elf64Dyn>())
+                .collect();
+        read_exact_at(
+            &self.file,
+            bytemuck::cast_slice_mut(&mut entries),
+            dynamic_header.p_offset(),
+        )?;
+        if self.has_foreign_endianness() {
+            entries.iter_mut().for_each(Elf64Dyn::swap_bytes);
+        }
+
+        Ok(entries
+            .into_iter()
+            .take_while(|entry| entry.d_tag != DT_NULL)
+            .collect())
+    }
+
+    // The dynamic entries refer to the memory of the loaded ELF, which the PT_LOAD segments map
+    fn file_offset(program_headers: &[ElfProgramHeader], address: u64) -> Option<u64> {
+        program_headers
+            .iter()
+            .filter(|header| header.p_type() == PT_LOAD)
+            .find(|header| {
+                header
+                    .p_vaddr()
+                    .checked_add(header.p_filesz())
+                    .is_some_and(|end| (header.p_vaddr()..end).contains(&address))
+            })
+            .and_then(|header| (address - header.p_vaddr()).checked_add(header.p_offset()))
+    }
+
+    // The strings of the dynamic entries with the tag, read from the string table of the PT_DYNAMIC
+    // segment
+    fn get_dynamic_strings(&self, tag: i64) -> std::result::Result<Vec<String>, io::Error> {
+        let entries = self.get_dynamic_entries()?;
+        let find_value = |tag| {
+            entries
+                .iter()
+                .find(|entry| entry.d_tag == tag)
+                .map(|entry| entry.d_val)
+        };
+        let (Some(strings_address), Some(strings_size)) =
+            (find_value(DT_STRTAB), find_value(DT_STRSZ))
+        else {
+            return Ok(Vec::new());
+        };
+        let Some(strings_offset) =
+            Self::file_offset(&self.get_all_program_headers()?, strings_address)
+        else {
+            return Ok(Vec::new());
+        };
+
+        let strings = StringTable {
+            data: read_data(&self.file, strings_offset, strings_size)?,
+        };
+
+        Ok(entries
+            .iter()
+            .filter(|entry| entry.d_tag == tag)
+            .filter_map(|entry| strings.get_string(entry.d_val as usize))
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    /// Returns the names of the shared libraries the ELF depends on (its DT_NEEDED entries),
+    /// like libpq.so.5. Statically linked ELFs have none.
+    pub fn get_needed_libraries(&self) -> std::result::Result<Vec<String>, io::Error> {
+        self.get_dynamic_strings(DT_NEEDED)
+    }
+
+    /// Returns the directories of the DT_RPATH entries, which the loader searches for the needed
+    /// libraries before LD_LIBRARY_PATH. They may contain $ORIGIN, the directory of the ELF.
+    pub fn get_rpath(&self) -> std::result::Result<Vec<String>, io::Error> {
+        Ok(split_search_path(self.get_dynamic_strings(DT_RPATH)?))
+    }
+
+    /// Returns the directories of the DT_RUNPATH entries, which the loader searches for the needed
+    /// libraries after LD_LIBRARY_PATH. When there is a DT_RUNPATH, the DT_RPATH is ignored.
+    pub fn get_runpath(&self) -> std::result::Result<Vec<String>, io::Error> {
+        Ok(split_search_path(self.get_dynamic_strings(DT_RUNPATH)?))
+    }
+}
+
+fn split_search_path(entries: Vec<String>) -> Vec<String> {
+    entries
+        .iter()
+        .flat_map(|entry| entry.split(':'))
+        .filter(|dir| !dir.is_empty())
+        .map(str::to_string)
+        .collect()
 }
 
 #[repr(C)]
@@ -189,6 +525,23 @@ impl Header {
         self.get_u16(0x12).unwrap()
     }
 
+    /// The name of the architecture, for the common values of e_machine
+    pub fn machine_name(&self) -> Option<&'static str> {
+        Some(match self.e_machine() {
+            0x03 => "x86",
+            0x08 => "mips",
+            0x14 => "powerpc",
+            0x15 => "powerpc64",
+            0x16 => "s390",
+            0x28 => "arm",
+            0x3E => "x86-64",
+            0xB7 => "aarch64",
+            0xF3 => "riscv",
+            0x102 => "loongarch",
+            _ => return None,
+        })
+    }
+
     pub fn e_version(&self) -> u32 {
         self.get_u32(0x14).unwrap()
     }
@@ -236,23 +589,52 @@ impl Header {
 
 pub struct SectionHeaders {
     pub headers: Vec<SectionHeader>,
+    names: StringTable,
 }
 
 impl SectionHeaders {
+    /// The name of a section, like .rodata
+    pub fn section_name(&self, section: &SectionHeader) -> Option<&CStr> {
+        self.names.get_string(section.sh_name() as usize)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<&SectionHeader> {
+        self.headers.iter().find(|section| {
+            self.section_name(section)
+                .is_some_and(|section_name| section_name.to_bytes() == name.as_bytes())
+        })
+    }
+
     pub fn find_symbol_table_header(&self) -> Option<&SectionHeader> {
         self.headers.iter().find(|section| section.sh_type() == 2)
     }
+
+    pub fn find_dynamic_symbol_table_header(&self) -> Option<&SectionHeader> {
+        self.headers.iter().find(|section| section.sh_type() == 0xB)
+    }
+
+    /// The section linked to another one, like the string table holding the names of the symbols
+    /// of a .symtab or .dynsym section
+    pub fn find_linked_header(&self, section: &SectionHeader) -> Option<&SectionHeader> {
+        self.headers.get(section.sh_link() as usize)
+    }
+
+    pub fn find_dynamic_header(&self) -> Option<&SectionHeader> {
+        self.headers.iter().find(|section| section.sh_type() == 6)
+    }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+const SECTION_HEADER_SIZE: usize = 64;
+
+#[derive(Copy, Clone, Debug)]
 pub struct SectionHeader {
-    data: [u8; 64],
+    data: [u8; SECTION_HEADER_SIZE],
+    little_endian: bool,
 }
 
 impl FetchInteger for SectionHeader {
     fn is_little_endian(&self) -> bool {
-        true
+        self.little_endian
     }
 
     fn data(&self) -> &[u8] {
@@ -302,12 +684,93 @@ impl SectionHeader {
     }
 }
 
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_INTERP: u32 = 3;
+pub const PT_NOTE: u32 = 4;
+
+pub const NT_GNU_BUILD_ID: u32 = 3;
+
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+#[derive(Copy, Clone, Debug)]
+pub struct ElfProgramHeader {
+    data: [u8; PROGRAM_HEADER_SIZE],
+    little_endian: bool,
+}
+
+impl FetchInteger for ElfProgramHeader {
+    fn is_little_endian(&self) -> bool {
+        self.little_endian
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl ElfProgramHeader {
+    pub fn p_type(&self) -> u32 {
+        self.get_u32(0x00).unwrap()
+    }
+
+    pub fn p_flags(&self) -> u32 {
+        self.get_u32(0x04).unwrap()
+    }
+
+    pub fn p_offset(&self) -> u64 {
+        self.get_u64(0x08).unwrap()
+    }
+
+    pub fn p_vaddr(&self) -> u64 {
+        self.get_u64(0x10).unwrap()
+    }
+
+    pub fn p_paddr(&self) -> u64 {
+        self.get_u64(0x18).unwrap()
+    }
+
+    pub fn p_filesz(&self) -> u64 {
+        self.get_u64(0x20).unwrap()
+    }
+
+    pub fn p_memsz(&self) -> u64 {
+        self.get_u64(0x28).unwrap()
+    }
+
+    pub fn p_align(&self) -> u64 {
+        self.get_u64(0x30).unwrap()
+    }
+}
+
 pub enum Section {
     Symbols(Vec<Elf64Sym>),
     Strings(StringTable),
+    Dynamic(Vec<Elf64Dyn>),
     NotImplemented,
 }
 
+pub const DT_NULL: i64 = 0;
+pub const DT_NEEDED: i64 = 1;
+pub const DT_STRTAB: i64 = 5;
+pub const DT_STRSZ: i64 = 10;
+pub const DT_RPATH: i64 = 15;
+pub const DT_RUNPATH: i64 = 29;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+pub struct Elf64Dyn {
+    pub d_tag: i64,
+    pub d_val: u64,
+}
+
+impl Elf64Dyn {
+    fn swap_bytes(&mut self) {
+        self.d_tag = self.d_tag.swap_bytes();
+        self.d_val = self.d_val.swap_bytes();
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
 pub struct Elf64Sym {
@@ -319,14 +782,365 @@ pub struct Elf64Sym {
     pub st_size: u64,
 }
 
+impl Elf64Sym {
+    fn swap_bytes(&mut self) {
+        self.st_name = self.st_name.swap_bytes();
+        self.st_shndx = self.st_shndx.swap_bytes();
+        self.st_value = self.st_value.swap_bytes();
+        self.st_size = self.st_size.swap_bytes();
+    }
+}
+
+/// The symbols of a symbol table, decoded one at a time, see `Elf::symbols`
+pub struct Symbols {
+    data: Vec<u8>,
+    position: usize,
+    foreign_endianness: bool,
+}
+
+impl Iterator for Symbols {
+    type Item = Elf64Sym;
+
+    fn next(&mut self) -> Option<Elf64Sym> {
+        let size = std::mem::size_of::<Elf64Sym>();
+        let bytes = self.data.get(self.position..self.position + size)?;
+        self.position += size;
+
+        let mut symbol: Elf64Sym = bytemuck::pod_read_unaligned(bytes);
+        if self.foreign_endianness {
+            symbol.swap_bytes();
+        }
+        Some(symbol)
+    }
+}
+
+/// Returns the NUL-terminated string at `index` of the content of a string table
+pub fn string_at(data: &[u8], index: usize) -> Option<&CStr> {
+    CStr::from_bytes_until_nul(data.get(index..)?).ok()
+}
+
+#[derive(Default)]
 pub struct StringTable {
     pub data: Vec<u8>,
 }
 
 impl<'a> StringTable {
     pub fn get_symbol_name(&'a self, symbol: &Elf64Sym) -> Option<&'a CStr> {
-        let symbol_string_index = symbol.st_name as usize;
-        let data_slice = self.data.get(symbol_string_index..)?;
-        CStr::from_bytes_until_nul(data_slice).ok()
+        self.get_string(symbol.st_name as usize)
+    }
+
+    pub fn get_string(&'a self, index: usize) -> Option<&'a CStr> {
+        string_at(&self.data, index)
+    }
+}
+
+// A file written by a test, removed when the test is done with it
+#[cfg(test)]
+pub(crate) struct TestFile(std::path::PathBuf);
+
+#[cfg(test)]
+impl std::ops::Deref for TestFile {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// Writes the bytes of a test to a file of its own, since the parsers read from files
+#[cfg(test)]
+pub(crate) fn write_test_file(name: &str, contents: &[u8]) -> TestFile {
+    let path = std::env::temp_dir().join(format!("elf_parser-{}-{name}", std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    TestFile(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The bytes of an ELF, with its integers written in its endianness
+    struct ElfBuilder {
+        data: Vec<u8>,
+        little_endian: bool,
+    }
+
+    impl ElfBuilder {
+        fn put(&mut self, offset: usize, bytes: &[u8]) {
+            if self.data.len() < offset + bytes.len() {
+                self.data.resize(offset + bytes.len(), 0);
+            }
+            self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+
+        fn put_u16(&mut self, offset: usize, value: u16) {
+            let bytes = if self.little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            };
+            self.put(offset, &bytes);
+        }
+
+        fn put_u32(&mut self, offset: usize, value: u32) {
+            let bytes = if self.little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            };
+            self.put(offset, &bytes);
+        }
+
+        fn put_u64(&mut self, offset: usize, value: u64) {
+            let bytes = if self.little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            };
+            self.put(offset, &bytes);
+        }
+    }
+
+    const LOAD_ADDRESS: u64 = 0x400000;
+
+    // An executable with an interpreter, a build-id, dynamic entries and a symbol table, laid out
+    // like:
+    //   0    ELF header
+    //   64   4 program headers: PT_INTERP, PT_NOTE, PT_LOAD of the whole file and PT_DYNAMIC
+    //   288  interpreter
+    //   304  GNU build-id note
+    //   328  dynamic strings
+    //   368  dynamic entries
+    //   448  section names
+    //   488  symbol names
+    //   512  symbols
+    //   584  5 section headers: null, .shstrtab, .strtab, .symtab and .dynamic
+    fn build_elf(little_endian: bool) -> Vec<u8> {
+        let mut elf = ElfBuilder {
+            data: Vec::new(),
+            little_endian,
+        };
+
+        elf.put(0, b"\x7FELF\x02");
+        elf.put(5, &[if little_endian { 1 } else { 2 }, 1]);
+        elf.put_u16(0x10, 2);
+        elf.put_u16(0x12, if little_endian { 0x3E } else { 0x15 });
+        elf.put_u32(0x14, 1);
+        elf.put_u64(0x20, 64);
+        elf.put_u64(0x28, 584);
+        elf.put_u16(0x34, 64);
+        elf.put_u16(0x36, PROGRAM_HEADER_SIZE as u16);
+        elf.put_u16(0x38, 4);
+        elf.put_u16(0x3A, SECTION_HEADER_SIZE as u16);
+        elf.put_u16(0x3C, 5);
+        elf.put_u16(0x3E, 1);
+
+        for (index, (p_type, offset, size)) in [
+            (PT_INTERP, 288, 15),
+            (PT_NOTE, 304, 20),
+            (PT_LOAD, 0, 904),
+            (PT_DYNAMIC, 368, 80),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let header = 64 + index * PROGRAM_HEADER_SIZE;
+            elf.put_u32(header, p_type);
+            elf.put_u64(header + 0x08, offset);
+            elf.put_u64(header + 0x10, LOAD_ADDRESS + offset);
+            elf.put_u64(header + 0x20, size);
+            elf.put_u64(header + 0x28, size);
+        }
+
+        elf.put(288, b"/lib/ld64.so.1\0");
+
+        elf.put_u32(304, 4);
+        elf.put_u32(308, 4);
+        elf.put_u32(312, NT_GNU_BUILD_ID);
+        elf.put(316, b"GNU\0\xDE\xAD\xBE\xEF");
+
+        let dynamic_strings = b"\0libgtest.so.1\0$ORIGIN/lib:/opt/lib\0";
+        elf.put(328, dynamic_strings);
+        for (index, (tag, value)) in [
+            (DT_NEEDED, 1),
+            (DT_RUNPATH, 15),
+            (DT_STRTAB, LOAD_ADDRESS + 328),
+            (DT_STRSZ, dynamic_strings.len() as u64),
+            (DT_NULL, 0),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            elf.put_u64(368 + index * 16, tag as u64);
+            elf.put_u64(368 + index * 16 + 8, value);
+        }
+
+        elf.put(448, b"\0.shstrtab\0.strtab\0.symtab\0.dynamic\0");
+        elf.put(488, b"\0main\0InitGoogleTest\0");
+
+        // The first symbol is the null one
+        for (index, (name, value)) in [(1, 0x401000), (6, 0x401100)].into_iter().enumerate() {
+            let symbol = 512 + (index + 1) * 24;
+            elf.put_u32(symbol, name);
+            elf.put(symbol + 4, &[0x12]);
+            elf.put_u16(symbol + 6, 1);
+            elf.put_u64(symbol + 8, value);
+            elf.put_u64(symbol + 16, 16);
+        }
+
+        // (name, type, offset, size, link)
+        for (index, (name, sh_type, offset, size, link)) in [
+            (1, 3, 448, 36, 0),
+            (11, 3, 488, 21, 0),
+            (19, 2, 512, 72, 2),
+            (27, 6, 368, 80, 0),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let header = 584 + (index + 1) * SECTION_HEADER_SIZE;
+            elf.put_u32(header, name);
+            elf.put_u32(header + 0x04, sh_type);
+            elf.put_u64(header + 0x18, offset);
+            elf.put_u64(header + 0x20, size);
+            elf.put_u32(header + 0x28, link);
+        }
+        // The last section header ends with zeroes
+        elf.put(584 + 5 * SECTION_HEADER_SIZE - 1, &[0]);
+
+        elf.data
+    }
+
+    fn check_elf(elf: &Elf, machine: &str) {
+        assert_eq!(elf.header.e_type(), 2);
+        assert_eq!(elf.header.machine_name(), Some(machine));
+
+        let headers = elf.get_all_section_headers().unwrap();
+        let symbol_table = headers.find_by_name(".symtab").unwrap();
+        assert_eq!(symbol_table.sh_type(), 2);
+        assert_eq!(
+            headers.find_symbol_table_header().unwrap().sh_offset(),
+            symbol_table.sh_offset()
+        );
+        assert!(headers.find_by_name(".text").is_none());
+
+        let Section::Strings(names) = elf
+            .get_section(headers.find_linked_header(symbol_table).unwrap())
+            .unwrap()
+        else {
+            panic!("The symbols aren't linked to a string table");
+        };
+        let Section::Symbols(symbols) = elf.get_section(symbol_table).unwrap() else {
+            panic!(".symtab isn't a symbol table");
+        };
+        let symbol_names = |symbols: &[Elf64Sym]| {
+            symbols
+                .iter()
+                .map(|symbol| {
+                    let name = names.get_symbol_name(symbol).unwrap();
+                    (name.to_str().unwrap().to_string(), symbol.st_value)
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = [
+            (String::new(), 0),
+            (String::from("main"), 0x401000),
+            (String::from("InitGoogleTest"), 0x401100),
+        ];
+        assert_eq!(symbol_names(&symbols), expected);
+        assert_eq!(
+            symbol_names(&elf.symbols(symbol_table).unwrap().collect::<Vec<_>>()),
+            expected
+        );
+
+        let Section::Dynamic(entries) = elf
+            .get_section(headers.find_dynamic_header().unwrap())
+            .unwrap()
+        else {
+            panic!(".dynamic isn't a dynamic section");
+        };
+        assert_eq!(entries[0].d_tag, DT_NEEDED);
+
+        assert_eq!(
+            elf.get_interpreter().unwrap().as_deref(),
+            Some("/lib/ld64.so.1")
+        );
+        assert_eq!(
+            elf.get_build_id().unwrap(),
+            Some(vec![0xDE, 0xAD, 0xBE, 0xEF])
+        );
+        assert_eq!(elf.get_needed_libraries().unwrap(), ["libgtest.so.1"]);
+        assert_eq!(elf.get_runpath().unwrap(), ["$ORIGIN/lib", "/opt/lib"]);
+        assert!(elf.get_rpath().unwrap().is_empty());
+    }
+
+    #[test]
+    fn little_endian_elf() {
+        let path = write_test_file("little_endian_elf", &build_elf(true));
+        check_elf(&Elf::new(&path).unwrap(), "x86-64");
+    }
+
+    #[test]
+    fn big_endian_elf() {
+        let path = write_test_file("big_endian_elf", &build_elf(false));
+        let elf = Elf::new(&path).unwrap();
+        assert!(!elf.header.is_little_endian());
+        check_elf(&elf, "powerpc64");
+    }
+
+    #[test]
+    fn sizes_past_the_end_of_the_file() {
+        let mut elf = build_elf(true);
+        // The size of .strtab
+        let strings_header = 584 + 2 * SECTION_HEADER_SIZE;
+        elf[strings_header + 0x20..strings_header + 0x28].copy_from_slice(&u64::MAX.to_le_bytes());
+        // The size of PT_INTERP
+        elf[64 + 0x20..64 + 0x28].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        // PT_LOAD ends past the end of the address space
+        let load_header = 64 + 2 * PROGRAM_HEADER_SIZE;
+        elf[load_header + 0x10..load_header + 0x18].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        let path = write_test_file("sizes_past_the_end_of_the_file", &elf);
+        let elf = Elf::new(&path).unwrap();
+
+        let headers = elf.get_all_section_headers().unwrap();
+        let strings = headers.find_by_name(".strtab").unwrap();
+        assert!(matches!(
+            elf.get_section(strings),
+            Err(error) if error.kind() == io::ErrorKind::InvalidData
+        ));
+        assert!(elf.get_interpreter().is_err());
+        assert!(elf.get_needed_libraries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn elf_32_bits() {
+        let mut elf = build_elf(true);
+        elf[4] = 1;
+        let path = write_test_file("elf_32_bits", &elf);
+        assert!(matches!(Elf::new(&path), Err(Error::Not64Bits)));
+    }
+
+    #[test]
+    fn short_files_are_neither_elf_nor_pe() {
+        let script = write_test_file("script", b"#!/bin/sh\n");
+        assert!(matches!(Elf::new(&script), Err(Error::NotAnElf)));
+        assert!(matches!(pe::Pe::new(&script), Err(Error::NotAPe)));
+
+        let truncated_elf = write_test_file("truncated_elf", b"\x7FELF\x02\x01");
+        assert!(matches!(Elf::new(&truncated_elf), Err(Error::NotAnElf)));
+
+        // Starts like an MS-DOS executable, with the PE header past the end
+        let mut dos_stub = b"MZ".to_vec();
+        dos_stub.resize(64, 0);
+        dos_stub[0x3c] = 0x80;
+        let dos_stub = write_test_file("dos_stub", &dos_stub);
+        assert!(matches!(pe::Pe::new(&dos_stub), Err(Error::NotAPe)));
     }
 }
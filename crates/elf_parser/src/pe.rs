@@ -0,0 +1,424 @@
+//! The executables of Windows, in the PE/COFF format: their sections and the DLLs they import,
+//! which is what the test executables of a Windows build tree are recognized from
+
+use crate::{read_data, read_exact_at, read_header, Error, Result};
+use std::{fs::File, io, path::Path};
+
+const PE_SIGNATURE: &[u8] = b"PE\0\0";
+const COFF_HEADER_SIZE: u64 = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const IMPORT_DESCRIPTOR_SIZE: usize = 20;
+
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+// The data directories follow the fixed fields of the optional header, which are longer in PE32+
+const PE32_DATA_DIRECTORIES_OFFSET: usize = 96;
+const PE32_PLUS_DATA_DIRECTORIES_OFFSET: usize = 112;
+const IMPORT_DIRECTORY_INDEX: usize = 1;
+const DEBUG_DIRECTORY_INDEX: usize = 6;
+const DEBUG_DIRECTORY_ENTRY_SIZE: usize = 28;
+const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+
+const IMAGE_FILE_DLL: u16 = 0x2000;
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[derive(Debug, Clone)]
+pub struct PeSection {
+    /// Like .text or .rdata, truncated to 8 bytes
+    pub name: String,
+    pub virtual_address: u32,
+    pub virtual_size: u32,
+    pub raw_data_offset: u32,
+    pub raw_data_size: u32,
+}
+
+impl PeSection {
+    fn file_offset(&self, address: u32) -> Option<u64> {
+        let size = self.virtual_size.max(self.raw_data_size);
+        (self.virtual_address..self.virtual_address + size)
+            .contains(&address)
+            .then(|| (address - self.virtual_address + self.raw_data_offset) as u64)
+    }
+}
+
+#[derive(Debug)]
+pub struct Pe {
+    file: File,
+    /// The IMAGE_FILE_MACHINE of the COFF header, like 0x8664 for x86-64
+    pub machine: u16,
+    /// The IMAGE_FILE flags of the COFF header
+    pub characteristics: u16,
+    pub sections: Vec<PeSection>,
+    // The address and the size of every data directory, like the imports or the debug info
+    data_directories: Vec<(u32, u32)>,
+}
+
+impl Pe {
+    pub fn new(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+
+        let mut dos_header = [0u8; 64];
+        read_header(&file, &mut dos_header, Error::NotAPe)?;
+        if &dos_header[0..2] != b"MZ" {
+            return Err(Error::NotAPe);
+        }
+        let pe_offset = u32_at(&dos_header, 0x3c).unwrap() as u64;
+
+        let mut signature_and_header = [0u8; 4 + COFF_HEADER_SIZE as usize];
+        // Past the end of a file that only starts like an MS-DOS executable
+        read_exact_at(&file, &mut signature_and_header, pe_offset).map_err(|error| {
+            if error.kind() == io::ErrorKind::UnexpectedEof {
+                Error::NotAPe
+            } else {
+                Error::IoError(error)
+            }
+        })?;
+        if &signature_and_header[0..4] != PE_SIGNATURE {
+            return Err(Error::NotAPe);
+        }
+        let coff_header = &signature_and_header[4..];
+        let machine = u16_at(coff_header, 0).unwrap();
+        let num_sections = u16_at(coff_header, 2).unwrap() as usize;
+        let optional_header_size = u16_at(coff_header, 16).unwrap() as usize;
+        let characteristics = u16_at(coff_header, 18).unwrap();
+
+        let optional_header_offset = pe_offset + 4 + COFF_HEADER_SIZE;
+        let mut optional_header = vec![0u8; optional_header_size];
+        read_exact_at(&file, &mut optional_header, optional_header_offset)?;
+        let data_directories_offset = match u16_at(&optional_header, 0) {
+            Some(PE32_MAGIC) => PE32_DATA_DIRECTORIES_OFFSET,
+            Some(PE32_PLUS_MAGIC) => PE32_PLUS_DATA_DIRECTORIES_OFFSET,
+            _ => return Err(Error::NotAPe),
+        };
+        // Every data directory is an address and a size
+        let num_data_directories =
+            u32_at(&optional_header, data_directories_offset - 4).unwrap_or_default() as usize;
+        let data_directories = (0..num_data_directories)
+            .map_while(|index| {
+                let offset = data_directories_offset + index * 8;
+                u32_at(&optional_header, offset).zip(u32_at(&optional_header, offset + 4))
+            })
+            .collect();
+
+        let mut section_headers = vec![0u8; num_sections * SECTION_HEADER_SIZE];
+        read_exact_at(
+            &file,
+            &mut section_headers,
+            optional_header_offset + optional_header_size as u64,
+        )?;
+        let sections = section_headers
+            .chunks_exact(SECTION_HEADER_SIZE)
+            .map(|header| PeSection {
+                name: String::from_utf8_lossy(&header[0..8])
+                    .trim_end_matches('\0')
+                    .to_string(),
+                virtual_size: u32_at(header, 8).unwrap(),
+                virtual_address: u32_at(header, 12).unwrap(),
+                raw_data_size: u32_at(header, 16).unwrap(),
+                raw_data_offset: u32_at(header, 20).unwrap(),
+            })
+            .collect();
+
+        Ok(Self {
+            file,
+            machine,
+            characteristics,
+            sections,
+            data_directories,
+        })
+    }
+
+    /// DLLs are PE files as well, which can't be run
+    pub fn is_dll(&self) -> bool {
+        self.characteristics & IMAGE_FILE_DLL != 0
+    }
+
+    /// The name of the architecture, for the common values of the machine
+    pub fn machine_name(&self) -> Option<&'static str> {
+        Some(match self.machine {
+            0x014c => "x86",
+            0x8664 => "x86-64",
+            0xaa64 => "aarch64",
+            0x01c4 => "arm",
+            _ => return None,
+        })
+    }
+
+    pub fn find_section(&self, name: &str) -> Option<&PeSection> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Returns the content of a section in the file, without the zeroes that pad it in memory
+    pub fn get_section_data(&self, section: &PeSection) -> std::result::Result<Vec<u8>, io::Error> {
+        read_data(
+            &self.file,
+            section.raw_data_offset as u64,
+            section.raw_data_size as u64,
+        )
+    }
+
+    // Reads the bytes at an address of the loaded image, from the section containing it
+    fn read_at_address(
+        &self,
+        address: u32,
+        size: usize,
+    ) -> std::result::Result<Option<Vec<u8>>, io::Error> {
+        let Some(offset) = self
+            .sections
+            .iter()
+            .find_map(|section| section.file_offset(address))
+        else {
+            return Ok(None);
+        };
+        Ok(Some(read_data(&self.file, offset, size as u64)?))
+    }
+
+    fn data_directory(&self, index: usize) -> Option<(u32, u32)> {
+        self.data_directories
+            .get(index)
+            .copied()
+            .filter(|(address, _)| *address != 0)
+    }
+
+    /// Returns the signature of the PDB file of the executable, from its CodeView debug entry:
+    /// its GUID and its age, in hexadecimal like the symbol servers name them. Like the GNU
+    /// build-id, it identifies the build the executable comes from.
+    pub fn get_pdb_id(&self) -> std::result::Result<Option<String>, io::Error> {
+        let Some((address, size)) = self.data_directory(DEBUG_DIRECTORY_INDEX) else {
+            return Ok(None);
+        };
+        let Some(entries) = self.read_at_address(address, size as usize)? else {
+            return Ok(None);
+        };
+
+        for entry in entries.chunks_exact(DEBUG_DIRECTORY_ENTRY_SIZE) {
+            if u32_at(entry, 12) != Some(IMAGE_DEBUG_TYPE_CODEVIEW) {
+                continue;
+            }
+            let data_offset = u32_at(entry, 24).unwrap() as u64;
+            // "RSDS", the GUID and the age, followed by the path of the PDB file
+            let mut codeview = [0u8; 24];
+            read_exact_at(&self.file, &mut codeview, data_offset)?;
+            if &codeview[0..4] != b"RSDS" {
+                continue;
+            }
+            let guid = &codeview[4..20];
+            let age = u32_at(&codeview, 20).unwrap();
+            // The first three fields of the GUID are little endian integers
+            let mut id = format!(
+                "{:08X}{:04X}{:04X}",
+                u32_at(guid, 0).unwrap(),
+                u16_at(guid, 4).unwrap(),
+                u16_at(guid, 6).unwrap()
+            );
+            for byte in &guid[8..] {
+                id += &format!("{byte:02X}");
+            }
+            id += &format!("{age:X}");
+            return Ok(Some(id));
+        }
+        Ok(None)
+    }
+
+    /// Returns the names of the DLLs that the executable imports, like gtest.dll or KERNEL32.dll
+    pub fn get_imported_dlls(&self) -> std::result::Result<Vec<String>, io::Error> {
+        let Some((address, size)) = self.data_directory(IMPORT_DIRECTORY_INDEX) else {
+            return Ok(Vec::new());
+        };
+        let Some(descriptors) = self.read_at_address(address, size as usize)? else {
+            return Ok(Vec::new());
+        };
+
+        let mut dlls = Vec::new();
+        // The descriptors end with one full of zeroes
+        for descriptor in descriptors.chunks_exact(IMPORT_DESCRIPTOR_SIZE) {
+            let name_address = u32_at(descriptor, 12).unwrap();
+            if name_address == 0 {
+                break;
+            }
+            // The names are short, and the section may end right after the last one
+            let Some(section) = self
+                .sections
+                .iter()
+                .find(|section| section.file_offset(name_address).is_some())
+            else {
+                continue;
+            };
+            let available =
+                (section.virtual_address + section.raw_data_size).saturating_sub(name_address);
+            let Some(name) = self.read_at_address(name_address, available.min(256) as usize)?
+            else {
+                continue;
+            };
+            let end = name
+                .iter()
+                .position(|byte| *byte == 0)
+                .unwrap_or(name.len());
+            dlls.push(String::from_utf8_lossy(&name[..end]).into_owned());
+        }
+        Ok(dlls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::write_test_file;
+
+    fn put(data: &mut Vec<u8>, offset: usize, bytes: &[u8]) {
+        if data.len() < offset + bytes.len() {
+            data.resize(offset + bytes.len(), 0);
+        }
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // An executable importing two DLLs, with a CodeView debug entry, laid out like:
+    //   0x000  MS-DOS header, pointing to the PE signature at 0x80
+    //   0x080  PE signature, COFF header and optional header
+    //   ...    2 section headers: .text and .rdata
+    //   0x200  .text, at the address 0x1000
+    //   0x400  .rdata, at the address 0x2000, with the import descriptors, the names of the DLLs,
+    //          the debug directory and the CodeView entry
+    fn build_pe(pe32_plus: bool, characteristics: u16) -> Vec<u8> {
+        let mut pe = b"MZ".to_vec();
+        put(&mut pe, 0x3c, &0x80u32.to_le_bytes());
+        put(&mut pe, 0x80, PE_SIGNATURE);
+
+        let data_directories_offset = if pe32_plus {
+            PE32_PLUS_DATA_DIRECTORIES_OFFSET
+        } else {
+            PE32_DATA_DIRECTORIES_OFFSET
+        };
+        let optional_header_size = data_directories_offset + 16 * 8;
+        let coff_header = 0x84;
+        put(&mut pe, coff_header, &0x8664u16.to_le_bytes());
+        put(&mut pe, coff_header + 2, &2u16.to_le_bytes());
+        put(
+            &mut pe,
+            coff_header + 16,
+            &(optional_header_size as u16).to_le_bytes(),
+        );
+        put(&mut pe, coff_header + 18, &characteristics.to_le_bytes());
+
+        let optional_header = coff_header + COFF_HEADER_SIZE as usize;
+        let magic = if pe32_plus {
+            PE32_PLUS_MAGIC
+        } else {
+            PE32_MAGIC
+        };
+        put(&mut pe, optional_header, &magic.to_le_bytes());
+        put(
+            &mut pe,
+            optional_header + data_directories_offset - 4,
+            &16u32.to_le_bytes(),
+        );
+        for (index, address, size) in [
+            (IMPORT_DIRECTORY_INDEX, 0x2000u32, 60u32),
+            (
+                DEBUG_DIRECTORY_INDEX,
+                0x2100,
+                DEBUG_DIRECTORY_ENTRY_SIZE as u32,
+            ),
+        ] {
+            let directory = optional_header + data_directories_offset + index * 8;
+            put(&mut pe, directory, &address.to_le_bytes());
+            put(&mut pe, directory + 4, &size.to_le_bytes());
+        }
+
+        let section_headers = optional_header + optional_header_size;
+        for (index, (name, virtual_address, raw_data_offset)) in [
+            (&b".text"[..], 0x1000u32, 0x200u32),
+            (b".rdata", 0x2000, 0x400),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let header = section_headers + index * SECTION_HEADER_SIZE;
+            put(&mut pe, header, name);
+            put(&mut pe, header + 8, &0x180u32.to_le_bytes());
+            put(&mut pe, header + 12, &virtual_address.to_le_bytes());
+            put(&mut pe, header + 16, &0x200u32.to_le_bytes());
+            put(&mut pe, header + 20, &raw_data_offset.to_le_bytes());
+        }
+
+        // The import descriptors end with one full of zeroes
+        for (index, name_address) in [0x2080u32, 0x2090].into_iter().enumerate() {
+            put(
+                &mut pe,
+                0x400 + index * IMPORT_DESCRIPTOR_SIZE + 12,
+                &name_address.to_le_bytes(),
+            );
+        }
+        put(&mut pe, 0x480, b"gtest.dll\0");
+        put(&mut pe, 0x490, b"KERNEL32.dll\0");
+
+        put(
+            &mut pe,
+            0x500 + 12,
+            &IMAGE_DEBUG_TYPE_CODEVIEW.to_le_bytes(),
+        );
+        put(&mut pe, 0x500 + 24, &0x520u32.to_le_bytes());
+        put(&mut pe, 0x520, b"RSDS");
+        put(&mut pe, 0x524, &0x12345678u32.to_le_bytes());
+        put(&mut pe, 0x528, &0x9ABCu16.to_le_bytes());
+        put(&mut pe, 0x52A, &0xDEF0u16.to_le_bytes());
+        put(&mut pe, 0x52C, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        put(&mut pe, 0x534, &1u32.to_le_bytes());
+        put(&mut pe, 0x538, b"C:\\build\\test.pdb\0");
+
+        pe.resize(0x600, 0);
+        pe
+    }
+
+    #[test]
+    fn pe_executables() {
+        for pe32_plus in [true, false] {
+            let path = write_test_file(&format!("pe_{pe32_plus}"), &build_pe(pe32_plus, 0x22));
+            let pe = Pe::new(&path).unwrap();
+
+            assert_eq!(pe.machine_name(), Some("x86-64"));
+            assert!(!pe.is_dll());
+            let names = pe.sections.iter().map(|section| section.name.as_str());
+            assert_eq!(names.collect::<Vec<_>>(), [".text", ".rdata"]);
+
+            let rdata = pe.find_section(".rdata").unwrap();
+            let data = pe.get_section_data(rdata).unwrap();
+            assert_eq!(data.len(), 0x200);
+            assert_eq!(&data[0x80..0x89], b"gtest.dll");
+
+            assert_eq!(
+                pe.get_imported_dlls().unwrap(),
+                ["gtest.dll", "KERNEL32.dll"]
+            );
+            assert_eq!(
+                pe.get_pdb_id().unwrap().as_deref(),
+                Some("123456789ABCDEF001020304050607081")
+            );
+        }
+    }
+
+    #[test]
+    fn pe_dll() {
+        let path = write_test_file("pe_dll", &build_pe(true, 0x2022));
+        assert!(Pe::new(&path).unwrap().is_dll());
+    }
+
+    #[test]
+    fn not_a_pe() {
+        let mut pe = build_pe(true, 0x22);
+        put(&mut pe, 0x80, b"NE\0\0");
+        let path = write_test_file("not_a_pe", &pe);
+        assert!(matches!(Pe::new(&path), Err(Error::NotAPe)));
+    }
+}